@@ -0,0 +1,82 @@
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+
+use zetr::bus::Memory;
+use zetr::cartridge::Cartridge;
+use zetr::cpu::CPU;
+use zetr::nes::NES;
+use zetr::ppu::PPU;
+
+const ROM: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/donkeykong.nes");
+const DOTS_PER_FRAME: usize = 341 * 262;
+
+struct FlatMemory {
+    ram: Vec<u8>,
+}
+
+impl Memory for FlatMemory {
+    fn read(&mut self, addr: u16) -> u8 {
+        self.ram[addr as usize]
+    }
+
+    fn write(&mut self, addr: u16, data: u8) {
+        self.ram[addr as usize] = data;
+    }
+}
+
+// One frame of PPU dots with background and sprites enabled, no CPU involved.
+fn ppu_frame(c: &mut Criterion) {
+    let mut cart = Cartridge::new(ROM).expect("donkeykong.nes is part of the repo");
+    let mut ppu = PPU::new();
+    ppu.cpu_write(0x2000, 0x80, &mut cart);
+    ppu.cpu_write(0x2001, 0x1E, &mut cart);
+    c.bench_function("ppu_frame", |b| {
+        b.iter(|| {
+            for _ in 0..DOTS_PER_FRAME {
+                ppu.step(&mut cart);
+            }
+        })
+    });
+}
+
+// A loop mixing loads, stores, arithmetic, compares and branches.
+fn cpu_dispatch(c: &mut Criterion) {
+    let program = [
+        0xA2, 0x00,       // LDX #$00
+        0xBD, 0x00, 0x02, // loop: LDA $0200,X
+        0x69, 0x03,       // ADC #$03
+        0x9D, 0x00, 0x03, // STA $0300,X
+        0x48,             // PHA
+        0x68,             // PLA
+        0xE8,             // INX
+        0xE0, 0x80,       // CPX #$80
+        0xD0, 0xF1,       // BNE loop
+        0x4C, 0x00, 0x80, // JMP $8000
+    ];
+    let mut memory = FlatMemory { ram: vec![0; 0x10000] };
+    memory.ram[0x8000..0x8000 + program.len()].copy_from_slice(&program);
+    memory.ram[0xFFFD] = 0x80;
+    let mut cpu = CPU::new();
+    cpu.reset(&mut memory);
+    c.bench_function("cpu_dispatch_10k", |b| {
+        b.iter(|| {
+            for _ in 0..10_000 {
+                black_box(cpu.step(&mut memory));
+            }
+        })
+    });
+}
+
+// The whole machine: CPU, PPU, DMA and the debugger hooks.
+fn nes_frame(c: &mut Criterion) {
+    let mut nes = NES::new();
+    nes.load_cartridge(ROM).expect("donkeykong.nes is part of the repo");
+    c.bench_function("nes_frame", |b| {
+        b.iter(|| {
+            nes.run_frame();
+            nes.frame_done();
+        })
+    });
+}
+
+criterion_group!(benches, ppu_frame, cpu_dispatch, nes_frame);
+criterion_main!(benches);
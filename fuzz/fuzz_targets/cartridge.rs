@@ -0,0 +1,18 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use zetr::cartridge::Cartridge;
+
+// Any byte string must either load or fail with an error, never panic.
+// A cartridge that loads must also survive reads across the whole address space.
+fuzz_target!(|data: &[u8]| {
+    if let Ok(cart) = Cartridge::from_bytes(data) {
+        for addr in (0..0x8000).step_by(0x3FF) {
+            cart.read_prg(addr);
+            cart.read_prg_ram(addr & 0x1FFF);
+        }
+        for addr in (0..0x2000).step_by(0xFF) {
+            cart.read_chr(addr);
+        }
+    }
+});
@@ -0,0 +1,43 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use zetr::bus::Memory;
+use zetr::cpu::CPU;
+
+struct FlatMemory {
+    ram: Vec<u8>,
+}
+
+impl Memory for FlatMemory {
+    fn read(&mut self, addr: u16) -> u8 {
+        self.ram[addr as usize]
+    }
+
+    fn write(&mut self, addr: u16, data: u8) {
+        self.ram[addr as usize] = data;
+    }
+}
+
+const STEPS: usize = 1000;
+
+// Runs the input as a program at $8000 (repeated to fill the top of memory, with the
+// vectors pointing into it) and checks that two runs end in the same state.
+fn run(program: &[u8]) -> (u8, u8, u8, u16, u8, u8, u64, Vec<u8>) {
+    let mut memory = FlatMemory { ram: vec![0; 0x10000] };
+    for (i, byte) in memory.ram[0x8000..].iter_mut().enumerate() {
+        *byte = program[i % program.len()];
+    }
+    let mut cpu = CPU::new();
+    cpu.reset(&mut memory);
+    for _ in 0..STEPS {
+        cpu.step(&mut memory);
+    }
+    (cpu.a, cpu.x, cpu.y, cpu.pc, cpu.sp, cpu.status, cpu.cycles, memory.ram)
+}
+
+fuzz_target!(|data: &[u8]| {
+    if data.is_empty() {
+        return;
+    }
+    assert!(run(data) == run(data), "CPU execution is not deterministic");
+});
@@ -0,0 +1,644 @@
+use std::io::{self, Read, Write};
+
+use crate::region::Region;
+use crate::stateful;
+use crate::stateful::Stateful;
+
+// Audio Processing Unit. Only the two pulse channels ($4000-$4007) and the frame counter
+// are emulated so far:
+//
+//   $4000/$4004  DDLC VVVV  duty, length halt / envelope loop, constant volume, volume or period
+//   $4001/$4005  EPPP NSSS  sweep enable, period, negate, shift
+//   $4002/$4006  TTTT TTTT  timer low
+//   $4003/$4007  LLLL LTTT  length counter load, timer high; restarts the envelope and duty
+//   $4015 write  ---D NT21  channel enables; a disabled channel's length counter is held at 0
+//   $4015 read   IF-D NT21  DMC IRQ, frame IRQ, channels with length left; clears the frame IRQ
+//   $4017        MI-- ----  frame counter: 5-step mode, IRQ inhibit
+//
+// The triangle, noise and DMC bits of $4015 read as 0 until those channels exist.
+//
+// It runs on the CPU clock. The mixed output is averaged down to the sample rate the
+// frontend asks for and collected until it takes the samples.
+//
+// Between the DAC and the audio jack the console has two high-pass filters (about 90 Hz and
+// 440 Hz) and a low-pass one (about 14 kHz). They take the DC offset out of the signal and
+// soften the edges of the square waves, and are a good part of why a recording of the real
+// thing sounds less harsh than the bare channels. They are applied to the output samples,
+// unless turned off.
+//
+// Output is mono unless the channels are panned, which gives interleaved left and right
+// samples. Each side then runs the DAC formula on its share of every channel.
+
+const DUTY: [[u8; 8]; 4] = [
+    [0, 1, 0, 0, 0, 0, 0, 0],
+    [0, 1, 1, 0, 0, 0, 0, 0],
+    [0, 1, 1, 1, 1, 0, 0, 0],
+    [1, 0, 0, 1, 1, 1, 1, 1],
+];
+
+// Frame counter steps in CPU cycles since it was reset, run as scheduler events by NES.
+// Quarter frames clock the envelopes, half frames (every other quarter) also the length
+// counters and sweeps. The 4-step sequence raises the frame IRQ over its last three cycles;
+// the 5-step one has a silent fourth step and no IRQ.
+struct FrameSteps {
+    quarters: [u32; 4],
+    four_step_end: u32,
+    five_step_half: u32,
+    five_step_end: u32,
+}
+
+const NTSC_STEPS: FrameSteps = FrameSteps {
+    quarters: [7457, 14913, 22371, 29829],
+    four_step_end: 29830,
+    five_step_half: 37281,
+    five_step_end: 37282,
+};
+
+const PAL_STEPS: FrameSteps = FrameSteps {
+    quarters: [8313, 16627, 24939, 33253],
+    four_step_end: 33254,
+    five_step_half: 41565,
+    five_step_end: 41566,
+};
+
+const LENGTH: [u8; 32] = [
+    10, 254, 20, 2, 40, 4, 80, 6, 160, 8, 60, 10, 14, 12, 26, 14,
+    12, 16, 24, 18, 48, 20, 96, 22, 192, 24, 72, 26, 16, 28, 32, 30,
+];
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Channel {
+    Pulse1,
+    Pulse2,
+    Triangle,
+    Noise,
+    Dmc,
+}
+
+impl Channel {
+    pub const ALL: [Channel; 5] = [Channel::Pulse1, Channel::Pulse2, Channel::Triangle, Channel::Noise, Channel::Dmc];
+
+    // Config name: pulse1, pulse2, triangle, noise or dmc
+    pub fn parse(name: &str) -> Option<Self> {
+        Channel::ALL.into_iter().find(|c| c.name().replace(' ', "").eq_ignore_ascii_case(name))
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Channel::Pulse1 => "Pulse 1",
+            Channel::Pulse2 => "Pulse 2",
+            Channel::Triangle => "Triangle",
+            Channel::Noise => "Noise",
+            Channel::Dmc => "DMC",
+        }
+    }
+
+    fn bit(self) -> u8 {
+        1 << self as u8
+    }
+}
+
+// First-order RC filter at the output sample rate.
+struct Filter {
+    high_pass: bool,
+    alpha: f32,
+    last_in: f32,
+    last_out: f32,
+}
+
+impl Filter {
+    fn new(high_pass: bool, cutoff: f64, sample_rate: u32) -> Self {
+        let rc = 1.0 / (2.0 * std::f64::consts::PI * cutoff);
+        let dt = 1.0 / sample_rate as f64;
+        let alpha = if high_pass { rc / (rc + dt) } else { dt / (rc + dt) };
+        Filter { high_pass, alpha: alpha as f32, last_in: 0.0, last_out: 0.0 }
+    }
+
+    fn apply(&mut self, x: f32) -> f32 {
+        self.last_out = if self.high_pass {
+            self.alpha * (self.last_out + x - self.last_in)
+        } else {
+            self.last_out + self.alpha * (x - self.last_out)
+        };
+        self.last_in = x;
+        self.last_out
+    }
+}
+
+// pulse_out = 95.52 / (8128 / (pulse1 + pulse2) + 100), by the sum of the two volumes
+const PULSE_MIX: [f32; 31] = {
+    let mut table = [0.0; 31];
+    let mut n = 1;
+    while n < 31 {
+        table[n] = 95.52 / (8128.0 / n as f32 + 100.0);
+        n += 1;
+    }
+    table
+};
+
+#[derive(Default)]
+struct Pulse {
+    second: bool, // Pulse 2 negates its sweep in two's complement, pulse 1 in one's
+    enabled: bool, // By $4015
+    duty: u8,
+    step: u8,
+    period: u16,
+    timer: u16,
+    length: u8,
+    halt: bool, // Also loops the envelope
+    constant: bool,
+    volume: u8, // Constant volume, or the envelope's period
+    envelope_start: bool,
+    envelope_divider: u8,
+    decay: u8,
+    sweep_enabled: bool,
+    sweep_period: u8,
+    sweep_negate: bool,
+    sweep_shift: u8,
+    sweep_reload: bool,
+    sweep_divider: u8,
+}
+
+stateful!(Pulse {
+    enabled, duty, step, period, timer, length, halt, constant, volume, envelope_start,
+    envelope_divider, decay, sweep_enabled, sweep_period, sweep_negate, sweep_shift,
+    sweep_reload, sweep_divider,
+});
+
+// A pulse channel's registers and counters as they stand, for the APU viewer.
+#[derive(Debug, Clone, Copy)]
+pub struct PulseState {
+    pub enabled: bool,
+    pub duty: u8,
+    pub period: u16,
+    pub constant: bool,
+    pub volume: u8, // Constant volume, or the envelope's period
+    pub decay: u8,  // Envelope level
+    pub halt: bool, // Length counter halted, envelope looping
+    pub length: u8,
+    pub sweep_enabled: bool,
+    pub sweep_period: u8,
+    pub sweep_negate: bool,
+    pub sweep_shift: u8,
+    pub sweep_muted: bool,
+}
+
+impl Pulse {
+    fn state(&self) -> PulseState {
+        PulseState {
+            enabled: self.enabled,
+            duty: self.duty,
+            period: self.period,
+            constant: self.constant,
+            volume: self.volume,
+            decay: self.decay,
+            halt: self.halt,
+            length: self.length,
+            sweep_enabled: self.sweep_enabled,
+            sweep_period: self.sweep_period,
+            sweep_negate: self.sweep_negate,
+            sweep_shift: self.sweep_shift,
+            sweep_muted: self.sweep_muted(),
+        }
+    }
+
+    fn write(&mut self, reg: u16, data: u8) {
+        match reg {
+            0 => {
+                self.duty = data >> 6;
+                self.halt = data & 0x20 != 0;
+                self.constant = data & 0x10 != 0;
+                self.volume = data & 0x0F;
+            }
+            1 => {
+                self.sweep_enabled = data & 0x80 != 0;
+                self.sweep_period = (data >> 4) & 0x07;
+                self.sweep_negate = data & 0x08 != 0;
+                self.sweep_shift = data & 0x07;
+                self.sweep_reload = true;
+            }
+            2 => self.period = (self.period & 0x700) | data as u16,
+            _ => {
+                self.period = (self.period & 0xFF) | ((data as u16 & 0x07) << 8);
+                if self.enabled {
+                    self.length = LENGTH[(data >> 3) as usize];
+                }
+                self.step = 0;
+                self.envelope_start = true;
+            }
+        }
+    }
+
+    fn set_enabled(&mut self, enabled: bool) {
+        self.enabled = enabled;
+        if !enabled {
+            self.length = 0;
+        }
+    }
+
+    // Every other CPU cycle
+    fn clock_timer(&mut self) {
+        if self.timer == 0 {
+            self.timer = self.period;
+            self.step = (self.step + 1) % 8;
+        } else {
+            self.timer -= 1;
+        }
+    }
+
+    fn clock_envelope(&mut self) {
+        if self.envelope_start {
+            self.envelope_start = false;
+            self.decay = 15;
+            self.envelope_divider = self.volume;
+        } else if self.envelope_divider == 0 {
+            self.envelope_divider = self.volume;
+            if self.decay > 0 {
+                self.decay -= 1;
+            } else if self.halt {
+                self.decay = 15;
+            }
+        } else {
+            self.envelope_divider -= 1;
+        }
+    }
+
+    fn clock_length_and_sweep(&mut self) {
+        if !self.halt && self.length > 0 {
+            self.length -= 1;
+        }
+        if self.sweep_divider == 0 && self.sweep_enabled && self.sweep_shift > 0 && !self.sweep_muted() {
+            self.period = self.sweep_target();
+        }
+        if self.sweep_divider == 0 || self.sweep_reload {
+            self.sweep_divider = self.sweep_period;
+            self.sweep_reload = false;
+        } else {
+            self.sweep_divider -= 1;
+        }
+    }
+
+    fn sweep_target(&self) -> u16 {
+        let change = self.period >> self.sweep_shift;
+        if !self.sweep_negate {
+            self.period + change
+        } else if self.second {
+            self.period.saturating_sub(change)
+        } else {
+            self.period.saturating_sub(change + 1)
+        }
+    }
+
+    // The sweep silences the channel whenever its target is out of range, even while disabled.
+    fn sweep_muted(&self) -> bool {
+        self.period < 8 || self.sweep_target() > 0x7FF
+    }
+
+    fn output(&self) -> u8 {
+        if self.length == 0 || self.sweep_muted() || DUTY[self.duty as usize][self.step as usize] == 0 {
+            0
+        } else if self.constant {
+            self.volume
+        } else {
+            self.decay
+        }
+    }
+}
+
+pub struct APU {
+    pulse1: Pulse,
+    pulse2: Pulse,
+    odd_cycle: bool,
+    five_step: bool,
+    irq_inhibit: bool,
+    frame_irq: bool,
+    frame_write: Option<u8>, // A $4017 write's reset delay, until NES schedules it
+    region: Region,
+
+    // Output, which isn't part of the state
+    sample_rate: u32,
+    rate_ratio: f64,
+    cycles_per_sample: f64,
+    sample_clock: f64,
+    sum: [f32; 2],
+    count: u32,
+    samples: Vec<f32>,
+    pan: Option<[f32; 5]>, // By channel, -1.0 (left) to 1.0 (right)
+    filtered: bool,
+    filters: Vec<Filter>, // One chain per output channel
+    muted: u8, // By Channel::bit
+}
+
+stateful!(APU { pulse1, pulse2, odd_cycle });
+
+#[derive(Debug, Clone, Copy)]
+pub struct ApuState {
+    pub pulses: [PulseState; 2],
+    pub five_step: bool,
+    pub irq_inhibit: bool,
+    pub frame_irq: bool,
+}
+
+// Where the frame sequence is, which NES keeps as scheduled events. It is saved with the APU.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct FrameTiming {
+    pub cycle: u32, // CPU cycles into the frame sequence
+    pub reset: u8,  // CPU cycles until a $4017 write resets the sequence, 0 for none
+}
+
+impl APU {
+    pub fn new() -> Self {
+        APU {
+            pulse1: Pulse::default(),
+            pulse2: Pulse { second: true, ..Pulse::default() },
+            odd_cycle: false,
+            five_step: false,
+            irq_inhibit: false,
+            frame_irq: false,
+            frame_write: None,
+            region: Region::Ntsc,
+            sample_rate: 0,
+            rate_ratio: 1.0,
+            cycles_per_sample: 0.0,
+            sample_clock: 0.0,
+            sum: [0.0; 2],
+            count: 0,
+            samples: Vec::new(),
+            pan: None,
+            filtered: true,
+            filters: Vec::new(),
+            muted: 0,
+        }
+    }
+
+    pub fn set_region(&mut self, region: Region) {
+        self.region = region;
+        self.set_sample_rate(self.sample_rate);
+    }
+
+    // Output samples per second; 0, the default, keeps no samples.
+    pub fn set_sample_rate(&mut self, rate: u32) {
+        self.sample_rate = rate;
+        self.update_cycles_per_sample();
+        self.samples.clear();
+        self.update_filters();
+    }
+
+    // The console's output filters, on by default. Off, samples are the bare mix, 0.0 to
+    // about 0.26.
+    pub fn set_filters(&mut self, on: bool) {
+        self.filtered = on;
+        self.update_filters();
+    }
+
+    pub fn filters(&self) -> bool {
+        self.filtered
+    }
+
+    // Leaves a channel out of the mix, for listening to a tune's parts one by one. It keeps
+    // running, so unmuting it picks up wherever the music is.
+    pub fn set_muted(&mut self, channel: Channel, muted: bool) {
+        if muted {
+            self.muted |= channel.bit();
+        } else {
+            self.muted &= !channel.bit();
+        }
+    }
+
+    pub fn muted(&self, channel: Channel) -> bool {
+        self.muted & channel.bit() != 0
+    }
+
+    // Mutes every other channel, or unmutes them all if this one was already the only one
+    // playing.
+    pub fn solo(&mut self, channel: Channel) {
+        let others = 0x1F & !channel.bit();
+        self.muted = if self.muted == others { 0 } else { others };
+    }
+
+    // Stereo output with each channel placed by `pan`, or mono for None.
+    pub fn set_panning(&mut self, pan: Option<[f32; 5]>) {
+        self.pan = pan;
+        self.samples.clear();
+        self.update_filters();
+    }
+
+    pub fn panning(&self) -> Option<[f32; 5]> {
+        self.pan
+    }
+
+    // Samples per frame of output: 1 or 2.
+    pub fn output_channels(&self) -> usize {
+        if self.pan.is_some() { 2 } else { 1 }
+    }
+
+    fn update_filters(&mut self) {
+        self.filters.clear();
+        if self.filtered && self.sample_rate > 0 {
+            for _ in 0..self.output_channels() {
+                self.filters.push(Filter::new(true, 90.0, self.sample_rate));
+                self.filters.push(Filter::new(true, 440.0, self.sample_rate));
+                self.filters.push(Filter::new(false, 14000.0, self.sample_rate));
+            }
+        }
+    }
+
+    // Produces `ratio` times as many samples as the sample rate says, so an audio output
+    // can keep its buffer level without resampling.
+    pub fn adjust_rate(&mut self, ratio: f64) {
+        self.rate_ratio = ratio;
+        self.update_cycles_per_sample();
+    }
+
+    fn update_cycles_per_sample(&mut self) {
+        let rate = self.sample_rate as f64 * self.rate_ratio;
+        self.cycles_per_sample = if rate > 0.0 { self.region.cpu_clock() / rate } else { 0.0 };
+    }
+
+    pub fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    pub fn write(&mut self, addr: u16, data: u8) {
+        match addr {
+            0x4000..=0x4003 => self.pulse1.write(addr - 0x4000, data),
+            0x4004..=0x4007 => self.pulse2.write(addr - 0x4004, data),
+            0x4015 => {
+                self.pulse1.set_enabled(data & 0x01 != 0);
+                self.pulse2.set_enabled(data & 0x02 != 0);
+            }
+            0x4017 => {
+                self.five_step = data & 0x80 != 0;
+                self.irq_inhibit = data & 0x40 != 0;
+                if self.irq_inhibit {
+                    self.frame_irq = false;
+                }
+                // The sequence restarts 3 or 4 cycles later, depending on the cycle the
+                // write landed on
+                self.frame_write = Some(if self.odd_cycle { 4 } else { 3 });
+            }
+            _ => {}
+        }
+    }
+
+    // $4015. Reading acknowledges the frame IRQ.
+    pub fn read_status(&mut self) -> u8 {
+        let status = (self.frame_irq as u8) << 6 | ((self.pulse2.length > 0) as u8) << 1 | (self.pulse1.length > 0) as u8;
+        self.frame_irq = false;
+        status
+    }
+
+    // The channels and frame counter, for the APU viewer.
+    pub fn state(&self) -> ApuState {
+        ApuState {
+            pulses: [self.pulse1.state(), self.pulse2.state()],
+            five_step: self.five_step,
+            irq_inhibit: self.irq_inhibit,
+            frame_irq: self.frame_irq,
+        }
+    }
+
+    // The frame IRQ line, held until acknowledged by $4015 or inhibited.
+    pub fn irq(&self) -> bool {
+        self.frame_irq
+    }
+
+    // One CPU cycle, followed by the frame sequence events due on it and sample().
+    pub fn step(&mut self) {
+        if self.odd_cycle {
+            self.pulse1.clock_timer();
+            self.pulse2.clock_timer();
+        }
+        self.odd_cycle = !self.odd_cycle;
+    }
+
+    pub fn sample(&mut self) {
+        if self.cycles_per_sample > 0.0 {
+            let mix = self.mix();
+            self.sum[0] += mix[0];
+            self.sum[1] += mix[1];
+            self.count += 1;
+            self.sample_clock += 1.0;
+            if self.sample_clock >= self.cycles_per_sample {
+                self.sample_clock -= self.cycles_per_sample;
+                let chains = self.filters.len() / self.output_channels();
+                for side in 0..self.output_channels() {
+                    let filters = self.filters.iter_mut().skip(side * chains).take(chains);
+                    let sample = filters.fold(self.sum[side] / self.count as f32, |x, filter| filter.apply(x));
+                    self.samples.push(sample);
+                }
+                self.sum = [0.0; 2];
+                self.count = 0;
+            }
+        }
+    }
+
+    fn frame_steps(&self) -> &'static FrameSteps {
+        match self.region {
+            Region::Pal => &PAL_STEPS,
+            Region::Ntsc | Region::Dendy => &NTSC_STEPS,
+        }
+    }
+
+    // The cycles into the sequence that something can happen on, in either mode, since the
+    // mode can change before a pending reset takes effect.
+    pub fn frame_step_cycles(&self) -> [u32; 8] {
+        let steps = self.frame_steps();
+        let [first, second, third, fourth] = steps.quarters;
+        [first, second, third, fourth - 1, fourth, steps.four_step_end, steps.five_step_half, steps.five_step_end]
+    }
+
+    // Where the sequence restarts in the current mode.
+    pub fn frame_end(&self) -> u32 {
+        let steps = self.frame_steps();
+        if self.five_step { steps.five_step_end } else { steps.four_step_end }
+    }
+
+    // The delay of a $4017 write since the last call, for NES to schedule the reset.
+    pub fn take_frame_write(&mut self) -> Option<u8> {
+        self.frame_write.take()
+    }
+
+    // The sequence restarting after a $4017 write.
+    pub fn reset_frame_sequence(&mut self) {
+        // Switching to 5-step mode clocks everything at once
+        if self.five_step {
+            self.clock_quarter_frame(true);
+        }
+    }
+
+    // The sequence reaching `cycle`. True when it restarts from there.
+    pub fn frame_step(&mut self, cycle: u32) -> bool {
+        let steps = self.frame_steps();
+        match steps.quarters.iter().position(|&quarter| quarter == cycle) {
+            Some(3) if self.five_step => {}
+            Some(step) => self.clock_quarter_frame(step % 2 == 1),
+            None if self.five_step && cycle == steps.five_step_half => self.clock_quarter_frame(true),
+            None => {}
+        }
+        if !self.five_step && !self.irq_inhibit && (steps.quarters[3] - 1..=steps.four_step_end).contains(&cycle) {
+            self.frame_irq = true;
+        }
+        cycle >= self.frame_end()
+    }
+
+    fn clock_quarter_frame(&mut self, half: bool) {
+        self.pulse1.clock_envelope();
+        self.pulse2.clock_envelope();
+        if half {
+            self.pulse1.clock_length_and_sweep();
+            self.pulse2.clock_length_and_sweep();
+        }
+    }
+
+    // The pulse DAC, 0.0 to about 0.26, for the left and right sides (the same when mono).
+    // It isn't linear: each channel is quieter with the other one loud. The triangle, noise
+    // and DMC DAC (tnd_out) adds to this once they exist.
+    fn mix(&self) -> [f32; 2] {
+        let pulse1 = if self.muted(Channel::Pulse1) { 0 } else { self.pulse1.output() };
+        let pulse2 = if self.muted(Channel::Pulse2) { 0 } else { self.pulse2.output() };
+        let Some(pan) = self.pan else {
+            let mono = PULSE_MIX[(pulse1 + pulse2) as usize];
+            return [mono, mono];
+        };
+        // A centered channel plays at full level on both sides
+        let side = |gain: fn(f32) -> f32| {
+            let n = pulse1 as f32 * gain(pan[0]) + pulse2 as f32 * gain(pan[1]);
+            if n > 0.0 { 95.52 / (8128.0 / n + 100.0) } else { 0.0 }
+        };
+        [side(|pan| (1.0 - pan).min(1.0)), side(|pan| (1.0 + pan).min(1.0))]
+    }
+
+    // Samples produced since the last call, interleaved when stereo.
+    pub fn take_samples(&mut self) -> Vec<f32> {
+        std::mem::take(&mut self.samples)
+    }
+
+    // The frame sequence fields go where they were when the APU kept them itself.
+    pub fn save_state(&self, w: &mut dyn Write, timing: FrameTiming) -> io::Result<()> {
+        Stateful::save(self, w)?;
+        timing.cycle.save(w)?;
+        self.five_step.save(w)?;
+        self.irq_inhibit.save(w)?;
+        self.frame_irq.save(w)?;
+        timing.reset.save(w)
+    }
+
+    pub fn load_state(&mut self, r: &mut dyn Read) -> io::Result<FrameTiming> {
+        let mut timing = FrameTiming::default();
+        Stateful::load(self, r)?;
+        timing.cycle.load(r)?;
+        self.five_step.load(r)?;
+        self.irq_inhibit.load(r)?;
+        self.frame_irq.load(r)?;
+        timing.reset.load(r)?;
+        self.frame_write = None;
+        Ok(timing)
+    }
+}
+
+impl Default for APU {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -0,0 +1,51 @@
+use crate::apu::{ApuState, PulseState};
+use crate::osd;
+use crate::region::Region;
+
+// Live APU state in the top-left corner (F4), under the counters if they are up, redrawn
+// every frame, for writing and debugging sound engines:
+//
+//   FRAME COUNTER 4-STEP  IRQ ON
+//   PULSE 1  ON  DUTY 50%  LEN 12
+//    $0FD  440.4 HZ  VOL 15
+//    ENV 3 LOOP  SWEEP 2 - 3
+//
+// VOL is what the channel plays at when its duty step is high: the constant volume, or the
+// envelope's level with ENV giving its period. A period or sweep that takes the channel out
+// of range is marked MUTED.
+
+pub const LINE_HEIGHT: usize = osd::CHAR_HEIGHT + 3;
+const DUTY: [&str; 4] = ["12.5%", "25%", "50%", "75%"];
+
+fn pulse_lines(number: usize, pulse: &PulseState, region: Region) -> [String; 3] {
+    let length = if pulse.halt { format!("LEN {} HALT", pulse.length) } else { format!("LEN {}", pulse.length) };
+    let hz = region.cpu_clock() / (16.0 * (pulse.period as f64 + 1.0));
+    let level = if pulse.constant { pulse.volume } else { pulse.decay };
+    let muted = if pulse.sweep_muted { "  MUTED" } else { "" };
+    let envelope = match (pulse.constant, pulse.halt) {
+        (true, _) => "ENV OFF".to_string(),
+        (false, false) => format!("ENV {}", pulse.volume),
+        (false, true) => format!("ENV {} LOOP", pulse.volume),
+    };
+    let sweep = if pulse.sweep_enabled {
+        format!("SWEEP {} {} {}", pulse.sweep_period, if pulse.sweep_negate { '-' } else { '+' }, pulse.sweep_shift)
+    } else {
+        "SWEEP OFF".to_string()
+    };
+    [
+        format!("PULSE {}  {}  DUTY {}  {}", number, if pulse.enabled { "ON" } else { "OFF" }, DUTY[pulse.duty as usize], length),
+        format!(" ${:03X}  {:.1} HZ  VOL {}{}", pulse.period, hz, level, muted),
+        format!(" {}  {}", envelope, sweep),
+    ]
+}
+
+pub fn draw(frame: &mut [u8], y: usize, state: &ApuState, region: Region) {
+    let irq = if state.irq_inhibit { "IRQ OFF" } else if state.frame_irq { "IRQ SET" } else { "IRQ ON" };
+    let mut lines = vec![format!("FRAME COUNTER {}  {}", if state.five_step { "5-STEP" } else { "4-STEP" }, irq)];
+    for (i, pulse) in state.pulses.iter().enumerate() {
+        lines.extend(pulse_lines(i + 1, pulse, region));
+    }
+    for (i, line) in lines.iter().enumerate() {
+        osd::draw_label(frame, 2, y + i * LINE_HEIGHT, line, osd::WHITE);
+    }
+}
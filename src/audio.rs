@@ -0,0 +1,208 @@
+use std::sync::atomic::{AtomicU32, AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use sdl2::audio::{AudioCallback, AudioDevice, AudioSpecDesired, AudioStatus};
+use sdl2::Sdl;
+
+// Sound output. The emulation thread pushes each frame's APU samples into a ring buffer and
+// SDL's audio thread pulls them out as the device asks. The ring is lock-free (one writer,
+// one reader), so neither side ever waits on the other.
+//
+// The emulated frame rate and the sound card's clock never agree exactly, so the buffer
+// would slowly drain or overflow. Instead the APU's sample rate is nudged by up to half a
+// percent each frame to keep the buffer near its target fill, which is far too little to
+// hear as a pitch change. A short underrun, e.g. while paused, repeats the last sample
+// rather than jumping to silence, which would click.
+//
+// In hard sync mode the sound card's clock paces emulation instead: a frame runs whenever the
+// buffer has drained below its target, the sample rate stays put, and the picture shows
+// frames as they come.
+//
+// Stereo output takes interleaved left and right samples, and the buffer only ever holds
+// whole pairs so the sides can't swap.
+
+pub const DEFAULT_RATE: u32 = 48000;
+pub const RATES: [u32; 3] = [44100, 48000, 96000];
+// Buffered sound, on top of the device's own buffer, which is about a quarter of it
+pub const DEFAULT_LATENCY_MS: u32 = 40;
+pub const MIN_LATENCY_MS: u32 = 10;
+pub const MAX_LATENCY_MS: u32 = 500;
+const MIN_CAPACITY: usize = 8192; // Sample frames
+const MAX_ADJUST: f64 = 0.005;
+// Longest a hard sync wait blocks, in case the device stops pulling without saying so
+const MAX_SYNC_WAIT: Duration = Duration::from_millis(100);
+
+// "48000" or "48k"; only the rates in RATES.
+pub fn parse_rate(text: &str) -> Option<u32> {
+    let rate = match text.to_ascii_lowercase().strip_suffix('k') {
+        Some(khz) => (khz.parse::<f64>().ok()? * 1000.0).round() as u32,
+        None => text.parse().ok()?,
+    };
+    RATES.contains(&rate).then_some(rate)
+}
+
+pub fn parse_latency(text: &str) -> Option<u32> {
+    text.strip_suffix("ms").unwrap_or(text).trim().parse().ok().filter(|ms| (MIN_LATENCY_MS..=MAX_LATENCY_MS).contains(ms))
+}
+
+// Playback devices SDL knows of, by the names open() takes.
+pub fn device_names(sdl: &Sdl) -> Result<Vec<String>, String> {
+    let audio = sdl.audio()?;
+    let count = audio.num_audio_playback_devices().unwrap_or(0);
+    Ok((0..count).filter_map(|i| audio.audio_playback_device_name(i).ok()).collect())
+}
+
+pub struct AudioSettings {
+    pub rate: u32,
+    pub latency_ms: u32,
+    pub device: Option<String>, // None for the system default
+}
+
+impl Default for AudioSettings {
+    fn default() -> Self {
+        AudioSettings { rate: DEFAULT_RATE, latency_ms: DEFAULT_LATENCY_MS, device: None }
+    }
+}
+
+// Single-producer, single-consumer queue of samples. Each index only moves forward and is
+// only written by its own side.
+pub struct RingBuffer {
+    samples: Box<[AtomicU32]>, // f32 bits
+    read: AtomicUsize,
+    write: AtomicUsize,
+}
+
+impl RingBuffer {
+    pub fn new(capacity: usize) -> Self {
+        RingBuffer {
+            samples: (0..capacity).map(|_| AtomicU32::new(0)).collect(),
+            read: AtomicUsize::new(0),
+            write: AtomicUsize::new(0),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.write.load(Ordering::Acquire).wrapping_sub(self.read.load(Ordering::Acquire))
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    // Producer side. Samples that don't fit are dropped; returns how many were queued.
+    pub fn push(&self, samples: &[f32]) -> usize {
+        let write = self.write.load(Ordering::Relaxed);
+        let free = self.samples.len() - write.wrapping_sub(self.read.load(Ordering::Acquire));
+        let count = samples.len().min(free);
+        for (i, sample) in samples[..count].iter().enumerate() {
+            self.samples[write.wrapping_add(i) % self.samples.len()].store(sample.to_bits(), Ordering::Relaxed);
+        }
+        self.write.store(write.wrapping_add(count), Ordering::Release);
+        count
+    }
+
+    // Consumer side. Returns how many samples were filled in.
+    pub fn pop(&self, out: &mut [f32]) -> usize {
+        let read = self.read.load(Ordering::Relaxed);
+        let available = self.write.load(Ordering::Acquire).wrapping_sub(read);
+        let count = out.len().min(available);
+        for (i, sample) in out[..count].iter_mut().enumerate() {
+            *sample = f32::from_bits(self.samples[read.wrapping_add(i) % self.samples.len()].load(Ordering::Relaxed));
+        }
+        self.read.store(read.wrapping_add(count), Ordering::Release);
+        count
+    }
+}
+
+struct Playback {
+    ring: Arc<RingBuffer>,
+    last: [f32; 2],
+    channels: usize,
+}
+
+impl AudioCallback for Playback {
+    type Channel = f32;
+
+    fn callback(&mut self, out: &mut [f32]) {
+        let filled = self.ring.pop(out);
+        if filled >= self.channels {
+            self.last[..self.channels].copy_from_slice(&out[filled - self.channels..filled]);
+        }
+        for (i, sample) in out[filled..].iter_mut().enumerate() {
+            *sample = self.last[i % self.channels];
+        }
+    }
+}
+
+pub struct AudioOutput {
+    device: AudioDevice<Playback>,
+    name: Option<String>,
+    ring: Arc<RingBuffer>,
+    rate: u32,
+    channels: usize,
+    capacity: usize,
+    target_fill: usize,
+}
+
+impl AudioOutput {
+    // f32 playback of 1 or 2 channels. The device may pick another rate than the one asked
+    // for; rate() is what the APU should produce.
+    pub fn open(sdl: &Sdl, settings: &AudioSettings, channels: usize) -> Result<Self, String> {
+        let audio = sdl.audio()?;
+        let buffered = (settings.rate as u64 * settings.latency_ms as u64 / 1000) as usize;
+        let device_samples = (buffered / 4).next_power_of_two().clamp(64, 4096) as u16;
+        let desired = AudioSpecDesired { freq: Some(settings.rate as i32), channels: Some(channels as u8), samples: Some(device_samples) };
+        let capacity = (buffered * 4).max(MIN_CAPACITY);
+        let ring = Arc::new(RingBuffer::new(capacity * channels));
+        let playback = Playback { ring: Arc::clone(&ring), last: [0.0; 2], channels };
+        let device = audio.open_playback(settings.device.as_deref(), &desired, |_| playback)?;
+        let rate = device.spec().freq as u32;
+        // Kept in time rather than samples if the device changed the rate
+        let target_fill = (rate as u64 * settings.latency_ms as u64 / 1000) as usize;
+        device.resume();
+        Ok(AudioOutput { device, name: settings.device.clone(), ring, rate, channels, capacity, target_fill })
+    }
+
+    pub fn rate(&self) -> u32 {
+        self.rate
+    }
+
+    // The device name it was opened with, None for the default.
+    pub fn device(&self) -> Option<&str> {
+        self.name.as_deref()
+    }
+
+    // The device has been unplugged. SDL stops a lost device for good, so it has to be
+    // opened again.
+    pub fn lost(&self) -> bool {
+        self.device.status() == AudioStatus::Stopped
+    }
+
+    // Samples that don't fit are dropped, whole frames at a time.
+    pub fn push(&self, samples: &[f32]) {
+        // Only this side adds samples, so the space can only grow while pushing
+        let free = self.capacity * self.channels - self.ring.len();
+        let count = samples.len().min(free) / self.channels * self.channels;
+        self.ring.push(&samples[..count]);
+    }
+
+    // Hard sync: blocks until the buffer is below its target fill. Returns true if it had
+    // run low, meaning emulation is falling behind.
+    pub fn wait_for_room(&self) -> bool {
+        let fill = || self.ring.len() / self.channels;
+        let behind = fill() < self.target_fill / 2;
+        let start = Instant::now();
+        while fill() >= self.target_fill && start.elapsed() < MAX_SYNC_WAIT && !self.lost() {
+            std::thread::sleep(Duration::from_micros(500));
+        }
+        behind
+    }
+
+    // Sample rate correction for the APU: above 1 when the buffer is running low.
+    pub fn rate_ratio(&self) -> f64 {
+        let fill = self.ring.len() / self.channels;
+        let error = (self.target_fill as f64 - fill as f64) / self.target_fill as f64;
+        1.0 + MAX_ADJUST * error.clamp(-1.0, 1.0)
+    }
+}
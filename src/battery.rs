@@ -0,0 +1,181 @@
+use std::fs;
+use std::io::{self, ErrorKind};
+use std::path::Path;
+
+use crate::cartridge::Cartridge;
+use crate::filelock::FileLock;
+use crate::romdb;
+
+// Battery-backed PRG RAM, kept in <rom_file>.sav. Changes are written back:
+//
+//   - when a write burst ends: games save by writing a run of bytes over a few frames and
+//     then leaving the RAM alone, so the file is written once it has been quiet for a while
+//   - every `interval` frames while it keeps changing, for games that also use the RAM as
+//     scratch space and never go quiet (0 turns this off)
+//   - on exit
+//
+// The first write of each session first rotates the existing file to .sav.1, .sav.2, ...
+// keeping `backups` copies, so a crash or bad cheat in one session can't destroy the save.
+// Files are written to a temporary name and renamed into place.
+//
+// iNES 1.0 battery flags are often wrong in both directions, so for those ROMs the saver
+// watches how the game uses PRG RAM instead of trusting the flag: once the game reads bytes
+// it hasn't written since power-on (save games check for their data at boot; scratch RAM is
+// always written before it is read), the RAM is kept, and until then nothing is written.
+// An existing .sav counts as an earlier session having seen it.
+//
+// Only one zetr at a time may keep a given .sav; see `lock`.
+
+const QUIET_FRAMES: u32 = 30;
+pub const DEFAULT_INTERVAL_SECS: u32 = 60;
+pub const DEFAULT_BACKUPS: usize = 3;
+
+// Why PRG RAM is or isn't kept, for the startup message.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Source {
+    Override,
+    Database,
+    Header,
+}
+
+// Whether PRG RAM should be kept: the game config's choice, then the ROM database, then an
+// NES 2.0 header. None leaves it to the saver to detect while the game runs.
+pub fn detect(cart: &Cartridge, forced: Option<bool>) -> Option<(bool, Source)> {
+    if let Some(battery) = forced {
+        return Some((battery, Source::Override));
+    }
+    if let Some(battery) = romdb::lookup(cart.rom_hash()).and_then(|entry| entry.battery) {
+        return Some((battery, Source::Database));
+    }
+    if cart.nes2 || cart.prg_ram.is_empty() {
+        return Some((cart.battery, Source::Header));
+    }
+    None
+}
+
+pub struct BatterySaver {
+    path: String,
+    detected: bool,
+    interval: u32,
+    backups: usize,
+    pending: bool,
+    quiet: u32,
+    since_flush: u32,
+    backed_up: bool,
+    lock: Option<FileLock>,
+}
+
+impl BatterySaver {
+    // `detected` is false to watch the game before keeping anything.
+    pub fn new(rom_path: &str, interval_frames: u32, backups: usize, detected: bool) -> Self {
+        BatterySaver {
+            path: format!("{}.sav", rom_path),
+            detected,
+            interval: interval_frames,
+            backups,
+            pending: false,
+            quiet: 0,
+            since_flush: 0,
+            backed_up: false,
+            lock: None,
+        }
+    }
+
+    pub fn path(&self) -> &str {
+        &self.path
+    }
+
+    pub fn detected(&self) -> bool {
+        self.detected
+    }
+
+    // Claims the save file for this instance, until the saver is dropped. False if another
+    // zetr is playing the same game, which should then run without battery saves.
+    pub fn lock(&mut self) -> io::Result<bool> {
+        self.lock = FileLock::try_acquire(&self.path)?;
+        Ok(self.lock.is_some())
+    }
+
+    // Restores the save file into PRG RAM. Returns false if there was none.
+    pub fn load(&mut self, cart: &mut Cartridge) -> io::Result<bool> {
+        let data = match fs::read(&self.path) {
+            Ok(data) => data,
+            Err(e) if e.kind() == ErrorKind::NotFound => return Ok(false),
+            Err(e) => return Err(e),
+        };
+        if !self.detected {
+            cart.set_battery();
+            self.detected = true;
+        }
+        let ram = cart.battery_ram_mut();
+        if data.len() != ram.len() {
+            return Err(io::Error::new(ErrorKind::InvalidData, format!("expected {} bytes, found {}", ram.len(), data.len())));
+        }
+        ram.copy_from_slice(&data);
+        cart.prg_ram_dirty = false;
+        Ok(true)
+    }
+
+    // Call once per emulated frame. Returns true when the file was written.
+    pub fn frame(&mut self, cart: &mut Cartridge) -> io::Result<bool> {
+        if !self.detected {
+            if !cart.prg_ram_read_unwritten {
+                return Ok(false);
+            }
+            cart.set_battery();
+            self.detected = true;
+        }
+        if cart.prg_ram_dirty {
+            cart.prg_ram_dirty = false;
+            self.pending = true;
+            self.quiet = 0;
+        } else {
+            self.quiet = self.quiet.saturating_add(1);
+        }
+        self.since_flush = self.since_flush.saturating_add(1);
+
+        let burst_over = self.quiet >= QUIET_FRAMES;
+        let interval_due = self.interval > 0 && self.since_flush >= self.interval;
+        if self.pending && (burst_over || interval_due) {
+            self.flush(cart)?;
+            return Ok(true);
+        }
+        Ok(false)
+    }
+
+    // Writes any unsaved changes, e.g. on exit.
+    pub fn finish(&mut self, cart: &mut Cartridge) -> io::Result<()> {
+        if self.detected && (self.pending || cart.prg_ram_dirty) {
+            cart.prg_ram_dirty = false;
+            self.flush(cart)?;
+        }
+        Ok(())
+    }
+
+    fn flush(&mut self, cart: &Cartridge) -> io::Result<()> {
+        if !self.backed_up {
+            self.rotate_backups()?;
+            self.backed_up = true;
+        }
+        let temp = format!("{}.tmp", self.path);
+        fs::write(&temp, cart.battery_ram())?;
+        fs::rename(&temp, &self.path)?;
+        self.pending = false;
+        self.since_flush = 0;
+        Ok(())
+    }
+
+    fn rotate_backups(&self) -> io::Result<()> {
+        if self.backups == 0 || !Path::new(&self.path).exists() {
+            return Ok(());
+        }
+        for i in (1..self.backups).rev() {
+            let from = format!("{}.{}", self.path, i);
+            if Path::new(&from).exists() {
+                fs::rename(&from, format!("{}.{}", self.path, i + 1))?;
+            }
+        }
+        fs::copy(&self.path, format!("{}.1", self.path))?;
+        Ok(())
+    }
+}
@@ -0,0 +1,141 @@
+// Eight-pixel background pipeline used by the PPU with `--features simd-ppu`.
+//
+// Instead of pulling one bit out of each shifter per dot, the PPU takes the eight bits that
+// will be shown next from each of the four shifters, expands them to eight 4-bit palette
+// indices (attribute << 2 | pattern) and looks up all eight palette entries at once.
+// x86_64 uses SSE2 (plus SSSE3 for the lookup when the CPU has it), aarch64 uses NEON,
+// and anything else falls back to the scalar loops.
+
+// The next eight background pixels from the PPU's shifters (pattern low and high, then
+// attribute low and high, next pixel at bit 15 - fine x): their palette indices and colors,
+// leftmost first. Index 0 of every palette shows the backdrop.
+pub fn background_group(shifters: [u16; 4], fine_x: u8, palette_ram: &[u8; 32]) -> ([u8; 8], [u8; 8]) {
+    let shift = 8 - fine_x as u16;
+    let [pattern_lo, pattern_hi, attrib_lo, attrib_hi] = shifters.map(|shifter| (shifter >> shift) as u8);
+    let indices = background_indices(pattern_lo, pattern_hi, attrib_lo, attrib_hi);
+    let mut table = [0u8; 16];
+    for (i, entry) in table.iter_mut().enumerate() {
+        *entry = palette_ram[if i & 3 == 0 { 0 } else { i }];
+    }
+    (indices, palette_lookup(&indices, &table))
+}
+
+// Palette indices for the next eight pixels, leftmost first.
+#[cfg(target_arch = "x86_64")]
+pub fn background_indices(pattern_lo: u8, pattern_hi: u8, attrib_lo: u8, attrib_hi: u8) -> [u8; 8] {
+    // SAFETY: SSE2 is part of the x86_64 baseline.
+    unsafe { x86::background_indices(pattern_lo, pattern_hi, attrib_lo, attrib_hi) }
+}
+
+#[cfg(target_arch = "aarch64")]
+pub fn background_indices(pattern_lo: u8, pattern_hi: u8, attrib_lo: u8, attrib_hi: u8) -> [u8; 8] {
+    // SAFETY: NEON is part of the aarch64 baseline.
+    unsafe { arm::background_indices(pattern_lo, pattern_hi, attrib_lo, attrib_hi) }
+}
+
+#[cfg(not(any(target_arch = "x86_64", target_arch = "aarch64")))]
+pub fn background_indices(pattern_lo: u8, pattern_hi: u8, attrib_lo: u8, attrib_hi: u8) -> [u8; 8] {
+    let mut out = [0u8; 8];
+    for (i, pixel) in out.iter_mut().enumerate() {
+        let bit = 7 - i;
+        *pixel = (pattern_lo >> bit & 1)
+            | (pattern_hi >> bit & 1) << 1
+            | (attrib_lo >> bit & 1) << 2
+            | (attrib_hi >> bit & 1) << 3;
+    }
+    out
+}
+
+// Maps each 4-bit index through a 16-entry table.
+#[cfg(target_arch = "x86_64")]
+pub fn palette_lookup(indices: &[u8; 8], table: &[u8; 16]) -> [u8; 8] {
+    if is_x86_feature_detected!("ssse3") {
+        // SAFETY: SSSE3 support was just checked.
+        unsafe { x86::palette_lookup(indices, table) }
+    } else {
+        indices.map(|i| table[i as usize & 0x0F])
+    }
+}
+
+#[cfg(target_arch = "aarch64")]
+pub fn palette_lookup(indices: &[u8; 8], table: &[u8; 16]) -> [u8; 8] {
+    // SAFETY: NEON is part of the aarch64 baseline.
+    unsafe { arm::palette_lookup(indices, table) }
+}
+
+#[cfg(not(any(target_arch = "x86_64", target_arch = "aarch64")))]
+pub fn palette_lookup(indices: &[u8; 8], table: &[u8; 16]) -> [u8; 8] {
+    indices.map(|i| table[i as usize & 0x0F])
+}
+
+#[cfg(target_arch = "x86_64")]
+mod x86 {
+    use std::arch::x86_64::*;
+
+    // Spreads the bits of `byte` over eight lanes as 0 or 1, bit 7 in lane 0.
+    #[target_feature(enable = "sse2")]
+    unsafe fn spread(byte: u8, masks: __m128i) -> __m128i {
+        let lanes = _mm_set1_epi8(byte as i8);
+        let hit = _mm_cmpeq_epi8(_mm_and_si128(lanes, masks), masks);
+        _mm_and_si128(hit, _mm_set1_epi8(1))
+    }
+
+    #[target_feature(enable = "sse2")]
+    pub unsafe fn background_indices(pattern_lo: u8, pattern_hi: u8, attrib_lo: u8, attrib_hi: u8) -> [u8; 8] {
+        let masks = _mm_setr_epi8(-128, 0x40, 0x20, 0x10, 0x08, 0x04, 0x02, 0x01, 0, 0, 0, 0, 0, 0, 0, 0);
+        // Lanes hold 0 or 1, so 16-bit shifts never carry into the neighbouring byte
+        let p0 = spread(pattern_lo, masks);
+        let p1 = _mm_slli_epi16::<1>(spread(pattern_hi, masks));
+        let a0 = _mm_slli_epi16::<2>(spread(attrib_lo, masks));
+        let a1 = _mm_slli_epi16::<3>(spread(attrib_hi, masks));
+        let combined = _mm_or_si128(_mm_or_si128(p0, p1), _mm_or_si128(a0, a1));
+        let mut out = [0u8; 16];
+        _mm_storeu_si128(out.as_mut_ptr() as *mut __m128i, combined);
+        [out[0], out[1], out[2], out[3], out[4], out[5], out[6], out[7]]
+    }
+
+    #[target_feature(enable = "ssse3")]
+    pub unsafe fn palette_lookup(indices: &[u8; 8], table: &[u8; 16]) -> [u8; 8] {
+        let table = _mm_loadu_si128(table.as_ptr() as *const __m128i);
+        let indices = _mm_loadl_epi64(indices.as_ptr() as *const __m128i);
+        let indices = _mm_and_si128(indices, _mm_set1_epi8(0x0F));
+        let mut out = [0u8; 8];
+        _mm_storel_epi64(out.as_mut_ptr() as *mut __m128i, _mm_shuffle_epi8(table, indices));
+        out
+    }
+}
+
+#[cfg(target_arch = "aarch64")]
+mod arm {
+    use std::arch::aarch64::*;
+
+    const MASKS: [u8; 8] = [0x80, 0x40, 0x20, 0x10, 0x08, 0x04, 0x02, 0x01];
+
+    // Spreads the bits of `byte` over eight lanes as 0 or 1, bit 7 in lane 0.
+    #[target_feature(enable = "neon")]
+    unsafe fn spread(byte: u8, masks: uint8x8_t) -> uint8x8_t {
+        let hit = vtst_u8(vdup_n_u8(byte), masks);
+        vand_u8(hit, vdup_n_u8(1))
+    }
+
+    #[target_feature(enable = "neon")]
+    pub unsafe fn background_indices(pattern_lo: u8, pattern_hi: u8, attrib_lo: u8, attrib_hi: u8) -> [u8; 8] {
+        let masks = vld1_u8(MASKS.as_ptr());
+        let p0 = spread(pattern_lo, masks);
+        let p1 = vshl_n_u8::<1>(spread(pattern_hi, masks));
+        let a0 = vshl_n_u8::<2>(spread(attrib_lo, masks));
+        let a1 = vshl_n_u8::<3>(spread(attrib_hi, masks));
+        let mut out = [0u8; 8];
+        vst1_u8(out.as_mut_ptr(), vorr_u8(vorr_u8(p0, p1), vorr_u8(a0, a1)));
+        out
+    }
+
+    #[target_feature(enable = "neon")]
+    pub unsafe fn palette_lookup(indices: &[u8; 8], table: &[u8; 16]) -> [u8; 8] {
+        let table = vld1q_u8(table.as_ptr());
+        let indices = vand_u8(vld1_u8(indices.as_ptr()), vdup_n_u8(0x0F));
+        let mut out = [0u8; 8];
+        vst1_u8(out.as_mut_ptr(), vqtbl1_u8(table, indices));
+        out
+    }
+}
@@ -1,37 +1,96 @@
+use crate::apu::APU;
 use crate::ppu::PPU;
 use crate::cartridge::Cartridge;
+use crate::expansion::ExpansionDevice;
+use crate::zapper::Zapper;
+
+// Everything behind $4016/$4017. Owned by the NES, since a Bus only lives for one access
+// and games strobe the controller in one instruction and read it in the next eight.
+#[derive(Default)]
+pub struct Ports {
+    pub controller1: u8, // Buttons as the game would latch them now
+    shift: u8,
+    strobe: bool,
+    pub zapper: Option<Zapper>, // Controller port 2
+    pub expansion: Option<Box<dyn ExpansionDevice>>,
+}
+
+impl Ports {
+    fn write(&mut self, data: u8) {
+        self.strobe = data & 1 != 0;
+        if self.strobe {
+            self.shift = self.controller1;
+        }
+        if let Some(device) = self.expansion.as_mut() {
+            device.write(data & 0x07);
+        }
+    }
+
+    // A first, then B, Select, Start, Up, Down, Left, Right, then 1s like an official pad.
+    // While the strobe is high it keeps reloading, so every read returns A.
+    fn read_controller1(&mut self) -> u8 {
+        if self.strobe {
+            return self.controller1 & 1;
+        }
+        let data = self.shift & 1;
+        self.shift = self.shift >> 1 | 0x80;
+        data
+    }
+
+    fn read(&mut self, addr: u16, ppu: &PPU) -> u8 {
+        let (data, expansion_bits, port) = match addr {
+            0x4016 => (self.read_controller1(), 0x02, 0),
+            _ => (self.zapper.map_or(0, |zapper| zapper.read(ppu)), 0x1E, 1),
+        };
+        data | self.expansion.as_mut().map_or(0, |device| device.read(port, ppu) & expansion_bits)
+    }
+
+    pub fn save_state(&self, w: &mut dyn std::io::Write) -> std::io::Result<()> {
+        w.write_all(&[self.shift, self.strobe as u8])?;
+        match self.expansion.as_ref() {
+            Some(device) => device.save_state(w),
+            None => Ok(()),
+        }
+    }
+
+    pub fn load_state(&mut self, r: &mut dyn std::io::Read) -> std::io::Result<()> {
+        let mut latch = [0u8; 2];
+        r.read_exact(&mut latch)?;
+        self.shift = latch[0];
+        self.strobe = latch[1] != 0;
+        match self.expansion.as_mut() {
+            Some(device) => device.load_state(r),
+            None => Ok(()),
+        }
+    }
+}
 
 pub struct Bus<'a> {
     pub ppu: &'a mut PPU,
     pub cartridge: &'a mut Cartridge,
     pub ram: &'a mut [u8; 2048],
-    pub controller1: u8,
-    pub controller1_shift: u8,
-    pub controller_strobe: bool,
+    pub ports: &'a mut Ports,
+    pub apu: &'a mut APU,
 }
 
 impl<'a> Bus<'a> {
-    pub fn new(ppu: &'a mut PPU, cartridge: &'a mut Cartridge, ram: &'a mut [u8; 2048]) -> Self {
-        Bus {
-            ppu,
-            cartridge,
-            ram,
-            controller1: 0,
-            controller1_shift: 0,
-            controller_strobe: false,
-        }
+    pub fn new(
+        ppu: &'a mut PPU,
+        cartridge: &'a mut Cartridge,
+        ram: &'a mut [u8; 2048],
+        ports: &'a mut Ports,
+        apu: &'a mut APU,
+    ) -> Self {
+        Bus { ppu, cartridge, ram, ports, apu }
     }
 
     pub fn read(&mut self, addr: u16) -> u8 {
         match addr {
             0x0000..=0x1FFF => self.ram[(addr & 0x07FF) as usize],
             0x2000..=0x3FFF => self.ppu.cpu_read(0x2000 + (addr & 0x0007), self.cartridge),
-            0x4016 => {
-                let data = (self.controller1_shift & 0x80) >> 7;
-                self.controller1_shift <<= 1;
-                data
-            }
-            0x4017 => 0, // Controller 2 not implemented
+            0x4015 => self.apu.read_status(),
+            0x4016 | 0x4017 => self.ports.read(addr, self.ppu),
+            0x6000..=0x7FFF => self.cartridge.cpu_read_prg_ram(addr - 0x6000),
             0x8000..=0xFFFF => self.cartridge.read_prg(addr - 0x8000),
             _ => 0,
         }
@@ -41,20 +100,29 @@ impl<'a> Bus<'a> {
         match addr {
             0x0000..=0x1FFF => self.ram[(addr & 0x07FF) as usize] = data,
             0x2000..=0x3FFF => self.ppu.cpu_write(0x2000 + (addr & 0x0007), data, self.cartridge),
-            0x4014 => {
-                // OAM DMA
-                self.ppu.oam_addr = data;
-                // DMA should be handled in the main loop, not here.
-                // This write just sets the OAM address.
-            }
-            0x4016 => {
-                self.controller_strobe = data & 1 != 0;
-                if self.controller_strobe {
-                    self.controller1_shift = self.controller1;
-                }
-            }
+            0x4000..=0x4007 | 0x4015 => self.apu.write(addr, data),
+            0x4014 => {} // OAM DMA is started by the CPU and run by the main loop
+            0x4016 => self.ports.write(data),
+            0x4017 => self.apu.write(addr, data),
+            0x6000..=0x7FFF => self.cartridge.write_prg_ram(addr - 0x6000, data),
             0x8000..=0xFFFF => self.cartridge.write_prg(addr - 0x8000, data),
             _ => {}
         }
     }
-}
\ No newline at end of file
+}
+
+// CPU address space. The console bus implements it; test harnesses can supply a flat 64KB memory.
+pub trait Memory {
+    fn read(&mut self, addr: u16) -> u8;
+    fn write(&mut self, addr: u16, data: u8);
+}
+
+impl Memory for Bus<'_> {
+    fn read(&mut self, addr: u16) -> u8 {
+        Bus::read(self, addr)
+    }
+
+    fn write(&mut self, addr: u16, data: u8) {
+        Bus::write(self, addr, data)
+    }
+}
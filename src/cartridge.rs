@@ -1,12 +1,34 @@
-use std::fs::File;
-use std::io::Read;
+use std::fs;
+use std::io::{self, Read, Write};
+
+use crate::hash;
+use crate::mapper::{self, Mapper, Nrom};
+use crate::profiler::{self, Section};
+use crate::region::Region;
 
 #[derive(Debug)]
 pub struct Cartridge {
     pub prg_rom: Vec<u8>,
     pub chr_rom: Vec<u8>,
-    pub mapper: u8,
-    pub mirroring: Mirroring,
+    pub prg_ram: Vec<u8>,       // Battery-backed PRG NVRAM first, then volatile PRG RAM
+    pub prg_nvram_len: usize,   // Bytes at the start of prg_ram kept by the battery
+    pub mapper: u16,
+    pub mirroring: Mirroring,   // As the board currently has it
+    pub chr_ram: bool,
+    pub timing: Option<Region>, // NES 2.0 CPU/PPU timing, None for iNES 1.0 or multi-region
+    pub nes2: bool,             // NES 2.0 header, whose RAM sizes can be trusted
+    pub battery: bool,          // PRG RAM is battery backed and should persist
+    pub prg_ram_dirty: bool,    // Battery-backed PRG RAM changed since the flag was last cleared
+    pub prg_ram_read_unwritten: bool, // The CPU read PRG RAM it hadn't written since power-on
+    prg_ram_written: Vec<bool>,
+
+    // The board, NROM for mappers that aren't emulated, and what it currently maps, read
+    // back after every register write so memory accesses don't go through it
+    board: Box<dyn Mapper>,
+    prg_map: [usize; 4], // PRG ROM offset of each 8KB window at $8000-$FFFF
+    chr_map: [usize; 8], // CHR offset of each 1KB window at PPU $0000-$1FFF
+    header_mirroring: Mirroring,
+    chr_writable: bool, // CHR RAM that the board isn't write-protecting
 }
 
 #[derive(Debug, Clone, Copy)]
@@ -18,9 +40,12 @@ pub enum Mirroring {
 
 impl Cartridge {
     pub fn new(rom_path: &str) -> Result<Self, Box<dyn std::error::Error>> {
-        let mut file = File::open(rom_path)?;
+        Self::from_bytes(&fs::read(rom_path)?)
+    }
+    
+    pub fn from_bytes(mut data: &[u8]) -> Result<Self, Box<dyn std::error::Error>> {
         let mut header = [0u8; 16];
-        file.read_exact(&mut header)?;
+        data.read_exact(&mut header)?;
         
         // Check for iNES header
         if &header[0..4] != b"NES\x1A" {
@@ -29,12 +54,44 @@ impl Cartridge {
         
         let prg_rom_size = header[4] as usize * 16384; // 16KB units
         let chr_rom_size = header[5] as usize * 8192;  // 8KB units
+        if prg_rom_size == 0 {
+            return Err("ROM has no PRG data".into());
+        }
         
         let flags6 = header[6];
         let flags7 = header[7];
         
-        let mapper = (flags7 & 0xF0) | (flags6 >> 4);
+        // NES 2.0 headers carry the mapper's top four bits in byte 8, and the CPU/PPU timing
+        // in byte 12
+        let nes2 = flags7 & 0x0C == 0x08;
+        let mut mapper = ((flags7 & 0xF0) | (flags6 >> 4)) as u16;
+        if nes2 {
+            mapper |= ((header[8] & 0x0F) as u16) << 8;
+        }
+        let timing = match header[12] & 0x03 {
+            0 if nes2 => Some(Region::Ntsc),
+            1 if nes2 => Some(Region::Pal),
+            3 if nes2 => Some(Region::Dendy),
+            _ => None,
+        };
         
+        // NES 2.0 gives each RAM size as a shift count (64 << n bytes, 0 for none). iNES 1.0
+        // boards get the traditional 8KB of PRG RAM, battery backed if the flag says so, and
+        // 8KB of CHR RAM when there is no CHR ROM.
+        let ram_size = |shift: u8| if shift == 0 { 0 } else { 64usize << shift };
+        let (prg_ram_size, prg_nvram_size) = if nes2 {
+            (ram_size(header[10] & 0x0F), ram_size(header[10] >> 4))
+        } else if flags6 & 0x02 != 0 {
+            (0, 8192)
+        } else {
+            (8192, 0)
+        };
+        let chr_ram_size = match (nes2, ram_size(header[11] & 0x0F) + ram_size(header[11] >> 4)) {
+            // A board with neither CHR ROM nor CHR RAM can't draw anything, so assume a bad header
+            (true, 0) | (false, _) => 8192,
+            (true, size) => size,
+        };
+
         let mirroring = if flags6 & 0x01 != 0 {
             Mirroring::Vertical
         } else {
@@ -44,74 +101,232 @@ impl Cartridge {
         // Skip trainer if present
         if flags6 & 0x04 != 0 {
             let mut trainer = [0u8; 512];
-            file.read_exact(&mut trainer)?;
+            data.read_exact(&mut trainer)?;
         }
         
         // Read PRG ROM
         let mut prg_rom = vec![0u8; prg_rom_size];
-        file.read_exact(&mut prg_rom)?;
+        data.read_exact(&mut prg_rom)?;
         
         // Read CHR ROM
         let mut chr_rom = vec![0u8; chr_rom_size];
-        if chr_rom_size > 0 {
-            file.read_exact(&mut chr_rom)?;
+        let chr_ram = chr_rom_size == 0;
+        if !chr_ram {
+            data.read_exact(&mut chr_rom)?;
         } else {
             // CHR RAM
-            chr_rom = vec![0u8; 8192];
+            chr_rom = vec![0u8; chr_ram_size];
         }
         
-        Ok(Cartridge {
+        let mut cartridge = Cartridge {
             prg_rom,
             chr_rom,
+            prg_ram: vec![0u8; prg_nvram_size + prg_ram_size],
+            prg_nvram_len: prg_nvram_size,
             mapper,
             mirroring,
-        })
+            chr_ram,
+            timing,
+            nes2,
+            battery: flags6 & 0x02 != 0 && prg_nvram_size > 0,
+            prg_ram_dirty: false,
+            prg_ram_read_unwritten: false,
+            prg_ram_written: vec![false; prg_nvram_size + prg_ram_size],
+            board: mapper::for_number(mapper).unwrap_or_else(|| Box::new(Nrom)),
+            prg_map: [0; 4],
+            chr_map: [0; 8],
+            header_mirroring: mirroring,
+            chr_writable: chr_ram,
+        };
+        cartridge.sync_board();
+        Ok(cartridge)
+    }
+
+    fn sync_board(&mut self) {
+        for (window, offset) in self.prg_map.iter_mut().enumerate() {
+            *offset = self.board.prg_offset(window as u16 * 0x2000, self.prg_rom.len());
+        }
+        for (window, offset) in self.chr_map.iter_mut().enumerate() {
+            *offset = self.board.chr_offset(window as u16 * 0x400, self.chr_rom.len());
+        }
+        self.mirroring = self.board.mirroring().unwrap_or(self.header_mirroring);
+        self.chr_writable = self.chr_ram && self.board.chr_writable();
     }
     
+    // The board's name, NROM for mappers that aren't emulated.
+    pub fn board_name(&self) -> &'static str {
+        self.board.name()
+    }
+
     pub fn read_prg(&self, address: u16) -> u8 {
-        let address = address as usize;
-        match self.prg_rom.len() {
-            16384 => {
-                // 16KB PRG ROM, mirrored
-                self.prg_rom[address % 16384]
-            }
-            32768 => {
-                // 32KB PRG ROM
-                self.prg_rom[address]
-            }
-            _ => {
-                // Other sizes, just use modulo
-                self.prg_rom[address % self.prg_rom.len()]
-            }
+        let _profile = profiler::scope(Section::PrgRead);
+        if self.prg_rom.is_empty() {
+            return 0;
+        }
+        self.prg_rom[self.prg_offset(address)]
+    }
+
+    fn prg_offset(&self, address: u16) -> usize {
+        self.prg_map[(address as usize >> 13) & 3] + (address & 0x1FFF) as usize
+    }
+    
+    // 16KB PRG bank mapped at a CPU address, for trace filtering.
+    pub fn prg_bank(&self, address: u16) -> Option<usize> {
+        if address < 0x8000 || self.prg_rom.is_empty() {
+            return None;
+        }
+        Some(self.prg_offset(address - 0x8000) / 16384)
+    }
+    
+    // PRG ROM itself can't be written; boards take these as register writes.
+    pub fn write_prg(&mut self, address: u16, data: u8) {
+        let _profile = profiler::scope(Section::PrgWrite);
+        self.board.write_register(address, data);
+        self.sync_board();
+    }
+    
+    // PRG RAM at $6000-$7FFF, mirrored if smaller than 8KB
+    pub fn read_prg_ram(&self, address: u16) -> u8 {
+        if self.prg_ram.is_empty() {
+            0
+        } else {
+            self.prg_ram[address as usize % self.prg_ram.len()]
+        }
+    }
+
+    // A read by the running game. Reading bytes it never wrote means the game expects them
+    // to have survived since the last session, which is what battery save detection looks for.
+    pub fn cpu_read_prg_ram(&mut self, address: u16) -> u8 {
+        if !self.prg_ram.is_empty() {
+            let index = address as usize % self.prg_ram.len();
+            self.prg_ram_read_unwritten |= !self.prg_ram_written[index];
         }
+        self.read_prg_ram(address)
     }
     
-    pub fn write_prg(&mut self, _address: u16, _data: u8) {
-        // Most cartridges don't support writing to PRG ROM
-        // Mapper-specific implementations would go here
+    pub fn write_prg_ram(&mut self, address: u16, data: u8) {
+        if !self.prg_ram.is_empty() {
+            let len = self.prg_ram.len();
+            let index = address as usize % len;
+            let cell = &mut self.prg_ram[index];
+            self.prg_ram_dirty |= index < self.prg_nvram_len && *cell != data;
+            *cell = data;
+            self.prg_ram_written[index] = true;
+        }
     }
     
     pub fn read_chr(&self, address: u16) -> u8 {
+        let _profile = profiler::scope(Section::ChrRead);
         if self.chr_rom.is_empty() {
             0 // Return 0 if no CHR ROM
         } else {
-            self.chr_rom[address as usize % self.chr_rom.len()]
+            self.chr_rom[self.chr_offset(address)]
         }
     }
     
+    // Byte of CHR memory behind a PPU address, also for the PPU bus log. CHR RAM can be
+    // smaller than a window.
+    pub fn chr_offset(&self, address: u16) -> usize {
+        (self.chr_map[(address as usize >> 10) & 7] + (address & 0x3FF) as usize) % self.chr_rom.len().max(1)
+    }
+
     pub fn write_chr(&mut self, address: u16, data: u8) {
-        // CHR RAM write
-        if self.chr_rom.len() == 8192 {
-            self.chr_rom[address as usize % 8192] = data;
+        let _profile = profiler::scope(Section::ChrWrite);
+        if self.chr_writable && !self.chr_rom.is_empty() {
+            let offset = self.chr_offset(address);
+            self.chr_rom[offset] = data;
+        }
+    }
+
+    // The console's reset button, before the CPU fetches the reset vector.
+    pub fn reset(&mut self) {
+        self.board.reset();
+        self.sync_board();
+    }
+
+    // One CPU cycle has passed, for boards that count them.
+    pub fn clock(&mut self) {
+        if self.board.clock() {
+            self.sync_board();
+        }
+    }
+
+    // The PPU has put an address on its bus, for boards that watch it.
+    pub fn ppu_addr(&mut self, address: u16) {
+        if self.board.ppu_addr(address) {
+            self.sync_board();
+        }
+    }
+
+    // The board's IRQ line, read live so a timer can raise it between register writes.
+    pub fn irq(&self) -> bool {
+        self.board.irq()
+    }
+    
+    // Hash of the ROM contents, used to check that a save state belongs to this game.
+    pub fn rom_hash(&self) -> u64 {
+        let chr: &[u8] = if self.chr_ram { &[] } else { &self.chr_rom };
+        hash::fnv1a(self.prg_rom.iter().chain(chr))
+    }
+    
+    // The part of PRG RAM that goes in the .sav file
+    pub fn battery_ram(&self) -> &[u8] {
+        &self.prg_ram[..self.prg_nvram_len]
+    }
+
+    pub fn battery_ram_mut(&mut self) -> &mut [u8] {
+        &mut self.prg_ram[..self.prg_nvram_len]
+    }
+
+    // Treats PRG RAM as battery backed even though the header says it isn't. Boards without
+    // NVRAM get all of their PRG RAM kept, marked dirty if the game has already written it.
+    pub fn set_battery(&mut self) {
+        if self.prg_nvram_len == 0 {
+            self.prg_nvram_len = self.prg_ram.len();
+            self.prg_ram_dirty = self.prg_ram_written.contains(&true);
+        }
+        self.battery = self.prg_nvram_len > 0;
+    }
+
+    pub fn save_state(&self, w: &mut dyn Write) -> io::Result<()> {
+        w.write_all(&self.prg_ram)?;
+        if self.chr_ram {
+            w.write_all(&self.chr_rom)?;
+        }
+        self.board.save_state(w)
+    }
+    
+    pub fn load_state(&mut self, r: &mut dyn Read) -> io::Result<()> {
+        r.read_exact(&mut self.prg_ram)?;
+        self.prg_ram_dirty = true;
+        if self.chr_ram {
+            r.read_exact(&mut self.chr_rom)?;
         }
+        self.board.load_state(r)?;
+        self.sync_board();
+        Ok(())
     }
     
     pub fn dummy() -> Self {
         Cartridge {
             prg_rom: vec![],
             chr_rom: vec![],
+            prg_ram: vec![],
+            prg_nvram_len: 0,
             mapper: 0,
             mirroring: Mirroring::Horizontal,
+            chr_ram: false,
+            timing: None,
+            nes2: false,
+            battery: false,
+            prg_ram_dirty: false,
+            prg_ram_read_unwritten: false,
+            prg_ram_written: vec![],
+            board: Box::new(Nrom),
+            prg_map: [0; 4],
+            chr_map: [0; 8],
+            header_mirroring: Mirroring::Horizontal,
+            chr_writable: false,
         }
     }
 }
@@ -0,0 +1,70 @@
+use std::env;
+use std::io::Write;
+use std::process::{Command, Stdio};
+
+// Clipboard sharing from the window: Ctrl+C copies a PNG screenshot, Ctrl+Shift+C copies a
+// save state as text and Ctrl+V loads one pasted back. SDL2's clipboard only carries text,
+// so images go through wl-copy (Wayland) or xclip (X11). States are plain text, fine to
+// paste into a chat message:
+//
+//   zetr-state:WkVUUlNUQVRFAQ...
+//
+// The text is the base64 of an ordinary state file, so a ROM mismatch is caught the same way.
+
+const STATE_PREFIX: &str = "zetr-state:";
+const ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+pub fn copy_png(png: &[u8]) -> Result<(), Box<dyn std::error::Error>> {
+    let (program, args): (&str, &[&str]) = if env::var_os("WAYLAND_DISPLAY").is_some() {
+        ("wl-copy", &["--type", "image/png"])
+    } else if env::var_os("DISPLAY").is_some() {
+        ("xclip", &["-selection", "clipboard", "-t", "image/png", "-i"])
+    } else {
+        return Err("copying images needs Wayland or X11".into());
+    };
+    // Both tools fork to keep serving the clipboard once their input is closed
+    let mut child = Command::new(program)
+        .args(args)
+        .stdin(Stdio::piped())
+        .spawn()
+        .map_err(|e| format!("{} not found: {}", program, e))?;
+    child.stdin.take().ok_or("no stdin")?.write_all(png)?;
+    let status = child.wait()?;
+    if !status.success() {
+        return Err(format!("{} failed: {}", program, status).into());
+    }
+    Ok(())
+}
+
+pub fn encode_state(state: &[u8]) -> String {
+    let mut text = String::from(STATE_PREFIX);
+    for chunk in state.chunks(3) {
+        let bytes = [chunk[0], *chunk.get(1).unwrap_or(&0), *chunk.get(2).unwrap_or(&0)];
+        let bits = u32::from_be_bytes([0, bytes[0], bytes[1], bytes[2]]);
+        for i in 0..4 {
+            if i <= chunk.len() {
+                text.push(ALPHABET[(bits >> (18 - i * 6)) as usize & 0x3F] as char);
+            } else {
+                text.push('=');
+            }
+        }
+    }
+    text
+}
+
+// Whitespace is skipped, since chat clients like to wrap long lines.
+pub fn decode_state(text: &str) -> Result<Vec<u8>, String> {
+    let text = text.trim().strip_prefix(STATE_PREFIX).ok_or("the clipboard doesn't hold a zetr state")?;
+    let mut state = Vec::new();
+    let (mut bits, mut count) = (0u32, 0);
+    for c in text.bytes().filter(|c| !c.is_ascii_whitespace()).take_while(|&c| c != b'=') {
+        let value = ALPHABET.iter().position(|&a| a == c).ok_or("the clipboard state is damaged")?;
+        bits = bits << 6 | value as u32;
+        count += 6;
+        if count >= 8 {
+            count -= 8;
+            state.push((bits >> count) as u8);
+        }
+    }
+    Ok(state)
+}
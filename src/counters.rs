@@ -0,0 +1,57 @@
+use std::time::{Duration, Instant};
+
+use crate::nes::Counters;
+use crate::osd;
+
+// Live counters in the top-left corner (F3), for timing homebrew routines by eye without
+// stopping in the debugger:
+//
+//   1.79 MHz
+//   CYC 53693175
+//   FRM 1800 SL 241 DOT 12
+//
+// The first line is how fast the emulated CPU clock is really running, measured against
+// the host clock, so it also shows slowdown, fast-forward and overclocking at a glance.
+
+const RATE_WINDOW: Duration = Duration::from_millis(500);
+const LINE_HEIGHT: usize = osd::CHAR_HEIGHT + 3;
+// Down to the bottom of the last line, for overlays that go under it
+pub const HEIGHT: usize = 2 + 3 * LINE_HEIGHT;
+
+pub struct CounterOverlay {
+    since: Instant,
+    since_cycles: u64,
+    mhz: Option<f64>,
+}
+
+impl CounterOverlay {
+    pub fn new(counters: Counters) -> Self {
+        CounterOverlay { since: Instant::now(), since_cycles: counters.cpu_cycles, mhz: None }
+    }
+
+    // Once per emulated frame.
+    pub fn update(&mut self, counters: Counters) {
+        let elapsed = self.since.elapsed();
+        if counters.cpu_cycles < self.since_cycles {
+            // Reset or state load
+            *self = CounterOverlay::new(counters);
+        } else if elapsed >= RATE_WINDOW {
+            let cycles = counters.cpu_cycles - self.since_cycles;
+            self.mhz = Some(cycles as f64 / elapsed.as_secs_f64() / 1_000_000.0);
+            self.since = Instant::now();
+            self.since_cycles = counters.cpu_cycles;
+        }
+    }
+
+    pub fn draw(&self, frame: &mut [u8], counters: Counters) {
+        let rate = self.mhz.map_or("-.-- MHz".to_string(), |mhz| format!("{:.2} MHz", mhz));
+        let lines = [
+            rate,
+            format!("CYC {}", counters.cpu_cycles),
+            format!("FRM {} SL {} DOT {}", counters.frame, counters.scanline, counters.dot),
+        ];
+        for (i, line) in lines.iter().enumerate() {
+            osd::draw_label(frame, 2, 2 + i * LINE_HEIGHT, line, osd::WHITE);
+        }
+    }
+}
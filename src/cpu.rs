@@ -1,4 +1,27 @@
-use crate::bus::Bus;
+use std::io::{self, Read, Write};
+
+use crate::bus::Memory;
+use crate::stateful;
+use crate::stateful::Stateful;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum FrameKind {
+    Subroutine,
+    Nmi,
+    Irq,
+    Brk,
+}
+
+// A JSR or interrupt that has not returned yet, for the debugger's call stack.
+#[derive(Debug, Clone, Copy)]
+pub struct CallFrame {
+    pub kind: FrameKind,
+    pub from: u16,      // Address of the JSR, or the interrupted instruction
+    pub to: u16,        // Subroutine or handler address
+    pub return_sp: u8,  // Stack pointer before the return address was pushed
+}
+
+const MAX_CALL_DEPTH: usize = 256;
 
 #[derive(Debug)]
 pub struct CPU {
@@ -11,6 +34,7 @@ pub struct CPU {
     pub cycles: u64,
     pub dma_request: bool,
     pub dma_page: u8,
+    pub call_stack: Vec<CallFrame>,
 }
 
 // Status flags
@@ -35,10 +59,11 @@ impl CPU {
             cycles: 0,
             dma_request: false,
             dma_page: 0,
+            call_stack: Vec::new(),
         }
     }
     
-    pub fn reset(&mut self, bus: &mut Bus) {
+    pub fn reset(&mut self, bus: &mut impl Memory) {
         self.a = 0;
         self.x = 0;
         self.y = 0;
@@ -51,9 +76,10 @@ impl CPU {
         self.pc = (hi << 8) | lo;
         
         self.cycles = 0;
+        self.call_stack.clear();
     }
     
-    pub fn step(&mut self, bus: &mut Bus) -> u8 {
+    pub fn step(&mut self, bus: &mut impl Memory) -> u8 {
         let opcode = bus.read(self.pc);
         self.pc = self.pc.wrapping_add(1);
         
@@ -62,7 +88,7 @@ impl CPU {
         cycles
     }
     
-    fn execute_instruction(&mut self, opcode: u8, bus: &mut Bus) -> u8 {
+    fn execute_instruction(&mut self, opcode: u8, bus: &mut impl Memory) -> u8 {
         match opcode {
             // LDA - Load Accumulator
             0xA9 => { let val = self.immediate(bus); self.lda(val); 2 }
@@ -102,10 +128,15 @@ impl CPU {
             0x6C => { self.pc = self.indirect_address(bus); 5 }
             
             // JSR - Jump to Subroutine
-            0x20 => { self.jsr(bus); 6 }
+            0x20 => {
+                let (from, sp) = (self.pc.wrapping_sub(1), self.sp);
+                self.jsr(bus);
+                self.enter_frame(FrameKind::Subroutine, from, sp);
+                6
+            }
             
             // RTS - Return from Subroutine
-            0x60 => { self.rts(bus); 6 }
+            0x60 => { self.rts(bus); self.leave_frames(); 6 }
             
             // BNE - Branch if Not Equal
             0xD0 => { self.branch(!self.get_flag(FLAG_ZERO), bus) }
@@ -222,16 +253,21 @@ impl CPU {
             0x28 => { self.status = (self.pull(bus) & !FLAG_BREAK) | FLAG_UNUSED; 4 }
             
             // TXS - Transfer X to Stack Pointer
-            0x9A => { self.sp = self.x; 2 }
+            0x9A => { self.sp = self.x; self.leave_frames(); 2 }
             
             // TSX - Transfer Stack Pointer to X
             0xBA => { self.x = self.sp; self.set_zn(self.x); 2 }
             
             // RTI - Return from Interrupt
-            0x40 => { self.rti(bus); 6 }
+            0x40 => { self.rti(bus); self.leave_frames(); 6 }
             
             // BRK - Break
-            0x00 => { self.brk(bus); 7 }
+            0x00 => {
+                let (from, sp) = (self.pc.wrapping_sub(1), self.sp);
+                self.brk(bus);
+                self.enter_frame(FrameKind::Brk, from, sp);
+                7
+            }
             
             _ => {
                 // Unknown opcode, treat as NOP
@@ -241,36 +277,36 @@ impl CPU {
     }
     
     // Addressing modes
-    fn immediate(&mut self, bus: &mut Bus) -> u8 {
+    fn immediate(&mut self, bus: &mut impl Memory) -> u8 {
         let val = bus.read(self.pc);
         self.pc = self.pc.wrapping_add(1);
         val
     }
     
-    fn zero_page(&mut self, bus: &mut Bus) -> u8 {
+    fn zero_page(&mut self, bus: &mut impl Memory) -> u8 {
         let addr = bus.read(self.pc) as u16;
         self.pc = self.pc.wrapping_add(1);
         bus.read(addr)
     }
     
-    fn zero_page_x(&mut self, bus: &mut Bus) -> u8 {
+    fn zero_page_x(&mut self, bus: &mut impl Memory) -> u8 {
         let addr = (bus.read(self.pc).wrapping_add(self.x)) as u16;
         self.pc = self.pc.wrapping_add(1);
         bus.read(addr)
     }
     
-    fn zero_page_y(&mut self, bus: &mut Bus) -> u8 {
+    fn zero_page_y(&mut self, bus: &mut impl Memory) -> u8 {
         let addr = (bus.read(self.pc).wrapping_add(self.y)) as u16;
         self.pc = self.pc.wrapping_add(1);
         bus.read(addr)
     }
     
-    fn absolute(&mut self, bus: &mut Bus) -> u8 {
+    fn absolute(&mut self, bus: &mut impl Memory) -> u8 {
         let addr = self.absolute_address(bus);
         bus.read(addr)
     }
     
-    fn absolute_address(&mut self, bus: &mut Bus) -> u16 {
+    fn absolute_address(&mut self, bus: &mut impl Memory) -> u16 {
         let lo = bus.read(self.pc) as u16;
         self.pc = self.pc.wrapping_add(1);
         let hi = bus.read(self.pc) as u16;
@@ -278,17 +314,17 @@ impl CPU {
         (hi << 8) | lo
     }
     
-    fn absolute_x(&mut self, bus: &mut Bus) -> u8 {
+    fn absolute_x(&mut self, bus: &mut impl Memory) -> u8 {
         let addr = self.absolute_address(bus).wrapping_add(self.x as u16);
         bus.read(addr)
     }
     
-    fn absolute_y(&mut self, bus: &mut Bus) -> u8 {
+    fn absolute_y(&mut self, bus: &mut impl Memory) -> u8 {
         let addr = self.absolute_address(bus).wrapping_add(self.y as u16);
         bus.read(addr)
     }
     
-    fn indexed_indirect(&mut self, bus: &mut Bus) -> u8 {
+    fn indexed_indirect(&mut self, bus: &mut impl Memory) -> u8 {
         let base = bus.read(self.pc);
         self.pc = self.pc.wrapping_add(1);
         let addr_lo = (base.wrapping_add(self.x)) as u16;
@@ -299,7 +335,7 @@ impl CPU {
         bus.read(addr)
     }
     
-    fn indirect_indexed(&mut self, bus: &mut Bus) -> u8 {
+    fn indirect_indexed(&mut self, bus: &mut impl Memory) -> u8 {
         let base = bus.read(self.pc) as u16;
         self.pc = self.pc.wrapping_add(1);
         let lo = bus.read(base) as u16;
@@ -308,7 +344,7 @@ impl CPU {
         bus.read(addr)
     }
     
-    fn indirect_address(&mut self, bus: &mut Bus) -> u16 {
+    fn indirect_address(&mut self, bus: &mut impl Memory) -> u16 {
         let addr_lo = bus.read(self.pc) as u16;
         self.pc = self.pc.wrapping_add(1);
         let addr_hi = bus.read(self.pc) as u16;
@@ -327,19 +363,19 @@ impl CPU {
     }
     
     // Write addressing modes
-    fn zero_page_write(&mut self, bus: &mut Bus, data: u8) {
+    fn zero_page_write(&mut self, bus: &mut impl Memory, data: u8) {
         let addr = bus.read(self.pc) as u16;
         self.pc = self.pc.wrapping_add(1);
         bus.write(addr, data);
     }
     
-    fn zero_page_x_write(&mut self, bus: &mut Bus, data: u8) {
+    fn zero_page_x_write(&mut self, bus: &mut impl Memory, data: u8) {
         let addr = (bus.read(self.pc).wrapping_add(self.x)) as u16;
         self.pc = self.pc.wrapping_add(1);
         bus.write(addr, data);
     }
     
-    fn absolute_write(&mut self, bus: &mut Bus, data: u8) {
+    fn absolute_write(&mut self, bus: &mut impl Memory, data: u8) {
         let addr = self.absolute_address(bus);
         if addr == 0x4014 {
             self.dma_request = true;
@@ -349,17 +385,17 @@ impl CPU {
         }
     }
     
-    fn absolute_x_write(&mut self, bus: &mut Bus, data: u8) {
+    fn absolute_x_write(&mut self, bus: &mut impl Memory, data: u8) {
         let addr = self.absolute_address(bus).wrapping_add(self.x as u16);
         bus.write(addr, data);
     }
     
-    fn absolute_y_write(&mut self, bus: &mut Bus, data: u8) {
+    fn absolute_y_write(&mut self, bus: &mut impl Memory, data: u8) {
         let addr = self.absolute_address(bus).wrapping_add(self.y as u16);
         bus.write(addr, data);
     }
     
-    fn indexed_indirect_write(&mut self, bus: &mut Bus, data: u8) {
+    fn indexed_indirect_write(&mut self, bus: &mut impl Memory, data: u8) {
         let base = bus.read(self.pc);
         self.pc = self.pc.wrapping_add(1);
         let addr_lo = (base.wrapping_add(self.x)) as u16;
@@ -370,7 +406,7 @@ impl CPU {
         bus.write(addr, data);
     }
     
-    fn indirect_indexed_write(&mut self, bus: &mut Bus, data: u8) {
+    fn indirect_indexed_write(&mut self, bus: &mut impl Memory, data: u8) {
         let base = bus.read(self.pc) as u16;
         self.pc = self.pc.wrapping_add(1);
         let lo = bus.read(base) as u16;
@@ -465,27 +501,27 @@ impl CPU {
         self.set_zn(self.a);
     }
     
-    fn jsr(&mut self, bus: &mut Bus) {
+    fn jsr(&mut self, bus: &mut impl Memory) {
         let ret_addr = self.pc.wrapping_sub(1);
         self.push(bus, (ret_addr >> 8) as u8);
         self.push(bus, ret_addr as u8);
         self.pc = self.absolute_address(bus);
     }
     
-    fn rts(&mut self, bus: &mut Bus) {
+    fn rts(&mut self, bus: &mut impl Memory) {
         let lo = self.pull(bus) as u16;
         let hi = self.pull(bus) as u16;
         self.pc = ((hi << 8) | lo).wrapping_add(1);
     }
     
-    fn rti(&mut self, bus: &mut Bus) {
+    fn rti(&mut self, bus: &mut impl Memory) {
         self.status = (self.pull(bus) & !FLAG_BREAK) | FLAG_UNUSED;
         let lo = self.pull(bus) as u16;
         let hi = self.pull(bus) as u16;
         self.pc = (hi << 8) | lo;
     }
     
-    fn brk(&mut self, bus: &mut Bus) {
+    fn brk(&mut self, bus: &mut impl Memory) {
         self.pc = self.pc.wrapping_add(1);
         self.push(bus, (self.pc >> 8) as u8);
         self.push(bus, self.pc as u8);
@@ -497,7 +533,7 @@ impl CPU {
         self.pc = (hi << 8) | lo;
     }
     
-    fn branch(&mut self, condition: bool, bus: &mut Bus) -> u8 {
+    fn branch(&mut self, condition: bool, bus: &mut impl Memory) -> u8 {
         let offset = self.immediate(bus) as i8;
         if condition {
             let old_pc = self.pc;
@@ -513,16 +549,32 @@ impl CPU {
         }
     }
     
-    fn push(&mut self, bus: &mut Bus, data: u8) {
+    fn push(&mut self, bus: &mut impl Memory, data: u8) {
         bus.write(0x0100 + self.sp as u16, data);
         self.sp = self.sp.wrapping_sub(1);
     }
     
-    fn pull(&mut self, bus: &mut Bus) -> u8 {
+    fn pull(&mut self, bus: &mut impl Memory) -> u8 {
         self.sp = self.sp.wrapping_add(1);
         bus.read(0x0100 + self.sp as u16)
     }
     
+    fn enter_frame(&mut self, kind: FrameKind, from: u16, return_sp: u8) {
+        if self.call_stack.len() >= MAX_CALL_DEPTH {
+            self.call_stack.remove(0);
+        }
+        self.call_stack.push(CallFrame { kind, from, to: self.pc, return_sp });
+    }
+    
+    // Drops frames whose return address is no longer on the stack. Popping by stack
+    // pointer rather than one frame per RTS keeps the call stack sane when games
+    // discard return addresses with PLA or reset the stack with TXS.
+    fn leave_frames(&mut self) {
+        while self.call_stack.last().is_some_and(|frame| frame.return_sp <= self.sp) {
+            self.call_stack.pop();
+        }
+    }
+    
     // Flag operations
     fn get_flag(&self, flag: u8) -> bool {
         self.status & flag != 0
@@ -541,7 +593,8 @@ impl CPU {
         self.set_flag(FLAG_NEGATIVE, val & 0x80 != 0);
     }
     
-    pub fn nmi(&mut self, bus: &mut Bus) {
+    pub fn nmi(&mut self, bus: &mut impl Memory) {
+        let (from, sp) = (self.pc, self.sp);
         self.push(bus, (self.pc >> 8) as u8);
         self.push(bus, self.pc as u8);
         self.push(bus, self.status & !FLAG_BREAK | FLAG_UNUSED);
@@ -550,5 +603,44 @@ impl CPU {
         let lo = bus.read(0xFFFA) as u16;
         let hi = bus.read(0xFFFB) as u16;
         self.pc = (hi << 8) | lo;
+        self.enter_frame(FrameKind::Nmi, from, sp);
+    }
+
+    // Level-triggered, so the caller checks the line before each instruction. Masked by the
+    // I flag, which the handler runs with set.
+    pub fn irq(&mut self, bus: &mut impl Memory) -> bool {
+        if self.get_flag(FLAG_INTERRUPT) {
+            return false;
+        }
+        let (from, sp) = (self.pc, self.sp);
+        self.push(bus, (self.pc >> 8) as u8);
+        self.push(bus, self.pc as u8);
+        self.push(bus, self.status & !FLAG_BREAK | FLAG_UNUSED);
+        self.set_flag(FLAG_INTERRUPT, true);
+
+        let lo = bus.read(0xFFFE) as u16;
+        let hi = bus.read(0xFFFF) as u16;
+        self.pc = (hi << 8) | lo;
+        self.enter_frame(FrameKind::Irq, from, sp);
+        true
     }
-}
\ No newline at end of file
+
+    pub fn save_state(&self, w: &mut dyn Write) -> io::Result<()> {
+        Stateful::save(self, w)
+    }
+
+    pub fn load_state(&mut self, r: &mut dyn Read) -> io::Result<()> {
+        Stateful::load(self, r)?;
+        // The call stack is a debugging aid and is not part of the state
+        self.call_stack.clear();
+        Ok(())
+    }
+}
+
+stateful!(CPU { a, x, y, sp, status, dma_request, dma_page, pc, cycles });
+
+impl Default for CPU {
+    fn default() -> Self {
+        Self::new()
+    }
+}
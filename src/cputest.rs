@@ -0,0 +1,177 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use serde::Deserialize;
+
+use crate::bus::Memory;
+use crate::cpu::CPU;
+use crate::disasm;
+
+// Runner for the ProcessorTests single-step vectors (github.com/SingleStepTests/ProcessorTests,
+// nes6502 set). Each `xx.json` holds cases for one opcode: the CPU and RAM before and after a
+// single instruction, plus every bus access it made.
+
+#[derive(Deserialize)]
+struct State {
+    pc: u16,
+    s: u8,
+    a: u8,
+    x: u8,
+    y: u8,
+    p: u8,
+    ram: Vec<(u16, u8)>,
+}
+
+#[derive(Deserialize)]
+struct Case {
+    name: String,
+    initial: State,
+    #[serde(rename = "final")]
+    expected: State,
+    cycles: Vec<(u16, u8, String)>,
+}
+
+// Flat 64KB memory that records every access.
+struct FlatMemory {
+    ram: Vec<u8>,
+    log: Vec<(u16, u8, bool)>,
+}
+
+impl Memory for FlatMemory {
+    fn read(&mut self, addr: u16) -> u8 {
+        let data = self.ram[addr as usize];
+        self.log.push((addr, data, false));
+        data
+    }
+
+    fn write(&mut self, addr: u16, data: u8) {
+        self.ram[addr as usize] = data;
+        self.log.push((addr, data, true));
+    }
+}
+
+#[derive(Default)]
+struct Tally {
+    cases: usize,
+    state: usize,
+    cycles: usize,
+    bus: usize,
+    first_failure: Option<String>,
+}
+
+fn describe((pc, sp, a, x, y, p): (u16, u8, u8, u8, u8, u8)) -> String {
+    format!("PC:{:04X} A:{:02X} X:{:02X} Y:{:02X} P:{:02X} SP:{:02X}", pc, a, x, y, p, sp)
+}
+
+fn run_case(case: &Case, memory: &mut FlatMemory, tally: &mut Tally) {
+    for &(addr, data) in &case.initial.ram {
+        memory.ram[addr as usize] = data;
+    }
+    memory.log.clear();
+
+    let mut cpu = CPU::new();
+    cpu.pc = case.initial.pc;
+    cpu.sp = case.initial.s;
+    cpu.a = case.initial.a;
+    cpu.x = case.initial.x;
+    cpu.y = case.initial.y;
+    cpu.status = case.initial.p;
+    let cycles = cpu.step(memory) as usize;
+
+    let want = &case.expected;
+    let mut problems = Vec::new();
+    let actual_regs = (cpu.pc, cpu.sp, cpu.a, cpu.x, cpu.y, cpu.status);
+    let expected_regs = (want.pc, want.s, want.a, want.x, want.y, want.p);
+    if actual_regs != expected_regs {
+        problems.push(format!("registers {} expected {}", describe(actual_regs), describe(expected_regs)));
+    }
+    for &(addr, data) in &want.ram {
+        if memory.ram[addr as usize] != data {
+            problems.push(format!("${:04X} = {:02X} expected {:02X}", addr, memory.ram[addr as usize], data));
+        }
+    }
+
+    tally.cases += 1;
+    if problems.is_empty() {
+        tally.state += 1;
+    } else if tally.first_failure.is_none() {
+        tally.first_failure = Some(format!("\"{}\": {}", case.name, problems.join(", ")));
+    }
+    if cycles == case.cycles.len() {
+        tally.cycles += 1;
+    }
+    let bus_matches = memory.log.len() == case.cycles.len()
+        && memory.log.iter().zip(&case.cycles).all(|(&(addr, data, write), (want_addr, want_data, kind))| {
+            addr == *want_addr && data == *want_data && write == (kind == "write")
+        });
+    if bus_matches {
+        tally.bus += 1;
+    }
+
+    // Put back the touched bytes so the next case starts from zeroed memory
+    for &(addr, _) in case.initial.ram.iter().chain(&want.ram) {
+        memory.ram[addr as usize] = 0;
+    }
+    for &(addr, _, _) in &memory.log {
+        memory.ram[addr as usize] = 0;
+    }
+}
+
+fn run_file(path: &Path, memory: &mut FlatMemory) -> Result<Tally, Box<dyn std::error::Error>> {
+    let cases: Vec<Case> = serde_json::from_str(&fs::read_to_string(path)?)?;
+    let mut tally = Tally::default();
+    for case in &cases {
+        run_case(case, memory, &mut tally);
+    }
+    Ok(tally)
+}
+
+// Runs the given vector files (or directories of them) and prints per-opcode pass counts
+// for final state, cycle count and exact bus activity. Returns true when everything passed.
+pub fn run_all(paths: &[String]) -> bool {
+    let mut files: Vec<PathBuf> = Vec::new();
+    for path in paths.iter().map(Path::new) {
+        match fs::read_dir(path) {
+            Ok(entries) => files.extend(
+                entries
+                    .flatten()
+                    .map(|entry| entry.path())
+                    .filter(|p| p.extension().is_some_and(|ext| ext == "json")),
+            ),
+            Err(_) => files.push(path.to_path_buf()),
+        }
+    }
+    files.sort();
+
+    let mut memory = FlatMemory { ram: vec![0; 0x10000], log: Vec::new() };
+    let mut all_passed = true;
+    let mut opcodes_passed = 0;
+    for file in &files {
+        let stem = file.file_stem().map(|s| s.to_string_lossy().into_owned()).unwrap_or_default();
+        let mnemonic = u8::from_str_radix(&stem, 16).map_or("???", |opcode| disasm::lookup(opcode).0);
+        match run_file(file, &mut memory) {
+            Ok(tally) => {
+                let passed = tally.state == tally.cases && tally.cycles == tally.cases && tally.bus == tally.cases;
+                println!(
+                    "{:>2} {:<4} state {:>5}/{}  cycles {:>5}/{}  bus {:>5}/{}",
+                    stem.to_uppercase(), mnemonic,
+                    tally.state, tally.cases, tally.cycles, tally.cases, tally.bus, tally.cases
+                );
+                if let Some(failure) = tally.first_failure {
+                    println!("        first failure {}", failure);
+                }
+                if passed {
+                    opcodes_passed += 1;
+                } else {
+                    all_passed = false;
+                }
+            }
+            Err(e) => {
+                println!("{} error: {}", file.display(), e);
+                all_passed = false;
+            }
+        }
+    }
+
+    println!("{}/{} opcodes fully passed", opcodes_passed, files.len());
+    all_passed
+}
@@ -0,0 +1,105 @@
+use std::backtrace::Backtrace;
+use std::fs;
+use std::panic::{self, AssertUnwindSafe};
+use std::path::Path;
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::disasm;
+use crate::log_error;
+use crate::nes::NES;
+use crate::savestate;
+
+// Crash bundles. If emulation panics, a directory is written next to the ROM,
+// <rom_file>.crash-<time>/, holding:
+//
+//   report.txt   panic message, backtrace, command line and machine summary
+//   trace.txt    the last instructions before the crash
+//   state.ss     the machine as it was, loadable with --load-state
+//   game.cfg     the per-game config, if there is one
+//
+// The state is taken mid-frame, wherever the panic hit, so loading it and running on
+// normally reproduces the crash.
+
+static PANIC_MESSAGE: Mutex<Option<String>> = Mutex::new(None);
+
+// Keeps the standard panic output, and also remembers the message and backtrace for the report.
+pub fn install_hook() {
+    let default_hook = panic::take_hook();
+    panic::set_hook(Box::new(move |info| {
+        let message = format!("{}\n\nBacktrace:\n{}", info, Backtrace::force_capture());
+        if let Ok(mut slot) = PANIC_MESSAGE.lock() {
+            *slot = Some(message);
+        }
+        default_hook(info);
+    }));
+}
+
+// Runs emulation code; on a panic writes a crash bundle and exits.
+pub fn guard<T>(nes: &mut NES, rom_path: &str, f: impl FnOnce(&mut NES) -> T) -> T {
+    catch(nes, rom_path, f).unwrap_or_else(|message| {
+        log_error!("{}", message);
+        std::process::exit(101);
+    })
+}
+
+// Like guard, but returns what happened instead of exiting, for the window to show. The
+// machine is left as the panic found it, so don't run it again.
+pub fn catch<T>(nes: &mut NES, rom_path: &str, f: impl FnOnce(&mut NES) -> T) -> Result<T, String> {
+    panic::catch_unwind(AssertUnwindSafe(|| f(nes))).map_err(|_| match write_bundle(nes, rom_path) {
+        Ok(dir) => format!("Emulation crashed. Details were saved to {}; please attach them to a bug report.", dir),
+        Err(e) => format!("Emulation crashed, and the crash report could not be written: {}", e),
+    })
+}
+
+fn trace_text(nes: &NES) -> String {
+    // Disassembled from memory as it is now, which can differ for code in RAM
+    let symbols = &nes.debugger().symbols;
+    let mut out = String::new();
+    for s in nes.history().iter() {
+        let (text, _) = disasm::disassemble(|addr| nes.peek(addr), s.pc, symbols);
+        out.push_str(&format!(
+            "{:04X}  {:<20}A:{:02X} X:{:02X} Y:{:02X} P:{:02X} SP:{:02X} SL:{} CYC:{}\n",
+            s.pc, text, s.a, s.x, s.y, s.p, s.sp, s.scanline, s.dot
+        ));
+    }
+    out
+}
+
+fn report_text(nes: &NES, rom_path: &str) -> String {
+    let message = PANIC_MESSAGE.lock().ok().and_then(|slot| slot.clone()).unwrap_or("(no panic message)".to_string());
+    let cpu = nes.cpu();
+    let args: Vec<String> = std::env::args().collect();
+    let mut out = format!("zetr {} crash report\n\n", env!("CARGO_PKG_VERSION"));
+    out.push_str(&format!("Command line: {}\n", args.join(" ")));
+    out.push_str(&format!("ROM: {}\n", rom_path));
+    if let Some(cart) = nes.cartridge() {
+        out.push_str(&format!("ROM hash: {:016x} (mapper {}, run as {})\n", cart.rom_hash(), cart.mapper, cart.board_name()));
+    }
+    out.push_str(&format!("Region: {}\n", nes.region().name()));
+    out.push_str(&format!("Frame: {}, scanline {}, dot {}\n", nes.ppu().frame_count, nes.ppu().scanline, nes.ppu().cycle));
+    out.push_str(&format!(
+        "CPU: PC:{:04X} A:{:02X} X:{:02X} Y:{:02X} P:{:02X} SP:{:02X} cycles {}\n\n",
+        cpu.pc, cpu.a, cpu.x, cpu.y, cpu.status, cpu.sp, cpu.cycles
+    ));
+    out.push_str(&format!("Panic: {}\n", message));
+    out
+}
+
+pub fn write_bundle(nes: &NES, rom_path: &str) -> Result<String, Box<dyn std::error::Error>> {
+    let now = SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |d| d.as_secs());
+    let stamp = format!("{}-{:02}", savestate::format_date(now).replace([' ', ':'], "-"), now % 60);
+    let dir = format!("{}.crash-{}", rom_path, stamp);
+    fs::create_dir_all(&dir)?;
+    let dir_path = Path::new(&dir);
+
+    // Write the text first, the state is the most likely part to fail
+    fs::write(dir_path.join("report.txt"), report_text(nes, rom_path))?;
+    fs::write(dir_path.join("trace.txt"), trace_text(nes))?;
+    let config = format!("{}.cfg", rom_path);
+    if Path::new(&config).exists() {
+        fs::copy(&config, dir_path.join("game.cfg"))?;
+    }
+    savestate::save(nes, &dir_path.join("state.ss").to_string_lossy(), "crash")?;
+    Ok(dir)
+}
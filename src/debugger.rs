@@ -0,0 +1,498 @@
+use std::io::{self, BufRead, Write};
+
+use crate::cpu::FrameKind;
+use crate::disasm;
+use crate::expr::{self, Expr};
+use crate::hash;
+use crate::layers::{Layer, NO_SPRITE};
+use crate::nes::NES;
+use crate::log;
+use crate::log_error;
+use crate::profiler;
+use crate::symbols::SymbolTable;
+use crate::trace::{Comparison, TraceCompare, TraceLog};
+
+// A breakpoint stops at addr (or at any instruction when addr is None) if its condition holds.
+#[derive(Debug, Clone)]
+pub struct Breakpoint {
+    pub addr: Option<u16>,
+    pub condition: Option<(String, Expr)>,
+}
+
+impl Breakpoint {
+    fn hit(&self, pc: u16, ctx: &dyn expr::Context) -> bool {
+        self.addr.is_none_or(|addr| addr == pc)
+            && self.condition.as_ref().is_none_or(|(_, expr)| expr.is_true(ctx))
+    }
+
+    fn describe(&self, symbols: &SymbolTable) -> String {
+        let addr = match self.addr {
+            Some(addr) => symbols.format_addr(addr, false),
+            None => "*".to_string(),
+        };
+        match &self.condition {
+            Some((text, _)) => format!("{} if {}", addr, text),
+            None => addr,
+        }
+    }
+}
+
+// Console debugger. The emulator stops before executing an instruction at a
+// breakpoint (or when F12 is pressed) and reads commands from stdin until resumed.
+#[derive(Debug, Default)]
+pub struct Debugger {
+    pub symbols: SymbolTable,
+    pub trace: bool,
+    pub trace_log: Option<TraceLog>,
+    pub compare: Option<TraceCompare>,
+    breakpoints: Vec<Breakpoint>,
+    break_requested: bool,
+    paused: bool,
+    skip_next: bool,
+    steps_remaining: Option<u32>,
+}
+
+impl Debugger {
+    pub fn new() -> Self {
+        Debugger::default()
+    }
+
+    pub fn request_break(&mut self) {
+        self.break_requested = true;
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.paused
+    }
+
+    pub fn add_breakpoint(&mut self, breakpoint: Breakpoint) {
+        self.breakpoints.push(breakpoint);
+    }
+
+    pub fn resume(&mut self, steps: Option<u32>) {
+        self.paused = false;
+        self.skip_next = true;
+        self.steps_remaining = steps;
+    }
+
+    pub fn write_trace(&mut self, line: &str) {
+        if let Some(log) = self.trace_log.as_mut() {
+            if let Err(e) = log.write(line) {
+                log_error!("Trace log {} failed, stopping: {}", log.path(), e);
+                self.trace_log = None;
+            }
+        }
+    }
+
+    // Checks a trace line against the --compare reference, pausing at the first difference.
+    pub fn compare_trace(&mut self, line: &str) {
+        let Some(compare) = self.compare.as_mut() else {
+            return;
+        };
+        match compare.check(line) {
+            Comparison::Match => {}
+            Comparison::Diverged(report) => {
+                println!("{}", report);
+                self.compare = None;
+                self.paused = true;
+            }
+            Comparison::Finished(message) => {
+                println!("{}", message);
+                self.compare = None;
+            }
+        }
+    }
+
+    // Called before each instruction. Returns true (and pauses) if execution should stop at pc.
+    pub fn should_break(&mut self, pc: u16, ctx: &dyn expr::Context) -> bool {
+        if self.skip_next {
+            self.skip_next = false;
+            return false;
+        }
+
+        let hit = match self.steps_remaining {
+            Some(n) if n <= 1 => {
+                self.steps_remaining = None;
+                true
+            }
+            Some(n) => {
+                self.steps_remaining = Some(n - 1);
+                false
+            }
+            None => false,
+        };
+
+        if hit || self.break_requested || self.breakpoints.iter().any(|bp| bp.hit(pc, ctx)) {
+            self.break_requested = false;
+            self.steps_remaining = None;
+            self.paused = true;
+        }
+        self.paused
+    }
+}
+
+pub fn parse_addr(text: &str, symbols: &SymbolTable) -> Option<u16> {
+    if let Some(addr) = symbols.address(text) {
+        return Some(addr);
+    }
+    let hex = text
+        .strip_prefix('$')
+        .or_else(|| text.strip_prefix("0x"))
+        .unwrap_or(text);
+    u16::from_str_radix(hex, 16).ok()
+}
+
+// Parses "<addr|label|*> [if <expr>]".
+fn parse_breakpoint(words: &[&str], symbols: &SymbolTable) -> Result<Breakpoint, String> {
+    let (&target, rest) = words.split_first().ok_or("missing address")?;
+    let addr = match target {
+        "*" => None,
+        _ => Some(parse_addr(target, symbols).ok_or(format!("unknown address '{}'", target))?),
+    };
+    let condition = match rest.split_first() {
+        None => None,
+        Some((&"if", expr)) => {
+            let text = expr.join(" ");
+            let parsed = Expr::parse(&text, symbols)?;
+            Some((text, parsed))
+        }
+        Some((word, _)) => return Err(format!("expected 'if', found '{}'", word)),
+    };
+    if addr.is_none() && condition.is_none() {
+        return Err("* needs a condition".to_string());
+    }
+    Ok(Breakpoint { addr, condition })
+}
+
+// One line of CPU state in the style of a nestest log, with labels substituted.
+pub fn trace_line(nes: &NES) -> String {
+    let cpu = nes.cpu();
+    let symbols = &nes.debugger().symbols;
+    let (text, _) = disasm::disassemble(|addr| nes.peek(addr), cpu.pc, symbols);
+    let label = symbols.label(cpu.pc).map(|l| format!("{}:", l)).unwrap_or_default();
+    format!(
+        "{:04X}  {:<12}{:<20}A:{:02X} X:{:02X} Y:{:02X} P:{:02X} SP:{:02X} SL:{} CYC:{}",
+        cpu.pc, label, text, cpu.a, cpu.x, cpu.y, cpu.status, cpu.sp,
+        nes.ppu().scanline, nes.ppu().cycle
+    )
+}
+
+// Picture and memory fingerprints at the current frame, for bisecting differences between
+// builds or settings with scripts. PRG RAM is only listed when the cartridge has some.
+pub fn hash_line(nes: &NES) -> String {
+    let mut line = format!(
+        "frame {} picture {:016x} ram {:016x}",
+        nes.ppu().frame_count,
+        hash::fnv1a(nes.get_frame_buffer()),
+        nes.ram_hash()
+    );
+    if let Some(cart) = nes.cartridge().filter(|cart| !cart.prg_ram.is_empty()) {
+        line += &format!(" prg-ram {:016x}", hash::fnv1a(&cart.prg_ram));
+    }
+    line
+}
+
+fn print_disassembly(nes: &NES, mut addr: u16, count: usize) {
+    let symbols = &nes.debugger().symbols;
+    for _ in 0..count {
+        if let Some(label) = symbols.label(addr) {
+            println!("{}:", label);
+        }
+        let (text, len) = disasm::disassemble(|a| nes.peek(a), addr, symbols);
+        let bytes: Vec<String> = (0..len).map(|i| format!("{:02X}", nes.peek(addr.wrapping_add(i)))).collect();
+        let marker = if addr == nes.cpu().pc { ">" } else { " " };
+        println!("{} {:04X}  {:<9} {}", marker, addr, bytes.join(" "), text);
+        addr = addr.wrapping_add(len);
+    }
+}
+
+fn print_memory(nes: &NES, addr: u16, len: u16) {
+    for row in (0..len).step_by(16) {
+        let start = addr.wrapping_add(row);
+        let bytes: Vec<String> = (0..16.min(len - row))
+            .map(|i| format!("{:02X}", nes.peek(start.wrapping_add(i))))
+            .collect();
+        println!("{:04X}: {}", start, bytes.join(" "));
+    }
+}
+
+// Shows where a pixel of the current frame came from. Lines the beam hasn't reached yet
+// still show the previous frame.
+fn pixel_command(nes: &mut NES, words: &[&str]) {
+    let coordinate = |i: usize, limit: usize| words.get(i).and_then(|w| w.parse::<usize>().ok()).filter(|&v| v < limit);
+    let (Some(x), Some(y)) = (coordinate(1, 256), coordinate(2, 240)) else {
+        println!("Usage: pixel <x 0-255> <y 0-239>");
+        return;
+    };
+    let Some(layers) = nes.layers() else {
+        nes.set_track_layers(true);
+        println!("Layer tracking is on from now; run a frame and ask again");
+        return;
+    };
+    let pixel = layers[y * 256 + x];
+    let ppu = nes.ppu();
+    let color = |addr: u8| ppu.palette_ram[addr as usize & 0x1F];
+    let shown = match pixel.shown() {
+        Layer::Backdrop => "backdrop",
+        Layer::Background => "background",
+        Layer::Sprite => "sprite",
+    };
+    println!("({}, {}): {}", x, y, shown);
+    match pixel.background {
+        0 => println!("  background: transparent, backdrop color ${:02X}", color(0)),
+        addr => println!("  background: palette ${:02X}, color ${:02X}", addr, color(addr)),
+    }
+    if pixel.sprite_index == NO_SPRITE {
+        println!("  sprite:     none");
+    } else {
+        let entry = &ppu.oam[pixel.sprite_index as usize * 4..][..4];
+        println!(
+            "  sprite:     #{} (tile ${:02X} at {}, {}), palette ${:02X}, color ${:02X}, {} background",
+            pixel.sprite_index, entry[1], entry[3], entry[0] as u16 + 1, pixel.sprite, color(pixel.sprite),
+            if pixel.behind { "behind" } else { "in front of" }
+        );
+    }
+}
+
+// Views, exports or imports the cartridge's PRG RAM, so saves can be hand-edited or migrated.
+fn sram_command(nes: &mut NES, words: &[&str]) {
+    let Some(cartridge) = nes.cartridge_mut() else {
+        println!("No cartridge loaded");
+        return;
+    };
+    if cartridge.prg_ram.is_empty() {
+        println!("Cartridge has no PRG RAM");
+        return;
+    }
+    match words.get(1..).unwrap_or_default() {
+        [] | ["view", ..] => {
+            let size = cartridge.prg_ram.len();
+            let offset = words.get(2).and_then(|w| usize::from_str_radix(w.trim_start_matches('$'), 16).ok()).unwrap_or(0);
+            let len = words.get(3).and_then(|w| w.parse().ok()).unwrap_or(256);
+            if offset >= size {
+                println!("Offset ${:04X} is past the end of PRG RAM ({} bytes)", offset, size);
+                return;
+            }
+            let end = offset.saturating_add(len).min(size);
+            for start in (offset..end).step_by(16) {
+                let row = &cartridge.prg_ram[start..(start + 16).min(end)];
+                let bytes: Vec<String> = row.iter().map(|b| format!("{:02X}", b)).collect();
+                let text: String = row.iter().map(|&b| if b.is_ascii_graphic() || b == b' ' { b as char } else { '.' }).collect();
+                println!("{:04X}: {:<47}  {}", 0x6000 + start, bytes.join(" "), text);
+            }
+        }
+        ["export", path] => match std::fs::write(path, &cartridge.prg_ram) {
+            Ok(()) => println!("Wrote {} bytes of PRG RAM to {}", cartridge.prg_ram.len(), path),
+            Err(e) => println!("Error writing {}: {}", path, e),
+        },
+        ["import", path] => match std::fs::read(path) {
+            Ok(data) if data.len() == cartridge.prg_ram.len() => {
+                cartridge.prg_ram.copy_from_slice(&data);
+                cartridge.prg_ram_dirty = true;
+                println!("Loaded {} bytes of PRG RAM from {}", data.len(), path);
+            }
+            Ok(data) => println!(
+                "{} is {} bytes but PRG RAM is {} bytes",
+                path, data.len(), cartridge.prg_ram.len()
+            ),
+            Err(e) => println!("Error reading {}: {}", path, e),
+        },
+        _ => println!("Usage: sram [view [offset] [len]] | sram export <file> | sram import <file>"),
+    }
+}
+
+fn print_call_stack(nes: &NES) {
+    let symbols = &nes.debugger().symbols;
+    let frames = &nes.cpu().call_stack;
+    if frames.is_empty() {
+        println!("Call stack is empty");
+    }
+    for (depth, frame) in frames.iter().rev().enumerate() {
+        let kind = match frame.kind {
+            FrameKind::Subroutine => "JSR",
+            FrameKind::Nmi => "NMI",
+            FrameKind::Irq => "IRQ",
+            FrameKind::Brk => "BRK",
+        };
+        println!(
+            "#{:<2} {} {} from {}",
+            depth, kind, symbols.format_addr(frame.to, false), symbols.format_addr(frame.from, false)
+        );
+    }
+}
+
+// Raw view of the used part of the $0100 page, marking the bytes each call frame pushed.
+fn print_stack(nes: &NES) {
+    let symbols = &nes.debugger().symbols;
+    let cpu = nes.cpu();
+    println!("SP = ${:02X}", cpu.sp);
+    for sp in (cpu.sp as u16 + 1)..=0xFF {
+        let addr = 0x0100 | sp;
+        let value = nes.peek(addr);
+        let note = cpu.call_stack.iter().find_map(|frame| {
+            let offset = (frame.return_sp as u16).wrapping_sub(sp);
+            let ret = (nes.peek(0x0100 | frame.return_sp as u16) as u16) << 8
+                | nes.peek(0x0100 | frame.return_sp.wrapping_sub(1) as u16) as u16;
+            match (frame.kind, offset) {
+                (FrameKind::Subroutine, 0) => Some(format!("return to {} (hi)", symbols.format_addr(ret.wrapping_add(1), false))),
+                (FrameKind::Subroutine, 1) => Some("(lo)".to_string()),
+                (_, 0) => Some(format!("interrupt return to {} (hi)", symbols.format_addr(ret, false))),
+                (_, 1) => Some("(lo)".to_string()),
+                (_, 2) => Some("(status)".to_string()),
+                _ => None,
+            }
+        });
+        println!("  ${:04X}: {:02X}  {}", addr, value, note.unwrap_or_default());
+    }
+}
+
+const HELP: &str = "\
+c                 continue
+s [n]             step n instructions (default 1)
+b <addr|label|*> [if <expr>]
+                  add breakpoint, * for any address, e.g. b * if A == #$3F && scanline > 200
+d <n>             delete breakpoint n
+bl                list breakpoints
+u [addr] [n]      disassemble n instructions (default: pc, 10)
+m <addr> [len]    dump memory (default 64 bytes)
+r                 show registers
+bt                show the call stack (JSR and interrupt frames)
+stack             show the $0100 page annotated with return addresses
+sym <file>        load a .nl or .dbg symbol file
+t                 toggle instruction trace to the console
+trace <file> [range <lo>-<hi>] [bank <n>] [max <MB>] [keep <n>] [if <expr>]
+                  trace to a file, rotating at max MB (default 256) and keeping n old files
+trace off         stop tracing to a file
+prof [reset]      show or clear the profiler report (needs --features profiler)
+log [filter]      show or set the log levels, e.g. log warn,apu=debug
+sram [view [offset] [len]]
+                  dump PRG RAM (battery save) at a hex offset (default: 0, 256 bytes)
+sram export <file>
+                  write PRG RAM to a raw .sav-style file
+sram import <file>
+                  replace PRG RAM with a file of the same size (flushed by the battery saver)
+pixel <x> <y>     show which background and sprite pixels were drawn at a screen position
+hash              show the frame number and hashes of the picture, RAM and PRG RAM
+q                 quit";
+
+// Runs the debugger prompt until the user resumes. Returns false if the user asked to quit.
+pub fn prompt(nes: &mut NES) -> bool {
+    println!("{}", trace_line(nes));
+    let stdin = io::stdin();
+    loop {
+        print!("zetr> ");
+        io::stdout().flush().ok();
+
+        let mut line = String::new();
+        if stdin.lock().read_line(&mut line).unwrap_or(0) == 0 {
+            return false;
+        }
+        let words: Vec<&str> = line.split_whitespace().collect();
+        let Some(&command) = words.first() else {
+            continue;
+        };
+        let arg_addr = |i: usize| words.get(i).and_then(|w| parse_addr(w, &nes.debugger().symbols));
+
+        match command {
+            "c" => {
+                nes.debugger_mut().resume(None);
+                return true;
+            }
+            "s" => {
+                let steps = words.get(1).and_then(|w| w.parse().ok()).unwrap_or(1);
+                nes.debugger_mut().resume(Some(steps));
+                return true;
+            }
+            "b" => match parse_breakpoint(&words[1..], &nes.debugger().symbols) {
+                Ok(bp) => {
+                    println!("Breakpoint {}", bp.describe(&nes.debugger().symbols));
+                    nes.debugger_mut().add_breakpoint(bp);
+                }
+                Err(e) => println!("{} (usage: b <addr|label|*> [if <expr>])", e),
+            },
+            "d" => {
+                let index: Option<usize> = words.get(1).and_then(|w| w.parse().ok());
+                let breakpoints = &mut nes.debugger_mut().breakpoints;
+                match index {
+                    Some(i) if i < breakpoints.len() => {
+                        breakpoints.remove(i);
+                    }
+                    _ => println!("Usage: d <n> (see bl)"),
+                }
+            }
+            "bl" => {
+                let debugger = nes.debugger();
+                for (i, bp) in debugger.breakpoints.iter().enumerate() {
+                    println!("{}: {}", i, bp.describe(&debugger.symbols));
+                }
+            }
+            "u" => {
+                let addr = arg_addr(1).unwrap_or(nes.cpu().pc);
+                let count = words.get(2).and_then(|w| w.parse().ok()).unwrap_or(10);
+                print_disassembly(nes, addr, count);
+            }
+            "m" => match arg_addr(1) {
+                Some(addr) => {
+                    let len = words.get(2).and_then(|w| w.parse().ok()).unwrap_or(64);
+                    print_memory(nes, addr, len);
+                }
+                None => println!("Usage: m <addr> [len]"),
+            },
+            "r" => println!("{}", trace_line(nes)),
+            "bt" => print_call_stack(nes),
+            "stack" => print_stack(nes),
+            "sym" => match words.get(1) {
+                Some(path) => match nes.debugger_mut().symbols.load(path) {
+                    Ok(count) => println!("Loaded {} symbols from {}", count, path),
+                    Err(e) => println!("Error loading {}: {}", path, e),
+                },
+                None => println!("Usage: sym <file>"),
+            },
+            "t" => {
+                let debugger = nes.debugger_mut();
+                debugger.trace = !debugger.trace;
+                println!("Trace {}", if debugger.trace { "on" } else { "off" });
+            }
+            "trace" => match words.get(1) {
+                Some(&"off") => {
+                    nes.debugger_mut().trace_log = None;
+                    println!("Trace log closed");
+                }
+                Some(_) => match TraceLog::from_args(&words[1..], &nes.debugger().symbols) {
+                    Ok(log) => {
+                        println!("Tracing to {}", log.path());
+                        nes.debugger_mut().trace_log = Some(log);
+                    }
+                    Err(e) => println!("{}", e),
+                },
+                None => match &nes.debugger().trace_log {
+                    Some(log) => println!("Tracing to {}", log.path()),
+                    None => println!("Not tracing to a file"),
+                },
+            },
+            "prof" => match words.get(1) {
+                Some(&"reset") => {
+                    profiler::reset();
+                    println!("Profiler reset");
+                }
+                _ => print!("{}", profiler::report()),
+            },
+            "log" => match words.get(1) {
+                Some(filter) => match log::set_filter(filter) {
+                    Ok(()) => println!("Logging {}", log::filter()),
+                    Err(e) => println!("{}", e),
+                },
+                None => match log::file_path() {
+                    Some(path) => println!("Logging {} to {}", log::filter(), path.display()),
+                    None => println!("Logging {} to the console", log::filter()),
+                },
+            },
+            "sram" => sram_command(nes, &words),
+            "pixel" => pixel_command(nes, &words),
+            "hash" => println!("{}", hash_line(nes)),
+            "q" => return false,
+            "h" | "?" => println!("{}", HELP),
+            _ => println!("Unknown command '{}', type h for help", command),
+        }
+    }
+}
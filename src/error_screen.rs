@@ -0,0 +1,84 @@
+use std::error::Error;
+use std::io;
+
+use crate::osd;
+
+// Errors that stop the game, shown in the window. Someone who opened a ROM from the file
+// manager never sees the console, least of all on Windows, so a bad ROM or a crash would
+// otherwise look like the window flashing and going away. The screen says what went wrong,
+// the error as zetr has it and what to try, and stays up until a key is pressed.
+
+const MARGIN: usize = 8;
+const LINE_HEIGHT: usize = osd::CHAR_HEIGHT + 2;
+const BACKGROUND: (u8, u8, u8) = (32, 16, 16);
+const RED: (u8, u8, u8) = (255, 96, 80);
+const GREY: (u8, u8, u8) = (176, 176, 176);
+
+pub struct ErrorScreen {
+    title: String,
+    details: String,
+    hints: Vec<String>,
+}
+
+impl ErrorScreen {
+    pub fn new(title: impl Into<String>, details: impl Into<String>, hints: Vec<String>) -> Self {
+        ErrorScreen { title: title.into(), details: details.into(), hints }
+    }
+
+    // A ROM that wouldn't load, with suggestions picked by what went wrong.
+    pub fn rom_error(rom_path: &str, error: &(dyn Error + 'static)) -> Self {
+        let mut hints = Vec::new();
+        match error.downcast_ref::<io::Error>().map(io::Error::kind) {
+            Some(io::ErrorKind::NotFound) => hints.push("Check the file name and folder; it may have been moved or renamed.".to_string()),
+            Some(io::ErrorKind::PermissionDenied) => hints.push("zetr isn't allowed to read the file. Check its permissions.".to_string()),
+            Some(io::ErrorKind::UnexpectedEof) => hints.push("The file is too short to be a ROM. It may be an incomplete download.".to_string()),
+            _ => {}
+        }
+        let message = error.to_string();
+        if message.contains("Invalid ROM file format") {
+            hints.push("zetr plays iNES ROMs (.nes). Zipped ROMs need extracting first.".to_string());
+        }
+        if message.contains("no PRG data") {
+            hints.push("The header says the ROM has no program, so the dump is probably bad.".to_string());
+        }
+        hints.push(format!("zetr info {} lists any problems with the header.", file_name(rom_path)));
+        ErrorScreen::new("Couldn't load the ROM", format!("{}: {}", file_name(rom_path), message), hints)
+    }
+
+    // Emulation panicked; `message` is crash::catch's, which says where the bundle went.
+    pub fn crash(message: &str) -> Self {
+        let hints = vec![
+            "This is a bug in zetr, not in your ROM. Please report it with the crash directory attached.".to_string(),
+            "The game can't continue, but battery saves made before the crash are kept.".to_string(),
+        ];
+        ErrorScreen::new("Emulation crashed", message, hints)
+    }
+
+    pub fn draw(&self, frame: &mut [u8]) {
+        for pixel in frame.chunks_exact_mut(3) {
+            pixel.copy_from_slice(&[BACKGROUND.0, BACKGROUND.1, BACKGROUND.2]);
+        }
+        let columns = (osd::WIDTH - 2 * MARGIN) / osd::CHAR_WIDTH;
+        let mut y = MARGIN;
+        osd::draw_text(frame, MARGIN, y, &self.title, RED);
+        y += LINE_HEIGHT * 2;
+        for line in osd::wrap(&self.details, columns) {
+            osd::draw_text(frame, MARGIN, y, &line, osd::WHITE);
+            y += LINE_HEIGHT;
+        }
+        y += LINE_HEIGHT;
+        for hint in &self.hints {
+            for (i, line) in osd::wrap(hint, columns - 2).iter().enumerate() {
+                osd::draw_text(frame, MARGIN, y, if i == 0 { "-" } else { " " }, osd::YELLOW);
+                osd::draw_text(frame, MARGIN + 2 * osd::CHAR_WIDTH, y, line, osd::YELLOW);
+                y += LINE_HEIGHT;
+            }
+            y += 2;
+        }
+        osd::draw_text(frame, MARGIN, osd::HEIGHT - MARGIN - osd::CHAR_HEIGHT, "Press any key to close", GREY);
+    }
+}
+
+fn file_name(path: &str) -> &str {
+    path.rsplit(['/', '\\']).next().unwrap_or(path)
+}
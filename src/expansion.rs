@@ -0,0 +1,58 @@
+use std::io::{self, Read, Write};
+
+use crate::hypershot::HyperShot;
+use crate::mahjong::Mahjong;
+use crate::ppu::PPU;
+
+// Devices on the Famicom expansion port: keyboards, paddles, mahjong controllers and the
+// like. They all see the same signals, so they plug into the bus the same way instead of
+// each patching the controller path:
+//
+//   $4016 write  bits 0-2  OUT0-OUT2, sent to the device (OUT0 is also the controller strobe)
+//   $4016 read   bit 1     from the device
+//   $4017 read   bits 1-4  from the device
+//
+// The device's bits are ORed with the standard controller and Zapper bits.
+
+pub trait ExpansionDevice {
+    fn name(&self) -> &'static str;
+
+    // OUT0-OUT2 from a $4016 write.
+    fn write(&mut self, out: u8);
+
+    // Bits for a read of $4016 (port 0) or $4017 (port 1). Bits outside the ones listed
+    // above are ignored.
+    fn read(&mut self, port: u8, ppu: &PPU) -> u8;
+
+    // Key or button `button` (numbered by the device) went down or up.
+    fn press(&mut self, _button: u8, _down: bool) {}
+
+    // Keyboard keys for the device's buttons, by key name ("Q", "Return"), which the
+    // frontend maps to its own keys. They stop driving controller 1 while it is plugged in.
+    fn keys(&self) -> &'static [(&'static str, u8)] {
+        &[]
+    }
+
+    // Key help for the help screen.
+    fn controls(&self) -> &'static str {
+        ""
+    }
+
+    // For save states, so a device halfway through a transfer survives a load.
+    fn save_state(&self, _w: &mut dyn Write) -> io::Result<()> {
+        Ok(())
+    }
+
+    fn load_state(&mut self, _r: &mut dyn Read) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+// Device by name, for --expansion and the `expansion` config key.
+pub fn by_name(name: &str) -> Option<Box<dyn ExpansionDevice>> {
+    match name.to_ascii_lowercase().as_str() {
+        "mahjong" => Some(Box::new(Mahjong::default())),
+        "hypershot" => Some(Box::new(HyperShot::default())),
+        _ => None,
+    }
+}
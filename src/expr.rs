@@ -0,0 +1,251 @@
+use crate::symbols::SymbolTable;
+
+// Debugger expressions such as `A == #$3F && scanline > 200` or `[PlayerX] >= $80`.
+// Numbers are hex with `$`/`0x` (optionally `#`-prefixed) or decimal; `[addr]` reads memory.
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Var {
+    A,
+    X,
+    Y,
+    SP,
+    P,
+    PC,
+    Scanline,
+    Cycle,
+    Frame,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum BinOp {
+    Or,
+    And,
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+    BitAnd,
+    BitOr,
+    BitXor,
+    Add,
+    Sub,
+}
+
+#[derive(Debug, Clone)]
+pub enum Expr {
+    Num(i64),
+    Var(Var),
+    Mem(Box<Expr>),
+    Not(Box<Expr>),
+    Neg(Box<Expr>),
+    Binary(BinOp, Box<Expr>, Box<Expr>),
+}
+
+pub trait Context {
+    fn var(&self, var: Var) -> i64;
+    fn read(&self, addr: u16) -> u8;
+}
+
+impl Expr {
+    pub fn parse(text: &str, symbols: &SymbolTable) -> Result<Expr, String> {
+        let tokens = tokenize(text)?;
+        let mut parser = Parser { tokens, pos: 0, symbols };
+        let expr = parser.or()?;
+        match parser.tokens.get(parser.pos) {
+            None => Ok(expr),
+            Some(token) => Err(format!("unexpected '{}'", token)),
+        }
+    }
+
+    pub fn eval(&self, ctx: &dyn Context) -> i64 {
+        match self {
+            Expr::Num(n) => *n,
+            Expr::Var(var) => ctx.var(*var),
+            Expr::Mem(addr) => ctx.read(addr.eval(ctx) as u16) as i64,
+            Expr::Not(e) => (e.eval(ctx) == 0) as i64,
+            Expr::Neg(e) => e.eval(ctx).wrapping_neg(),
+            Expr::Binary(op, lhs, rhs) => {
+                let l = lhs.eval(ctx);
+                // Short-circuit so `[addr]` reads on the right are skipped when not needed
+                match op {
+                    BinOp::Or => return (l != 0 || rhs.eval(ctx) != 0) as i64,
+                    BinOp::And => return (l != 0 && rhs.eval(ctx) != 0) as i64,
+                    _ => {}
+                }
+                let r = rhs.eval(ctx);
+                match op {
+                    BinOp::Eq => (l == r) as i64,
+                    BinOp::Ne => (l != r) as i64,
+                    BinOp::Lt => (l < r) as i64,
+                    BinOp::Le => (l <= r) as i64,
+                    BinOp::Gt => (l > r) as i64,
+                    BinOp::Ge => (l >= r) as i64,
+                    BinOp::BitAnd => l & r,
+                    BinOp::BitOr => l | r,
+                    BinOp::BitXor => l ^ r,
+                    // Wrapping, so a large literal can't panic the debugger
+                    BinOp::Add => l.wrapping_add(r),
+                    BinOp::Sub => l.wrapping_sub(r),
+                    BinOp::Or | BinOp::And => unreachable!(),
+                }
+            }
+        }
+    }
+
+    pub fn is_true(&self, ctx: &dyn Context) -> bool {
+        self.eval(ctx) != 0
+    }
+}
+
+fn tokenize(text: &str) -> Result<Vec<String>, String> {
+    let chars: Vec<char> = text.chars().collect();
+    let mut tokens = Vec::new();
+    let mut i = 0;
+    while i < chars.len() {
+        let c = chars[i];
+        if c.is_whitespace() {
+            i += 1;
+        } else if c.is_ascii_alphanumeric() || c == '_' || c == '$' || c == '#' || c == '@' {
+            let start = i;
+            i += 1;
+            while i < chars.len() && (chars[i].is_ascii_alphanumeric() || chars[i] == '_' || chars[i] == '$') {
+                i += 1;
+            }
+            tokens.push(chars[start..i].iter().collect());
+        } else {
+            let pair: String = chars[i..(i + 2).min(chars.len())].iter().collect();
+            if ["==", "!=", "<=", ">=", "&&", "||"].contains(&pair.as_str()) {
+                tokens.push(pair);
+                i += 2;
+            } else if "<>&|^+-![]()".contains(c) {
+                tokens.push(c.to_string());
+                i += 1;
+            } else {
+                return Err(format!("unexpected character '{}'", c));
+            }
+        }
+    }
+    Ok(tokens)
+}
+
+struct Parser<'a> {
+    tokens: Vec<String>,
+    pos: usize,
+    symbols: &'a SymbolTable,
+}
+
+impl Parser<'_> {
+    fn peek(&self) -> Option<&str> {
+        self.tokens.get(self.pos).map(|s| s.as_str())
+    }
+
+    fn next(&mut self) -> Option<String> {
+        let token = self.tokens.get(self.pos).cloned();
+        self.pos += 1;
+        token
+    }
+
+    fn binary(&mut self, ops: &[(&str, BinOp)], operand: fn(&mut Self) -> Result<Expr, String>) -> Result<Expr, String> {
+        let mut lhs = operand(self)?;
+        while let Some(&(_, op)) = ops.iter().find(|(s, _)| Some(*s) == self.peek()) {
+            self.pos += 1;
+            let rhs = operand(self)?;
+            lhs = Expr::Binary(op, Box::new(lhs), Box::new(rhs));
+        }
+        Ok(lhs)
+    }
+
+    fn or(&mut self) -> Result<Expr, String> {
+        self.binary(&[("||", BinOp::Or)], Self::and)
+    }
+
+    fn and(&mut self) -> Result<Expr, String> {
+        self.binary(&[("&&", BinOp::And)], Self::comparison)
+    }
+
+    fn comparison(&mut self) -> Result<Expr, String> {
+        let ops = [
+            ("==", BinOp::Eq), ("!=", BinOp::Ne), ("<=", BinOp::Le),
+            (">=", BinOp::Ge), ("<", BinOp::Lt), (">", BinOp::Gt),
+        ];
+        self.binary(&ops, Self::bits)
+    }
+
+    fn bits(&mut self) -> Result<Expr, String> {
+        self.binary(&[("&", BinOp::BitAnd), ("|", BinOp::BitOr), ("^", BinOp::BitXor)], Self::sum)
+    }
+
+    fn sum(&mut self) -> Result<Expr, String> {
+        self.binary(&[("+", BinOp::Add), ("-", BinOp::Sub)], Self::unary)
+    }
+
+    fn unary(&mut self) -> Result<Expr, String> {
+        match self.peek() {
+            Some("!") => {
+                self.pos += 1;
+                Ok(Expr::Not(Box::new(self.unary()?)))
+            }
+            Some("-") => {
+                self.pos += 1;
+                Ok(Expr::Neg(Box::new(self.unary()?)))
+            }
+            _ => self.atom(),
+        }
+    }
+
+    fn expect(&mut self, token: &str) -> Result<(), String> {
+        match self.next() {
+            Some(t) if t == token => Ok(()),
+            Some(t) => Err(format!("expected '{}', found '{}'", token, t)),
+            None => Err(format!("expected '{}'", token)),
+        }
+    }
+
+    fn atom(&mut self) -> Result<Expr, String> {
+        let token = self.next().ok_or("unexpected end of expression")?;
+        match token.as_str() {
+            "(" => {
+                let expr = self.or()?;
+                self.expect(")")?;
+                Ok(expr)
+            }
+            "[" => {
+                let addr = self.or()?;
+                self.expect("]")?;
+                Ok(Expr::Mem(Box::new(addr)))
+            }
+            _ => self.value(&token),
+        }
+    }
+
+    fn value(&self, token: &str) -> Result<Expr, String> {
+        let var = match token.to_ascii_lowercase().as_str() {
+            "a" => Some(Var::A),
+            "x" => Some(Var::X),
+            "y" => Some(Var::Y),
+            "sp" => Some(Var::SP),
+            "p" => Some(Var::P),
+            "pc" => Some(Var::PC),
+            "scanline" | "sl" => Some(Var::Scanline),
+            "cycle" | "dot" => Some(Var::Cycle),
+            "frame" => Some(Var::Frame),
+            _ => None,
+        };
+        if let Some(var) = var {
+            return Ok(Expr::Var(var));
+        }
+        if let Some(addr) = self.symbols.address(token) {
+            return Ok(Expr::Num(addr as i64));
+        }
+
+        let digits = token.trim_start_matches('#');
+        let number = if let Some(hex) = digits.strip_prefix('$').or_else(|| digits.strip_prefix("0x")) {
+            i64::from_str_radix(hex, 16)
+        } else {
+            digits.parse()
+        };
+        number.map(Expr::Num).map_err(|_| format!("unknown value '{}'", token))
+    }
+}
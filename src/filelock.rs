@@ -0,0 +1,39 @@
+use std::fs::{self, File, OpenOptions, TryLockError};
+use std::io;
+use std::path::Path;
+
+// Advisory locks so several zetr windows can run side by side without writing over each
+// other's files. The lock is taken on `<file>.lock` rather than the file itself, because
+// guarded files are replaced by renaming a new copy over them. It is held until the
+// FileLock is dropped or the process exits; leftover .lock files are harmless.
+
+pub struct FileLock {
+    _file: File,
+}
+
+fn open(path: &str) -> io::Result<File> {
+    let lock_path = format!("{}.lock", path);
+    if let Some(dir) = Path::new(&lock_path).parent().filter(|dir| !dir.as_os_str().is_empty()) {
+        fs::create_dir_all(dir)?;
+    }
+    OpenOptions::new().write(true).create(true).truncate(false).open(lock_path)
+}
+
+impl FileLock {
+    // Waits for any other instance holding it.
+    pub fn acquire(path: &str) -> io::Result<Self> {
+        let file = open(path)?;
+        file.lock()?;
+        Ok(FileLock { _file: file })
+    }
+
+    // None if another instance holds it.
+    pub fn try_acquire(path: &str) -> io::Result<Option<Self>> {
+        let file = open(path)?;
+        match file.try_lock() {
+            Ok(()) => Ok(Some(FileLock { _file: file })),
+            Err(TryLockError::WouldBlock) => Ok(None),
+            Err(TryLockError::Error(e)) => Err(e),
+        }
+    }
+}
@@ -0,0 +1,60 @@
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+
+use crate::image;
+
+// Writes frames into a directory as frame_000000.png, frame_000001.png, ... numbered by
+// emulated frame, so dumps from two builds can be compared file by file. Raw dumps are
+// bare 256x240 RGB24, e.g. for `ffmpeg -f rawvideo -pixel_format rgb24 -video_size 256x240
+// -framerate 60.0988 -i frame_%06d.rgb`.
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum DumpFormat {
+    Png,
+    Ppm,
+    Raw,
+}
+
+impl DumpFormat {
+    pub fn parse(name: &str) -> Option<Self> {
+        match name {
+            "png" => Some(DumpFormat::Png),
+            "ppm" => Some(DumpFormat::Ppm),
+            "raw" => Some(DumpFormat::Raw),
+            _ => None,
+        }
+    }
+
+    fn extension(self) -> &'static str {
+        match self {
+            DumpFormat::Png => "png",
+            DumpFormat::Ppm => "ppm",
+            DumpFormat::Raw => "rgb",
+        }
+    }
+}
+
+pub struct FrameDumper {
+    dir: PathBuf,
+    format: DumpFormat,
+    pub written: u64,
+}
+
+impl FrameDumper {
+    pub fn new(dir: &str, format: DumpFormat) -> io::Result<Self> {
+        fs::create_dir_all(dir)?;
+        Ok(FrameDumper { dir: PathBuf::from(dir), format, written: 0 })
+    }
+
+    pub fn write(&mut self, frame_number: u64, frame: &[u8]) -> io::Result<()> {
+        let path = self.dir.join(format!("frame_{:06}.{}", frame_number, self.format.extension()));
+        match self.format {
+            DumpFormat::Png => fs::write(path, image::encode_png(frame))?,
+            DumpFormat::Ppm => fs::write(path, image::encode_ppm(frame))?,
+            DumpFormat::Raw => fs::write(path, frame)?,
+        }
+        self.written += 1;
+        Ok(())
+    }
+}
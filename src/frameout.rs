@@ -0,0 +1,98 @@
+use std::fs::{File, OpenOptions};
+use std::io::{self, Seek, SeekFrom, Write};
+use std::path::PathBuf;
+
+use byteorder::{LittleEndian, WriteBytesExt};
+
+// Live frame output for OBS plugins, bots and analyzers, without screen capture.
+//
+// Every frame is a 32-byte little-endian header followed by the pixels:
+//
+//   0  "ZETR"          magic
+//   4  u16 version     1
+//   6  u16 header size 32
+//   8  u16 width       256
+//  10  u16 height      240
+//  12  u32 format      0 = RGB24 (audio blocks will get their own format once there is an APU)
+//  16  u64 frame       emulated frame number
+//  24  u32 length      payload bytes after the header
+//  28  u32 sequence    shared memory only: odd while a frame is being written
+//
+// A plain path (usually a FIFO made with mkfifo) gets one record after another. `shm:<name>`
+// keeps a single record in /dev/shm/<name> (the temp directory on systems without it) that is
+// rewritten in place each frame; readers map the file, and retry if the sequence was odd or
+// changed while they copied the frame.
+
+pub const HEADER_SIZE: usize = 32;
+const VERSION: u16 = 1;
+const FORMAT_RGB24: u32 = 0;
+const WIDTH: u16 = 256;
+const HEIGHT: u16 = 240;
+
+enum Target {
+    Stream(File),
+    Shared { file: File, sequence: u32 },
+}
+
+pub struct FrameOutput {
+    path: PathBuf,
+    target: Target,
+}
+
+impl FrameOutput {
+    pub fn open(target: &str) -> io::Result<Self> {
+        match target.strip_prefix("shm:") {
+            Some(name) => {
+                let dir = PathBuf::from("/dev/shm");
+                let path = if dir.is_dir() { dir } else { std::env::temp_dir() }.join(name);
+                let file = OpenOptions::new().read(true).write(true).create(true).truncate(true).open(&path)?;
+                file.set_len((HEADER_SIZE + WIDTH as usize * HEIGHT as usize * 3) as u64)?;
+                Ok(FrameOutput { path, target: Target::Shared { file, sequence: 0 } })
+            }
+            None => {
+                let file = OpenOptions::new().write(true).create(true).truncate(true).open(target)?;
+                Ok(FrameOutput { path: PathBuf::from(target), target: Target::Stream(file) })
+            }
+        }
+    }
+
+    pub fn path(&self) -> String {
+        self.path.display().to_string()
+    }
+
+    pub fn write(&mut self, frame_number: u64, frame: &[u8]) -> io::Result<()> {
+        match &mut self.target {
+            Target::Stream(file) => {
+                let mut record = Vec::with_capacity(HEADER_SIZE + frame.len());
+                write_header(&mut record, frame_number, frame.len(), 0)?;
+                record.extend_from_slice(frame);
+                file.write_all(&record)
+            }
+            Target::Shared { file, sequence } => {
+                *sequence = sequence.wrapping_add(1);
+                file.seek(SeekFrom::Start(28))?;
+                file.write_u32::<LittleEndian>(*sequence)?;
+                let mut record = Vec::with_capacity(HEADER_SIZE + frame.len());
+                write_header(&mut record, frame_number, frame.len(), *sequence)?;
+                record.extend_from_slice(frame);
+                file.seek(SeekFrom::Start(0))?;
+                file.write_all(&record)?;
+                *sequence = sequence.wrapping_add(1);
+                file.seek(SeekFrom::Start(28))?;
+                file.write_u32::<LittleEndian>(*sequence)
+            }
+        }
+    }
+}
+
+fn write_header(w: &mut Vec<u8>, frame_number: u64, length: usize, sequence: u32) -> io::Result<()> {
+    w.write_all(b"ZETR")?;
+    w.write_u16::<LittleEndian>(VERSION)?;
+    w.write_u16::<LittleEndian>(HEADER_SIZE as u16)?;
+    w.write_u16::<LittleEndian>(WIDTH)?;
+    w.write_u16::<LittleEndian>(HEIGHT)?;
+    w.write_u32::<LittleEndian>(FORMAT_RGB24)?;
+    w.write_u64::<LittleEndian>(frame_number)?;
+    w.write_u32::<LittleEndian>(length as u32)?;
+    w.write_u32::<LittleEndian>(sequence)
+}
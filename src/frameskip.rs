@@ -0,0 +1,44 @@
+// Frame skipping for slow machines. Skipped frames are fully emulated (timing, flags,
+// NMI) but the PPU doesn't produce pixels and the frontend doesn't present them.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum FrameSkip {
+    // Draw one frame, then skip this many
+    Fixed(u32),
+    // Skip only while emulation is falling behind real time
+    Auto,
+}
+
+// Consecutive frames auto mode may skip before it draws one anyway.
+const MAX_AUTO_SKIP: u32 = 3;
+
+impl FrameSkip {
+    // "auto" or a count from 0 to 3.
+    pub fn parse(text: &str) -> Option<Self> {
+        match text {
+            "auto" => Some(FrameSkip::Auto),
+            _ => text.parse().ok().filter(|&n| n <= 3).map(FrameSkip::Fixed),
+        }
+    }
+}
+
+pub struct FrameSkipper {
+    mode: FrameSkip,
+    skipped: u32,
+}
+
+impl FrameSkipper {
+    pub fn new(mode: FrameSkip) -> Self {
+        FrameSkipper { mode, skipped: 0 }
+    }
+
+    // Decides whether the next frame is skipped. `behind` says whether the last frame
+    // took longer than its time slot.
+    pub fn next(&mut self, behind: bool) -> bool {
+        let skip = match self.mode {
+            FrameSkip::Fixed(n) => self.skipped < n,
+            FrameSkip::Auto => behind && self.skipped < MAX_AUTO_SKIP,
+        };
+        self.skipped = if skip { self.skipped + 1 } else { 0 };
+        skip
+    }
+}
@@ -0,0 +1,65 @@
+use std::fs;
+use std::io::ErrorKind;
+
+// Per-game settings, read from <rom_file>.cfg next to the ROM. One `key = value` per line,
+// with # starting a comment:
+//
+//   # Always run this one at PAL speed
+//   region = pal
+//
+// Unknown keys are kept so newer settings don't break older builds.
+
+#[derive(Debug, Default)]
+pub struct GameConfig {
+    pub path: String,
+    entries: Vec<(String, String)>,
+}
+
+impl GameConfig {
+    // A missing file is not an error, it just means no overrides.
+    pub fn load_for_rom(rom_path: &str) -> Result<Self, Box<dyn std::error::Error>> {
+        Self::load(&format!("{}.cfg", rom_path))
+    }
+
+    // Any file in the same format, such as input.cfg. Missing is the same as empty.
+    pub fn load(path: &str) -> Result<Self, Box<dyn std::error::Error>> {
+        let path = path.to_string();
+        let text = match fs::read_to_string(&path) {
+            Ok(text) => text,
+            Err(e) if e.kind() == ErrorKind::NotFound => String::new(),
+            Err(e) => return Err(e.into()),
+        };
+        let mut config = Self::parse(&text).map_err(|e| format!("{}: {}", path, e))?;
+        config.path = path;
+        Ok(config)
+    }
+
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let mut config = GameConfig::default();
+        for (number, line) in text.lines().enumerate() {
+            let line = line.split('#').next().unwrap_or("").trim();
+            if line.is_empty() {
+                continue;
+            }
+            let Some((key, value)) = line.split_once('=') else {
+                return Err(format!("line {}: expected 'key = value'", number + 1));
+            };
+            config.entries.push((key.trim().to_ascii_lowercase(), value.trim().to_string()));
+        }
+        Ok(config)
+    }
+
+    pub fn entries(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.entries.iter().map(|(k, v)| (k.as_str(), v.as_str()))
+    }
+
+    // Overrides the file, e.g. from --set key=value.
+    pub fn set(&mut self, key: &str, value: &str) {
+        self.entries.push((key.trim().to_ascii_lowercase(), value.trim().to_string()));
+    }
+
+    // Last one wins if a key is repeated
+    pub fn get(&self, key: &str) -> Option<&str> {
+        self.entries.iter().rev().find(|(k, _)| k == key).map(|(_, v)| v.as_str())
+    }
+}
@@ -0,0 +1,122 @@
+use std::fs;
+use std::path::Path;
+
+use crate::hash;
+use crate::image;
+use crate::nes::{self, NES};
+
+// Golden-frame checks. A manifest lists one case per line, paths relative to the manifest:
+//
+//   # rom              frames  hash              input
+//   roms/donkeykong.nes  300   0123456789abcdef  120=start 130= 200=right+a
+//
+// Each case boots the ROM, runs `frames` frames and hashes the final frame buffer.
+// Input entries set the held buttons from that frame on (empty means release all).
+// A hash of `-` marks a case that has no baseline yet.
+struct Case {
+    line: usize,
+    rom: String,
+    frames: u32,
+    hash: Option<u64>,
+    input: Vec<(u32, u8)>,
+}
+
+fn parse_input(token: &str) -> Result<(u32, u8), String> {
+    let (frame, buttons) = token.split_once('=').ok_or(format!("expected frame=buttons, got '{}'", token))?;
+    let frame = frame.parse().map_err(|_| format!("bad frame number '{}'", frame))?;
+    let mut mask = 0;
+    for name in buttons.split('+').filter(|name| !name.is_empty()) {
+        mask |= nes::button_mask(name).ok_or(format!("unknown button '{}'", name))?;
+    }
+    Ok((frame, mask))
+}
+
+fn parse_case(line: usize, text: &str) -> Result<Case, String> {
+    let mut words = text.split_whitespace();
+    let rom = words.next().ok_or("missing ROM path")?.to_string();
+    let frames = words.next().and_then(|n| n.parse().ok()).ok_or("missing frame count")?;
+    let hash = match words.next().ok_or("missing hash")? {
+        "-" => None,
+        hex => Some(u64::from_str_radix(hex, 16).map_err(|_| format!("bad hash '{}'", hex))?),
+    };
+    let mut input = words.map(parse_input).collect::<Result<Vec<_>, _>>()?;
+    input.sort_by_key(|&(frame, _)| frame);
+    Ok(Case { line, rom, frames, hash, input })
+}
+
+// Runs a case and returns the NES in its final state.
+fn run_case(case: &Case, base: &Path) -> Result<NES, Box<dyn std::error::Error>> {
+    let mut nes = NES::new();
+    nes.load_cartridge(&base.join(&case.rom).to_string_lossy())?;
+    let mut input = case.input.iter().peekable();
+    for frame in 0..case.frames {
+        while let Some(&&(_, buttons)) = input.peek().filter(|(at, _)| *at <= frame) {
+            nes.set_controller1(buttons);
+            input.next();
+        }
+        nes.run_frame();
+        nes.frame_done();
+    }
+    Ok(nes)
+}
+
+// Checks every case, or with `update` rewrites the manifest with fresh hashes.
+// Returns true when all cases matched (always true after an update).
+pub fn run(manifest: &str, update: bool) -> Result<bool, Box<dyn std::error::Error>> {
+    let text = fs::read_to_string(manifest)?;
+    let base = Path::new(manifest).parent().unwrap_or(Path::new("."));
+    let mut out = String::new();
+    let mut total = 0;
+    let mut passed = 0;
+
+    for (number, line) in text.lines().enumerate() {
+        let trimmed = line.trim();
+        if trimmed.is_empty() || trimmed.starts_with('#') {
+            out.push_str(line);
+            out.push('\n');
+            continue;
+        }
+        let case = parse_case(number + 1, trimmed).map_err(|e| format!("{}:{}: {}", manifest, number + 1, e))?;
+        total += 1;
+
+        let nes = match run_case(&case, base) {
+            Ok(nes) => nes,
+            Err(e) => {
+                println!("ERROR    {} ({})", case.rom, e);
+                out.push_str(line);
+                out.push('\n');
+                continue;
+            }
+        };
+        let actual = hash::fnv1a(nes.get_frame_buffer());
+
+        if update {
+            let mut words: Vec<String> = trimmed.split_whitespace().map(str::to_string).collect();
+            words[2] = format!("{:016x}", actual);
+            out.push_str(&words.join(" "));
+            out.push('\n');
+            if case.hash != Some(actual) {
+                println!("UPDATED  {} frame {} -> {:016x}", case.rom, case.frames, actual);
+            }
+            passed += 1;
+        } else if case.hash == Some(actual) {
+            println!("PASS     {} frame {}", case.rom, case.frames);
+            passed += 1;
+        } else {
+            let dump = base.join(format!("{}.{}.actual.ppm", case.rom, case.frames));
+            let expected = case.hash.map_or("none".to_string(), |h| format!("{:016x}", h));
+            println!("FAIL     {} frame {} (line {}): expected {}, got {:016x}",
+                case.rom, case.frames, case.line, expected, actual);
+            match fs::write(&dump, image::encode_ppm(nes.get_frame_buffer())) {
+                Ok(()) => println!("         wrote {}", dump.display()),
+                Err(e) => println!("         could not write {}: {}", dump.display(), e),
+            }
+        }
+    }
+
+    if update {
+        fs::write(manifest, out)?;
+    }
+    println!("{}/{} golden frames {}", passed, total, if update { "updated" } else { "matched" });
+    Ok(passed == total)
+}
@@ -0,0 +1,100 @@
+use crate::hotkeys::Hotkey;
+use crate::inputmap::Profile;
+use crate::nes;
+use crate::osd;
+use crate::quick_menu::Nav;
+
+// The controls as they are bound right now, over the paused game: controller 1's buttons
+// from the input profile, each hotkey's key and gamepad chord, the fixed keys, and whatever
+// else the window was set up with (Zapper, expansion device, macros, splits). It opens with
+// F2 or from the quick menu; up and down scroll, anything else closes it.
+
+const BUTTONS: [&str; 8] = ["a", "b", "select", "start", "up", "down", "left", "right"];
+
+// Keys handled in the window rather than through the hotkey table
+const OTHER_KEYS: [(&str, &str); 7] = [
+    ("mute", "1-5 (pulse 1, pulse 2, triangle, noise, DMC)"),
+    ("solo", "Shift+1-5"),
+    ("undo save", "Shift+F5"),
+    ("undo load", "Shift+F7"),
+    ("screenshot", "Ctrl+C"),
+    ("copy state", "Ctrl+Shift+C"),
+    ("paste state", "Ctrl+V"),
+];
+
+const NAME_COLUMNS: usize = 13;
+const COLUMNS: usize = 40;
+const ROW_HEIGHT: usize = osd::CHAR_HEIGHT + 2;
+const TOP: usize = 24;
+const ROWS: usize = (osd::HEIGHT - TOP - ROW_HEIGHT - 8) / ROW_HEIGHT;
+
+pub struct HelpScreen {
+    lines: Vec<(String, (u8, u8, u8))>,
+    scroll: usize,
+}
+
+impl HelpScreen {
+    // `extra` adds rows of (function, inputs) for what this window has on top of the profile.
+    pub fn new(profile: &Profile, extra: &[(String, String)]) -> Self {
+        let mut screen = HelpScreen { lines: Vec::new(), scroll: 0 };
+        screen.heading(&format!("CONTROLLER ({})", profile.name));
+        for button in BUTTONS {
+            let inputs: Vec<String> = nes::button_mask(button).map_or(Vec::new(), |mask| profile.inputs(mask).map(|input| input.describe()).collect());
+            screen.row(button, &inputs.join(", "));
+        }
+        screen.heading("HOTKEYS");
+        for hotkey in Hotkey::all() {
+            let chords = profile.chords().iter().filter(|chord| chord.hotkey == hotkey).map(|chord| format!("pad {}", chord.describe()));
+            let inputs: Vec<String> = std::iter::once(hotkey.key().name()).chain(chords).collect();
+            screen.row(&hotkey.name().replace('_', " "), &inputs.join(", "));
+        }
+        for (name, keys) in OTHER_KEYS {
+            screen.row(name, keys);
+        }
+        if !extra.is_empty() {
+            screen.heading("THIS GAME");
+            for (name, inputs) in extra {
+                screen.row(name, inputs);
+            }
+        }
+        screen
+    }
+
+    fn heading(&mut self, text: &str) {
+        if !self.lines.is_empty() {
+            self.lines.push((String::new(), osd::WHITE));
+        }
+        self.lines.push((text.to_string(), osd::YELLOW));
+    }
+
+    fn row(&mut self, name: &str, inputs: &str) {
+        let inputs = if inputs.is_empty() { "-" } else { inputs };
+        for (i, line) in osd::wrap(inputs, COLUMNS - NAME_COLUMNS).into_iter().enumerate() {
+            let name = if i == 0 { name } else { "" };
+            self.lines.push((format!("{:<width$}{}", name, line, width = NAME_COLUMNS), osd::WHITE));
+        }
+    }
+
+    // False once the screen should close. Keys and buttons with no Nav meaning close it too,
+    // which the window does by passing Back for them.
+    pub fn handle(&mut self, nav: Nav) -> bool {
+        let max_scroll = self.lines.len().saturating_sub(ROWS);
+        match nav {
+            Nav::Up => self.scroll = self.scroll.saturating_sub(1),
+            Nav::Down => self.scroll = (self.scroll + 1).min(max_scroll),
+            Nav::Left | Nav::Right | Nav::Choose | Nav::Back => return false,
+        }
+        true
+    }
+
+    pub fn draw(&self, frame: &mut [u8]) {
+        osd::shade_rect(frame, 0, 0, osd::WIDTH, osd::HEIGHT);
+        osd::draw_text(frame, 8, 8, &format!("ZETR {}", env!("CARGO_PKG_VERSION")), osd::WHITE);
+        for (i, (text, color)) in self.lines.iter().skip(self.scroll).take(ROWS).enumerate() {
+            osd::draw_text(frame, 8, TOP + i * ROW_HEIGHT, text, *color);
+        }
+        let more = self.scroll + ROWS < self.lines.len();
+        let help = if self.scroll > 0 || more { "UP/DOWN:SCROLL ESC:CLOSE" } else { "ESC:CLOSE" };
+        osd::draw_text(frame, 8, osd::HEIGHT - osd::CHAR_HEIGHT - 4, help, osd::GREEN);
+    }
+}
@@ -0,0 +1,129 @@
+use sdl2::controller::Button;
+use sdl2::event::Event;
+use sdl2::keyboard::{Keycode, Mod};
+
+// Emulator functions on gamepad button chords, for playing without a keyboard. A chord fires
+// its hotkey when its last button goes down while the others are held, and the window then
+// sees the hotkey's key being pressed, so every function keeps a single implementation.
+// The buttons still reach the game until the chord completes; the held ones are released
+// then, so the game doesn't see Select stuck down after Select+Start opens the menu.
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Hotkey {
+    Menu,
+    Help,
+    Pause,
+    Save,
+    NextSlot,
+    Load,
+    States,
+    Counters,
+    Apu,
+    Layers,
+    Debugger,
+    Quit,
+}
+
+const HOTKEYS: [(&str, Hotkey, Keycode); 12] = [
+    ("menu", Hotkey::Menu, Keycode::F1),
+    ("help", Hotkey::Help, Keycode::F2),
+    ("pause", Hotkey::Pause, Keycode::Pause),
+    ("save", Hotkey::Save, Keycode::F5),
+    ("next_slot", Hotkey::NextSlot, Keycode::F6),
+    ("load", Hotkey::Load, Keycode::F7),
+    ("states", Hotkey::States, Keycode::F8),
+    ("counters", Hotkey::Counters, Keycode::F3),
+    ("apu", Hotkey::Apu, Keycode::F4),
+    ("layers", Hotkey::Layers, Keycode::F10),
+    ("debugger", Hotkey::Debugger, Keycode::F12),
+    ("quit", Hotkey::Quit, Keycode::Escape),
+];
+
+impl Hotkey {
+    pub fn parse(name: &str) -> Option<Self> {
+        HOTKEYS.iter().find(|(n, ..)| n.eq_ignore_ascii_case(name)).map(|&(_, hotkey, _)| hotkey)
+    }
+
+    pub fn name(self) -> &'static str {
+        HOTKEYS.iter().find(|&&(_, h, _)| h == self).map_or("", |(name, ..)| name)
+    }
+
+    // The keyboard key that does the same
+    pub fn key(self) -> Keycode {
+        HOTKEYS.iter().find(|&&(_, h, _)| h == self).map_or(Keycode::Pause, |&(.., key)| key)
+    }
+
+    pub fn names() -> impl Iterator<Item = &'static str> {
+        HOTKEYS.iter().map(|(name, ..)| *name)
+    }
+
+    pub fn all() -> impl Iterator<Item = Hotkey> {
+        HOTKEYS.iter().map(|&(_, hotkey, _)| hotkey)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Chord {
+    pub buttons: Vec<Button>,
+    pub hotkey: Hotkey,
+}
+
+impl Chord {
+    // "back+start" style, for the help screen
+    pub fn describe(&self) -> String {
+        self.buttons.iter().map(|button| button.string()).collect::<Vec<_>>().join("+")
+    }
+}
+
+pub struct ChordTracker {
+    chords: Vec<Chord>,
+    held: Vec<Button>,
+}
+
+impl ChordTracker {
+    pub fn new(chords: &[Chord]) -> Self {
+        ChordTracker { chords: chords.to_vec(), held: Vec::new() }
+    }
+
+    // The events to handle in place of `event`: itself, or for a completed chord the
+    // releases of its other buttons and the hotkey's key.
+    pub fn translate(&mut self, event: Event) -> Vec<Event> {
+        match event {
+            Event::ControllerButtonDown { timestamp, which, button } => {
+                if !self.held.contains(&button) {
+                    self.held.push(button);
+                }
+                // The longest chord wins, so Select+Start+A can live alongside Select+Start
+                let held = &self.held;
+                let chord = self
+                    .chords
+                    .iter()
+                    .filter(|chord| chord.buttons.contains(&button) && chord.buttons.iter().all(|b| held.contains(b)))
+                    .max_by_key(|chord| chord.buttons.len());
+                let Some(chord) = chord else {
+                    return vec![event];
+                };
+                let mut events: Vec<Event> = chord
+                    .buttons
+                    .iter()
+                    .filter(|&&b| b != button)
+                    .map(|&b| Event::ControllerButtonUp { timestamp, which, button: b })
+                    .collect();
+                events.push(Event::KeyDown {
+                    timestamp,
+                    window_id: 0,
+                    keycode: Some(chord.hotkey.key()),
+                    scancode: None,
+                    keymod: Mod::NOMOD,
+                    repeat: false,
+                });
+                events
+            }
+            Event::ControllerButtonUp { button, .. } => {
+                self.held.retain(|&b| b != button);
+                vec![event]
+            }
+            event => vec![event],
+        }
+    }
+}
@@ -0,0 +1,70 @@
+use std::io::{self, Read, Write};
+
+use crate::expansion::ExpansionDevice;
+use crate::ppu::PPU;
+
+// Konami's Hyper Shot, two pairs of Run and Jump buttons for the Hyper Olympic games. A 0 in
+// $4016 bit 1 enables player 1's buttons and a 0 in bit 2 player 2's; $4017 reads them
+// directly, 1 for pressed:
+//
+//   bit 1  player 1 Jump     bit 3  player 2 Jump
+//   bit 2  player 1 Run      bit 4  player 2 Run
+//
+// Buttons are numbered 0-3 in that order.
+
+#[derive(Default)]
+pub struct HyperShot {
+    held: u8,
+    out: u8,
+}
+
+impl ExpansionDevice for HyperShot {
+    fn name(&self) -> &'static str {
+        "Hyper Shot"
+    }
+
+    fn write(&mut self, out: u8) {
+        self.out = out;
+    }
+
+    fn read(&mut self, port: u8, _ppu: &PPU) -> u8 {
+        if port == 0 {
+            return 0;
+        }
+        let mut enabled = 0;
+        if self.out & 0x02 == 0 {
+            enabled |= 0x03;
+        }
+        if self.out & 0x04 == 0 {
+            enabled |= 0x0C;
+        }
+        (self.held & enabled) << 1
+    }
+
+    fn press(&mut self, button: u8, down: bool) {
+        if down {
+            self.held |= 1 << button;
+        } else {
+            self.held &= !(1 << button);
+        }
+    }
+
+    fn keys(&self) -> &'static [(&'static str, u8)] {
+        &[("Q", 0), ("W", 1), ("O", 2), ("P", 3)]
+    }
+
+    fn controls(&self) -> &'static str {
+        "Q/W: Player 1 Jump/Run, O/P: Player 2 Jump/Run"
+    }
+
+    fn save_state(&self, w: &mut dyn Write) -> io::Result<()> {
+        w.write_all(&[self.out])
+    }
+
+    fn load_state(&mut self, r: &mut dyn Read) -> io::Result<()> {
+        let mut out = [0u8; 1];
+        r.read_exact(&mut out)?;
+        self.out = out[0] & 0x07;
+        Ok(())
+    }
+}
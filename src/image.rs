@@ -0,0 +1,68 @@
+// Minimal image encoders for the 256x240 RGB frame buffer, so screenshots and dumps don't
+// need an image library.
+
+use crate::hash;
+
+const WIDTH: usize = 256;
+const HEIGHT: usize = 240;
+
+// Binary PPM, readable by most image viewers and ffmpeg.
+pub fn encode_ppm(frame: &[u8]) -> Vec<u8> {
+    let mut data = format!("P6\n{} {}\n255\n", WIDTH, HEIGHT).into_bytes();
+    data.extend_from_slice(frame);
+    data
+}
+
+fn adler32(bytes: &[u8]) -> u32 {
+    let (mut a, mut b) = (1u32, 0u32);
+    for chunk in bytes.chunks(5552) {
+        for &byte in chunk {
+            a += byte as u32;
+            b += a;
+        }
+        a %= 65521;
+        b %= 65521;
+    }
+    b << 16 | a
+}
+
+fn write_chunk(out: &mut Vec<u8>, kind: &[u8; 4], data: &[u8]) {
+    out.extend_from_slice(&(data.len() as u32).to_be_bytes());
+    let start = out.len();
+    out.extend_from_slice(kind);
+    out.extend_from_slice(data);
+    let crc = hash::crc32(&out[start..]);
+    out.extend_from_slice(&crc.to_be_bytes());
+}
+
+// 8-bit RGB PNG. The image data goes in uncompressed deflate blocks: files are bigger
+// (about 180KB a frame) but encoding costs next to nothing, which matters when dumping
+// every frame. Re-encode afterwards if size matters.
+pub fn encode_png(frame: &[u8]) -> Vec<u8> {
+    let mut raw = Vec::with_capacity(HEIGHT * (WIDTH * 3 + 1));
+    for row in frame.chunks(WIDTH * 3) {
+        raw.push(0); // No filter
+        raw.extend_from_slice(row);
+    }
+
+    let mut zlib = vec![0x78, 0x01];
+    let mut blocks = raw.chunks(0xFFFF).peekable();
+    while let Some(block) = blocks.next() {
+        zlib.push(blocks.peek().is_none() as u8); // BFINAL, BTYPE 00 (stored)
+        zlib.extend_from_slice(&(block.len() as u16).to_le_bytes());
+        zlib.extend_from_slice(&(!(block.len() as u16)).to_le_bytes());
+        zlib.extend_from_slice(block);
+    }
+    zlib.extend_from_slice(&adler32(&raw).to_be_bytes());
+
+    let mut header = Vec::new();
+    header.extend_from_slice(&(WIDTH as u32).to_be_bytes());
+    header.extend_from_slice(&(HEIGHT as u32).to_be_bytes());
+    header.extend_from_slice(&[8, 2, 0, 0, 0]); // 8-bit, RGB, deflate, no filter, no interlace
+
+    let mut png = b"\x89PNG\r\n\x1a\n".to_vec();
+    write_chunk(&mut png, b"IHDR", &header);
+    write_chunk(&mut png, b"IDAT", &zlib);
+    write_chunk(&mut png, b"IEND", &[]);
+    png
+}
@@ -0,0 +1,153 @@
+use std::fs;
+use std::path::Path;
+
+use crate::cartridge::Cartridge;
+use crate::hash;
+use crate::mapper;
+use crate::quirks;
+use crate::romdb;
+
+// `zetr info <rom>`: what the header says, the hashes ROM databases use and anything that
+// looks wrong with the file, without starting the emulator. Meant for sorting out a
+// collection, so problems are reported rather than treated as errors.
+
+const HEADER_SIZE: usize = 16;
+const TRAINER_SIZE: usize = 512;
+
+fn mapper_name(mapper: u16) -> &'static str {
+    match mapper {
+        0 => "NROM",
+        1 => "MMC1",
+        2 => "UxROM",
+        3 => "CNROM",
+        4 => "MMC3",
+        5 => "MMC5",
+        7 => "AxROM",
+        9 => "MMC2",
+        10 => "MMC4",
+        11 => "Color Dreams",
+        15 => "100-in-1 Contra Function 16",
+        66 => "GxROM",
+        69 => "Sunsoft FME-7",
+        225 => "225 multicart",
+        228 => "Action 52",
+        _ => "unknown",
+    }
+}
+
+fn kb(bytes: usize) -> String {
+    if bytes.is_multiple_of(1024) { format!("{} KB", bytes / 1024) } else { format!("{} bytes", bytes) }
+}
+
+fn ram(bytes: usize) -> String {
+    if bytes == 0 { "none".to_string() } else { kb(bytes) }
+}
+
+pub fn report(path: &str) -> Result<String, Box<dyn std::error::Error>> {
+    let data = fs::read(path)?;
+    if data.len() < HEADER_SIZE || &data[0..4] != b"NES\x1A" {
+        return Err("not an iNES or NES 2.0 ROM (no NES<EOF> header)".into());
+    }
+    let header = &data[..HEADER_SIZE];
+    let (flags6, flags7) = (header[6], header[7]);
+    let nes2 = flags7 & 0x0C == 0x08;
+    let mut problems = Vec::new();
+
+    // Old dumping tools signed the unused end of the header ("DiskDude!"), which garbles
+    // the upper mapper nibble in byte 7
+    let junk = !nes2 && header[7..].iter().skip(5).any(|&b| b != 0);
+    if junk {
+        problems.push("junk in header bytes 12-15; the upper mapper bits are ignored".to_string());
+    }
+    if flags7 & 0x0C == 0x04 {
+        problems.push("header uses the reserved archaic iNES format".to_string());
+    }
+
+    let mut mapper = (flags6 >> 4) as u16;
+    if !junk {
+        mapper |= (flags7 & 0xF0) as u16;
+    }
+    let mut submapper = None;
+    if nes2 {
+        mapper |= ((header[8] & 0x0F) as u16) << 8;
+        submapper = Some(header[8] >> 4);
+        if header[9] != 0 {
+            problems.push("PRG/CHR size MSBs in byte 9 are not supported".to_string());
+        }
+    }
+    let prg_size = header[4] as usize * 16384;
+    let chr_size = header[5] as usize * 8192;
+    let trainer = flags6 & 0x04 != 0;
+
+    let expected = HEADER_SIZE + if trainer { TRAINER_SIZE } else { 0 } + prg_size + chr_size;
+    if data.len() < expected {
+        problems.push(format!("truncated: the header needs {} bytes but the file has {}", expected, data.len()));
+    } else if data.len() > expected {
+        problems.push(format!("overdump: {} extra bytes after the ROM data", data.len() - expected));
+    }
+    if prg_size == 0 {
+        problems.push("no PRG ROM".to_string());
+    }
+    if chr_size == 0 && matches!(mapper, 0 | 3) {
+        problems.push(format!("no CHR ROM, which {} boards always have (missing CHR?)", mapper_name(mapper)));
+    }
+    if !mapper::SUPPORTED.contains(&mapper) {
+        problems.push(format!("mapper {} is not emulated yet", mapper));
+    }
+
+    let name = Path::new(path).file_name().map_or(path.into(), |n| n.to_string_lossy());
+    let mut out = String::new();
+    out += &format!("File:        {} ({} bytes)\n", name, data.len());
+    out += &format!("Format:      {}\n", if nes2 { "NES 2.0" } else { "iNES 1.0" });
+    out += &format!("Mapper:      {} ({})", mapper, mapper_name(mapper));
+    if let Some(sub) = submapper {
+        out += &format!(", submapper {}", sub);
+    }
+    out += "\n";
+    out += &format!("PRG ROM:     {}\n", kb(prg_size));
+    out += &format!("CHR ROM:     {}\n", ram(chr_size));
+    let mirroring = if flags6 & 0x08 != 0 {
+        "four-screen"
+    } else if flags6 & 0x01 != 0 {
+        "vertical"
+    } else {
+        "horizontal"
+    };
+    out += &format!("Mirroring:   {}\n", mirroring);
+    out += &format!("Battery:     {}\n", if flags6 & 0x02 != 0 { "yes" } else { "no" });
+    out += &format!("Trainer:     {}\n", if trainer { "yes" } else { "no" });
+
+    // The rest needs the ROM data, so only for files the emulator can load
+    match Cartridge::from_bytes(&data) {
+        Ok(cart) => {
+            out += &format!("PRG RAM:     {}\n", ram(cart.prg_ram.len() - cart.prg_nvram_len));
+            out += &format!("PRG NVRAM:   {}\n", ram(cart.prg_nvram_len));
+            if cart.chr_ram {
+                out += &format!("CHR RAM:     {}\n", kb(cart.chr_rom.len()));
+            }
+            if let Some(timing) = cart.timing {
+                out += &format!("Timing:      {}\n", timing.name());
+            }
+            let chr: &[u8] = if cart.chr_ram { &[] } else { &cart.chr_rom };
+            out += &format!("CRC32:       {:08X} (PRG+CHR, no header)\n", hash::crc32(cart.prg_rom.iter().chain(chr)));
+            out += &format!("ROM hash:    {:016x}\n", cart.rom_hash());
+            let entry = romdb::lookup(cart.rom_hash());
+            out += &format!("Database:    {}\n", entry.map_or("no match", |entry| entry.name));
+            let quirks: Vec<String> = quirks::detect(cart.rom_hash(), &[]).iter().map(|(quirk, _)| quirk.describe()).collect();
+            if !quirks.is_empty() {
+                out += &format!("Quirks:      {}\n", quirks.join(", "));
+            }
+        }
+        Err(e) => problems.push(format!("can't be loaded: {}", e)),
+    }
+
+    if problems.is_empty() {
+        out += "Problems:    none\n";
+    } else {
+        out += "Problems:\n";
+        for problem in &problems {
+            out += &format!("  - {}\n", problem);
+        }
+    }
+    Ok(out)
+}
@@ -0,0 +1,243 @@
+use sdl2::controller::Button;
+use sdl2::keyboard::Keycode;
+
+use crate::gameconfig::GameConfig;
+use crate::hotkeys::{Chord, Hotkey};
+use crate::nes;
+
+// Named input profiles mapping host keys and gamepad buttons to controller 1. The built-in
+// ones are `keyboard` (Z/X = A/B, A/S = Select/Start, arrows), and `8bitdo` and `fightstick`,
+// which add a gamepad on top of the keyboard. More can be defined in input.cfg in the data
+// directory, using the game config syntax:
+//
+//   profile arcade = keyboard      # a copy of a built-in or earlier profile
+//   bind arcade.a = k              # replaces the inputs for A
+//   bind arcade.b = j, pad:x       # keys by SDL name, gamepad buttons as pad:<name>
+//   input = arcade                 # profile used when a game doesn't pick one
+//   chord arcade.save = pad:back + pad:rightshoulder   # hotkey on a gamepad chord
+//
+// A game's own .cfg can pick a profile with `input = fightstick` and rebind buttons for
+// that game alone with `bind a = pad:rightshoulder`, or chords with `chord load = ...`.
+// `none` removes a hotkey's chord.
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Input {
+    Key(Keycode),
+    Pad(Button),
+}
+
+impl Input {
+    // SDL's key name, or the gamepad button as pad <name>, for the help screen
+    pub fn describe(self) -> String {
+        match self {
+            Input::Key(key) => key.name(),
+            Input::Pad(button) => format!("pad {}", button.string()),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Profile {
+    pub name: String,
+    binds: Vec<(Input, u8)>,
+    chords: Vec<Chord>,
+}
+
+fn pad_button(name: &str) -> Option<Button> {
+    Some(match name {
+        "a" => Button::A,
+        "b" => Button::B,
+        "x" => Button::X,
+        "y" => Button::Y,
+        "back" => Button::Back,
+        "guide" => Button::Guide,
+        "start" => Button::Start,
+        "leftstick" => Button::LeftStick,
+        "rightstick" => Button::RightStick,
+        "leftshoulder" => Button::LeftShoulder,
+        "rightshoulder" => Button::RightShoulder,
+        "dpup" => Button::DPadUp,
+        "dpdown" => Button::DPadDown,
+        "dpleft" => Button::DPadLeft,
+        "dpright" => Button::DPadRight,
+        _ => return None,
+    })
+}
+
+fn parse_input(text: &str) -> Result<Input, String> {
+    match text.strip_prefix("pad:") {
+        Some(name) => pad_button(&name.to_ascii_lowercase()).map(Input::Pad).ok_or(format!("unknown gamepad button '{}'", name)),
+        None => Keycode::from_name(text).map(Input::Key).ok_or(format!("unknown key '{}'", text)),
+    }
+}
+
+// Buttons joined with `+`, with or without the pad: prefix
+fn parse_chord(text: &str) -> Result<Vec<Button>, String> {
+    text.split('+')
+        .map(|name| {
+            let name = name.trim();
+            let name = name.strip_prefix("pad:").unwrap_or(name);
+            pad_button(&name.to_ascii_lowercase()).ok_or(format!("unknown gamepad button '{}'", name))
+        })
+        .collect()
+}
+
+fn keyboard() -> Vec<(Input, u8)> {
+    [
+        (Keycode::Z, "a"), (Keycode::X, "b"), (Keycode::A, "select"), (Keycode::S, "start"),
+        (Keycode::Up, "up"), (Keycode::Down, "down"), (Keycode::Left, "left"), (Keycode::Right, "right"),
+    ]
+    .into_iter()
+    .filter_map(|(key, button)| Some((Input::Key(key), nes::button_mask(button)?)))
+    .collect()
+}
+
+// The keyboard plus a gamepad, given the pad buttons for NES A and B
+fn gamepad(a: Button, b: Button) -> Vec<(Input, u8)> {
+    let pad = [
+        (a, "a"), (b, "b"), (Button::Back, "select"), (Button::Start, "start"),
+        (Button::DPadUp, "up"), (Button::DPadDown, "down"), (Button::DPadLeft, "left"), (Button::DPadRight, "right"),
+    ];
+    let pad = pad.into_iter().filter_map(|(button, name)| Some((Input::Pad(button), nes::button_mask(name)?)));
+    keyboard().into_iter().chain(pad).collect()
+}
+
+// Select, the pad's Back, plus another button, as on most handhelds
+fn gamepad_chords() -> Vec<Chord> {
+    [
+        (Button::Start, Hotkey::Menu),
+        (Button::RightShoulder, Hotkey::Save),
+        (Button::LeftShoulder, Hotkey::Load),
+        (Button::DPadRight, Hotkey::NextSlot),
+    ]
+    .into_iter()
+    .map(|(button, hotkey)| Chord { buttons: vec![Button::Back, button], hotkey })
+    .collect()
+}
+
+impl Profile {
+    pub fn builtin(name: &str) -> Option<Self> {
+        let binds = match name {
+            "keyboard" => keyboard(),
+            // SDL names buttons by position, Xbox style: the SNES-layout right button is B
+            "8bitdo" => gamepad(Button::B, Button::A),
+            // The first two buttons of the top row
+            "fightstick" => gamepad(Button::Y, Button::X),
+            _ => return None,
+        };
+        let chords = if name == "keyboard" { Vec::new() } else { gamepad_chords() };
+        Some(Profile { name: name.to_string(), binds, chords })
+    }
+
+    fn bind(&mut self, button: &str, inputs: &str) -> Result<(), String> {
+        let mask = nes::button_mask(button).ok_or(format!("unknown button '{}'", button))?;
+        let inputs = inputs.split(',').map(|input| parse_input(input.trim())).collect::<Result<Vec<_>, _>>()?;
+        self.binds.retain(|&(_, m)| m != mask);
+        self.binds.extend(inputs.into_iter().map(|input| (input, mask)));
+        Ok(())
+    }
+
+    fn chord(&mut self, hotkey: &str, buttons: &str) -> Result<(), String> {
+        let names = Hotkey::names().collect::<Vec<_>>().join(", ");
+        let hotkey = Hotkey::parse(hotkey).ok_or(format!("unknown hotkey '{}' (hotkeys: {})", hotkey, names))?;
+        self.chords.retain(|chord| chord.hotkey != hotkey);
+        if !buttons.trim().eq_ignore_ascii_case("none") {
+            self.chords.push(Chord { buttons: parse_chord(buttons)?, hotkey });
+        }
+        Ok(())
+    }
+
+    pub fn chords(&self) -> &[Chord] {
+        &self.chords
+    }
+
+    // Controller buttons an input is bound to, or 0
+    pub fn buttons(&self, input: Input) -> u8 {
+        self.binds.iter().filter(|&&(i, _)| i == input).fold(0, |mask, &(_, m)| mask | m)
+    }
+
+    // Inputs bound to a controller button mask
+    pub fn inputs(&self, mask: u8) -> impl Iterator<Item = Input> + '_ {
+        self.binds.iter().filter(move |&&(_, m)| m == mask).map(|&(input, _)| input)
+    }
+
+    pub fn uses_gamepad(&self) -> bool {
+        self.binds.iter().any(|(input, _)| matches!(input, Input::Pad(_))) || !self.chords.is_empty()
+    }
+}
+
+// Built-in and user profiles, and which one to use by default.
+pub struct Profiles {
+    profiles: Vec<Profile>,
+    default: String,
+}
+
+impl Profiles {
+    // Adds the profiles from input.cfg, if there is one.
+    pub fn load(config: &GameConfig) -> Result<Self, String> {
+        let mut profiles = Profiles {
+            profiles: ["keyboard", "8bitdo", "fightstick"].iter().filter_map(|name| Profile::builtin(name)).collect(),
+            default: "keyboard".to_string(),
+        };
+        for (key, value) in config.entries() {
+            let result = if let Some(name) = key.strip_prefix("profile ") {
+                profiles.define(name.trim(), value)
+            } else if let Some(target) = key.strip_prefix("bind ") {
+                match target.trim().split_once('.') {
+                    Some((name, button)) => profiles.get_mut(name).and_then(|profile| profile.bind(button, value)),
+                    None => Err(format!("'{}' should be 'bind <profile>.<button>'", key)),
+                }
+            } else if let Some(target) = key.strip_prefix("chord ") {
+                match target.trim().split_once('.') {
+                    Some((name, hotkey)) => profiles.get_mut(name).and_then(|profile| profile.chord(hotkey, value)),
+                    None => Err(format!("'{}' should be 'chord <profile>.<hotkey>'", key)),
+                }
+            } else if key == "input" {
+                profiles.select(value)
+            } else {
+                Ok(())
+            };
+            result.map_err(|e| format!("{}: {}", config.path, e))?;
+        }
+        Ok(profiles)
+    }
+
+    fn define(&mut self, name: &str, base: &str) -> Result<(), String> {
+        let base = base.to_ascii_lowercase();
+        let mut profile = self.profiles.iter().find(|p| p.name == base).ok_or(format!("unknown profile '{}'", base))?.clone();
+        profile.name = name.to_string();
+        self.profiles.retain(|p| p.name != name);
+        self.profiles.push(profile);
+        Ok(())
+    }
+
+    fn select(&mut self, name: &str) -> Result<(), String> {
+        let name = name.to_ascii_lowercase();
+        self.get_mut(&name)?;
+        self.default = name;
+        Ok(())
+    }
+
+    fn get_mut(&mut self, name: &str) -> Result<&mut Profile, String> {
+        self.profiles.iter_mut().find(|p| p.name == name).ok_or(format!("unknown profile '{}'", name))
+    }
+
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.profiles.iter().map(|p| p.name.as_str())
+    }
+
+    // The profile for a game: the command line's choice, else the game config's, else the
+    // default, with the game's own `bind <button>` and `chord <hotkey>` lines applied on top.
+    pub fn for_game(&self, forced: Option<&str>, config: &GameConfig) -> Result<Profile, String> {
+        let name = forced.or(config.get("input")).unwrap_or(&self.default).to_ascii_lowercase();
+        let mut profile = self.profiles.iter().find(|p| p.name == name).ok_or(format!("unknown input profile '{}'", name))?.clone();
+        for (key, value) in config.entries() {
+            if let Some(button) = key.strip_prefix("bind ") {
+                profile.bind(button.trim(), value).map_err(|e| format!("{}: {}", config.path, e))?;
+            } else if let Some(hotkey) = key.strip_prefix("chord ") {
+                profile.chord(hotkey.trim(), value).map_err(|e| format!("{}: {}", config.path, e))?;
+            }
+        }
+        Ok(profile)
+    }
+}
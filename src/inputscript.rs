@@ -0,0 +1,173 @@
+use std::fs;
+
+use serde::Deserialize;
+
+use crate::movie::{COMMAND_POWER, COMMAND_SOFT_RESET};
+use crate::nes::{self, NES};
+
+// Input scripts for demos and automated runs, simpler to write by hand than an .fm2 movie.
+// Frame numbers count from when the script starts:
+//
+//   # frame: action
+//   120: press start for 10     # held for frames 120-129
+//   200: press right+a for 30
+//   300: hold left              # until released
+//   400: release                # lets go of everything held
+//   500: reset
+//
+// `press` without `for` lasts one frame, and `power` power-cycles the console. The same
+// actions can be given as JSON, which is easier to generate:
+//
+//   [{ "frame": 120, "action": "press start for 10" }]
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Action {
+    Press(u8, u64),
+    Hold(u8),
+    Release,
+    Reset,
+    Power,
+}
+
+#[derive(Debug, Deserialize)]
+struct JsonEntry {
+    frame: u64,
+    action: String,
+}
+
+#[derive(Debug, Default)]
+pub struct InputScript {
+    actions: Vec<(u64, Action)>,
+}
+
+fn parse_buttons(names: &str) -> Result<u8, String> {
+    let mut mask = 0;
+    for name in names.split('+') {
+        mask |= nes::button_mask(name).ok_or(format!("unknown button '{}'", name))?;
+    }
+    Ok(mask)
+}
+
+fn parse_action(text: &str) -> Result<Action, String> {
+    let words: Vec<&str> = text.split_whitespace().collect();
+    match words[..] {
+        ["press", buttons] => Ok(Action::Press(parse_buttons(buttons)?, 1)),
+        ["press", buttons, "for", frames] => {
+            let frames = frames.parse().ok().filter(|&n| n > 0).ok_or(format!("bad frame count '{}'", frames))?;
+            Ok(Action::Press(parse_buttons(buttons)?, frames))
+        }
+        ["hold", buttons] => Ok(Action::Hold(parse_buttons(buttons)?)),
+        ["release"] => Ok(Action::Release),
+        ["reset"] => Ok(Action::Reset),
+        ["power"] => Ok(Action::Power),
+        _ => Err(format!("unknown action '{}'", text)),
+    }
+}
+
+impl InputScript {
+    pub fn load(path: &str) -> Result<Self, Box<dyn std::error::Error>> {
+        let text = fs::read_to_string(path)?;
+        let script = if path.ends_with(".json") { Self::parse_json(&text) } else { Self::parse(&text) };
+        Ok(script.map_err(|e| format!("{}: {}", path, e))?)
+    }
+
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let mut script = InputScript::default();
+        for (number, line) in text.lines().enumerate() {
+            let line = line.split('#').next().unwrap_or("").trim();
+            if line.is_empty() {
+                continue;
+            }
+            let (frame, action) = line.split_once(':').ok_or(format!("line {}: expected 'frame: action'", number + 1))?;
+            let frame = frame.trim().parse().map_err(|_| format!("line {}: bad frame number '{}'", number + 1, frame.trim()))?;
+            let action = parse_action(&action.to_ascii_lowercase()).map_err(|e| format!("line {}: {}", number + 1, e))?;
+            script.actions.push((frame, action));
+        }
+        script.actions.sort_by_key(|&(frame, _)| frame);
+        Ok(script)
+    }
+
+    pub fn parse_json(text: &str) -> Result<Self, String> {
+        let entries: Vec<JsonEntry> = serde_json::from_str(text).map_err(|e| e.to_string())?;
+        let mut script = InputScript::default();
+        for entry in entries {
+            let action = parse_action(&entry.action.to_ascii_lowercase()).map_err(|e| format!("frame {}: {}", entry.frame, e))?;
+            script.actions.push((entry.frame, action));
+        }
+        script.actions.sort_by_key(|&(frame, _)| frame);
+        Ok(script)
+    }
+
+    // First frame after the last action has finished
+    pub fn len(&self) -> u64 {
+        self.actions.iter().map(|&(frame, action)| match action {
+            Action::Press(_, frames) => frame + frames,
+            _ => frame + 1,
+        }).max().unwrap_or(0)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.actions.is_empty()
+    }
+}
+
+// Steps through a script one emulated frame at a time.
+pub struct ScriptPlayer {
+    script: InputScript,
+    frame: u64,
+    next: usize,
+    held: u8,
+    pressed: Vec<(u64, u8)>, // (last frame, buttons)
+    commands: u8,
+}
+
+impl ScriptPlayer {
+    pub fn new(script: InputScript) -> Self {
+        ScriptPlayer { script, frame: 0, next: 0, held: 0, pressed: Vec::new(), commands: 0 }
+    }
+
+    pub fn len(&self) -> u64 {
+        self.script.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.script.is_empty()
+    }
+
+    pub fn finished(&self) -> bool {
+        self.frame >= self.script.len()
+    }
+
+    // Resets and power cycles the last next_frame did, as movie command bits.
+    pub fn commands(&self) -> u8 {
+        self.commands
+    }
+
+    // Applies this frame's actions and returns the buttons to hold. Call once per emulated frame.
+    pub fn next_frame(&mut self, nes: &mut NES) -> u8 {
+        self.commands = 0;
+        while let Some(&(frame, action)) = self.script.actions.get(self.next).filter(|(frame, _)| *frame == self.frame) {
+            match action {
+                Action::Press(buttons, frames) => self.pressed.push((frame + frames - 1, buttons)),
+                Action::Hold(buttons) => self.held |= buttons,
+                Action::Release => {
+                    self.held = 0;
+                    self.pressed.clear();
+                }
+                Action::Reset => {
+                    nes.reset();
+                    self.commands |= COMMAND_SOFT_RESET;
+                }
+                Action::Power => {
+                    nes.power_cycle();
+                    self.commands |= COMMAND_POWER;
+                }
+            }
+            self.next += 1;
+        }
+        let frame = self.frame;
+        self.pressed.retain(|&(last, _)| last >= frame);
+        self.frame += 1;
+        self.pressed.iter().fold(self.held, |mask, &(_, buttons)| mask | buttons)
+    }
+}
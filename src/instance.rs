@@ -0,0 +1,137 @@
+use std::collections::hash_map::RandomState;
+use std::fs;
+use std::hash::BuildHasher;
+use std::io::{self, ErrorKind, Read, Write};
+use std::net::{Ipv4Addr, TcpListener, TcpStream};
+#[cfg(unix)]
+use std::os::unix::fs::{OpenOptionsExt, PermissionsExt};
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+use crate::filelock::FileLock;
+use crate::playtime;
+
+// Single-instance mode, for file associations: opening another ROM from the desktop switches
+// the running window to it instead of starting a second one. The first window takes a lock
+// in the data directory and listens on a loopback port, written with a random token to
+// instance.port. Later launches find the lock taken and send the token and their ROM's full
+// path there, on one line, then exit. The token keeps other users on the machine out, so
+// instance.port is readable by its owner only (on Unix; Windows data directories are
+// per-user already).
+//
+// The window polls for launches every frame, so connections are read without blocking and
+// a line can arrive over several polls.
+
+// How long a launch keeps trying to reach an instance that has only just taken the lock.
+const CONNECT_WAIT: Duration = Duration::from_secs(2);
+// How long a connection has to send its line before it's dropped.
+const LINE_TIMEOUT: Duration = Duration::from_secs(2);
+const MAX_LINE: usize = 4096;
+
+// A connection whose line hasn't all come in yet
+struct Pending {
+    stream: TcpStream,
+    line: Vec<u8>,
+    since: Instant,
+}
+
+pub struct Instance {
+    listener: TcpListener,
+    token: String,
+    pending: Vec<Pending>,
+    _lock: FileLock,
+}
+
+pub enum Claim {
+    First(Instance),
+    Forwarded,
+}
+
+impl Instance {
+    // Becomes the instance others forward to, or forwards `rom_path` to the one running.
+    pub fn claim(rom_path: &str) -> io::Result<Claim> {
+        let dir = playtime::data_dir().ok_or_else(|| io::Error::other("no data directory"))?;
+        let port_path = dir.join("instance.port");
+        let Some(lock) = FileLock::try_acquire(&dir.join("instance").to_string_lossy())? else {
+            forward(&port_path, rom_path)?;
+            return Ok(Claim::Forwarded);
+        };
+        let listener = TcpListener::bind((Ipv4Addr::LOCALHOST, 0))?;
+        listener.set_nonblocking(true)?;
+        let token = format!("{:016x}", RandomState::new().hash_one(std::process::id()));
+        write_private(&port_path, &format!("{} {}\n", listener.local_addr()?.port(), token))?;
+        Ok(Claim::First(Instance { listener, token, pending: Vec::new(), _lock: lock }))
+    }
+
+    // A ROM path sent by a later launch, if one has come in. Doesn't block.
+    pub fn poll(&mut self) -> Option<String> {
+        while let Ok((stream, _)) = self.listener.accept() {
+            // Accepted sockets don't inherit the listener's non-blocking mode everywhere
+            if stream.set_nonblocking(true).is_ok() {
+                self.pending.push(Pending { stream, line: Vec::new(), since: Instant::now() });
+            }
+        }
+        let mut received = None;
+        self.pending.retain_mut(|conn| {
+            let mut buf = [0; 512];
+            loop {
+                match conn.stream.read(&mut buf) {
+                    Ok(0) => return false,
+                    Ok(n) => conn.line.extend_from_slice(&buf[..n]),
+                    Err(e) if e.kind() == ErrorKind::WouldBlock => break,
+                    Err(e) if e.kind() == ErrorKind::Interrupted => {}
+                    Err(_) => return false,
+                }
+                if let Some(end) = conn.line.iter().position(|&b| b == b'\n') {
+                    let line = String::from_utf8_lossy(&conn.line[..end]);
+                    if let Some((token, path)) = line.trim_end().split_once(' ') {
+                        if token == self.token && received.is_none() {
+                            received = Some(path.to_string());
+                        }
+                    }
+                    return false;
+                }
+                if conn.line.len() > MAX_LINE {
+                    return false;
+                }
+            }
+            conn.since.elapsed() < LINE_TIMEOUT
+        });
+        received
+    }
+}
+
+// Creates or replaces a file that only its owner can read.
+fn write_private(path: &Path, contents: &str) -> io::Result<()> {
+    let mut options = fs::OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    options.mode(0o600);
+    let mut file = options.open(path)?;
+    // The mode only applies to new files; one left by an older version may be readable
+    #[cfg(unix)]
+    file.set_permissions(fs::Permissions::from_mode(0o600))?;
+    file.write_all(contents.as_bytes())
+}
+
+fn forward(port_path: &Path, rom_path: &str) -> io::Result<()> {
+    // The running instance has another working directory
+    let rom_path = fs::canonicalize(rom_path).map_or(rom_path.to_string(), |path| path.to_string_lossy().into_owned());
+    let start = Instant::now();
+    loop {
+        let sent = fs::read_to_string(port_path).and_then(|text| {
+            let (port, token) = text
+                .trim()
+                .split_once(' ')
+                .and_then(|(port, token)| Some((port.parse::<u16>().ok()?, token.to_string())))
+                .ok_or_else(|| io::Error::other("instance.port is malformed"))?;
+            let mut stream = TcpStream::connect((Ipv4Addr::LOCALHOST, port))?;
+            writeln!(stream, "{} {}", token, rom_path)
+        });
+        match sent {
+            Ok(()) => return Ok(()),
+            Err(e) if start.elapsed() >= CONNECT_WAIT => return Err(e),
+            Err(_) => std::thread::sleep(Duration::from_millis(100)),
+        }
+    }
+}
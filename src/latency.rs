@@ -0,0 +1,138 @@
+use crate::osd;
+
+// Input latency test (--latency-test), for tuning vsync, frame skipping and audio settings.
+// Every controller button press flashes the picture white once and measures two delays:
+//
+//   HOST  from SDL getting the input event to the flashed picture being presented, in
+//         milliseconds and frames. This is what pacing, vsync and buffering add.
+//   GAME  emulated frames from the press until the game's own picture changes, the lag
+//         built into the game, for comparing against other emulators and hardware.
+//
+// Animated parts of the screen would count as a response to every press, so GAME only
+// looks at pixels that had been still for STILL_FRAMES before the change. Presses whose
+// response is in an area that was already moving, or in a game that scrolls all the time,
+// time out and aren't counted.
+//
+// The display's lag comes on top of HOST. Filming the button and the flash with a
+// high-speed camera measures the whole chain.
+
+// Presses averaged in the overlay
+const KEEP: usize = 20;
+// A press the game doesn't react to within this many frames isn't counted
+const GIVE_UP_FRAMES: u32 = 60;
+// Pixels that changed more recently than this are animation, not a response
+const STILL_FRAMES: u16 = 30;
+
+pub struct LatencyTest {
+    frame_ms: f64,
+    flash: Option<u32>,
+    press: Option<u32>, // Frames since the press the game hasn't answered yet
+    last_frame: Vec<u8>,
+    still: Vec<u16>, // Frames each pixel has gone unchanged, up to STILL_FRAMES
+    host_ms: Vec<u32>,
+    game_frames: Vec<u32>,
+}
+
+fn average(samples: &[u32]) -> Option<f64> {
+    (!samples.is_empty()).then(|| samples.iter().sum::<u32>() as f64 / samples.len() as f64)
+}
+
+fn record(samples: &mut Vec<u32>, sample: u32) {
+    if samples.len() == KEEP {
+        samples.remove(0);
+    }
+    samples.push(sample);
+}
+
+impl LatencyTest {
+    pub fn new(frame_rate: f64) -> Self {
+        LatencyTest {
+            frame_ms: 1000.0 / frame_rate,
+            flash: None,
+            press: None,
+            last_frame: Vec::new(),
+            still: Vec::new(),
+            host_ms: Vec::new(),
+            game_frames: Vec::new(),
+        }
+    }
+
+    // `at_ms` is the input event's SDL timestamp.
+    pub fn press(&mut self, at_ms: u32) {
+        self.flash = Some(at_ms);
+        self.press = Some(0);
+    }
+
+    // Once per emulated frame, with the RGB24 picture.
+    pub fn frame(&mut self, frame: &[u8]) {
+        if self.last_frame.len() != frame.len() {
+            self.last_frame = frame.to_vec();
+            self.still = vec![0; frame.len() / 3];
+            return;
+        }
+        let mut responded = false;
+        for ((pixel, last), still) in frame.chunks_exact(3).zip(self.last_frame.chunks_exact_mut(3)).zip(&mut self.still) {
+            if pixel == last {
+                *still = (*still + 1).min(STILL_FRAMES);
+            } else {
+                responded |= *still == STILL_FRAMES;
+                *still = 0;
+                last.copy_from_slice(pixel);
+            }
+        }
+
+        let Some(frames) = self.press.as_mut() else {
+            return;
+        };
+        *frames += 1;
+        if responded {
+            record(&mut self.game_frames, *frames);
+            self.press = None;
+        } else if *frames >= GIVE_UP_FRAMES {
+            self.press = None;
+        }
+    }
+
+    // Called right after the picture drawn with draw() is presented, with SDL's clock.
+    pub fn presented(&mut self, now_ms: u32) {
+        if let Some(at_ms) = self.flash.take() {
+            record(&mut self.host_ms, now_ms.wrapping_sub(at_ms));
+        }
+    }
+
+    fn lines(&self) -> [String; 2] {
+        let host = match (self.host_ms.last(), average(&self.host_ms)) {
+            (Some(&last), Some(avg)) => format!("HOST {}MS {:.1}F AVG {:.0}MS", last, last as f64 / self.frame_ms, avg),
+            _ => "HOST -".to_string(),
+        };
+        let game = match (self.game_frames.last(), average(&self.game_frames)) {
+            (Some(&last), Some(avg)) => format!("GAME {}F AVG {:.1}F", last, avg),
+            _ => "GAME -".to_string(),
+        };
+        [host, game]
+    }
+
+    pub fn draw(&self, frame: &mut [u8]) {
+        if self.flash.is_some() {
+            frame.fill(255);
+        }
+        let row_height = osd::CHAR_HEIGHT + 3;
+        for (i, line) in self.lines().iter().enumerate() {
+            let y = 240 - (2 - i) * row_height - 2;
+            osd::draw_label(frame, 256 - osd::text_width(line) - 4, y, line, osd::YELLOW);
+        }
+    }
+
+    // For the log on exit; None before any press was measured.
+    pub fn summary(&self) -> Option<String> {
+        let host = average(&self.host_ms)?;
+        let game = average(&self.game_frames).map_or("-".to_string(), |frames| format!("{:.1} frames", frames));
+        Some(format!(
+            "Input latency over the last {} presses: host {:.1} ms ({:.2} frames), game {}",
+            self.host_ms.len(),
+            host,
+            host / self.frame_ms,
+            game
+        ))
+    }
+}
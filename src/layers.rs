@@ -0,0 +1,79 @@
+use crate::ppu::PPU;
+
+// Per-pixel layer information, recorded by the PPU next to the RGB frame while
+// `PPU::track_layers` is on: the background and sprite pixels under each dot and which of
+// them was shown. The debugger's `pixel` command and the F10 layer view read it, and
+// post-processing can use it to treat the layers differently.
+
+pub const NO_SPRITE: u8 = 0xFF;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct LayerPixel {
+    pub background: u8,   // Palette RAM address of the background pixel, 0 if transparent
+    pub sprite: u8,       // Palette RAM address of the first opaque sprite pixel, 0 if none
+    pub sprite_index: u8, // That sprite's OAM index, or NO_SPRITE
+    pub behind: bool,     // The sprite has its behind-background priority bit set
+}
+
+impl Default for LayerPixel {
+    fn default() -> Self {
+        LayerPixel { background: 0, sprite: 0, sprite_index: NO_SPRITE, behind: false }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Layer {
+    Backdrop,
+    Background,
+    Sprite,
+}
+
+impl LayerPixel {
+    pub fn shown(&self) -> Layer {
+        if self.sprite != 0 && (self.background == 0 || !self.behind) {
+            Layer::Sprite
+        } else if self.background != 0 {
+            Layer::Background
+        } else {
+            Layer::Backdrop
+        }
+    }
+}
+
+// The F10 layer view: one layer on its own over the backdrop color.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum View {
+    Background,
+    Sprites,
+}
+
+impl View {
+    pub fn name(self) -> &'static str {
+        match self {
+            View::Background => "Background only",
+            View::Sprites => "Sprites only",
+        }
+    }
+
+    // Normal picture, background only, sprites only, and back
+    pub fn next(view: Option<View>) -> Option<View> {
+        match view {
+            None => Some(View::Background),
+            Some(View::Background) => Some(View::Sprites),
+            Some(View::Sprites) => None,
+        }
+    }
+}
+
+// Redraws an RGB24 frame from the layer buffer. Colors come from palette RAM as it is now,
+// so mid-frame palette changes and emphasis are not shown.
+pub fn draw(view: View, layers: &[LayerPixel], ppu: &PPU, out: &mut [u8]) {
+    for (pixel, rgb) in layers.iter().zip(out.chunks_exact_mut(3)) {
+        let addr = match view {
+            View::Background => pixel.background,
+            View::Sprites => pixel.sprite,
+        };
+        let (r, g, b) = ppu.palette.color(ppu.palette_ram[addr as usize & 0x1F]);
+        rgb.copy_from_slice(&[r, g, b]);
+    }
+}
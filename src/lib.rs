@@ -0,0 +1,70 @@
+pub mod apu;
+pub mod apu_viewer;
+pub mod audio;
+pub mod battery;
+#[cfg(feature = "simd-ppu")]
+pub mod bgpipe;
+pub mod bus;
+pub mod cartridge;
+pub mod clipboard;
+pub mod counters;
+pub mod cpu;
+pub mod cputest;
+pub mod crash;
+pub mod debugger;
+pub mod disasm;
+pub mod error_screen;
+pub mod expansion;
+pub mod expr;
+pub mod filelock;
+pub mod framedump;
+pub mod frameout;
+pub mod frameskip;
+pub mod gameconfig;
+pub mod golden;
+pub mod hash;
+pub mod help;
+pub mod hotkeys;
+pub mod hypershot;
+pub mod image;
+pub mod info;
+pub mod instance;
+pub mod inputmap;
+pub mod inputscript;
+pub mod latency;
+pub mod layers;
+pub mod library;
+pub mod log;
+pub mod macros;
+pub mod mahjong;
+pub mod mapper;
+pub mod movie;
+pub mod movietest;
+pub mod nes;
+pub mod osd;
+pub mod pacer;
+pub mod palette;
+pub mod pianoroll;
+pub mod playtime;
+pub mod postfx;
+pub mod ppu;
+pub mod ppulog;
+pub mod profiler;
+pub mod quick_menu;
+pub mod quirks;
+pub mod region;
+pub mod render;
+pub mod romdb;
+pub mod savestate;
+pub mod scheduler;
+pub mod session;
+pub mod snapshot;
+pub mod speedrun;
+pub mod state_menu;
+pub mod stateful;
+pub mod symbols;
+pub mod testrom;
+pub mod trace;
+pub mod vsync;
+pub mod watch;
+pub mod zapper;
@@ -0,0 +1,165 @@
+use std::collections::HashMap;
+use std::fs;
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+use std::thread::{self, JoinHandle};
+use std::time::UNIX_EPOCH;
+
+use serde::{Deserialize, Serialize};
+
+use crate::cartridge::Cartridge;
+use crate::playtime;
+use crate::region;
+use crate::romdb;
+
+// Index of the ROM collection, from the directories listed in library.cfg in the data
+// directory:
+//
+//   dir = /home/me/roms
+//   dir = /media/usb/nes
+//
+// Lines starting with # are comments. Unlike game configs a # later in the line is part of
+// the path, as it is in session.cfg.
+//
+// Directories are searched recursively for .nes files. Each one is hashed and matched
+// against the ROM database, and the results are cached in library.json with the file's size
+// and modification time, so a rescan only reads files that changed. The window rescans in a
+// background thread while a game runs, which keeps `zetr library` instant.
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Rom {
+    pub path: String,
+    pub size: u64,
+    pub modified: u64,
+    pub title: String, // Database name, or the file name
+    pub in_database: bool,
+    pub rom_hash: Option<u64>,
+    pub region: Option<String>,
+    pub mapper: Option<u16>,
+    pub error: Option<String>, // Why the file couldn't be loaded
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct Library {
+    pub roms: Vec<Rom>,
+}
+
+fn data_path(name: &str) -> Result<PathBuf, String> {
+    playtime::data_dir().map(|dir| dir.join(name)).ok_or("no home directory".to_string())
+}
+
+// The directories from library.cfg, none if it doesn't exist.
+pub fn dirs() -> Result<Vec<PathBuf>, Box<dyn std::error::Error>> {
+    let text = match fs::read_to_string(data_path("library.cfg")?) {
+        Ok(text) => text,
+        Err(e) if e.kind() == ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(e.into()),
+    };
+    Ok(text
+        .lines()
+        .filter(|line| !line.trim_start().starts_with('#'))
+        .filter_map(|line| line.split_once('='))
+        .filter(|(key, _)| key.trim() == "dir")
+        .map(|(_, dir)| PathBuf::from(dir.trim()))
+        .collect())
+}
+
+// Symlinked directories are skipped so a link loop can't recurse forever.
+fn find_roms(dir: &Path, out: &mut Vec<PathBuf>) {
+    let Ok(entries) = fs::read_dir(dir) else {
+        return;
+    };
+    for entry in entries.flatten() {
+        let path = entry.path();
+        if entry.file_type().is_ok_and(|t| t.is_dir()) {
+            find_roms(&path, out);
+        } else if path.extension().is_some_and(|ext| ext.eq_ignore_ascii_case("nes")) {
+            out.push(path);
+        }
+    }
+}
+
+fn describe(path: &Path, size: u64, modified: u64) -> Rom {
+    let path_text = path.to_string_lossy().into_owned();
+    let mut rom = Rom {
+        title: path.file_stem().map_or(path_text.clone(), |stem| stem.to_string_lossy().into_owned()),
+        path: path_text,
+        size,
+        modified,
+        in_database: false,
+        rom_hash: None,
+        region: None,
+        mapper: None,
+        error: None,
+    };
+    let cart = fs::read(path).map_err(|e| e.into()).and_then(|data| Cartridge::from_bytes(&data));
+    match cart {
+        Ok(cart) => {
+            if let Some(entry) = romdb::lookup(cart.rom_hash()) {
+                rom.title = entry.name.to_string();
+                rom.in_database = true;
+            }
+            rom.rom_hash = Some(cart.rom_hash());
+            rom.region = Some(region::detect(&cart, &rom.path, None).0.name().to_string());
+            rom.mapper = Some(cart.mapper);
+        }
+        Err(e) => rom.error = Some(e.to_string()),
+    }
+    rom
+}
+
+impl Library {
+    pub fn load() -> Result<Self, Box<dyn std::error::Error>> {
+        let path = data_path("library.json")?;
+        match fs::read_to_string(&path) {
+            Ok(text) => Ok(serde_json::from_str(&text).map_err(|e| format!("{}: {}", path.display(), e))?),
+            Err(e) if e.kind() == ErrorKind::NotFound => Ok(Library::default()),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    // Written to a temporary file first, so a scan cut short by quitting leaves the old index.
+    pub fn save(&self) -> Result<(), Box<dyn std::error::Error>> {
+        let path = data_path("library.json")?;
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        let temp = path.with_extension("json.tmp");
+        fs::write(&temp, serde_json::to_string_pretty(self)?)?;
+        fs::rename(&temp, &path)?;
+        Ok(())
+    }
+
+    // A fresh index of `dirs`, reusing this one's entries for files that haven't changed.
+    pub fn scan(&self, dirs: &[PathBuf]) -> Library {
+        let mut files = Vec::new();
+        for dir in dirs {
+            find_roms(dir, &mut files);
+        }
+        let cached: HashMap<&str, &Rom> = self.roms.iter().map(|rom| (rom.path.as_str(), rom)).collect();
+        let mut roms: Vec<Rom> = files
+            .iter()
+            .filter_map(|path| {
+                let meta = fs::metadata(path).ok()?;
+                let modified = meta.modified().ok()?.duration_since(UNIX_EPOCH).map_or(0, |d| d.as_secs());
+                let cached = cached.get(&*path.to_string_lossy()).filter(|rom| rom.size == meta.len() && rom.modified == modified);
+                Some(cached.map_or_else(|| describe(path, meta.len(), modified), |&rom| rom.clone()))
+            })
+            .collect();
+        roms.sort_by(|a, b| a.title.to_lowercase().cmp(&b.title.to_lowercase()).then_with(|| a.path.cmp(&b.path)));
+        Library { roms }
+    }
+}
+
+// Rescans and saves the index on a thread of its own. Does nothing without library.cfg.
+pub fn scan_in_background() -> JoinHandle<Result<Library, String>> {
+    thread::spawn(|| {
+        let dirs = dirs().map_err(|e| e.to_string())?;
+        if dirs.is_empty() {
+            return Ok(Library::default());
+        }
+        let library = Library::load().map_err(|e| e.to_string())?.scan(&dirs);
+        library.save().map_err(|e| e.to_string())?;
+        Ok(library)
+    })
+}
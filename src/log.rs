@@ -0,0 +1,200 @@
+use std::fmt;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::savestate;
+
+// Leveled log for diagnosing problems on machines we can't look at. Messages go to the
+// console as before (errors and warnings to stderr, the rest to stdout), and with a
+// timestamp, level and module to zetr.log in the data directory:
+//
+//   2026-10-16 14:02:11 WARN  main: game.nes.cfg: vsync must be auto, on or off, not 'yes'
+//
+// The file rotates at MAX_FILE_SIZE, keeping KEEP_FILES old ones (zetr.log.1 is the
+// newest). Which messages get through is set by a filter: a default level and any per-module
+// levels, e.g. `info,apu=debug,main=warn`. Modules are the source files' names, `main` for
+// the frontend. --log sets it at startup and the debugger's `log` command while running.
+//
+// Program output proper (test results, hashes, help, the debugger console) isn't logging
+// and is still printed directly.
+
+const MAX_FILE_SIZE: u64 = 1024 * 1024;
+const KEEP_FILES: usize = 3;
+
+#[derive(Debug, Clone, Copy, PartialEq, PartialOrd)]
+pub enum Level {
+    Error,
+    Warn,
+    Info,
+    Debug,
+}
+
+impl Level {
+    pub fn parse(name: &str) -> Option<Self> {
+        match name.to_ascii_lowercase().as_str() {
+            "error" => Some(Level::Error),
+            "warn" | "warning" => Some(Level::Warn),
+            "info" => Some(Level::Info),
+            "debug" => Some(Level::Debug),
+            _ => None,
+        }
+    }
+
+    fn label(self) -> &'static str {
+        match self {
+            Level::Error => "ERROR",
+            Level::Warn => "WARN ",
+            Level::Info => "INFO ",
+            Level::Debug => "DEBUG",
+        }
+    }
+}
+
+struct Logger {
+    default: Level,
+    modules: Vec<(String, Level)>,
+    console: bool,
+    file: Option<File>,
+    path: Option<PathBuf>,
+    size: u64,
+}
+
+static LOGGER: Mutex<Logger> = Mutex::new(Logger {
+    default: Level::Info,
+    modules: Vec::new(),
+    console: true,
+    file: None,
+    path: None,
+    size: 0,
+});
+
+fn logger() -> std::sync::MutexGuard<'static, Logger> {
+    // A panic while logging doesn't make the settings unusable
+    LOGGER.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+// `zetr::apu` is apu, the binary's root module main.
+fn module_name(path: &str) -> &str {
+    match path.strip_prefix("zetr::") {
+        Some(rest) => rest.split("::").next().unwrap_or(rest),
+        None => "main",
+    }
+}
+
+fn rotate(path: &Path) {
+    for n in (1..KEEP_FILES).rev() {
+        let _ = fs::rename(path.with_extension(format!("log.{}", n)), path.with_extension(format!("log.{}", n + 1)));
+    }
+    let _ = fs::rename(path, path.with_extension("log.1"));
+}
+
+// Starts writing to `path` as well as the console, rotating it first if it's full.
+pub fn open_file(path: &Path) -> io::Result<()> {
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    if fs::metadata(path).is_ok_and(|meta| meta.len() >= MAX_FILE_SIZE) {
+        rotate(path);
+    }
+    let file = OpenOptions::new().create(true).append(true).open(path)?;
+    let mut logger = logger();
+    logger.size = file.metadata()?.len();
+    logger.file = Some(file);
+    logger.path = Some(path.to_path_buf());
+    Ok(())
+}
+
+pub fn file_path() -> Option<PathBuf> {
+    logger().path.clone()
+}
+
+// Whether messages also go to the console. They always go to the file.
+pub fn set_console(on: bool) {
+    logger().console = on;
+}
+
+// `level` or `level,module=level,...`; a bare `module=level` list keeps the default level.
+pub fn set_filter(spec: &str) -> Result<(), String> {
+    let mut default = logger().default;
+    let mut modules = Vec::new();
+    for part in spec.split(',').map(str::trim).filter(|part| !part.is_empty()) {
+        let bad_level = |name: &str| format!("unknown log level '{}' (error, warn, info or debug)", name);
+        match part.split_once('=') {
+            Some((module, level)) => modules.push((module.trim().to_string(), Level::parse(level.trim()).ok_or(bad_level(level))?)),
+            None => default = Level::parse(part).ok_or(bad_level(part))?,
+        }
+    }
+    let mut logger = logger();
+    logger.default = default;
+    logger.modules = modules;
+    Ok(())
+}
+
+// The filter in set_filter's syntax.
+pub fn filter() -> String {
+    let logger = logger();
+    let levels = logger.modules.iter().map(|(module, level)| format!("{}={}", module, level.label().trim().to_lowercase()));
+    std::iter::once(logger.default.label().trim().to_lowercase()).chain(levels).collect::<Vec<_>>().join(",")
+}
+
+pub fn enabled(level: Level, module_path: &str) -> bool {
+    let logger = logger();
+    let module = module_name(module_path);
+    let max = logger.modules.iter().rev().find(|(m, _)| m == module).map_or(logger.default, |&(_, level)| level);
+    level <= max
+}
+
+// Used by the log_* macros.
+pub fn write(level: Level, module_path: &str, args: fmt::Arguments) {
+    if !enabled(level, module_path) {
+        return;
+    }
+    let mut logger = logger();
+    if logger.console {
+        match level {
+            Level::Error | Level::Warn => eprintln!("{}", args),
+            Level::Info | Level::Debug => println!("{}", args),
+        }
+    }
+    if logger.file.is_none() {
+        return;
+    }
+    let secs = SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |d| d.as_secs());
+    let line = format!("{}:{:02} {} {}: {}\n", savestate::format_date(secs), secs % 60, level.label(), module_name(module_path), args);
+    if logger.size + line.len() as u64 > MAX_FILE_SIZE {
+        if let Some(path) = logger.path.clone() {
+            rotate(&path);
+            logger.file = OpenOptions::new().create(true).append(true).open(&path).ok();
+            logger.size = 0;
+        }
+    }
+    if let Some(file) = logger.file.as_mut() {
+        // Nowhere left to report a failure to
+        if file.write_all(line.as_bytes()).is_ok() {
+            logger.size += line.len() as u64;
+        }
+    }
+}
+
+#[macro_export]
+macro_rules! log_error {
+    ($($arg:tt)*) => { $crate::log::write($crate::log::Level::Error, module_path!(), format_args!($($arg)*)) };
+}
+
+#[macro_export]
+macro_rules! log_warn {
+    ($($arg:tt)*) => { $crate::log::write($crate::log::Level::Warn, module_path!(), format_args!($($arg)*)) };
+}
+
+#[macro_export]
+macro_rules! log_info {
+    ($($arg:tt)*) => { $crate::log::write($crate::log::Level::Info, module_path!(), format_args!($($arg)*)) };
+}
+
+#[macro_export]
+macro_rules! log_debug {
+    ($($arg:tt)*) => { $crate::log::write($crate::log::Level::Debug, module_path!(), format_args!($($arg)*)) };
+}
@@ -0,0 +1,140 @@
+use sdl2::keyboard::Keycode;
+
+use crate::gameconfig::GameConfig;
+use crate::nes;
+
+// Host keys bound to controller button sequences, from `macro <key> = ...` lines in the
+// game config. Steps are separated by commas; each is a chord of buttons joined with `+`
+// (or `wait`) and how many frames to hold it:
+//
+//   macro q = a 1, wait 2, b 1     # tap A, two idle frames, tap B
+//   macro e = a+b                  # A and B together for as long as E is held
+//
+// A macro with a single step and no frame count is a combo held with the key. Anything
+// else plays through once per key press, even if the key is released early.
+
+#[derive(Debug, Clone, Copy)]
+struct Step {
+    buttons: u8,
+    frames: u32,
+}
+
+#[derive(Debug)]
+struct Macro {
+    key: Keycode,
+    steps: Vec<Step>,
+    hold: bool,
+}
+
+// A sequence in progress
+struct Playing {
+    index: usize,
+    step: usize,
+    frames_left: u32,
+}
+
+#[derive(Default)]
+pub struct Macros {
+    macros: Vec<Macro>,
+    playing: Vec<Playing>,
+    held: Vec<usize>,
+}
+
+fn parse_step(text: &str) -> Result<(Step, bool), String> {
+    let mut words = text.split_whitespace();
+    let chord = words.next().ok_or("empty step")?;
+    let mut buttons = 0;
+    if chord != "wait" {
+        for name in chord.split('+') {
+            buttons |= nes::button_mask(name).ok_or(format!("unknown button '{}'", name))?;
+        }
+    }
+    let (frames, counted) = match words.next() {
+        Some(n) => (n.parse().ok().filter(|&n| n > 0).ok_or(format!("bad frame count '{}'", n))?, true),
+        None => (1, false),
+    };
+    if let Some(extra) = words.next() {
+        return Err(format!("unexpected '{}'", extra));
+    }
+    Ok((Step { buttons, frames }, counted))
+}
+
+fn parse_macro(key: &str, value: &str) -> Result<Macro, String> {
+    let key = Keycode::from_name(key).ok_or(format!("unknown key '{}'", key))?;
+    let mut steps = Vec::new();
+    let mut counted = false;
+    for text in value.split(',') {
+        let (step, has_count) = parse_step(&text.to_ascii_lowercase())?;
+        steps.push(step);
+        counted |= has_count;
+    }
+    let hold = steps.len() == 1 && !counted;
+    Ok(Macro { key, steps, hold })
+}
+
+impl Macros {
+    pub fn from_config(config: &GameConfig) -> Result<Self, String> {
+        let mut macros = Macros::default();
+        for (key, value) in config.entries() {
+            if let Some(name) = key.strip_prefix("macro ") {
+                let m = parse_macro(name.trim(), value).map_err(|e| format!("{}: macro {}: {}", config.path, name.trim(), e))?;
+                macros.macros.push(m);
+            }
+        }
+        Ok(macros)
+    }
+
+    pub fn len(&self) -> usize {
+        self.macros.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.macros.is_empty()
+    }
+
+    // Bound keys shouldn't also be passed to the controller
+    pub fn binds(&self, keycode: Keycode) -> bool {
+        self.macros.iter().any(|m| m.key == keycode)
+    }
+
+    pub fn keys(&self) -> impl Iterator<Item = Keycode> + '_ {
+        self.macros.iter().map(|m| m.key)
+    }
+
+    pub fn key_down(&mut self, keycode: Keycode) {
+        let Some(index) = self.macros.iter().position(|m| m.key == keycode) else {
+            return;
+        };
+        if self.macros[index].hold {
+            if !self.held.contains(&index) {
+                self.held.push(index);
+            }
+        } else if !self.playing.iter().any(|p| p.index == index) {
+            let frames_left = self.macros[index].steps[0].frames;
+            self.playing.push(Playing { index, step: 0, frames_left });
+        }
+    }
+
+    pub fn key_up(&mut self, keycode: Keycode) {
+        if let Some(index) = self.macros.iter().position(|m| m.key == keycode) {
+            self.held.retain(|&i| i != index);
+        }
+    }
+
+    // Buttons to press this frame. Call once per emulated frame.
+    pub fn next_frame(&mut self) -> u8 {
+        let mut buttons = self.held.iter().fold(0, |mask, &i| mask | self.macros[i].steps[0].buttons);
+        for playing in &mut self.playing {
+            let steps = &self.macros[playing.index].steps;
+            buttons |= steps[playing.step].buttons;
+            playing.frames_left -= 1;
+            if playing.frames_left == 0 {
+                playing.step += 1;
+                playing.frames_left = steps.get(playing.step).map_or(0, |step| step.frames);
+            }
+        }
+        let macros = &self.macros;
+        self.playing.retain(|p| p.step < macros[p.index].steps.len());
+        buttons
+    }
+}
@@ -0,0 +1,104 @@
+use std::io::{self, Read, Write};
+
+use crate::expansion::ExpansionDevice;
+use crate::ppu::PPU;
+
+// Capcom's mahjong controller (HC-MJ), for the Jissen Mahjong games. The $4016 write picks
+// one of three rows of keys in bits 1-2, and $4017 bit 1 shifts that row out one key at a
+// time, 1 for pressed:
+//
+//   row 1  -  -      N       M    L    K     J      I
+//   row 2  H  G      F       E    D    C     B      A
+//   row 3  -  Start  Select  Kan  Pon  Chii  Reach  Ron
+//
+// Keys A-N are buttons 0-13, then Start, Select, Kan, Pon, Chii, Reach and Ron are 14-20.
+
+const START: u8 = 14;
+const NONE: u8 = 0xFF;
+
+const ROWS: [[u8; 8]; 3] = [
+    [NONE, NONE, 13, 12, 11, 10, 9, 8],
+    [7, 6, 5, 4, 3, 2, 1, 0],
+    [NONE, START, START + 1, START + 2, START + 3, START + 4, START + 5, START + 6],
+];
+
+#[derive(Default)]
+pub struct Mahjong {
+    held: u32, // Bit per button
+    row: u8,
+    strobe: bool,
+    shift: u8, // Next key in bit 0
+}
+
+impl Mahjong {
+    fn latch(&mut self) {
+        self.shift = match self.row {
+            1..=3 => ROWS[self.row as usize - 1]
+                .iter()
+                .enumerate()
+                .filter(|&(_, &button)| button != NONE && self.held & 1 << button != 0)
+                .fold(0, |bits, (i, _)| bits | 1 << i),
+            _ => 0,
+        };
+    }
+}
+
+impl ExpansionDevice for Mahjong {
+    fn name(&self) -> &'static str {
+        "Mahjong controller"
+    }
+
+    fn write(&mut self, out: u8) {
+        self.strobe = out & 1 != 0;
+        self.row = (out >> 1) & 3;
+        if self.strobe {
+            self.latch();
+        }
+    }
+
+    fn read(&mut self, port: u8, _ppu: &PPU) -> u8 {
+        if port == 0 {
+            return 0;
+        }
+        if self.strobe {
+            self.latch();
+        }
+        let data = self.shift & 1;
+        self.shift >>= 1;
+        data << 1
+    }
+
+    fn press(&mut self, button: u8, down: bool) {
+        if down {
+            self.held |= 1 << button;
+        } else {
+            self.held &= !(1 << button);
+        }
+    }
+
+    fn keys(&self) -> &'static [(&'static str, u8)] {
+        &[
+            ("A", 0), ("B", 1), ("C", 2), ("D", 3), ("E", 4), ("F", 5), ("G", 6), ("H", 7),
+            ("I", 8), ("J", 9), ("K", 10), ("L", 11), ("M", 12), ("N", 13),
+            ("Return", START), ("Space", START + 1), ("1", START + 2), ("2", START + 3),
+            ("3", START + 4), ("4", START + 5), ("5", START + 6),
+        ]
+    }
+
+    fn controls(&self) -> &'static str {
+        "A-N: Tiles, Enter: Start, Space: Select, 1-5: Kan, Pon, Chii, Reach, Ron"
+    }
+
+    fn save_state(&self, w: &mut dyn Write) -> io::Result<()> {
+        w.write_all(&[self.row, self.strobe as u8, self.shift])
+    }
+
+    fn load_state(&mut self, r: &mut dyn Read) -> io::Result<()> {
+        let mut state = [0u8; 3];
+        r.read_exact(&mut state)?;
+        self.row = state[0] & 3;
+        self.strobe = state[1] != 0;
+        self.shift = state[2];
+        Ok(())
+    }
+}
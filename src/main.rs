@@ -1,106 +1,1929 @@
-mod cartridge;
-mod ppu;
-mod nes;
-mod cpu;
-mod bus;
-
 use std::env;
-use std::time::{Duration, Instant};
-use sdl2::event::Event;
-use sdl2::keyboard::Keycode;
-use sdl2::pixels::PixelFormatEnum;
+use std::process::Command;
+use std::time::Instant;
+use sdl2::event::{Event, WindowEvent};
+use sdl2::keyboard::{Keycode, Mod};
+use sdl2::mouse::MouseButton;
+use sdl2::pixels::{Color, PixelFormatEnum};
 use sdl2::render::TextureAccess;
 
-use nes::NES;
+use zetr::{apu_viewer, audio, battery, clipboard, crash, cputest, debugger, expansion, golden, hash, image, info, log, mapper, movietest, profiler, savestate, snapshot, testrom};
+use zetr::{log_error, log_info, log_warn};
+use zetr::counters::{self, CounterOverlay};
+use zetr::error_screen::ErrorScreen;
+use zetr::filelock::FileLock;
+use zetr::framedump::{DumpFormat, FrameDumper};
+use zetr::apu::Channel;
+use zetr::audio::{AudioOutput, AudioSettings};
+use zetr::battery::BatterySaver;
+use zetr::frameout::FrameOutput;
+use zetr::frameskip::{FrameSkip, FrameSkipper};
+use zetr::gameconfig::GameConfig;
+use zetr::help::HelpScreen;
+use zetr::hotkeys::{ChordTracker, Hotkey};
+use zetr::instance::{Claim, Instance};
+use zetr::inputmap::{Input, Profiles};
+use zetr::inputscript::{InputScript, ScriptPlayer};
+use zetr::latency::LatencyTest;
+use zetr::layers::{self, View};
+use zetr::library::{self, Library};
+use zetr::macros::Macros;
+use zetr::movie::{Movie, MoviePlayer, MovieRecorder, COMMAND_SOFT_RESET};
+use zetr::pacer::FramePacer;
+use zetr::palette::Palette;
+use zetr::pianoroll::PianoRoll;
+use zetr::playtime::{self, PlayStats};
+use zetr::postfx::{self, PostFx};
+use zetr::ppulog::PpuBusLog;
+use zetr::profiler::Section;
+use zetr::quick_menu::{Nav, QuickAction, QuickMenu};
+use zetr::quirks::{self, Quirk};
+use zetr::region::{self, Region, Source};
+use zetr::render::{self, RenderOptions};
+use zetr::nes::NES;
+use zetr::osd::Notice;
+use zetr::session::{self, Session};
+use zetr::speedrun::SpeedrunTimer;
+use zetr::symbols::SymbolTable;
+use zetr::state_menu::{MenuAction, StateMenu};
+use zetr::trace::{TraceCompare, TraceLog};
+use zetr::vsync::{self, Refresh, RefreshScheduler};
+use zetr::watch::RomWatcher;
 
 const SCREEN_WIDTH: usize = 256;
 const SCREEN_HEIGHT: usize = 240;
 const SCALE: u32 = 3;
+const CTRL: Mod = Mod::LCTRLMOD.union(Mod::RCTRLMOD);
+const SHIFT: Mod = Mod::LSHIFTMOD.union(Mod::RSHIFTMOD);
+// How often an idle window wakes up with no events, so notices can time out
+const IDLE_WAKE_MS: u32 = 100;
+
+// zetr render <rom> <movie.fm2> -o <output> [--scale n]
+fn render_command(args: &[String]) -> ! {
+    let mut positional = Vec::new();
+    let mut output = None;
+    let mut scale = Some(1);
+    let mut arg_iter = args.iter();
+    while let Some(arg) = arg_iter.next() {
+        match arg.as_str() {
+            "-o" => output = arg_iter.next().cloned(),
+            "--scale" => scale = arg_iter.next().and_then(|n| n.parse().ok()).filter(|&n| (1..=8).contains(&n)),
+            _ => positional.push(arg.clone()),
+        }
+    }
+    let (Some(output), Some(scale), [rom, movie]) = (output, scale, &positional[..]) else {
+        eprintln!("Usage: zetr render <rom_file> <movie.fm2> -o <output> [--scale 1-8]");
+        eprintln!("Replays the movie headlessly and encodes it with ffmpeg, with sound.");
+        std::process::exit(2);
+    };
+    let options = RenderOptions { rom: rom.clone(), movie: movie.clone(), output, scale };
+    match render::run(&options) {
+        Ok(frames) => {
+            println!("Rendered {} frames to {}", frames, options.output);
+            std::process::exit(0);
+        }
+        Err(e) => {
+            log_error!("Error rendering movie: {}", e);
+            std::process::exit(1);
+        }
+    }
+}
+
+// zetr info <rom>
+fn info_command(args: &[String]) -> ! {
+    let [rom] = args else {
+        eprintln!("Usage: zetr info <rom_file>");
+        eprintln!("Prints the header, sizes, hashes, database match and problems found in the file.");
+        std::process::exit(2);
+    };
+    match info::report(rom) {
+        Ok(report) => {
+            print!("{}", report);
+            std::process::exit(0);
+        }
+        Err(e) => {
+            log_error!("Error reading {}: {}", rom, e);
+            std::process::exit(1);
+        }
+    }
+}
+
+// zetr library [--rescan]
+fn library_command(args: &[String]) -> ! {
+    let rescan = match args {
+        [] => false,
+        [flag] if flag == "--rescan" => true,
+        _ => {
+            eprintln!("Usage: zetr library [--rescan]");
+            eprintln!("Lists the ROMs in the directories named in library.cfg, from the cached index.");
+            std::process::exit(2);
+        }
+    };
+    let dirs = library::dirs().unwrap_or_else(|e| {
+        log_error!("Error reading library.cfg: {}", e);
+        std::process::exit(1);
+    });
+    if dirs.is_empty() {
+        let dir = playtime::data_dir().map_or("the data directory".into(), |dir| dir.display().to_string());
+        log_warn!("No ROM directories yet; add 'dir = <path>' lines to library.cfg in {}", dir);
+        std::process::exit(1);
+    }
+    let mut index = Library::load().unwrap_or_else(|e| {
+        log_warn!("Error loading the library index, rescanning: {}", e);
+        Library::default()
+    });
+    if rescan || index.roms.is_empty() {
+        index = index.scan(&dirs);
+        if let Err(e) = index.save() {
+            log_error!("Error saving the library index: {}", e);
+        }
+    }
+    for rom in &index.roms {
+        let region = rom.region.as_deref().unwrap_or("-");
+        let mapper = rom.mapper.map_or("-".to_string(), |m| m.to_string());
+        match rom.error.as_deref() {
+            None => println!("{:<32} {:<6} {:>3}  {}", rom.title, region, mapper, rom.path),
+            Some(e) => println!("{:<32} {:<6} {:>3}  {} ({})", rom.title, region, mapper, rom.path, e),
+        }
+    }
+    println!("{} ROMs", index.roms.len());
+    std::process::exit(0);
+}
 
 fn main() -> Result<(), Box<dyn std::error::Error>> {
+    crash::install_hook();
     let args: Vec<String> = env::args().collect();
-    if args.len() != 2 {
-        eprintln!("Usage: {} <rom_file>", args[0]);
+    if args.get(1).map(String::as_str) == Some("render") {
+        render_command(&args[2..]);
+    }
+    if args.get(1).map(String::as_str) == Some("info") {
+        info_command(&args[2..]);
+    }
+    if args.get(1).map(String::as_str) == Some("library") {
+        library_command(&args[2..]);
+    }
+    let mut rom_path = None;
+    let mut symbol_files = Vec::new();
+    let mut debug = false;
+    let mut trace_path = None;
+    let mut ppu_log_path = None;
+    let mut ppu_log_frames = (0, None);
+    let mut compare_path = None;
+    let mut splits_path = None;
+    let mut livesplit_addr = None;
+    let mut test_mode = false;
+    let mut test_paths = Vec::new();
+    let mut frame_limit = None;
+    let mut golden = None;
+    let mut movie_tests = None;
+    let mut cpu_tests = false;
+    let mut show_stats = false;
+    let mut export_stats = None;
+    let mut frame_skip = FrameSkip::Fixed(0);
+    let mut forced_region = None;
+    let mut zapper = false;
+    let mut expansion_name = None;
+    let mut script_path = None;
+    let mut headless = false;
+    let mut hash_frames = false;
+    let mut snapshot_path = None;
+    let mut snapshot_ranges = Vec::new();
+    let mut latency_test = false;
+    let mut boot_state = None;
+    let mut movie_path = None;
+    let mut record_path = None;
+    let mut dump_dir = None;
+    let mut dump_format = DumpFormat::Png;
+    let mut palette_name = None;
+    let mut export_palette = None;
+    let mut filter_args = Vec::new();
+    let mut frame_output_target = None;
+    let mut forced_refresh = None;
+    let mut input_profile = None;
+    let mut resume = false;
+    let mut watch = false;
+    let mut keep_ram = false;
+    let mut save_session = true;
+    let mut config_path = None;
+    let mut config_overrides = Vec::new();
+    let mut data_dir = None;
+    let mut log_filter = None;
+    let mut gamepad = None;
+    let mut audio_rate = None;
+    let mut audio_latency = None;
+    let mut audio_device = None;
+    let mut list_audio_devices = false;
+    let mut single_instance = false;
+    // An option whose value is missing or doesn't parse
+    let mut bad_option = None;
+
+    let mut arg_iter = args.iter().skip(1);
+    while let Some(arg) = arg_iter.next() {
+        match arg.as_str() {
+            "--debug" => debug = true,
+            "--symbols" => match arg_iter.next() {
+                Some(path) => symbol_files.push(path.clone()),
+                None => bad_option = Some(arg),
+            },
+            "--trace" => match arg_iter.next() {
+                Some(path) => trace_path = Some(path.clone()),
+                None => bad_option = Some(arg),
+            },
+            "--ppu-log" => match arg_iter.next() {
+                Some(path) => ppu_log_path = Some(path.clone()),
+                None => bad_option = Some(arg),
+            },
+            "--ppu-log-frames" => match arg_iter.next().and_then(|frames| PpuBusLog::parse_frames(frames)) {
+                Some(frames) => ppu_log_frames = frames,
+                None => bad_option = Some(arg),
+            },
+            "--compare" => match arg_iter.next() {
+                Some(path) => compare_path = Some(path.clone()),
+                None => bad_option = Some(arg),
+            },
+            "--splits" => match arg_iter.next() {
+                Some(path) => splits_path = Some(path.clone()),
+                None => bad_option = Some(arg),
+            },
+            "--livesplit" => match arg_iter.next() {
+                Some(addr) => livesplit_addr = Some(addr.clone()),
+                None => bad_option = Some(arg),
+            },
+            "--frameskip" => match arg_iter.next().and_then(|mode| FrameSkip::parse(mode)) {
+                Some(mode) => frame_skip = mode,
+                None => bad_option = Some(arg),
+            },
+            "--region" => match arg_iter.next().and_then(|name| Region::parse(name)) {
+                Some(region) => forced_region = Some(region),
+                None => bad_option = Some(arg),
+            },
+            "--refresh" => match arg_iter.next().and_then(|hz| region::refresh_rate(hz)) {
+                Some(rate) => forced_refresh = Some(rate),
+                None => bad_option = Some(arg),
+            },
+            "--input" => match arg_iter.next() {
+                Some(name) => input_profile = Some(name.clone()),
+                None => bad_option = Some(arg),
+            },
+            "--zapper" => zapper = true,
+            "--expansion" => match arg_iter.next() {
+                Some(name) => expansion_name = Some(name.clone()),
+                None => bad_option = Some(arg),
+            },
+            "--script" => match arg_iter.next() {
+                Some(path) => script_path = Some(path.clone()),
+                None => bad_option = Some(arg),
+            },
+            "--headless" => headless = true,
+            "--hash-frames" => hash_frames = true,
+            "--snapshot" => match arg_iter.next() {
+                Some(path) => snapshot_path = Some(path.clone()),
+                None => bad_option = Some(arg),
+            },
+            "--snapshot-memory" => match arg_iter.next() {
+                Some(range) => snapshot_ranges.push(range.clone()),
+                None => bad_option = Some(arg),
+            },
+            "--latency-test" => latency_test = true,
+            "--resume" => resume = true,
+            "--watch" => watch = true,
+            "--keep-ram" => keep_ram = true,
+            "--no-session" => save_session = false,
+            "--config" => match arg_iter.next() {
+                Some(path) => config_path = Some(path.clone()),
+                None => bad_option = Some(arg),
+            },
+            "--set" => match arg_iter.next().and_then(|setting| setting.split_once('=')) {
+                Some((key, value)) => config_overrides.push((key.to_string(), value.to_string())),
+                None => bad_option = Some(arg),
+            },
+            "--log" => match arg_iter.next() {
+                Some(filter) => log_filter = Some(filter.clone()),
+                None => bad_option = Some(arg),
+            },
+            "--quiet" => log::set_console(false),
+            "--data-dir" => match arg_iter.next() {
+                Some(dir) => data_dir = Some(dir.clone()),
+                None => bad_option = Some(arg),
+            },
+            "--gamepad" => match arg_iter.next().and_then(|n| n.parse::<u32>().ok()) {
+                Some(n) => gamepad = Some(n),
+                None => bad_option = Some(arg),
+            },
+            "--single-instance" => single_instance = true,
+            "--audio-rate" => match arg_iter.next().and_then(|rate| audio::parse_rate(rate)) {
+                Some(rate) => audio_rate = Some(rate),
+                None => bad_option = Some(arg),
+            },
+            "--audio-latency" => match arg_iter.next().and_then(|ms| audio::parse_latency(ms)) {
+                Some(ms) => audio_latency = Some(ms),
+                None => bad_option = Some(arg),
+            },
+            "--audio-device" => match arg_iter.next() {
+                Some(name) => audio_device = Some(name.clone()),
+                None => bad_option = Some(arg),
+            },
+            "--audio-devices" => list_audio_devices = true,
+            "--load-state" => match arg_iter.next() {
+                Some(target) => boot_state = Some(target.clone()),
+                None => bad_option = Some(arg),
+            },
+            "--play-movie" => match arg_iter.next() {
+                Some(path) => movie_path = Some(path.clone()),
+                None => bad_option = Some(arg),
+            },
+            "--record-movie" => match arg_iter.next() {
+                Some(path) => record_path = Some(path.clone()),
+                None => bad_option = Some(arg),
+            },
+            "--dump-frames" => match arg_iter.next() {
+                Some(dir) => dump_dir = Some(dir.clone()),
+                None => bad_option = Some(arg),
+            },
+            "--dump-format" => match arg_iter.next().and_then(|name| DumpFormat::parse(name)) {
+                Some(format) => dump_format = format,
+                None => bad_option = Some(arg),
+            },
+            "--palette" => match arg_iter.next() {
+                Some(name) => palette_name = Some(name.clone()),
+                None => bad_option = Some(arg),
+            },
+            "--export-palette" => match arg_iter.next() {
+                Some(path) => export_palette = Some(path.clone()),
+                None => bad_option = Some(arg),
+            },
+            "--scanlines" | "--vignette" | "--brightness" | "--contrast" => match arg_iter.next() {
+                Some(value) => filter_args.push((arg[2..].to_string(), value.clone())),
+                None => bad_option = Some(arg),
+            },
+            "--frame-output" => match arg_iter.next() {
+                Some(target) => frame_output_target = Some(target.clone()),
+                None => bad_option = Some(arg),
+            },
+            "--test" => test_mode = true,
+            "--cpu-tests" => cpu_tests = true,
+            "--stats" => show_stats = true,
+            "--export-stats" => match arg_iter.next() {
+                Some(path) => export_stats = Some(path.clone()),
+                None => bad_option = Some(arg),
+            },
+            "--golden" | "--update-golden" => match arg_iter.next() {
+                Some(path) => golden = Some((path.clone(), arg == "--update-golden")),
+                None => bad_option = Some(arg),
+            },
+            "--movie-tests" | "--update-movie-tests" => match arg_iter.next() {
+                Some(path) => movie_tests = Some((path.clone(), arg == "--update-movie-tests")),
+                None => bad_option = Some(arg),
+            },
+            // Process serial number that older macOS versions add when Finder launches an app
+            arg if arg.starts_with("-psn_") => {}
+            "--frames" => match arg_iter.next().and_then(|n| n.parse().ok()) {
+                Some(n) => frame_limit = Some(n),
+                None => bad_option = Some(arg),
+            },
+            _ => {
+                rom_path = Some(arg.clone());
+                test_paths.push(arg.clone());
+            }
+        }
+    }
+    if let Some(option) = bad_option {
+        eprintln!("{}: missing or invalid value (run {} with no arguments for usage)", option, args[0]);
+        std::process::exit(2);
+    }
+
+    if let Some(dir) = data_dir {
+        playtime::set_data_dir(dir.into());
+    }
+    if let Some(Err(e)) = log_filter.map(|filter| log::set_filter(&filter)) {
+        log_error!("--log: {}", e);
+        return Ok(());
+    }
+    if let Some(Err(e)) = playtime::data_dir().map(|dir| log::open_file(&dir.join("zetr.log"))) {
+        log_warn!("Error opening the log file, logging to the console only: {}", e);
+    }
+
+    if let Some((manifest, update)) = golden {
+        let all_matched = golden::run(&manifest, update).unwrap_or_else(|e| {
+            log_error!("Error running golden frames: {}", e);
+            false
+        });
+        std::process::exit(if all_matched { 0 } else { 1 });
+    }
+
+    if let Some((manifest, update)) = movie_tests {
+        let all_matched = movietest::run(&manifest, update).unwrap_or_else(|e| {
+            log_error!("Error running movie tests: {}", e);
+            false
+        });
+        std::process::exit(if all_matched { 0 } else { 1 });
+    }
+
+    if list_audio_devices {
+        let names = sdl2::init().and_then(|sdl| audio::device_names(&sdl)).unwrap_or_else(|e| {
+            log_warn!("Error listing audio devices: {}", e);
+            std::process::exit(1);
+        });
+        for name in names {
+            println!("{}", name);
+        }
+        std::process::exit(0);
+    }
+
+    if show_stats || export_stats.is_some() {
+        let stats = PlayStats::load().unwrap_or_else(|e| {
+            log_error!("Error loading play stats: {}", e);
+            std::process::exit(1);
+        });
+        if let Some(path) = export_stats {
+            if let Err(e) = std::fs::write(&path, stats.to_json()) {
+                log_error!("Error writing {}: {}", path, e);
+                std::process::exit(1);
+            }
+            println!("Exported play stats to {}", path);
+        } else {
+            for game in stats.games() {
+                println!("{:<32} {}", game.name, game.summary());
+            }
+        }
+        std::process::exit(0);
+    }
+
+    // Writes the chosen palette as a .pal file to edit and load back with --palette
+    if let Some(path) = export_palette {
+        let palette = Palette::load(palette_name.as_deref().unwrap_or("default")).unwrap_or_else(|e| {
+            log_error!("Error loading palette {}", e);
+            std::process::exit(1);
+        });
+        if let Err(e) = std::fs::write(&path, palette.to_bytes()) {
+            log_error!("Error writing {}: {}", path, e);
+            std::process::exit(1);
+        }
+        println!("Exported {} palette to {}", palette.name, path);
+        std::process::exit(0);
+    }
+
+    if cpu_tests && !test_paths.is_empty() {
+        let all_passed = cputest::run_all(&test_paths);
+        std::process::exit(if all_passed { 0 } else { 1 });
+    }
+
+    if test_mode && rom_path.is_some() {
+        let all_passed = testrom::run_all(&test_paths, frame_limit.unwrap_or(testrom::DEFAULT_MAX_FRAMES));
+        std::process::exit(if all_passed { 0 } else { 1 });
+    }
+
+    let mut window_position = None;
+    if resume {
+        match Session::load() {
+            Ok(Some(session)) => {
+                println!("Resuming {}", session.rom_path);
+                rom_path = Some(session.rom_path);
+                boot_state = boot_state.or(session::state_path().ok());
+                forced_refresh = forced_refresh.or(session.rate);
+                window_position = session.window;
+            }
+            Ok(None) => {
+                log_warn!("No session to resume");
+                return Ok(());
+            }
+            Err(e) => {
+                log_error!("Error loading session: {}", e);
+                return Ok(());
+            }
+        }
+    }
+
+    // Finder hands over the document zetr was opened with as an event, not an argument
+    #[cfg(target_os = "macos")]
+    if rom_path.is_none() && args.iter().skip(1).all(|arg| arg.starts_with("-psn_")) {
+        rom_path = wait_for_open_file();
+    }
+
+    let Some(rom_path) = rom_path else {
+        if let (1, Ok(Some(session))) = (args.len(), Session::load()) {
+            eprintln!("Last played {}, continue with: {} --resume", session.rom_path, args[0]);
+        }
+        eprintln!("Usage: {} [options] <rom_file>", args[0]);
+        eprintln!("  --debug                 Break into the debugger at reset");
+        eprintln!("  --symbols <file>        Load a .nl or .dbg symbol file");
+        eprintln!("  --trace <file>          Write an instruction trace to a file");
+        eprintln!("  --ppu-log <file>        Write every PPU memory access to a CSV file");
+        eprintln!("  --ppu-log-frames <n[-m]>  Frames to log with --ppu-log (default: all, from 0)");
+        eprintln!("  --compare <file>        Stop at the first difference from a reference --trace");
+        eprintln!("  --splits <file>         Speedrun split file (default: <rom_file>.splits)");
+        eprintln!("  --livesplit <host:port> Send splits to LiveSplit Server (usually port 16834)");
+        eprintln!("  --frameskip <auto|0-3>  Skip drawing frames on slow machines (default 0)");
+        eprintln!("  --region <ntsc|pal|dendy>  Force the console region (default: detect)");
+        eprintln!("  --refresh <50|60>       Run at 50 or 60 frames per second whatever the region");
+        eprintln!("  --input <profile>       Input profile: keyboard, 8bitdo, fightstick or one from input.cfg");
+        eprintln!("  --zapper                Plug a Zapper into port 2, aimed and fired with the mouse");
+        eprintln!("  --expansion <device>    Famicom expansion port device: mahjong or hypershot");
+        eprintln!("  --script <file>         Drive controller 1 from a text or JSON input script");
+        eprintln!("  --headless              Run without a window until the script or movie ends (or --frames)");
+        eprintln!("  --hash-frames           With --headless, print picture and RAM hashes after every frame");
+        eprintln!("  --snapshot <file>       With --headless, write registers, counters and memory as JSON at the end");
+        eprintln!("  --snapshot-memory <range>  Memory for --snapshot, like 0000-07FF or $6000+100 (repeatable)");
+        eprintln!("  --latency-test          Flash on button presses and show the input latency");
+        eprintln!("  --load-state <slot|file>  Start from a save state slot (0-9) or state file");
+        eprintln!("  --resume                Continue the last game from where the window was closed");
+        eprintln!("  --watch                 Reload the ROM whenever the file changes (and --load-state after it)");
+        eprintln!("  --keep-ram              With --watch, carry console and PRG RAM over to the new build");
+        eprintln!("  --no-session            Don't save this game for --resume on exit");
+        eprintln!("  --config <file>         Game config to use instead of <rom_file>.cfg");
+        eprintln!("  --set <key=value>       Override one game config setting (repeatable)");
+        eprintln!("  --data-dir <dir>        Keep play stats, the session, input.cfg and zetr.log here");
+        eprintln!("  --log <filter>          Log levels, e.g. debug or info,apu=debug (default info)");
+        eprintln!("  --quiet                 Log to zetr.log only, not the console");
+        eprintln!("  --gamepad <n>           Only use the nth gamepad (from 0)");
+        eprintln!("  --audio-rate <hz>       Sample rate: 44100, 48000 (default) or 96000");
+        eprintln!("  --audio-latency <ms>    Sound buffered ahead, {}-{} (default {})", audio::MIN_LATENCY_MS, audio::MAX_LATENCY_MS, audio::DEFAULT_LATENCY_MS);
+        eprintln!("  --audio-device <name>   Play through this device instead of the default");
+        eprintln!("  --audio-devices         List the audio devices and exit");
+        eprintln!("  --single-instance       Open the ROM in the zetr window already running, if any");
+        eprintln!("  --play-movie <file>     Play back an .fm2 movie on controller 1");
+        eprintln!("  --record-movie <file>   Record controller 1 from power-on to an .fm2 movie");
+        eprintln!("  --dump-frames <dir>     Write every frame to a numbered image file");
+        eprintln!("  --dump-format <fmt>     Frame dump format: png (default), ppm or raw");
+        eprintln!("  --frame-output <file|shm:name>  Publish raw frames to a pipe or shared memory");
+        eprintln!("  --palette <name|file>   Display palette: default, protanopia, deuteranopia, tritanopia or a .pal file");
+        eprintln!("  --export-palette <file> Write the --palette (or default) palette as a .pal file and exit");
+        eprintln!("  --scanlines <0-100>     Darken every other line by this much");
+        eprintln!("  --vignette <0-100>      Darken the edges of the picture");
+        eprintln!("  --brightness <-100-100>, --contrast <-100-100>  Adjust the picture");
+        eprintln!("  --test <roms or dirs>   Run blargg-style test ROMs headlessly and report results");
+        eprintln!("  --cpu-tests <files>     Run ProcessorTests nes6502 JSON vectors against the CPU");
+        eprintln!("  --golden <manifest>     Compare frame hashes against a golden manifest");
+        eprintln!("  --update-golden <file>  Regenerate the hashes in a golden manifest");
+        eprintln!("  --movie-tests <file>    Play .fm2 movies and compare end-of-movie state hashes");
+        eprintln!("  --update-movie-tests <file>  Regenerate the hashes in a movie test manifest");
+        eprintln!("  --stats                 List play time for every game and exit");
+        eprintln!("  --export-stats <file>   Write play time stats as JSON and exit");
+        eprintln!("  --frames <n>            Frame limit per test ROM (default {}) or headless run", testrom::DEFAULT_MAX_FRAMES);
+        eprintln!("       {} render <rom_file> <movie.fm2> -o <output>  Encode a movie to video", args[0]);
+        eprintln!("       {} info <rom_file>  Show the ROM header, hashes and problems", args[0]);
+        eprintln!("       {} library [--rescan]  List the ROM collection", args[0]);
         eprintln!("Example: {} donkeykong.nes", args[0]);
         return Ok(());
+    };
+    
+    let mut instance = match (single_instance && !headless).then(|| Instance::claim(&rom_path)) {
+        None => None,
+        Some(Ok(Claim::First(instance))) => Some(instance),
+        Some(Ok(Claim::Forwarded)) => {
+            log_info!("Opened {} in the running zetr window", rom_path);
+            return Ok(());
+        }
+        Some(Err(e)) => {
+            log_warn!("No single instance, opening a new window: {}", e);
+            None
+        }
+    };
+    // Another ROM opened from the desktop or dropped on the window. The game then closes as
+    // usual and zetr starts again with the new one, so nothing of the old game carries over
+    let mut next_rom = None;
+
+    // Initialize NES
+    let mut nes = NES::new();
+    if let Err(e) = nes.load_cartridge(&rom_path) {
+        log_error!("Error loading ROM: {}", e);
+        // Opened from a file manager there's no console to read that on
+        if !headless {
+            show_error(&ErrorScreen::rom_error(&rom_path, e.as_ref()))?;
+        }
+        return Ok(());
+    }
+    if let Some(cart) = nes.cartridge().filter(|cart| !mapper::SUPPORTED.contains(&cart.mapper)) {
+        log_warn!("Mapper {} isn't emulated yet; running the game as NROM, which will probably fail", cart.mapper);
+    }
+
+    // Command line beats the per-game config, which beats detection
+    let config = match config_path.as_deref() {
+        Some(path) => GameConfig::load(path),
+        None => GameConfig::load_for_rom(&rom_path),
+    };
+    let mut config = match config {
+        Ok(config) => config,
+        Err(e) => {
+            log_error!("Error loading game config: {}", e);
+            GameConfig::default()
+        }
+    };
+    for (key, value) in &config_overrides {
+        config.set(key, value);
+    }
+    let config_region = config.get("region").and_then(|name| {
+        let region = Region::parse(name);
+        if region.is_none() {
+            log_warn!("{}: unknown region '{}'", config.path, name);
+        }
+        region
+    });
+    if let Some(cart) = nes.cartridge() {
+        let (region, source) = region::detect(cart, &rom_path, forced_region.or(config_region));
+        let source = match source {
+            Source::Override if forced_region.is_some() => "command line",
+            Source::Override => "game config",
+            Source::Database => "ROM database",
+            Source::Header => "NES 2.0 header",
+            Source::FileName => "file name",
+            Source::Default => "default",
+        };
+        log_info!("Region: {} ({})", region.name(), source);
+        nes.set_region(region);
+    }
+    let mut forced_quirks = Vec::new();
+    if let Some(value) = config.get("alignment") {
+        match value.parse::<u8>() {
+            Ok(dots) if dots <= 2 => forced_quirks.push(Quirk::Alignment(dots)),
+            _ => log_warn!("{}: alignment must be 0, 1 or 2", config.path),
+        }
+    }
+    if let Some(value) = config.get("overclock") {
+        match value.parse::<u16>() {
+            Ok(lines) if lines <= quirks::MAX_OVERCLOCK => forced_quirks.push(Quirk::Overclock(lines)),
+            _ => log_warn!("{}: overclock must be a number of scanlines up to {}", config.path, quirks::MAX_OVERCLOCK),
+        }
+    }
+    let active_quirks = nes.cartridge().map_or(Vec::new(), |cart| quirks::detect(cart.rom_hash(), &forced_quirks));
+    for &(quirk, source) in &active_quirks {
+        let source = match source {
+            quirks::Source::Override => "game config",
+            quirks::Source::Database => "ROM database",
+        };
+        log_info!("Quirk: {} ({})", quirk.describe(), source);
+        quirk.apply(&mut nes);
+    }
+    nes.reset();
+
+    if let Some(name) = palette_name.as_deref().or(config.get("palette")) {
+        match Palette::load(name) {
+            Ok(palette) => nes.set_palette(palette),
+            Err(e) => log_error!("Error loading palette {}", e),
+        }
     }
 
-    let rom_path = &args[1];
+    load_symbols(&mut nes, &rom_path, &symbol_files);
+    if let Some(path) = trace_path {
+        match TraceLog::create(&path) {
+            Ok(log) => nes.debugger_mut().trace_log = Some(log),
+            Err(e) => log_error!("Error creating trace log {}: {}", path, e),
+        }
+    }
+    if let Some(path) = ppu_log_path {
+        match PpuBusLog::create(&path, ppu_log_frames.0, ppu_log_frames.1) {
+            Ok(log) => nes.set_ppu_bus_log(Some(log)),
+            Err(e) => log_error!("Error creating PPU bus log {}: {}", path, e),
+        }
+    }
+    if let Some(path) = compare_path {
+        match TraceCompare::open(&path) {
+            Ok(compare) => nes.debugger_mut().compare = Some(compare),
+            Err(e) => {
+                log_error!("Error opening reference trace {}: {}", path, e);
+                return Ok(());
+            }
+        }
+    }
+    if debug {
+        nes.debugger_mut().request_break();
+    }
+    if zapper {
+        nes.connect_zapper();
+    }
+    let mut expansion_keys: Vec<(Keycode, u8)> = Vec::new();
+    let mut expansion_controls = None;
+    if let Some(name) = expansion_name.as_deref().or(config.get("expansion")) {
+        match expansion::by_name(name) {
+            Some(device) => {
+                log_info!("Expansion port: {}", device.name());
+                // The device names its keys; SDL knows them by the same names
+                for &(name, button) in device.keys() {
+                    match Keycode::from_name(name) {
+                        Some(key) => expansion_keys.push((key, button)),
+                        None => log_warn!("{}: unknown key '{}'", device.name(), name),
+                    }
+                }
+                expansion_controls = Some((device.name(), device.controls()));
+                nes.connect_expansion(device);
+            }
+            None => log_warn!("Unknown expansion port device '{}' (mahjong or hypershot)", name),
+        }
+    }
+    let expansion_button = |keycode| expansion_keys.iter().find(|&&(key, _)| key == keycode).map(|&(_, button)| button);
+    let mut macros = Macros::from_config(&config).unwrap_or_else(|e| {
+        log_error!("Error loading macros: {}", e);
+        Macros::default()
+    });
+    if !macros.is_empty() {
+        log_info!("Loaded {} input macros", macros.len());
+    }
+    let mut script = match script_path.map(|path| InputScript::load(&path)).transpose() {
+        Ok(script) => script.map(ScriptPlayer::new),
+        Err(e) => {
+            log_error!("Error loading input script: {}", e);
+            return Ok(());
+        }
+    };
+
+    let movie = match movie_path.map(|path| Movie::load(&path)).transpose() {
+        Ok(movie) => movie,
+        Err(e) => {
+            log_error!("Error loading movie: {}", e);
+            return Ok(());
+        }
+    };
+    // Movies start from power-on, so recordings and playback begin from the same state
+    let movie_mode = movie.is_some() || record_path.is_some();
+    if movie_mode {
+        nes.power_cycle();
+    }
+    if record_path.is_some() && boot_state.is_some() {
+        log_error!("--record-movie records from power-on and can't start from --load-state or --resume");
+        return Ok(());
+    }
+    if let Some(movie) = movie.as_ref() {
+        match movie.verify(&nes) {
+            Ok(warnings) => warnings.iter().for_each(|w| log_warn!("Warning: movie {}", w)),
+            Err(e) => {
+                log_error!("Error: movie {}", e);
+                return Ok(());
+            }
+        }
+    }
+    let mut movie = movie.map(MoviePlayer::new);
+    let mut recorder = record_path.map(|path| MovieRecorder::new(&path, &rom_path, &nes));
+
+    let mut dumper = match dump_dir.map(|dir| FrameDumper::new(&dir, dump_format).map_err(|e| (dir, e))).transpose() {
+        Ok(dumper) => dumper,
+        Err((dir, e)) => {
+            log_error!("Error creating frame dump directory {}: {}", dir, e);
+            return Ok(());
+        }
+    };
+
+    let mut frame_output = match frame_output_target.map(|target| FrameOutput::open(&target).map_err(|e| (target, e))).transpose() {
+        Ok(output) => output,
+        Err((target, e)) => {
+            log_error!("Error opening frame output {}: {}", target, e);
+            return Ok(());
+        }
+    };
+    if let Some(output) = frame_output.as_ref() {
+        log_info!("Publishing frames to {}", output.path());
+    }
+
+    if headless {
+        let length = script.as_ref().map(|s| s.len()).max(movie.as_ref().map(|m| m.len() as u64));
+        let Some(frames) = frame_limit.map(u64::from).or(length) else {
+            log_error!("--headless needs --script, --play-movie or --frames to know when to stop");
+            return Ok(());
+        };
+        let ranges = match snapshot_ranges.iter().map(|range| snapshot::parse_range(range, &nes.debugger().symbols)).collect::<Result<Vec<_>, _>>() {
+            Ok(ranges) if ranges.is_empty() => snapshot::DEFAULT_RANGES.to_vec(),
+            Ok(ranges) => ranges,
+            Err(e) => {
+                log_error!("{}", e);
+                return Ok(());
+            }
+        };
+        if let Some(target) = boot_state.as_deref() {
+            if let Err(e) = load_boot_state(&mut nes, &rom_path, target) {
+                log_error!("Error loading state {}: {}", target, e);
+                return Ok(());
+            }
+        }
+        for frame in 0..frames {
+            if let Some(script) = script.as_mut() {
+                let buttons = script.next_frame(&mut nes);
+                nes.set_auto_buttons(buttons);
+                if let Some(recorder) = recorder.as_mut() {
+                    recorder.command(script.commands());
+                }
+            }
+            if let Some(player) = movie.as_mut() {
+                player.next_frame(&mut nes);
+            }
+            crash::guard(&mut nes, &rom_path, |nes| nes.run_frame());
+            if nes.debugger().is_paused() {
+                println!("Stopped in frame {}", frame);
+                std::process::exit(1);
+            }
+            if let Some(recorder) = recorder.as_mut() {
+                recorder.frame(&nes);
+            }
+            if let Some(desync) = movie.as_mut().and_then(|player| player.check_sync(&nes)) {
+                log_warn!("Warning: movie desynced by frame {}", desync);
+            }
+            if let Some(dumper) = dumper.as_mut() {
+                if let Err(e) = dumper.write(frame, nes.get_frame_buffer()) {
+                    log_error!("Error writing frame {}: {}", frame, e);
+                    return Ok(());
+                }
+            }
+            if let Some(output) = frame_output.as_mut() {
+                if let Err(e) = output.write(frame, nes.get_frame_buffer()) {
+                    log_error!("Error publishing frame {} to {}: {}", frame, output.path(), e);
+                    return Ok(());
+                }
+            }
+            if hash_frames {
+                println!("{}", debugger::hash_line(&nes));
+            }
+            nes.frame_done();
+            if nes.debugger().is_paused() {
+                println!("Stopped after frame {}", frame);
+                std::process::exit(1);
+            }
+        }
+        println!("Ran {} frames", frames);
+        if let Some(recorder) = recorder.as_ref() {
+            save_movie(recorder);
+        }
+        if let Some(dumper) = dumper.as_ref() {
+            println!("Dumped {} frames", dumper.written);
+        }
+        println!("Frame hash: {:016x}", hash::fnv1a(nes.get_frame_buffer()));
+        println!("RAM hash: {:016x}", nes.ram_hash());
+        println!("State hash: {:016x}", nes.state_hash());
+        if let Some(path) = snapshot_path {
+            if let Err(e) = std::fs::write(&path, nes.snapshot(&ranges).to_json()) {
+                log_error!("Error writing {}: {}", path, e);
+                return Ok(());
+            }
+            println!("Wrote snapshot to {}", path);
+        }
+        return Ok(());
+    }
+
+    let default_splits = format!("{}.splits", rom_path);
+    let splits_path = splits_path.or(std::path::Path::new(&default_splits).exists().then_some(default_splits));
+    let mut timer = None;
+    if let Some(path) = splits_path {
+        match SpeedrunTimer::load(&path, &nes.debugger().symbols) {
+            Ok(mut t) => {
+                t.set_region(nes.region());
+                timer = Some(t);
+            }
+            Err(e) => log_error!("Error loading splits: {}", e),
+        }
+    }
+    if let (Some(timer), Some(addr)) = (timer.as_mut(), livesplit_addr) {
+        if let Err(e) = timer.connect_livesplit(&addr) {
+            log_error!("Could not connect to LiveSplit Server at {}: {}", addr, e);
+        }
+    }
     
+    // Only the window reads the keyboard and gamepads
+    let input_config = playtime::data_dir().map(|dir| dir.join("input.cfg").to_string_lossy().into_owned());
+    let profiles = match input_config.map(|path| GameConfig::load(&path)).transpose() {
+        Ok(input_config) => Profiles::load(&input_config.unwrap_or_default()),
+        Err(e) => Err(e.to_string()),
+    };
+    let profile = match profiles.and_then(|profiles| {
+        profiles.for_game(input_profile.as_deref(), &config).map_err(|e| {
+            format!("{} (profiles: {})", e, profiles.names().collect::<Vec<_>>().join(", "))
+        })
+    }) {
+        Ok(profile) => profile,
+        Err(e) => {
+            log_error!("Error loading input profile: {}", e);
+            return Ok(());
+        }
+    };
+    let mut filters = postfx::Settings::default();
+    for key in postfx::KEYS {
+        if let Some(Err(e)) = config.get(key).map(|value| filters.set(key, value)) {
+            log_warn!("{}: {}", config.path, e);
+        }
+    }
+    for (key, value) in &filter_args {
+        if let Err(e) = filters.set(key, value) {
+            log_error!("--{}", e);
+            return Ok(());
+        }
+    }
+    let mut postfx = (!filters.is_off()).then(|| PostFx::new(filters));
+
     // Initialize SDL2
     let sdl_context = sdl2::init()?;
     let video_subsystem = sdl_context.video()?;
+    let clipboard_util = video_subsystem.clipboard();
     
-    let window = video_subsystem
-        .window("ZETR - NES Emulator", SCREEN_WIDTH as u32 * SCALE, SCREEN_HEIGHT as u32 * SCALE)
-        .position_centered()
-        .build()?;
-    
-    let mut canvas = window.into_canvas().build()?;
+    let mut window = video_subsystem.window("ZETR - NES Emulator", SCREEN_WIDTH as u32 * SCALE, SCREEN_HEIGHT as u32 * SCALE);
+    match window_position {
+        Some((x, y)) => window.position(x, y),
+        None => window.position_centered(),
+    };
+    let window = window.build()?;
+
+    let config_refresh = config.get("refresh").and_then(|hz| {
+        let rate = region::refresh_rate(hz);
+        if rate.is_none() {
+            log_warn!("{}: refresh must be 50 or 60", config.path);
+        }
+        rate
+    });
+    // Real-time rate of the window; the speedrun timer keeps counting console time
+    let frame_rate = forced_refresh.or(config_refresh).unwrap_or(nes.region().frame_rate());
+    if frame_rate != nes.region().frame_rate() {
+        log_info!("Running {} timing at {:.0} Hz", nes.region().name(), frame_rate);
+    }
+    let vsync_mode = match config.get("vsync").map(|value| (value, vsync::Mode::parse(value))) {
+        None => vsync::Mode::Auto,
+        Some((_, Some(mode))) => mode,
+        Some((value, None)) => {
+            log_warn!("{}: vsync must be auto, on or off, not '{}'", config.path, value);
+            vsync::Mode::Auto
+        }
+    };
+    // The sound card paces emulation, and the picture shows frames as they come
+    let audio_sync = match config.get("audio_sync") {
+        Some("yes") => true,
+        Some("no") | None => false,
+        Some(value) => {
+            log_warn!("{}: audio_sync must be yes or no, not '{}'", config.path, value);
+            false
+        }
+    };
+    let display_refresh = window.display_index().and_then(|i| video_subsystem.current_display_mode(i)).map_or(0, |mode| mode.refresh_rate);
+    // With vsync each pass of the main loop is one host refresh, and this picks the ones
+    // that get a new frame; without it the pacer times frames
+    let mut refreshes = (!audio_sync && vsync_mode.enabled(display_refresh)).then(|| RefreshScheduler::new(frame_rate, display_refresh));
+    match config.get("black_frames") {
+        Some("yes") => match refreshes.as_mut().map(|scheduler| scheduler.insert_black_frames()) {
+            Some(Ok(())) => log_info!("Inserting black frames"),
+            Some(Err(e)) => log_warn!("{}: {}", config.path, e),
+            None => log_warn!("{}: black frames need vsync, which is off on this {} Hz display", config.path, display_refresh),
+        },
+        Some("no") | None => {}
+        Some(value) => log_warn!("{}: black_frames must be yes or no, not '{}'", config.path, value),
+    }
+    let mut canvas = match refreshes.as_ref() {
+        Some(scheduler) => {
+            log_info!("Vsync at {} Hz, each frame shown for {:.1} refreshes", display_refresh, scheduler.refreshes_per_frame());
+            window.into_canvas().present_vsync().build()?
+        }
+        None => window.into_canvas().build()?,
+    };
+    if audio_sync {
+        log_info!("Audio sync: the sound card paces emulation, vsync is off");
+    }
     let texture_creator = canvas.texture_creator();
     
     let mut texture = texture_creator.create_texture(
         PixelFormatEnum::RGB24,
         TextureAccess::Streaming,
         SCREEN_WIDTH as u32,
-        SCREEN_HEIGHT as u32,
+        postfx.as_ref().map_or(SCREEN_HEIGHT, |fx| fx.height()) as u32,
     )?;
     
-    // Initialize NES
-    let mut nes = NES::new();
-    if let Err(e) = nes.load_cartridge(rom_path) {
-        eprintln!("Error loading ROM: {}", e);
-        return Ok(());
-    }
-    nes.reset();
-    
+    // Controllers are opened as SDL reports them and must be kept to stay open
+    let controller_subsystem = if profile.uses_gamepad() { Some(sdl_context.game_controller()?) } else { None };
+    let mut controllers = Vec::new();
+    log_info!("Input profile: {}", profile.name);
+    let mut chords = ChordTracker::new(profile.chords());
+
     let mut event_pump = sdl_context.event_pump()?;
-    let frame_duration = Duration::from_nanos(1_000_000_000 / 60); // 60 FPS
+    let mut pacer = FramePacer::new(frame_rate);
+    let sound = match config.get("audio") {
+        Some("yes") | None => true,
+        Some("no") => false,
+        Some(value) => {
+            log_warn!("{}: audio must be yes or no, not '{}'", config.path, value);
+            true
+        }
+    };
+    match config.get("audio_filters") {
+        Some("yes") | None => {}
+        Some("no") => nes.set_audio_filters(false),
+        Some(value) => log_warn!("{}: audio_filters must be yes or no, not '{}'", config.path, value),
+    }
+    // The command line beats the game config here too
+    let config_rate = config.get("audio_rate").and_then(|rate| {
+        let parsed = audio::parse_rate(rate);
+        if parsed.is_none() {
+            log_warn!("{}: audio_rate must be 44100, 48000 or 96000, not '{}'", config.path, rate);
+        }
+        parsed
+    });
+    let config_latency = config.get("audio_latency").and_then(|ms| {
+        let parsed = audio::parse_latency(ms);
+        if parsed.is_none() {
+            log_warn!("{}: audio_latency must be {} to {} ms, not '{}'", config.path, audio::MIN_LATENCY_MS, audio::MAX_LATENCY_MS, ms);
+        }
+        parsed
+    });
+    let audio_settings = AudioSettings {
+        rate: audio_rate.or(config_rate).unwrap_or(audio::DEFAULT_RATE),
+        latency_ms: audio_latency.or(config_latency).unwrap_or(audio::DEFAULT_LATENCY_MS),
+        device: audio_device.or(config.get("audio_device").map(str::to_string)),
+    };
+    // Any `pan <channel> = <position>` line makes the sound stereo, others staying centered
+    let mut pan = None;
+    for (key, value) in config.entries() {
+        let Some(name) = key.strip_prefix("pan ") else {
+            continue;
+        };
+        match (Channel::parse(name.trim()), pan_position(value)) {
+            (Some(channel), Some(position)) => pan.get_or_insert([0.0; 5])[channel as usize] = position,
+            (None, _) => log_warn!("{}: unknown channel '{}', not pulse1, pulse2, triangle, noise or dmc", config.path, name.trim()),
+            (_, None) => log_warn!("{}: pan must be left, center, right or -1.0 to 1.0, not '{}'", config.path, value),
+        }
+    }
+    nes.set_audio_panning(pan);
+    let mut audio = match sound.then(|| open_audio(&sdl_context, &audio_settings, true, &mut nes, frame_rate)) {
+        Some(Ok(output)) => Some(output),
+        Some(Err(e)) => {
+            log_error!("Error opening audio, running without sound: {}", e);
+            None
+        }
+        None => None,
+    };
+    let mut display = vec![0u8; SCREEN_WIDTH * SCREEN_HEIGHT * 3];
+    let mut state_slot = 0;
+    // The machine before the last state load, in memory only, for undoing it
+    let mut before_load: Option<Vec<u8>> = None;
+    let mut state_menu: Option<StateMenu> = None;
+    let mut quick_menu: Option<QuickMenu> = None;
+    let mut help: Option<HelpScreen> = None;
+    // Set when emulation crashes, which ends the game but leaves the window up to say so
+    let mut error_screen: Option<ErrorScreen> = None;
+    // Shown at startup so an odd-looking game isn't mistaken for an emulation bug
+    let mut notice = (!active_quirks.is_empty()).then(|| {
+        let names: Vec<String> = active_quirks.iter().map(|(quirk, _)| quirk.describe()).collect();
+        Notice::new(format!("Quirks: {}", names.join(", ")))
+    });
+    let mut layer_view: Option<View> = None;
+    let timer_subsystem = sdl_context.timer()?;
+    let mut latency = latency_test.then(|| LatencyTest::new(frame_rate));
+    let mut counters = match config.get("counters") {
+        Some("yes") => Some(CounterOverlay::new(nes.counters())),
+        Some("no") | None => None,
+        Some(value) => {
+            log_warn!("{}: counters must be yes or no, not '{}'", config.path, value);
+            None
+        }
+    };
+    let mut apu_viewer = false;
+    let mut piano_roll: Option<PianoRoll> = None;
+    let mut frame_skipper = FrameSkipper::new(frame_skip);
+    let mut frame_number = 0;
+
+    // Battery saves are only used in the window, so headless runs stay reproducible
+    let mut battery = None;
+    let forced_battery = match config.get("battery") {
+        Some("yes") => Some(true),
+        Some("no") => Some(false),
+        Some("auto") | None => None,
+        Some(value) => {
+            log_warn!("{}: battery must be yes, no or auto, not '{}'", config.path, value);
+            None
+        }
+    };
+    // Nor while a movie plays or records, since it has to start from a blank save
+    let save_type = nes.cartridge().filter(|_| !movie_mode).map(|cart| battery::detect(cart, forced_battery));
+    if let Some(source) = save_type.flatten().filter(|&(keep, _)| keep).map(|(_, source)| source) {
+        let source = match source {
+            battery::Source::Override => "game config",
+            battery::Source::Database => "ROM database",
+            battery::Source::Header => "header",
+        };
+        log_info!("Battery save: yes ({})", source);
+    }
+    if let (Some(cart), Some(None | Some((true, _)))) = (nes.cartridge_mut(), save_type) {
+        let setting = |key: &str, default: u32| match config.get(key).map(|v| v.parse::<u32>()) {
+            Some(Ok(value)) => value,
+            Some(Err(_)) => {
+                log_warn!("{}: {} must be a number", config.path, key);
+                default
+            }
+            None => default,
+        };
+        let interval = setting("battery_interval", battery::DEFAULT_INTERVAL_SECS) as f64 * frame_rate;
+        let backups = setting("battery_backups", battery::DEFAULT_BACKUPS as u32) as usize;
+        let detected = save_type.flatten().is_some();
+        if detected {
+            cart.set_battery();
+        }
+        let mut saver = BatterySaver::new(&rom_path, interval as u32, backups, detected);
+        // A lock that can't be taken at all (say, a read-only directory) shouldn't stop saves
+        let claimed = saver.lock().unwrap_or_else(|e| {
+            log_error!("Error locking battery save {}: {}", saver.path(), e);
+            true
+        });
+        if claimed {
+            match saver.load(cart) {
+                Ok(true) => log_info!("Loaded battery save {}", saver.path()),
+                Ok(false) => {}
+                Err(e) => log_error!("Error loading battery save {}: {}", saver.path(), e),
+            }
+            battery = Some(saver);
+        } else {
+            log_info!("{} is in use by another zetr, battery saves are off in this one", saver.path());
+        }
+    }
+
+    // Loaded after the battery save so the state's own RAM wins
+    if let Some(target) = boot_state.as_deref() {
+        match load_boot_state(&mut nes, &rom_path, target) {
+            Ok(slot) => {
+                state_slot = slot.unwrap_or(state_slot);
+                notice = Some(Notice::new(format!("Loaded {}", target)));
+            }
+            Err(e) => {
+                log_error!("Error loading state {}: {}", target, e);
+                return Ok(());
+            }
+        }
+    }
+
+    // Play time is counted in the window only, not in headless or test runs
+    let rom_hash = nes.cartridge().map_or(0, |cart| cart.rom_hash());
+    let rom_name = std::path::Path::new(&rom_path).file_name().map_or(rom_path.clone(), |n| n.to_string_lossy().into_owned());
+    let mut session = PlayStats::update(|stats| {
+        if let Some(game) = stats.get(rom_hash) {
+            log_info!("{}", game.summary());
+        }
+        stats.start_session(rom_hash, &rom_name)
+    })
+    .map_err(|e| log_error!("Error updating play stats, not tracking this session: {}", e))
+    .ok();
+    let mut zapper_aim = None;
+    let mut zapper_trigger = false;
     
-    println!("Controls:");
-    println!("Arrow keys: D-pad");
-    println!("Z: A button");
-    println!("X: B button");
-    println!("A: Select");
-    println!("S: Start");
-    println!("ESC: Quit");
+    // Rows for the help screen beyond the profile's bindings
+    let mut help_extras = Vec::new();
+    if zapper {
+        help_extras.push(("zapper".to_string(), "mouse aims, left button fires".to_string()));
+    }
+    if let Some((name, controls)) = expansion_controls.filter(|(_, controls)| !controls.is_empty()) {
+        help_extras.push((name.to_string(), controls.to_string()));
+    }
+    if !macros.is_empty() {
+        help_extras.push(("macros".to_string(), macros.keys().map(|key| key.name()).collect::<Vec<_>>().join(", ")));
+    }
+    if timer.is_some() {
+        help_extras.push(("reset splits".to_string(), "F9".to_string()));
+    }
+    log_info!("Press {} for the controls", Hotkey::Help.key().name());
     
+    // Keeps `zetr library` current without making anyone wait for a scan. Quitting mid-scan
+    // just leaves the previous index
+    let _library_scan = library::scan_in_background();
+
+    let mut watcher = watch.then(|| RomWatcher::new(&rom_path));
+    if watcher.is_some() {
+        log_info!("Watching {} for changes", rom_path);
+    }
+    let mut paused = false;
+    let mut focused = true;
+    let pause_in_background = match config.get("background") {
+        Some("pause") | None => true,
+        Some("run") => false,
+        Some(value) => {
+            log_warn!("{}: background must be pause or run, not '{}'", config.path, value);
+            true
+        }
+    };
+
     'running: loop {
-        let frame_start = Instant::now();
-        
+        // Frame rate limiting. This comes first so events are polled right before the frame
+        // that uses them, and the pacer times the wait so that frame is presented on schedule.
+        // Paused, in the background or in the state manager nothing moves, so the loop
+        // blocks on events instead and uses next to no CPU.
+        let idle = paused || state_menu.is_some() || quick_menu.is_some() || help.is_some() || error_screen.is_some() || (!focused && pause_in_background);
+        let pacing_profile = profiler::scope(Section::Pacing);
+        let refresh = refreshes.as_mut().filter(|_| !idle).map(|scheduler| scheduler.next_refresh());
+        // Without a sound device audio sync falls back on the pacer
+        let behind = match audio.as_ref().filter(|_| audio_sync && !idle) {
+            Some(output) => output.wait_for_room(),
+            None => !idle && refresh.is_none() && pacer.wait(),
+        };
+        let mut events: Vec<Event> = Vec::new();
+        if idle {
+            events.extend(event_pump.wait_event_timeout(IDLE_WAKE_MS));
+        }
+        drop(pacing_profile);
+        // Only frames the pacer waited for are timed. An idle pass is mostly the event wait
+        // and a redraw, which would skew the work estimate the pacer's wake-up relies on.
+        let work_start = (!idle).then(Instant::now);
+
         // Handle events
-        for event in event_pump.poll_iter() {
+        let events_profile = profiler::scope(Section::Events);
+        events.extend(event_pump.poll_iter());
+        let events: Vec<Event> = events.into_iter().flat_map(|event| chords.translate(event)).collect();
+        if let Some(path) = instance.as_mut().and_then(Instance::poll) {
+            next_rom = Some(path);
+            break 'running;
+        }
+        let woken = !events.is_empty();
+        for event in events {
+            match event {
+                Event::Window { win_event: WindowEvent::FocusGained, .. } => focused = true,
+                Event::Window { win_event: WindowEvent::FocusLost, .. } => focused = false,
+                // Unplugged: on to the default device, or silence until one turns up
+                Event::AudioDeviceRemoved { iscapture: false, .. } if audio.as_ref().is_some_and(AudioOutput::lost) => {
+                    audio = None;
+                    nes.set_sample_rate(0);
+                    let default = AudioSettings { device: None, ..audio_settings };
+                    match open_audio(&sdl_context, &default, false, &mut nes, frame_rate) {
+                        Ok(output) => {
+                            log_warn!("The audio device was unplugged, playing through the default");
+                            notice = Some(Notice::new("Audio device unplugged, using the default"));
+                            audio = Some(output);
+                        }
+                        Err(e) => {
+                            log_warn!("The audio device was unplugged and no other opened ({}), running without sound", e);
+                            notice = Some(Notice::new("Audio device unplugged, no sound"));
+                        }
+                    }
+                }
+                // Plugged back in, or a first device after running without one
+                Event::AudioDeviceAdded { iscapture: false, .. }
+                    if sound && audio.as_ref().is_none_or(|output| output.device() != audio_settings.device.as_deref()) =>
+                {
+                    if let Ok(output) = open_audio(&sdl_context, &audio_settings, audio.is_none(), &mut nes, frame_rate) {
+                        log_info!("Playing through {}", output.device().unwrap_or("the default audio device"));
+                        notice = Some(Notice::new("Sound is back"));
+                        audio = Some(output);
+                    }
+                }
+                _ => {}
+            }
+            if error_screen.is_some() {
+                match event {
+                    Event::Quit { .. } | Event::KeyDown { repeat: false, .. } | Event::MouseButtonDown { .. } | Event::ControllerButtonDown { .. } => break 'running,
+                    _ => continue,
+                }
+            }
+            if let Some(menu) = state_menu.as_mut() {
+                match event {
+                    Event::Quit { .. } => break 'running,
+                    Event::KeyDown { keycode: Some(keycode), .. } => match menu.handle_key(keycode) {
+                        MenuAction::None => {}
+                        MenuAction::Close => {
+                            state_slot = menu.selected();
+                            state_menu = None;
+                        }
+                        MenuAction::Load(slot) => {
+                            state_slot = slot;
+                            notice = Some(load_state(&mut nes, &rom_path, slot, &mut before_load, recorder.is_some()));
+                            state_menu = None;
+                        }
+                    },
+                    Event::TextInput { text, .. } => menu.handle_text(&text),
+                    _ => {}
+                }
+                continue;
+            }
+            if let Some(menu) = quick_menu.as_mut() {
+                let nav = match event {
+                    Event::Quit { .. } => break 'running,
+                    Event::KeyDown { keycode: Some(keycode), .. } => Nav::from_key(keycode),
+                    Event::ControllerButtonDown { button, .. } => Nav::from_button(button),
+                    _ => None,
+                };
+                let action = nav.map(|nav| menu.handle(nav));
+                state_slot = menu.slot();
+                match action {
+                    None | Some(QuickAction::None) => {}
+                    Some(QuickAction::Resume) => quick_menu = None,
+                    Some(QuickAction::Save(slot)) => {
+                        notice = Some(match savestate::save_slot(&nes, &savestate::slot_path(&rom_path, slot)) {
+                            Ok(()) => Notice::new(format!("Saved slot {}", slot)),
+                            Err(e) => Notice::new(format!("Save failed: {}", e)),
+                        });
+                        state_slot = slot;
+                        quick_menu = None;
+                    }
+                    Some(QuickAction::Load(slot)) => {
+                        notice = Some(load_state(&mut nes, &rom_path, slot, &mut before_load, recorder.is_some()));
+                        state_slot = slot;
+                        quick_menu = None;
+                    }
+                    Some(QuickAction::Reset) => {
+                        nes.reset();
+                        if let Some(recorder) = recorder.as_mut() {
+                            recorder.command(COMMAND_SOFT_RESET);
+                        }
+                        notice = Some(Notice::new("Reset"));
+                        quick_menu = None;
+                    }
+                    Some(QuickAction::Counters(on)) => counters = on.then(|| CounterOverlay::new(nes.counters())),
+                    Some(QuickAction::AudioFilters(on)) => nes.set_audio_filters(on),
+                    Some(QuickAction::Help) => {
+                        help = Some(HelpScreen::new(&profile, &help_extras));
+                        quick_menu = None;
+                    }
+                    Some(QuickAction::Quit) => break 'running,
+                }
+                continue;
+            }
+            if let Some(screen) = help.as_mut() {
+                // Anything but scrolling closes it, F2 included
+                let nav = match event {
+                    Event::Quit { .. } => break 'running,
+                    Event::KeyDown { keycode: Some(keycode), .. } => Some(Nav::from_key(keycode).unwrap_or(Nav::Back)),
+                    Event::ControllerButtonDown { button, .. } => Some(Nav::from_button(button).unwrap_or(Nav::Back)),
+                    _ => None,
+                };
+                if nav.is_some_and(|nav| !screen.handle(nav)) {
+                    help = None;
+                }
+                continue;
+            }
+
             match event {
                 Event::Quit { .. } => break 'running,
                 Event::KeyDown { keycode: Some(Keycode::Escape), .. } => break 'running,
-                Event::KeyDown { keycode: Some(keycode), .. } => {
-                    nes.handle_key_down(keycode);
+                // Also how macOS delivers files opened with zetr while it runs
+                Event::DropFile { filename, .. } => {
+                    next_rom = Some(filename);
+                    break 'running;
+                }
+                Event::KeyDown { keycode: Some(Keycode::F12), .. } => {
+                    nes.debugger_mut().request_break();
+                }
+                Event::KeyDown { keycode: Some(Keycode::F1), .. } => {
+                    quick_menu = Some(QuickMenu::open(state_slot, counters.is_some(), nes.audio_filters()));
+                    // Releases the menu swallows would otherwise leave buttons held
+                    nes.set_controller1(0);
+                }
+                Event::KeyDown { keycode: Some(Keycode::F2), .. } => {
+                    help = Some(HelpScreen::new(&profile, &help_extras));
+                    nes.set_controller1(0);
+                }
+                Event::KeyDown { keycode: Some(Keycode::Pause), .. } => {
+                    paused = !paused;
+                    notice = Some(Notice::new(if paused { "Paused" } else { "Resumed" }));
+                }
+                Event::KeyDown { keycode: Some(Keycode::F5), keymod, .. } if keymod.intersects(SHIFT) => {
+                    notice = Some(match savestate::undo_save(&savestate::slot_path(&rom_path, state_slot)) {
+                        Ok(()) => Notice::new(format!("Undid save to slot {}", state_slot)),
+                        Err(e) => Notice::new(format!("Undo failed: {}", e)),
+                    });
+                }
+                Event::KeyDown { keycode: Some(Keycode::F5), .. } => {
+                    let path = savestate::slot_path(&rom_path, state_slot);
+                    notice = Some(match savestate::save_slot(&nes, &path) {
+                        Ok(()) => Notice::new(format!("Saved slot {}", state_slot)),
+                        Err(e) => Notice::new(format!("Save failed: {}", e)),
+                    });
+                }
+                Event::KeyDown { keycode: Some(Keycode::F6), .. } => {
+                    state_slot = (state_slot + 1) % savestate::SLOTS;
+                    notice = Some(Notice::new(format!("Slot {}", state_slot)));
+                }
+                Event::KeyDown { keycode: Some(Keycode::F7), keymod, .. } if keymod.intersects(SHIFT) => {
+                    notice = Some(match before_load.take() {
+                        None => Notice::new("No load to undo"),
+                        Some(state) => match load_undoable(&mut nes, &state, &mut before_load, recorder.is_some()) {
+                            Ok(()) => Notice::new("Undid load"),
+                            Err(e) => {
+                                before_load = Some(state);
+                                Notice::new(format!("Undo failed: {}", e))
+                            }
+                        },
+                    });
+                }
+                Event::KeyDown { keycode: Some(Keycode::F7), .. } => {
+                    notice = Some(load_state(&mut nes, &rom_path, state_slot, &mut before_load, recorder.is_some()));
+                }
+                Event::KeyDown { keycode: Some(Keycode::F8), .. } => {
+                    state_menu = Some(StateMenu::open(&rom_path, state_slot, nes.region()));
+                }
+                Event::KeyDown { keycode: Some(Keycode::F9), .. } => {
+                    if let Some(timer) = timer.as_mut() {
+                        timer.reset();
+                    }
+                }
+                Event::KeyDown { keycode: Some(Keycode::C), keymod, .. } if keymod.intersects(CTRL) => {
+                    let copied = if keymod.intersects(SHIFT) {
+                        savestate::to_bytes(&nes, "").and_then(|state| {
+                            Ok(clipboard_util.set_clipboard_text(&clipboard::encode_state(&state))?)
+                        })
+                    } else {
+                        clipboard::copy_png(&image::encode_png(nes.get_frame_buffer()))
+                    };
+                    let what = if keymod.intersects(SHIFT) { "state" } else { "screenshot" };
+                    notice = Some(match copied {
+                        Ok(()) => Notice::new(format!("Copied {}", what)),
+                        Err(e) => Notice::new(format!("Copy failed: {}", e)),
+                    });
+                }
+                Event::KeyDown { keycode: Some(Keycode::V), keymod, .. } if keymod.intersects(CTRL) => {
+                    let pasted = clipboard_util.clipboard_text().map_err(|e| e.to_string()).and_then(|text| clipboard::decode_state(&text));
+                    notice = Some(match pasted.map_err(|e| e.into()).and_then(|state| load_undoable(&mut nes, &state, &mut before_load, recorder.is_some())) {
+                        Ok(_) => Notice::new("Loaded pasted state"),
+                        Err(e) => Notice::new(format!("Paste failed: {}", e)),
+                    });
+                }
+                Event::KeyDown { keycode: Some(Keycode::F3), .. } => {
+                    counters = match counters {
+                        Some(_) => None,
+                        None => Some(CounterOverlay::new(nes.counters())),
+                    };
+                }
+                // Channel state, then the piano roll, then neither
+                Event::KeyDown { keycode: Some(Keycode::F4), .. } => {
+                    (apu_viewer, piano_roll) = match (apu_viewer, piano_roll.is_some()) {
+                        (false, false) => (true, None),
+                        (true, _) => (false, Some(PianoRoll::new())),
+                        (false, true) => (false, None),
+                    };
+                }
+                Event::KeyDown { keycode: Some(Keycode::F10), .. } => {
+                    layer_view = View::next(layer_view);
+                    // Tracking is left on for the debugger's pixel command once turned on
+                    if layer_view.is_some() && nes.layers().is_none() {
+                        nes.set_track_layers(true);
+                    }
+                    notice = Some(Notice::new(layer_view.map_or("All layers", View::name)));
+                }
+                Event::KeyDown { keycode: Some(keycode), .. } if expansion_button(keycode).is_some() => {
+                    if let (Some(device), Some(button)) = (nes.expansion_mut(), expansion_button(keycode)) {
+                        device.press(button, true);
+                    }
+                }
+                Event::KeyUp { keycode: Some(keycode), .. } if expansion_button(keycode).is_some() => {
+                    if let (Some(device), Some(button)) = (nes.expansion_mut(), expansion_button(keycode)) {
+                        device.press(button, false);
+                    }
+                }
+                Event::KeyDown { keycode: Some(keycode), repeat: false, .. } if macros.binds(keycode) => {
+                    macros.key_down(keycode);
+                }
+                Event::KeyDown { keycode: Some(keycode), .. } if macros.binds(keycode) => {}
+                Event::KeyUp { keycode: Some(keycode), .. } if macros.binds(keycode) => {
+                    macros.key_up(keycode);
+                }
+                Event::KeyDown { keycode: Some(keycode), keymod, .. } if channel_key(keycode).is_some() && profile.buttons(Input::Key(keycode)) == 0 => {
+                    match channel_key(keycode) {
+                        Some(channel) if keymod.intersects(SHIFT) => nes.solo_channel(channel),
+                        Some(channel) => nes.set_channel_muted(channel, !nes.channel_muted(channel)),
+                        None => {}
+                    }
+                    let muted: Vec<&str> = Channel::ALL.iter().filter(|&&c| nes.channel_muted(c)).map(|c| c.name()).collect();
+                    notice = Some(Notice::new(if muted.is_empty() { "All channels on".to_string() } else { format!("Muted: {}", muted.join(", ")) }));
+                }
+                Event::KeyDown { timestamp, keycode: Some(keycode), repeat, .. } => {
+                    let buttons = profile.buttons(Input::Key(keycode));
+                    if let Some(test) = latency.as_mut().filter(|_| buttons != 0 && !repeat) {
+                        test.press(timestamp);
+                    }
+                    nes.press_buttons(buttons);
                 }
                 Event::KeyUp { keycode: Some(keycode), .. } => {
-                    nes.handle_key_up(keycode);
+                    nes.release_buttons(profile.buttons(Input::Key(keycode)));
+                }
+                Event::ControllerDeviceAdded { which, .. } if gamepad.is_none_or(|n| n == which) => {
+                    if let Some(subsystem) = controller_subsystem.as_ref() {
+                        match claim_gamepad(which).map(|claim| (subsystem.open(which), claim)) {
+                            Some((Ok(controller), claim)) => {
+                                notice = Some(Notice::new(format!("{} connected", controller.name())));
+                                controllers.push((controller, claim));
+                            }
+                            Some((Err(e), _)) => log_error!("Could not open gamepad {}: {}", which, e),
+                            None => log_info!("Gamepad {} is in use by another zetr", which),
+                        }
+                    }
+                }
+                Event::ControllerDeviceRemoved { which, .. } => {
+                    controllers.retain(|(controller, _)| controller.instance_id() != which);
+                }
+                Event::ControllerButtonDown { timestamp, button, .. } => {
+                    let buttons = profile.buttons(Input::Pad(button));
+                    if let Some(test) = latency.as_mut().filter(|_| buttons != 0) {
+                        test.press(timestamp);
+                    }
+                    nes.press_buttons(buttons);
+                }
+                Event::ControllerButtonUp { button, .. } => {
+                    nes.release_buttons(profile.buttons(Input::Pad(button)));
+                }
+                Event::MouseMotion { x, y, .. } => {
+                    zapper_aim = Some(((x / SCALE as i32) as i16, (y / SCALE as i32) as i16));
+                    nes.set_zapper(zapper_aim, zapper_trigger);
+                }
+                Event::MouseButtonDown { mouse_btn: MouseButton::Left, .. } => {
+                    zapper_trigger = true;
+                    nes.set_zapper(zapper_aim, zapper_trigger);
+                }
+                Event::MouseButtonUp { mouse_btn: MouseButton::Left, .. } => {
+                    zapper_trigger = false;
+                    nes.set_zapper(zapper_aim, zapper_trigger);
                 }
                 _ => {}
             }
         }
         
-        // Run NES for one frame
-        nes.run_frame();
+        drop(events_profile);
+
+        // A rebuilt ROM replaces the game in place; one that fails to load leaves it running
+        // until the next build
+        let rebuilt = watcher.as_mut().is_some_and(RomWatcher::poll);
+        if rebuilt && recorder.is_some() {
+            // A movie has to play back on the ROM it was recorded with
+            log_warn!("{} changed, but it isn't reloaded while recording a movie", rom_path);
+            notice = Some(Notice::new("Not reloaded while recording"));
+        } else if rebuilt {
+            if let (Some(saver), Some(cart)) = (battery.as_mut(), nes.cartridge_mut()) {
+                if let Err(e) = saver.finish(cart) {
+                    log_error!("Error writing battery save {}: {}", saver.path(), e);
+                }
+            }
+            match nes.reload_cartridge(&rom_path, keep_ram) {
+                Ok(()) => {
+                    if let (Some(saver), Some(cart)) = (battery.as_mut(), nes.cartridge_mut()) {
+                        if saver.detected() {
+                            cart.set_battery();
+                        }
+                        if !keep_ram {
+                            if let Err(e) = saver.load(cart) {
+                                log_error!("Error loading battery save {}: {}", saver.path(), e);
+                            }
+                        }
+                    }
+                    load_symbols(&mut nes, &rom_path, &symbol_files);
+                    let loaded = boot_state.as_deref().map(|target| load_boot_state(&mut nes, &rom_path, target).map_err(|e| (target, e)));
+                    notice = Some(match loaded {
+                        Some(Err((target, e))) => Notice::new(format!("Reloaded, but loading {} failed: {}", target, e)),
+                        _ => Notice::new("Reloaded ROM"),
+                    });
+                    log_info!("Reloaded {}", rom_path);
+                    error_screen = None;
+                }
+                Err(e) => {
+                    log_error!("Error reloading {}: {}", rom_path, e);
+                    notice = Some(Notice::new(format!("Reload failed: {}", e)));
+                }
+            }
+        }
+
+        // Run NES for one frame, unless it is paused
+        let idle = paused || state_menu.is_some() || quick_menu.is_some() || help.is_some() || error_screen.is_some() || (!focused && pause_in_background);
+        let mut skipped = false;
+        if !idle && refresh != Some(Refresh::Repeat) {
+            // Dumps, published frames and traces need every frame drawn
+            let every_frame = dumper.is_some() || frame_output.is_some() || nes.debugger().trace_log.is_some() || nes.debugger().compare.is_some();
+            skipped = !every_frame && frame_skipper.next(behind);
+            nes.set_skip_output(skipped);
+            if !nes.debugger().is_paused() {
+                let mut buttons = macros.next_frame();
+                if let Some(script) = script.as_mut() {
+                    buttons |= script.next_frame(&mut nes);
+                    if let Some(recorder) = recorder.as_mut() {
+                        recorder.command(script.commands());
+                    }
+                }
+                nes.set_auto_buttons(buttons);
+                if movie.as_mut().is_some_and(|player| !player.next_frame(&mut nes)) {
+                    notice = Some(Notice::new("Movie finished"));
+                    nes.set_controller1(0);
+                    movie = None;
+                }
+            }
+            if let Err(message) = crash::catch(&mut nes, &rom_path, |nes| nes.run_frame()) {
+                log_error!("{}", message);
+                error_screen = Some(ErrorScreen::crash(&message));
+            } else if nes.debugger().is_paused() && !debugger::prompt(&mut nes) {
+                break 'running;
+            }
+        }
         
         // Render
-        if nes.frame_ready() {
-            let frame_buffer = nes.get_frame_buffer();
-            texture.update(None, frame_buffer, SCREEN_WIDTH * 3)?;
+        // A crashed machine's frame is whatever the panic left behind
+        let new_frame = nes.frame_ready() && error_screen.is_none();
+        if new_frame {
+            if let Some(recorder) = recorder.as_mut() {
+                recorder.frame(&nes);
+            }
+            if let Some(desync) = movie.as_mut().and_then(|player| player.check_sync(&nes)) {
+                log_warn!("Warning: movie desynced by frame {}", desync);
+                notice = Some(Notice::new(format!("Movie desynced by frame {}", desync)));
+            }
+            if let Some(timer) = timer.as_mut() {
+                timer.update(nes.ppu().frame_count, &nes.view());
+            }
+            if let Some(counters) = counters.as_mut() {
+                counters.update(nes.counters());
+            }
+            if let Some(roll) = piano_roll.as_mut() {
+                roll.update(&nes.apu().state(), nes.region());
+            }
+            if let Some(test) = latency.as_mut() {
+                test.frame(nes.get_frame_buffer());
+            }
+            if let Some(audio) = audio.as_ref() {
+                audio.push(&nes.take_samples());
+                if !audio_sync {
+                    nes.adjust_sample_rate(audio.rate_ratio());
+                }
+            }
+            if let Some(Err(e)) = dumper.as_mut().map(|d| d.write(frame_number, nes.get_frame_buffer())) {
+                log_error!("Error writing frame {}, dump stopped: {}", frame_number, e);
+                dumper = None;
+            }
+            if let Some(Err(e)) = frame_output.as_mut().map(|o| o.write(frame_number, nes.get_frame_buffer())) {
+                log_error!("Error publishing frame {}, frame output stopped: {}", frame_number, e);
+                frame_output = None;
+            }
+            frame_number += 1;
+            if let (Some(saver), Some(cart)) = (battery.as_mut(), nes.cartridge_mut()) {
+                let detected = saver.detected();
+                if let Err(e) = saver.frame(cart) {
+                    log_error!("Error writing battery save {}: {}", saver.path(), e);
+                }
+                if !detected && saver.detected() {
+                    log_info!("Game reads back PRG RAM it didn't write, keeping it in {}", saver.path());
+                }
+            }
+            // Saved once a minute so a crash loses little
+            let minute = (frame_rate * 60.0).round() as u64;
+            if let Some(session) = session.as_mut().filter(|_| frame_number % minute == 0) {
+                let seconds = (frame_number as f64 / frame_rate) as u64;
+                PlayStats::update(|stats| stats.update_session(session, seconds))
+                    .unwrap_or_else(|e| log_error!("Error saving play stats: {}", e));
+            }
+        }
+        if (new_frame && !skipped) || (idle && (woken || notice.is_some() || error_screen.is_some())) {
+            let _profile = profiler::scope(Section::Render);
+            display.copy_from_slice(nes.get_frame_buffer());
+            if let (Some(view), Some(layers)) = (layer_view, nes.layers()) {
+                layers::draw(view, layers, nes.ppu(), &mut display);
+            }
+            if let Some(timer) = timer.as_ref() {
+                timer.draw(&mut display);
+            }
+            if let Some(overlay) = counters.as_ref() {
+                overlay.draw(&mut display, nes.counters());
+            }
+            if apu_viewer {
+                let top = if counters.is_some() { counters::HEIGHT } else { 2 };
+                apu_viewer::draw(&mut display, top, &nes.apu().state(), nes.region());
+            }
+            if let Some(roll) = piano_roll.as_ref() {
+                roll.draw(&mut display);
+            }
+            if let Some(menu) = state_menu.as_ref() {
+                menu.draw(&mut display);
+            }
+            if let Some(menu) = quick_menu.as_ref() {
+                menu.draw(&mut display);
+            }
+            if let Some(screen) = help.as_ref() {
+                screen.draw(&mut display);
+            }
+            notice = notice.filter(|n| !n.expired());
+            if let Some(n) = notice.as_ref() {
+                n.draw(&mut display);
+            }
+            if let Some(test) = latency.as_ref() {
+                test.draw(&mut display);
+            }
+            if let Some(screen) = error_screen.as_ref() {
+                screen.draw(&mut display);
+            }
+            match postfx.as_mut() {
+                Some(fx) => texture.update(None, fx.apply(&display), SCREEN_WIDTH * 3)?,
+                None => texture.update(None, &display, SCREEN_WIDTH * 3)?,
+            }
+            canvas.copy(&texture, None, None)?;
+            canvas.present();
+            if let Some(test) = latency.as_mut() {
+                test.presented(timer_subsystem.ticks());
+            }
+        } else if refresh == Some(Refresh::Black) {
+            let _profile = profiler::scope(Section::Render);
+            canvas.set_draw_color(Color::RGB(0, 0, 0));
+            canvas.clear();
+            canvas.present();
+        } else if refresh.is_some() {
+            // Shows the last frame again, and waits for the refresh
+            let _profile = profiler::scope(Section::Render);
             canvas.copy(&texture, None, None)?;
             canvas.present();
-            nes.frame_done();
         }
-        
-        // Frame rate limiting
-        let frame_time = frame_start.elapsed();
-        if frame_time < frame_duration {
-            std::thread::sleep(frame_duration - frame_time);
+        nes.frame_done();
+        if let Some(start) = work_start {
+            pacer.record_work(start.elapsed());
         }
     }
     
+    // After a crash PRG RAM may be garbage, so the last save written before it stays
+    if let (Some(saver), Some(cart), None) = (battery.as_mut(), nes.cartridge_mut(), error_screen.as_ref()) {
+        if let Err(e) = saver.finish(cart) {
+            log_error!("Error writing battery save {}: {}", saver.path(), e);
+        }
+    }
+    if let Some(recorder) = recorder.as_ref() {
+        save_movie(recorder);
+    }
+    // A movie can't be resumed halfway, so sessions are only kept for normal play
+    if save_session && !movie_mode && error_screen.is_none() {
+        let session = Session {
+            rom_path: std::fs::canonicalize(&rom_path).map_or(rom_path.clone(), |path| path.to_string_lossy().into_owned()),
+            window: Some(canvas.window().position()),
+            rate: forced_refresh,
+        };
+        match session.save(&nes) {
+            Ok(()) => log_info!("Saved session, continue with --resume"),
+            Err(e) => log_error!("Error saving session: {}", e),
+        }
+    }
+    let seconds = (frame_number as f64 / frame_rate) as u64;
+    if let Some(session) = session.as_mut() {
+        PlayStats::update(|stats| stats.update_session(session, seconds))
+            .unwrap_or_else(|e| log_error!("Error saving play stats: {}", e));
+    }
+    log_info!("Played {} this session", playtime::format_duration(seconds));
+    if let Some(summary) = latency.as_ref().and_then(LatencyTest::summary) {
+        log_info!("{}", summary);
+    }
+
+    if profiler::enabled() {
+        print!("{}", profiler::report());
+    }
+
+    if let Some(path) = next_rom {
+        // Released first, or the new process would forward the ROM straight back here
+        drop(instance);
+        log_info!("Opening {}", path);
+        if let Err(e) = Command::new(env::current_exe()?).args(relaunch_args(&args, &path)).spawn() {
+            log_error!("Error starting zetr for {}: {}", path, e);
+        }
+    }
+
+    Ok(())
+}
+
+// Options about the frontend rather than the game, kept when zetr restarts with another ROM.
+const CARRIED_OPTIONS: [&str; 10] =
+    ["--data-dir", "--gamepad", "--input", "--palette", "--scanlines", "--vignette", "--brightness", "--contrast", "--frameskip", "--log"];
+const CARRIED_FLAGS: [&str; 2] = ["--quiet", "--single-instance"];
+
+fn relaunch_args(args: &[String], rom_path: &str) -> Vec<String> {
+    let mut kept = Vec::new();
+    let mut arg_iter = args.iter().skip(1);
+    while let Some(arg) = arg_iter.next() {
+        if CARRIED_OPTIONS.contains(&arg.as_str()) {
+            kept.push(arg.clone());
+            kept.extend(arg_iter.next().cloned());
+        } else if CARRIED_FLAGS.contains(&arg.as_str()) {
+            kept.push(arg.clone());
+        }
+    }
+    kept.push(rom_path.to_string());
+    kept
+}
+
+// The file from Finder's open event, which comes in shortly after SDL starts.
+#[cfg(target_os = "macos")]
+fn wait_for_open_file() -> Option<String> {
+    let sdl_context = sdl2::init().ok()?;
+    let _video = sdl_context.video().ok()?;
+    let mut event_pump = sdl_context.event_pump().ok()?;
+    let deadline = Instant::now() + std::time::Duration::from_secs(1);
+    while let Some(wait) = deadline.checked_duration_since(Instant::now()) {
+        if let Some(Event::DropFile { filename, .. }) = event_pump.wait_event_timeout(wait.as_millis() as u32) {
+            return Some(filename);
+        }
+    }
+    None
+}
+
+// A window showing just the error, for failures before the game's window is up. Closes on
+// any key or click.
+fn show_error(screen: &ErrorScreen) -> Result<(), Box<dyn std::error::Error>> {
+    let sdl_context = sdl2::init()?;
+    let video_subsystem = sdl_context.video()?;
+    let window = video_subsystem
+        .window("ZETR - Error", SCREEN_WIDTH as u32 * SCALE, SCREEN_HEIGHT as u32 * SCALE)
+        .position_centered()
+        .build()?;
+    let mut canvas = window.into_canvas().build()?;
+    let texture_creator = canvas.texture_creator();
+    let mut texture = texture_creator.create_texture(PixelFormatEnum::RGB24, TextureAccess::Streaming, SCREEN_WIDTH as u32, SCREEN_HEIGHT as u32)?;
+    let mut frame = vec![0u8; SCREEN_WIDTH * SCREEN_HEIGHT * 3];
+    screen.draw(&mut frame);
+    texture.update(None, &frame, SCREEN_WIDTH * 3)?;
+    let mut event_pump = sdl_context.event_pump()?;
+    loop {
+        // Redrawn on every event, which covers the window being uncovered or resized
+        canvas.copy(&texture, None, None)?;
+        canvas.present();
+        match event_pump.wait_event() {
+            Event::Quit { .. } | Event::KeyDown { repeat: false, .. } | Event::MouseButtonDown { .. } => return Ok(()),
+            _ => {}
+        }
+    }
+}
+
+fn save_movie(recorder: &MovieRecorder) {
+    match recorder.save() {
+        Ok(()) => log_info!("Recorded {} frames to {}", recorder.len(), recorder.path()),
+        Err(e) => log_error!("Error writing movie {}: {}", recorder.path(), e),
+    }
+}
+
+// Gamepads are claimed with a lock per SDL device index in the default data directory, so
+// windows running side by side don't all follow the same pad, even with their own
+// --data-dir. None if another zetr has this one.
+fn claim_gamepad(index: u32) -> Option<Option<FileLock>> {
+    let Some(dir) = playtime::default_data_dir() else {
+        return Some(None);
+    };
+    match FileLock::try_acquire(&dir.join(format!("gamepad{}", index)).to_string_lossy()) {
+        Ok(lock) => lock.map(Some),
+        Err(e) => {
+            log_error!("Error claiming gamepad {}: {}", index, e);
+            Some(None)
+        }
+    }
+}
+
+// The configured audio device, or with `fallback` the default one if that won't open. The
+// APU's sample rate is set to match the device that opened.
+fn open_audio(sdl: &sdl2::Sdl, settings: &AudioSettings, fallback: bool, nes: &mut NES, frame_rate: f64) -> Result<AudioOutput, String> {
+    let output = AudioOutput::open(sdl, settings, nes.audio_channels()).or_else(|e| match settings.device.as_deref() {
+        // An unplugged or renamed device shouldn't cost the sound altogether
+        Some(name) if fallback => {
+            let devices = audio::device_names(sdl).unwrap_or_default().join(", ");
+            log_warn!("Audio device '{}' didn't open ({}), using the default. Devices: {}", name, e, devices);
+            AudioOutput::open(sdl, &AudioSettings { device: None, ..*settings }, nes.audio_channels())
+        }
+        _ => Err(e),
+    })?;
+    if output.rate() != settings.rate {
+        log_info!("The audio device plays at {} Hz rather than {}", output.rate(), settings.rate);
+    }
+    // Sound keeps pace with the window, so a game sped up by `refresh` also plays higher
+    nes.set_sample_rate((output.rate() as f64 * nes.region().frame_rate() / frame_rate) as u32);
+    Ok(output)
+}
+
+fn pan_position(text: &str) -> Option<f32> {
+    match text {
+        "left" => Some(-1.0),
+        "center" => Some(0.0),
+        "right" => Some(1.0),
+        _ => text.parse().ok().filter(|pan: &f32| (-1.0..=1.0).contains(pan)),
+    }
+}
+
+// Number keys 1-5 mute the APU channels in register order.
+fn channel_key(keycode: Keycode) -> Option<Channel> {
+    let keys = [Keycode::Num1, Keycode::Num2, Keycode::Num3, Keycode::Num4, Keycode::Num5];
+    keys.iter().position(|&k| k == keycode).map(|i| Channel::ALL[i])
+}
+
+fn load_state(nes: &mut NES, rom_path: &str, slot: usize, before_load: &mut Option<Vec<u8>>, recording: bool) -> Notice {
+    let loaded = std::fs::read(savestate::slot_path(rom_path, slot)).map_err(|e| e.into()).and_then(|state| load_undoable(nes, &state, before_load, recording));
+    match loaded {
+        Ok(()) => Notice::new(format!("Loaded slot {}", slot)),
+        Err(e) => Notice::new(format!("Load failed: {}", e)),
+    }
+}
+
+// Loads a state file's contents, keeping the machine as it was for Shift+F7 to go back to.
+// Refused while recording a movie, which has no way to say the machine jumped.
+fn load_undoable(nes: &mut NES, state: &[u8], before_load: &mut Option<Vec<u8>>, recording: bool) -> Result<(), Box<dyn std::error::Error>> {
+    if recording {
+        return Err("not while recording a movie".into());
+    }
+    let before = savestate::to_bytes(nes, "")?;
+    savestate::from_bytes(nes, state)?;
+    *before_load = Some(before);
     Ok(())
 }
+
+// Labels from the files next to the ROM and from --symbols, in place of any loaded before.
+fn load_symbols(nes: &mut NES, rom_path: &str, symbol_files: &[String]) {
+    let prg_banks = nes.cartridge().map_or(0, |cart| cart.prg_rom.len() / 16384);
+    let symbols = &mut nes.debugger_mut().symbols;
+    *symbols = SymbolTable::default();
+    symbols.load_for_rom(rom_path, prg_banks);
+    for path in symbol_files {
+        if let Err(e) = symbols.load(path) {
+            log_error!("Error loading symbols from {}: {}", path, e);
+        }
+    }
+    if !symbols.is_empty() {
+        log_info!("Loaded {} symbols", symbols.len());
+    }
+}
+
+// --load-state: a slot number or a state file. Returns the slot, if it was one.
+fn load_boot_state(nes: &mut NES, rom_path: &str, target: &str) -> Result<Option<usize>, Box<dyn std::error::Error>> {
+    match target.parse::<usize>() {
+        Ok(slot) if slot < savestate::SLOTS => {
+            savestate::load(nes, &savestate::slot_path(rom_path, slot))?;
+            Ok(Some(slot))
+        }
+        _ => {
+            savestate::load(nes, target)?;
+            Ok(None)
+        }
+    }
+}
@@ -0,0 +1,344 @@
+use std::fmt;
+use std::io::{self, Read, Write};
+
+use crate::cartridge::Mirroring;
+use crate::stateful;
+use crate::stateful::Stateful;
+
+// The board logic of a cartridge. Cartridge keeps the ROM and RAM; the mapper decides which
+// part of them an address reaches, takes the writes that set its registers, and can switch
+// the nametable mirroring or raise an IRQ. Boards are picked by iNES mapper number.
+//
+// Cartridge asks for the banks at the start of each 8KB PRG and 1KB CHR window, and for the
+// mirroring, once at power-on and after every register write or state load, and keeps the
+// answers for the memory accesses in between. So no board can bank in smaller pieces than
+// that. Boards that change their outputs on their own, from a cycle counter or by watching
+// the PPU bus, say so from clock or ppu_addr and get asked again. The IRQ line is read
+// every CPU cycle.
+
+pub trait Mapper {
+    fn name(&self) -> &'static str;
+
+    // Offset into PRG ROM for the start of an 8KB window, given from $8000 (0 is $8000).
+    fn prg_offset(&self, addr: u16, prg_len: usize) -> usize;
+
+    // A CPU write to $8000-$FFFF, given from $8000, where boards keep their registers.
+    fn write_register(&mut self, _addr: u16, _data: u8) {}
+
+    // Offset into CHR ROM or RAM for the start of a 1KB window in PPU $0000-$1FFF.
+    fn chr_offset(&self, addr: u16, chr_len: usize) -> usize;
+
+    // Mirroring set by the board, or None for the header's.
+    fn mirroring(&self) -> Option<Mirroring> {
+        None
+    }
+
+    // The board's IRQ line, ORed with the APU's.
+    fn irq(&self) -> bool {
+        false
+    }
+
+    // Every CPU cycle, for boards with cycle-counting IRQ timers. True if the banks or
+    // mirroring changed.
+    fn clock(&mut self) -> bool {
+        false
+    }
+
+    // Whether CHR RAM takes writes, for boards that can write-protect it.
+    fn chr_writable(&self) -> bool {
+        true
+    }
+
+    // The console's reset button. Cartridges don't see it, but multicart boards are
+    // expected to go back to their menu.
+    fn reset(&mut self) {}
+
+    // Every address the PPU reads or writes, $0000-$3FFF, for boards that count scanlines
+    // from A12 or latch CHR banks on tile fetches. True if the banks or mirroring changed.
+    fn ppu_addr(&mut self, _addr: u16) -> bool {
+        false
+    }
+
+    // Bank registers and the like, for save states.
+    fn save_state(&self, _w: &mut dyn Write) -> io::Result<()> {
+        Ok(())
+    }
+
+    fn load_state(&mut self, _r: &mut dyn Read) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl fmt::Debug for dyn Mapper {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+// Mapper numbers with a board below, for `zetr info`.
+pub const SUPPORTED: [u16; 5] = [0, 3, 15, 225, 228];
+
+// The board for an iNES mapper number, None if it isn't emulated.
+pub fn for_number(number: u16) -> Option<Box<dyn Mapper>> {
+    match number {
+        0 => Some(Box::new(Nrom)),
+        3 => Some(Box::new(Cnrom::default())),
+        15 => Some(Box::new(Contra100::default())),
+        225 => Some(Box::new(Multicart225::default())),
+        228 => Some(Box::new(Action52::default())),
+        _ => None,
+    }
+}
+
+// Offset into PRG ROM for an 8KB window of 16KB banks `low` at $8000 and `high` at $C000.
+fn prg_16k(addr: u16, low: usize, high: usize, prg_len: usize) -> usize {
+    let bank = if addr < 0x4000 { low } else { high };
+    (bank * 0x4000 + (addr & 0x3FFF) as usize) % prg_len.max(1)
+}
+
+fn mirroring_bit(horizontal: bool) -> Mirroring {
+    if horizontal { Mirroring::Horizontal } else { Mirroring::Vertical }
+}
+
+// NROM: no registers. 16KB of PRG ROM is mirrored at $C000, and CHR is a single 8KB bank.
+pub struct Nrom;
+
+impl Mapper for Nrom {
+    fn name(&self) -> &'static str {
+        "NROM"
+    }
+
+    fn prg_offset(&self, addr: u16, prg_len: usize) -> usize {
+        addr as usize % prg_len.max(1)
+    }
+
+    fn chr_offset(&self, addr: u16, chr_len: usize) -> usize {
+        addr as usize % chr_len.max(1)
+    }
+}
+
+// CNROM: NROM's PRG with 8KB CHR banks picked by writes anywhere in $8000-$FFFF. Boards
+// decode only as many bits as they have banks, so the written value is masked to the CHR
+// that is actually there; some games set the unused bits. CNROM CHR always comes in a
+// power of two of banks.
+#[derive(Default)]
+pub struct Cnrom {
+    chr_bank: u8,
+}
+
+impl Mapper for Cnrom {
+    fn name(&self) -> &'static str {
+        "CNROM"
+    }
+
+    fn prg_offset(&self, addr: u16, prg_len: usize) -> usize {
+        addr as usize % prg_len.max(1)
+    }
+
+    fn write_register(&mut self, _addr: u16, data: u8) {
+        self.chr_bank = data;
+    }
+
+    fn chr_offset(&self, addr: u16, chr_len: usize) -> usize {
+        let banks = (chr_len / 0x2000).max(1);
+        ((self.chr_bank as usize & (banks - 1)) * 0x2000 + addr as usize) % chr_len.max(1)
+    }
+
+    fn save_state(&self, w: &mut dyn Write) -> io::Result<()> {
+        Stateful::save(self, w)
+    }
+
+    fn load_state(&mut self, r: &mut dyn Read) -> io::Result<()> {
+        Stateful::load(self, r)
+    }
+}
+
+stateful!(Cnrom { chr_bank });
+
+// Mapper 15, the 100-in-1 Contra Function 16 multicart. Writes to $8000-$FFFF pick a PRG
+// mode with address bits 0-1 and a bank and mirroring with the data:
+//
+//   data  bit 7    8KB half of the bank, for mode 2
+//         bit 6    mirroring, 1 for horizontal
+//         bits 0-5 16KB PRG bank B
+//
+//   mode 0  B at $8000, B | 1 at $C000           (NROM-256)
+//   mode 1  B at $8000, B | 7 at $C000           (UNROM)
+//   mode 2  8KB bank 2B + half everywhere        (NROM-64)
+//   mode 3  B at $8000 and $C000                 (NROM-128)
+//
+// CHR is 8KB of RAM, write-protected in modes 0 and 3.
+#[derive(Default)]
+pub struct Contra100 {
+    mode: u8,
+    data: u8,
+}
+
+impl Mapper for Contra100 {
+    fn name(&self) -> &'static str {
+        "100-in-1 Contra Function 16"
+    }
+
+    fn prg_offset(&self, addr: u16, prg_len: usize) -> usize {
+        let bank = (self.data & 0x3F) as usize;
+        match self.mode {
+            0 => prg_16k(addr, bank, bank | 1, prg_len),
+            1 => prg_16k(addr, bank, bank | 7, prg_len),
+            2 => ((bank * 2 + (self.data >> 7) as usize) * 0x2000) % prg_len.max(1),
+            _ => prg_16k(addr, bank, bank, prg_len),
+        }
+    }
+
+    fn write_register(&mut self, addr: u16, data: u8) {
+        self.mode = (addr & 0x03) as u8;
+        self.data = data;
+    }
+
+    fn chr_offset(&self, addr: u16, chr_len: usize) -> usize {
+        addr as usize % chr_len.max(1)
+    }
+
+    fn mirroring(&self) -> Option<Mirroring> {
+        Some(mirroring_bit(self.data & 0x40 != 0))
+    }
+
+    fn chr_writable(&self) -> bool {
+        matches!(self.mode, 1 | 2)
+    }
+
+    fn reset(&mut self) {
+        *self = Self::default();
+    }
+
+    fn save_state(&self, w: &mut dyn Write) -> io::Result<()> {
+        Stateful::save(self, w)
+    }
+
+    fn load_state(&mut self, r: &mut dyn Read) -> io::Result<()> {
+        Stateful::load(self, r)
+    }
+}
+
+stateful!(Contra100 { mode, data });
+
+// Mapper 225, the 52-in-1, 64-in-1 and similar multicarts. Everything is latched from the
+// address of a write to $8000-$FFFF:
+//
+//   bit 14     high bit of both the PRG and CHR bank
+//   bit 13     mirroring, 1 for horizontal
+//   bit 12     1 for one 16KB bank at $8000 and $C000, 0 for a 32KB bank
+//   bits 6-11  16KB PRG bank (its low bit is ignored in 32KB mode)
+//   bits 0-5   8KB CHR bank
+//
+// The 4-bit RAM some of these boards have at $5800 isn't emulated.
+#[derive(Default)]
+pub struct Multicart225 {
+    latch: u16,
+}
+
+impl Mapper for Multicart225 {
+    fn name(&self) -> &'static str {
+        "225 multicart"
+    }
+
+    fn prg_offset(&self, addr: u16, prg_len: usize) -> usize {
+        let high = (self.latch >> 14 & 1) as usize;
+        let bank = high << 6 | (self.latch >> 6 & 0x3F) as usize;
+        if self.latch & 0x1000 != 0 {
+            prg_16k(addr, bank, bank, prg_len)
+        } else {
+            prg_16k(addr, bank & !1, bank | 1, prg_len)
+        }
+    }
+
+    fn write_register(&mut self, addr: u16, _data: u8) {
+        self.latch = addr;
+    }
+
+    fn chr_offset(&self, addr: u16, chr_len: usize) -> usize {
+        let bank = ((self.latch >> 14 & 1) << 6 | (self.latch & 0x3F)) as usize;
+        (bank * 0x2000 + addr as usize) % chr_len.max(1)
+    }
+
+    fn mirroring(&self) -> Option<Mirroring> {
+        Some(mirroring_bit(self.latch & 0x2000 != 0))
+    }
+
+    fn reset(&mut self) {
+        *self = Self::default();
+    }
+
+    fn save_state(&self, w: &mut dyn Write) -> io::Result<()> {
+        Stateful::save(self, w)
+    }
+
+    fn load_state(&mut self, r: &mut dyn Read) -> io::Result<()> {
+        Stateful::load(self, r)
+    }
+}
+
+stateful!(Multicart225 { latch });
+
+// Mapper 228, Active Enterprises' Action 52 and Cheetahmen II. A write to $8000-$FFFF
+// latches its address and the low two bits of its data:
+//
+//   address bit 13     mirroring, 1 for horizontal
+//   address bits 11-12 PRG chip; Action 52 has no chip 2, so chip 3 follows chip 1 in the file
+//   address bits 6-10  16KB PRG bank in the chip (its low bit is ignored in 32KB mode)
+//   address bit 5      1 for one 16KB bank at $8000 and $C000, 0 for a 32KB bank
+//   address bits 0-3   high four bits of the 8KB CHR bank
+//   data bits 0-1      low two bits of the CHR bank
+//
+// The 4-bit RAM at $4020-$5FFF isn't emulated.
+#[derive(Default)]
+pub struct Action52 {
+    latch: u16,
+    data: u8,
+}
+
+impl Mapper for Action52 {
+    fn name(&self) -> &'static str {
+        "Action 52"
+    }
+
+    fn prg_offset(&self, addr: u16, prg_len: usize) -> usize {
+        let chip = match self.latch >> 11 & 3 {
+            3 => 2,
+            chip => chip,
+        } as usize;
+        let bank = chip << 5 | (self.latch >> 6 & 0x1F) as usize;
+        if self.latch & 0x20 != 0 {
+            prg_16k(addr, bank, bank, prg_len)
+        } else {
+            prg_16k(addr, bank & !1, bank | 1, prg_len)
+        }
+    }
+
+    fn write_register(&mut self, addr: u16, data: u8) {
+        self.latch = addr;
+        self.data = data & 0x03;
+    }
+
+    fn chr_offset(&self, addr: u16, chr_len: usize) -> usize {
+        let bank = ((self.latch & 0x0F) << 2 | self.data as u16) as usize;
+        (bank * 0x2000 + addr as usize) % chr_len.max(1)
+    }
+
+    fn mirroring(&self) -> Option<Mirroring> {
+        Some(mirroring_bit(self.latch & 0x2000 != 0))
+    }
+
+    fn reset(&mut self) {
+        *self = Self::default();
+    }
+
+    fn save_state(&self, w: &mut dyn Write) -> io::Result<()> {
+        Stateful::save(self, w)
+    }
+
+    fn load_state(&mut self, r: &mut dyn Read) -> io::Result<()> {
+        Stateful::load(self, r)
+    }
+}
+
+stateful!(Action52 { latch, data });
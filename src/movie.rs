@@ -0,0 +1,249 @@
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use crate::nes::NES;
+
+// FCEUX .fm2 input movies. Only what playback needs is kept: the header fields and,
+// per frame, the command bits and the first controller.
+//
+//   version 3
+//   romFilename donkeykong
+//   |0|........|||
+//   |0|...T....|||      <- Start held on this frame
+//
+// Buttons are written in the order RLDUTSBA, which matches the controller's bits 7 to 0.
+//
+// Movies recorded by zetr also carry header lines other emulators ignore, so playback can
+// tell a wrong ROM or a desync apart from a bad movie:
+//
+//   zetrVersion 0.1.0
+//   zetrRomHash 63de58a49f533251       Cartridge::rom_hash
+//   zetrStartHash 0123456789abcdef     NES::state_hash at power-on, before the first frame
+//   zetrSync 300 0123456789abcdef      state hash after 300 frames, every SYNC_INTERVAL
+//
+// A movie for another ROM is refused. Another version or start state only warns, since the
+// movie may still play correctly, and the first sync point that doesn't match is reported.
+
+pub const COMMAND_SOFT_RESET: u8 = 0x01;
+pub const COMMAND_POWER: u8 = 0x02;
+
+pub const SYNC_INTERVAL: usize = 300;
+const BUTTONS: &[u8; 8] = b"RLDUTSBA";
+
+#[derive(Debug, Clone, Copy)]
+pub struct MovieFrame {
+    pub commands: u8,
+    pub port0: u8,
+}
+
+#[derive(Debug, Default)]
+pub struct Movie {
+    pub header: Vec<(String, String)>,
+    pub frames: Vec<MovieFrame>,
+}
+
+fn parse_buttons(field: &str) -> Result<u8, String> {
+    if field.is_empty() {
+        return Ok(0);
+    }
+    if field.len() != 8 {
+        return Err(format!("expected 8 button columns, got '{}'", field));
+    }
+    Ok(field.chars().fold(0, |mask, c| (mask << 1) | (c != '.' && c != ' ') as u8))
+}
+
+fn format_buttons(mask: u8) -> String {
+    BUTTONS.iter().enumerate().map(|(i, &c)| if mask & (0x80 >> i) != 0 { c as char } else { '.' }).collect()
+}
+
+fn parse_hash(text: &str) -> Option<u64> {
+    u64::from_str_radix(text, 16).ok()
+}
+
+impl Movie {
+    pub fn load(path: &str) -> Result<Self, Box<dyn std::error::Error>> {
+        Ok(Self::parse(&fs::read_to_string(path)?).map_err(|e| format!("{}: {}", path, e))?)
+    }
+
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let mut movie = Movie::default();
+        for (number, line) in text.lines().enumerate() {
+            let line = line.trim_end_matches('\r');
+            if let Some(record) = line.strip_prefix('|') {
+                let mut fields = record.split('|');
+                let commands = fields.next().unwrap_or("0");
+                let commands = commands.trim().parse().map_err(|_| format!("line {}: bad command field '{}'", number + 1, commands))?;
+                let port0 = parse_buttons(fields.next().unwrap_or("")).map_err(|e| format!("line {}: {}", number + 1, e))?;
+                movie.frames.push(MovieFrame { commands, port0 });
+            } else if let Some((key, value)) = line.split_once(' ') {
+                movie.header.push((key.to_string(), value.to_string()));
+            }
+        }
+        if movie.frames.is_empty() {
+            return Err("no input frames".to_string());
+        }
+        Ok(movie)
+    }
+
+    pub fn save(&self, path: &str) -> io::Result<()> {
+        let mut text = String::new();
+        for (key, value) in &self.header {
+            text += &format!("{} {}\n", key, value);
+        }
+        for frame in &self.frames {
+            text += &format!("|{}|{}|||\n", frame.commands, format_buttons(frame.port0));
+        }
+        fs::write(path, text)
+    }
+
+    fn get(&self, key: &str) -> Option<&str> {
+        self.header.iter().find(|(k, _)| k == key).map(|(_, v)| v.as_str())
+    }
+
+    // (frames played, state hash) pairs from zetrSync lines
+    fn sync_points(&self) -> Vec<(usize, u64)> {
+        self.header.iter()
+            .filter(|(key, _)| key == "zetrSync")
+            .filter_map(|(_, value)| {
+                let (frame, hash) = value.split_once(' ')?;
+                Some((frame.parse().ok()?, parse_hash(hash.trim())?))
+            })
+            .collect()
+    }
+
+    // Checks the metadata against the machine at power-on, before the first frame. Returns
+    // warnings, or an error for a movie recorded with another ROM.
+    pub fn verify(&self, nes: &NES) -> Result<Vec<String>, String> {
+        let mut warnings = Vec::new();
+        let rom_hash = nes.cartridge().map_or(0, |cart| cart.rom_hash());
+        if let Some(hash) = self.get("zetrRomHash").and_then(parse_hash) {
+            if hash != rom_hash {
+                return Err(format!("recorded with a different ROM (hash {:016x}, this one is {:016x})", hash, rom_hash));
+            }
+        }
+        if let Some(version) = self.get("zetrVersion").filter(|&v| v != env!("CARGO_PKG_VERSION")) {
+            warnings.push(format!("recorded with zetr {}, this is {}; it may desync", version, env!("CARGO_PKG_VERSION")));
+        }
+        if self.get("zetrStartHash").and_then(parse_hash).is_some_and(|hash| hash != nes.state_hash()) {
+            warnings.push("the power-on state differs from the recording; it will probably desync".to_string());
+        }
+        Ok(warnings)
+    }
+}
+
+// Applies a frame's reset/power commands and controller input, ready for run_frame.
+pub fn apply_frame(nes: &mut NES, frame: &MovieFrame) {
+    if frame.commands & COMMAND_POWER != 0 {
+        nes.power_cycle();
+    } else if frame.commands & COMMAND_SOFT_RESET != 0 {
+        nes.reset();
+    }
+    nes.set_controller1(frame.port0);
+}
+
+// Plays a movie back one frame at a time alongside the normal main loop.
+pub struct MoviePlayer {
+    movie: Movie,
+    next: usize,
+    sync: Vec<(usize, u64)>,
+    desynced: bool,
+}
+
+impl MoviePlayer {
+    pub fn new(movie: Movie) -> Self {
+        let sync = movie.sync_points();
+        MoviePlayer { movie, next: 0, sync, desynced: false }
+    }
+
+    pub fn len(&self) -> usize {
+        self.movie.frames.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.movie.frames.is_empty()
+    }
+
+    pub fn finished(&self) -> bool {
+        self.next >= self.movie.frames.len()
+    }
+
+    // Sets up the next frame's input. Returns false once the movie is over.
+    pub fn next_frame(&mut self, nes: &mut NES) -> bool {
+        let Some(frame) = self.movie.frames.get(self.next) else {
+            return false;
+        };
+        apply_frame(nes, frame);
+        self.next += 1;
+        true
+    }
+
+    // Call after each frame. Returns the frame number the first time the machine doesn't
+    // match the recording's sync hash.
+    pub fn check_sync(&mut self, nes: &NES) -> Option<usize> {
+        if self.desynced {
+            return None;
+        }
+        let &(_, hash) = self.sync.iter().find(|&&(frame, _)| frame == self.next)?;
+        self.desynced = hash != nes.state_hash();
+        self.desynced.then_some(self.next)
+    }
+}
+
+// Records controller 1 from power-on, kept in memory and written out when recording stops.
+// Resets and power cycles go in with the next frame, which is when playback applies them;
+// a state load can't be written to an .fm2, so the frontend refuses them while recording.
+pub struct MovieRecorder {
+    path: String,
+    movie: Movie,
+    commands: u8,
+}
+
+impl MovieRecorder {
+    // Starts recording; the machine must be at power-on.
+    pub fn new(path: &str, rom_path: &str, nes: &NES) -> Self {
+        let rom_name = Path::new(rom_path).file_stem().map_or(rom_path.into(), |n| n.to_string_lossy());
+        let rom_hash = nes.cartridge().map_or(0, |cart| cart.rom_hash());
+        let header = [
+            ("version", "3".to_string()),
+            ("emuVersion", "0".to_string()),
+            ("romFilename", rom_name.into_owned()),
+            ("zetrVersion", env!("CARGO_PKG_VERSION").to_string()),
+            ("zetrRomHash", format!("{:016x}", rom_hash)),
+            ("zetrStartHash", format!("{:016x}", nes.state_hash())),
+        ];
+        let header = header.into_iter().map(|(key, value)| (key.to_string(), value)).collect();
+        MovieRecorder { path: path.to_string(), movie: Movie { header, frames: Vec::new() }, commands: 0 }
+    }
+
+    pub fn path(&self) -> &str {
+        &self.path
+    }
+
+    pub fn len(&self) -> usize {
+        self.movie.frames.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.movie.frames.is_empty()
+    }
+
+    // COMMAND_SOFT_RESET or COMMAND_POWER, done to the machine since the last frame.
+    pub fn command(&mut self, command: u8) {
+        self.commands |= command;
+    }
+
+    // Call after each frame, before the input changes.
+    pub fn frame(&mut self, nes: &NES) {
+        let commands = std::mem::take(&mut self.commands);
+        self.movie.frames.push(MovieFrame { commands, port0: nes.controller1() });
+        if self.movie.frames.len().is_multiple_of(SYNC_INTERVAL) {
+            let sync = format!("{} {:016x}", self.movie.frames.len(), nes.state_hash());
+            self.movie.header.push(("zetrSync".to_string(), sync));
+        }
+    }
+
+    pub fn save(&self) -> io::Result<()> {
+        self.movie.save(&self.path)
+    }
+}
@@ -0,0 +1,93 @@
+use std::fs;
+use std::path::Path;
+
+use crate::movie::{self, Movie, MovieFrame};
+use crate::nes::NES;
+
+// Movie regression suite. Each manifest line plays an .fm2 movie from power-on and
+// compares a hash of the whole machine state after the last frame:
+//
+//   # rom                movie                    state hash
+//   roms/donkeykong.nes  movies/dk-level1.fm2     0123456789abcdef
+//
+// Paths are relative to the manifest, and `-` marks a case without a baseline yet.
+// Playback is fully deterministic, so any difference means emulation changed.
+
+// Runs one movie frame. The frame buffer holds the result until the next call.
+pub fn play_frame(nes: &mut NES, frame: &MovieFrame) {
+    movie::apply_frame(nes, frame);
+    nes.run_frame();
+    nes.frame_done();
+}
+
+pub fn play(nes: &mut NES, movie: &Movie) {
+    for frame in &movie.frames {
+        play_frame(nes, frame);
+    }
+}
+
+fn run_case(rom: &Path, movie: &Path) -> Result<u64, Box<dyn std::error::Error>> {
+    let movie = Movie::load(&movie.to_string_lossy())?;
+    let mut nes = NES::new();
+    nes.load_cartridge(&rom.to_string_lossy())?;
+    movie.verify(&nes)?;
+    play(&mut nes, &movie);
+    Ok(nes.state_hash())
+}
+
+// Checks every case, or with `update` rewrites the manifest with fresh hashes.
+// Returns true when all cases matched.
+pub fn run(manifest: &str, update: bool) -> Result<bool, Box<dyn std::error::Error>> {
+    let text = fs::read_to_string(manifest)?;
+    let base = Path::new(manifest).parent().unwrap_or(Path::new("."));
+    let mut out = String::new();
+    let mut total = 0;
+    let mut passed = 0;
+
+    for (number, line) in text.lines().enumerate() {
+        let words: Vec<&str> = line.split_whitespace().collect();
+        if words.is_empty() || words[0].starts_with('#') {
+            out.push_str(line);
+            out.push('\n');
+            continue;
+        }
+        let [rom, movie, expected] = words[..] else {
+            return Err(format!("{}:{}: expected 'rom movie hash'", manifest, number + 1).into());
+        };
+        let expected = match expected {
+            "-" => None,
+            hex => Some(u64::from_str_radix(hex, 16).map_err(|_| format!("{}:{}: bad hash '{}'", manifest, number + 1, hex))?),
+        };
+        total += 1;
+
+        let actual = match run_case(&base.join(rom), &base.join(movie)) {
+            Ok(hash) => hash,
+            Err(e) => {
+                println!("ERROR    {} ({})", movie, e);
+                out.push_str(line);
+                out.push('\n');
+                continue;
+            }
+        };
+
+        if update {
+            out.push_str(&format!("{} {} {:016x}\n", rom, movie, actual));
+            if expected != Some(actual) {
+                println!("UPDATED  {} -> {:016x}", movie, actual);
+            }
+            passed += 1;
+        } else if expected == Some(actual) {
+            println!("PASS     {}", movie);
+            passed += 1;
+        } else {
+            let expected = expected.map_or("none".to_string(), |h| format!("{:016x}", h));
+            println!("FAIL     {} (line {}): expected {}, got {:016x}", movie, number + 1, expected, actual);
+        }
+    }
+
+    if update {
+        fs::write(manifest, out)?;
+    }
+    println!("{}/{} movies {}", passed, total, if update { "updated" } else { "matched" });
+    Ok(passed == total)
+}
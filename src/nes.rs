@@ -1,7 +1,24 @@
+use std::io::{self, Read, Write};
+use byteorder::{LittleEndian, ReadBytesExt, WriteBytesExt};
+
+use crate::apu::{Channel, FrameTiming, APU};
 use crate::cartridge::Cartridge;
 use crate::cpu::CPU;
+use crate::palette::Palette;
 use crate::ppu::PPU;
-use crate::bus::Bus;
+use crate::ppulog::PpuBusLog;
+use crate::bus::{Bus, Ports};
+use crate::debugger::{self, Debugger};
+use crate::expr::{self, Var};
+use crate::hash;
+use crate::layers::LayerPixel;
+use crate::profiler::{self, Section};
+use crate::region::Region;
+use crate::scheduler::{Event, Scheduler};
+use crate::snapshot::Snapshot;
+use crate::trace::{self, CpuHistory, CpuSnapshot};
+use crate::expansion::ExpansionDevice;
+use crate::zapper::Zapper;
 
 // Controller button constants
 const BUTTON_A: u8 = 0x01;
@@ -13,13 +30,92 @@ const BUTTON_DOWN: u8 = 0x20;
 const BUTTON_LEFT: u8 = 0x40;
 const BUTTON_RIGHT: u8 = 0x80;
 
+// Borrowed view of the machine for debugger expressions and side-effect free reads.
+pub struct MachineView<'a> {
+    cpu: &'a CPU,
+    ppu: &'a PPU,
+    ram: &'a [u8; 2048],
+    cartridge: Option<&'a Cartridge>,
+}
+
+impl MachineView<'_> {
+    // PPU and I/O registers read as 0 so peeking never disturbs their state.
+    pub fn peek(&self, addr: u16) -> u8 {
+        match addr {
+            0x0000..=0x1FFF => self.ram[(addr & 0x07FF) as usize],
+            0x6000..=0x7FFF => self.cartridge.map_or(0, |cart| cart.read_prg_ram(addr - 0x6000)),
+            0x8000..=0xFFFF => self.cartridge.map_or(0, |cart| cart.read_prg(addr - 0x8000)),
+            _ => 0,
+        }
+    }
+}
+
+impl expr::Context for MachineView<'_> {
+    fn var(&self, var: Var) -> i64 {
+        match var {
+            Var::A => self.cpu.a as i64,
+            Var::X => self.cpu.x as i64,
+            Var::Y => self.cpu.y as i64,
+            Var::SP => self.cpu.sp as i64,
+            Var::P => self.cpu.status as i64,
+            Var::PC => self.cpu.pc as i64,
+            Var::Scanline => self.ppu.scanline as i64,
+            Var::Cycle => self.ppu.cycle as i64,
+            Var::Frame => self.ppu.frame_count as i64,
+        }
+    }
+
+    fn read(&self, addr: u16) -> u8 {
+        self.peek(addr)
+    }
+}
+
+// Controller button by name, for input scripts.
+pub fn button_mask(name: &str) -> Option<u8> {
+    match name.to_ascii_lowercase().as_str() {
+        "a" => Some(BUTTON_A),
+        "b" => Some(BUTTON_B),
+        "select" => Some(BUTTON_SELECT),
+        "start" => Some(BUTTON_START),
+        "up" => Some(BUTTON_UP),
+        "down" => Some(BUTTON_DOWN),
+        "left" => Some(BUTTON_LEFT),
+        "right" => Some(BUTTON_RIGHT),
+        _ => None,
+    }
+}
+
+// Where emulation is, for timing code from outside the debugger.
+#[derive(Debug, Clone, Copy)]
+pub struct Counters {
+    pub cpu_cycles: u64, // CPU clock cycles since reset
+    pub frame: u64,
+    pub scanline: i16,
+    pub dot: u16,
+}
+
 pub struct NES {
     cpu: CPU,
     ppu: PPU,
+    apu: APU,
     ram: [u8; 2048],
     cartridge: Option<Cartridge>,
     controller1: u8,
-    cycles: u64,
+    auto_buttons: u8,
+    ports: Ports,
+    master: u64, // Master clock cycles since reset; the PPU takes one dot per master_per_dot
+    region: Region,
+    cpu_phase: u8, // Master clock cycles into the current CPU cycle
+    alignment: u8, // PPU dots before the first CPU cycle after reset
+    overclock: u16,
+    history: CpuHistory,
+
+    // Events and the CPU cycles they are counted in. The CPU cycle count is only relative,
+    // and isn't saved: the frame sequence is rebuilt from its FrameTiming after a load.
+    scheduler: Scheduler,
+    cpu_clock: i64, // The current CPU cycle, or the next one between them
+    frame_start: i64, // The CPU cycle the frame sequence last restarted on
+    frame_reset_at: Option<i64>,
 
     // DMA state
     dma_page: u8,
@@ -27,23 +123,40 @@ pub struct NES {
     dma_data: u8,
     dma_transfer: bool,
     dma_dummy: bool,
+
+    debugger: Debugger,
 }
 
 impl NES {
     pub fn new() -> Self {
-        NES {
+        let mut nes = NES {
             cpu: CPU::new(),
             ppu: PPU::new(),
+            apu: APU::new(),
             ram: [0; 2048],
             cartridge: None,
             controller1: 0,
-            cycles: 0,
+            auto_buttons: 0,
+            ports: Ports::default(),
+            master: 0,
+            region: Region::Ntsc,
+            cpu_phase: 0,
+            alignment: 0,
+            overclock: 0,
+            history: CpuHistory::default(),
+            scheduler: Scheduler::new(),
+            cpu_clock: 0,
+            frame_start: 0,
+            frame_reset_at: None,
             dma_page: 0,
             dma_addr: 0,
             dma_data: 0,
             dma_transfer: false,
             dma_dummy: true,
-        }
+            debugger: Debugger::new(),
+        };
+        nes.set_frame_timing(FrameTiming::default());
+        nes
     }
 
     pub fn load_cartridge(&mut self, rom_path: &str) -> Result<(), Box<dyn std::error::Error>> {
@@ -53,12 +166,138 @@ impl NES {
         Ok(())
     }
 
+    // Swaps in a rebuilt ROM and powers on, for --watch. With `keep_ram` the console RAM and,
+    // if the size is unchanged, the PRG RAM carry over from the old build.
+    pub fn reload_cartridge(&mut self, rom_path: &str, keep_ram: bool) -> Result<(), Box<dyn std::error::Error>> {
+        let mut cartridge = Cartridge::new(rom_path)?;
+        if let Some(old) = self.cartridge.as_ref().filter(|old| keep_ram && old.prg_ram.len() == cartridge.prg_ram.len()) {
+            cartridge.prg_ram.copy_from_slice(&old.prg_ram);
+        }
+        let ram = self.ram;
+        self.cartridge = Some(cartridge);
+        self.power_cycle();
+        if keep_ram {
+            self.ram = ram;
+        }
+        Ok(())
+    }
+
     pub fn reset(&mut self) {
         if let Some(cart) = self.cartridge.as_mut() {
-            let mut bus = Bus::new(&mut self.ppu, cart, &mut self.ram);
+            cart.reset();
+            let mut bus = Bus::new(&mut self.ppu, cart, &mut self.ram, &mut self.ports, &mut self.apu);
             self.cpu.reset(&mut bus);
         }
-        self.cycles = 0;
+        let timing = self.frame_timing();
+        self.master = 0;
+        self.cpu_phase = self.start_phase();
+        self.set_frame_timing(timing);
+    }
+
+    fn start_phase(&self) -> u8 {
+        let per_cpu = self.region.master_per_cpu_cycle();
+        (per_cpu - self.alignment * self.region.master_per_dot()) % per_cpu
+    }
+
+    // The clock position in PPU dots and fifths of a dot into the CPU cycle, which is how
+    // save states have it, and carries it across a region change.
+    fn dot_position(&self) -> (u64, u8) {
+        let per_dot = self.region.master_per_dot();
+        (self.master / per_dot as u64, self.cpu_phase * 5 / per_dot)
+    }
+
+    fn set_dot_position(&mut self, dots: u64, fifths: u8) {
+        let per_dot = self.region.master_per_dot();
+        self.master = dots * per_dot as u64;
+        self.cpu_phase = (fifths as u16 * per_dot as u16 / 5) as u8 % self.region.master_per_cpu_cycle();
+    }
+
+    // CPU/PPU clock alignment, 0-2. Which PPU dot the CPU's cycles fall on is random on a
+    // real console; this picks it, from the next reset on.
+    pub fn set_alignment(&mut self, dots: u8) {
+        self.alignment = dots.min(2);
+    }
+
+    // Extra vblank scanlines per frame, for games whose frame logic runs long.
+    pub fn set_overclock(&mut self, lines: u16) {
+        self.overclock = lines;
+        self.ppu.set_extra_lines(lines);
+    }
+
+    pub fn set_region(&mut self, region: Region) {
+        let timing = self.frame_timing();
+        let (dots, fifths) = self.dot_position();
+        self.region = region;
+        self.ppu.set_region(region);
+        self.apu.set_region(region);
+        self.set_dot_position(dots, fifths);
+        self.set_frame_timing(timing);
+    }
+
+    pub fn region(&self) -> Region {
+        self.region
+    }
+
+    // Power off and on: everything but the cartridge and debugger starts over.
+    pub fn power_cycle(&mut self) {
+        let cartridge = self.cartridge.take();
+        let debugger = std::mem::take(&mut self.debugger);
+        let region = self.region;
+        let zapper = self.ports.zapper;
+        let expansion = self.ports.expansion.take();
+        let sample_rate = self.apu.sample_rate();
+        let audio_filters = self.apu.filters();
+        let panning = self.apu.panning();
+        let muted: Vec<Channel> = Channel::ALL.into_iter().filter(|&c| self.apu.muted(c)).collect();
+        let palette = std::mem::take(&mut self.ppu.palette);
+        let track_layers = self.ppu.track_layers;
+        let bus_log = self.ppu.bus_log.take();
+        let (alignment, overclock) = (self.alignment, self.overclock);
+        *self = NES::new();
+        self.cartridge = cartridge;
+        self.debugger = debugger;
+        self.set_region(region);
+        self.set_alignment(alignment);
+        self.set_overclock(overclock);
+        self.ports.zapper = zapper;
+        self.ports.expansion = expansion;
+        self.apu.set_filters(audio_filters);
+        self.apu.set_panning(panning);
+        for channel in muted {
+            self.apu.set_muted(channel, true);
+        }
+        self.apu.set_sample_rate(sample_rate);
+        self.ppu.palette = palette;
+        self.ppu.track_light = zapper.is_some();
+        self.set_track_layers(track_layers);
+        self.ppu.bus_log = bus_log;
+        self.reset();
+    }
+
+    // Hash of the serialized machine, for comparing runs.
+    pub fn state_hash(&self) -> u64 {
+        let mut state = Vec::new();
+        self.save_state(&mut state).expect("writing to a Vec cannot fail");
+        hash::fnv1a(&state)
+    }
+
+    // Registers, counters and the given memory ranges (inclusive), for scripts to check.
+    pub fn snapshot(&self, ranges: &[(u16, u16)]) -> Snapshot {
+        Snapshot::capture(self, ranges)
+    }
+
+    pub fn counters(&self) -> Counters {
+        Counters {
+            cpu_cycles: self.master / self.region.master_per_cpu_cycle() as u64,
+            frame: self.ppu.frame_count,
+            scanline: self.ppu.scanline,
+            dot: self.ppu.cycle,
+        }
+    }
+
+    // Hash of the console's 2KB of RAM.
+    pub fn ram_hash(&self) -> u64 {
+        hash::fnv1a(&self.ram)
     }
 
     pub fn run_frame(&mut self) {
@@ -66,17 +305,71 @@ impl NES {
             return;
         }
 
-        while !self.ppu.frame_complete {
+        let _profile = profiler::scope(Section::Emulation);
+        while !self.ppu.frame_complete && !self.debugger.is_paused() {
             self.clock();
         }
     }
 
     fn clock(&mut self) {
-        let cart = self.cartridge.as_mut().unwrap();
-        
-        self.ppu.step(cart);
+        // A CPU cycle starts during this dot: every 3 dots, 3.2 on PAL
+        let per_dot = self.region.master_per_dot();
+        let cpu_tick = self.cpu_phase < per_dot;
+
+        // Give the debugger a look before the CPU starts a new instruction
+        if cpu_tick && !self.dma_transfer && !self.cpu.dma_request {
+            let _profile = profiler::scope(Section::Debugger);
+            let view = MachineView {
+                cpu: &self.cpu,
+                ppu: &self.ppu,
+                ram: &self.ram,
+                cartridge: self.cartridge.as_ref(),
+            };
+            if self.debugger.should_break(self.cpu.pc, &view) {
+                return;
+            }
+            self.history.record(CpuSnapshot {
+                pc: self.cpu.pc,
+                a: self.cpu.a,
+                x: self.cpu.x,
+                y: self.cpu.y,
+                p: self.cpu.status,
+                sp: self.cpu.sp,
+                scanline: self.ppu.scanline,
+                dot: self.ppu.cycle,
+            });
+            let bank = self.cartridge.as_ref().and_then(|cart| cart.prg_bank(self.cpu.pc));
+            let log_trace = self.debugger.trace_log.as_ref().is_some_and(|log| log.wants(self.cpu.pc, bank, &view));
+            let compare = self.debugger.compare.is_some();
+            if self.debugger.trace || log_trace || compare {
+                let line = debugger::trace_line(self);
+                if compare {
+                    self.debugger.compare_trace(&line);
+                    if self.debugger.is_paused() {
+                        return;
+                    }
+                }
+                if self.debugger.trace {
+                    println!("{}", line);
+                }
+                if log_trace {
+                    self.debugger.write_trace(&line);
+                }
+            }
+        }
+
+        {
+            let _profile = profiler::scope(Section::Ppu);
+            self.ppu.step(self.cartridge.as_mut().unwrap());
+        }
+
+        if cpu_tick {
+            self.apu.step();
+            self.run_events();
+            self.apu.sample();
+            let cart = self.cartridge.as_mut().unwrap();
+            cart.clock();
 
-        if self.cycles % 3 == 0 {
             if self.cpu.dma_request {
                 self.dma_transfer = true;
                 self.dma_page = self.cpu.dma_page;
@@ -86,17 +379,20 @@ impl NES {
             }
 
             if self.dma_transfer {
+                let _profile = profiler::scope(Section::Dma);
+                let odd_dot = self.master / per_dot as u64 % 2 == 1;
                 if self.dma_dummy {
-                    if self.cycles % 2 == 1 {
+                    if odd_dot {
                         self.dma_dummy = false;
                     }
                 } else {
-                    if self.cycles % 2 == 0 {
+                    if !odd_dot {
                         let addr = (self.dma_page as u16) << 8 | self.dma_addr as u16;
-                        let mut bus = Bus::new(&mut self.ppu, cart, &mut self.ram);
+                        let mut bus = Bus::new(&mut self.ppu, cart, &mut self.ram, &mut self.ports, &mut self.apu);
                         self.dma_data = bus.read(addr);
                     } else {
-                        self.ppu.oam[self.dma_addr as usize] = self.dma_data;
+                        // Through $2004, so it starts at OAMADDR and is subject to rendering
+                        self.ppu.write_oam_data(self.dma_data);
                         self.dma_addr = self.dma_addr.wrapping_add(1);
                         if self.dma_addr == 0 {
                             self.dma_transfer = false;
@@ -105,51 +401,224 @@ impl NES {
                     }
                 }
             } else {
-                let mut bus = Bus::new(&mut self.ppu, cart, &mut self.ram);
-                bus.controller1 = self.controller1;
-                self.cpu.step(&mut bus);
+                let _profile = profiler::scope(Section::Cpu);
+                self.ports.controller1 = self.controller1 | self.auto_buttons;
+                // The IRQ line is sampled between instructions; taking it uses up this one
+                let irq = self.apu.irq() || cart.irq();
+                let mut bus = Bus::new(&mut self.ppu, cart, &mut self.ram, &mut self.ports, &mut self.apu);
+                if !(irq && self.cpu.irq(&mut bus)) {
+                    self.cpu.step(&mut bus);
+                }
+                if let Some(delay) = self.apu.take_frame_write() {
+                    self.schedule_frame_reset(self.cpu_clock + delay as i64);
+                    self.check_frame_overrun(self.cpu_clock + 1);
+                }
             }
+            self.cpu_clock += 1;
         }
 
         if self.ppu.nmi_occurred {
             self.ppu.nmi_occurred = false;
-            let mut bus = Bus::new(&mut self.ppu, cart, &mut self.ram);
+            let cart = self.cartridge.as_mut().unwrap();
+            let mut bus = Bus::new(&mut self.ppu, cart, &mut self.ram, &mut self.ports, &mut self.apu);
             self.cpu.nmi(&mut bus);
         }
 
-        self.cycles += 1;
+        self.master += per_dot as u64;
+        self.cpu_phase = (self.cpu_phase + per_dot) % self.region.master_per_cpu_cycle();
+    }
+
+    // The master cycle of the PPU dot that a CPU cycle at or after the current one starts in.
+    // The current one starts in this dot, or the first one after it where cpu_phase wraps.
+    fn cpu_cycle_master(&self, cpu_cycle: i64) -> u64 {
+        let per_dot = self.region.master_per_dot() as i64;
+        let per_cpu = self.region.master_per_cpu_cycle() as i64;
+        let phase = self.cpu_phase as i64;
+        // CPU cycles start where the phase, counted without wrapping, passes a multiple of per_cpu
+        let passed = (phase - per_dot).div_euclid(per_cpu) + cpu_cycle - self.cpu_clock + 1;
+        let dots = -(phase - passed * per_cpu).div_euclid(per_dot);
+        (self.master as i64 + dots * per_dot) as u64
     }
 
-    pub fn handle_key_down(&mut self, keycode: sdl2::keyboard::Keycode) {
-        use sdl2::keyboard::Keycode;
-        match keycode {
-            Keycode::Z => self.controller1 |= BUTTON_A,
-            Keycode::X => self.controller1 |= BUTTON_B,
-            Keycode::A => self.controller1 |= BUTTON_SELECT,
-            Keycode::S => self.controller1 |= BUTTON_START,
-            Keycode::Up => self.controller1 |= BUTTON_UP,
-            Keycode::Down => self.controller1 |= BUTTON_DOWN,
-            Keycode::Left => self.controller1 |= BUTTON_LEFT,
-            Keycode::Right => self.controller1 |= BUTTON_RIGHT,
-            _ => {}
+    fn run_events(&mut self) {
+        while let Some(event) = self.scheduler.pop_due(self.master) {
+            match event {
+                Event::FrameReset => {
+                    self.frame_reset_at = None;
+                    self.apu.reset_frame_sequence();
+                    self.start_frame_sequence(self.cpu_clock, 0);
+                }
+                Event::FrameStep(cycle) => {
+                    if self.apu.frame_step(cycle) {
+                        self.start_frame_sequence(self.cpu_clock, 0);
+                    }
+                }
+            }
         }
     }
 
-    pub fn handle_key_up(&mut self, keycode: sdl2::keyboard::Keycode) {
-        use sdl2::keyboard::Keycode;
-        match keycode {
-            Keycode::Z => self.controller1 &= !BUTTON_A,
-            Keycode::X => self.controller1 &= !BUTTON_B,
-            Keycode::A => self.controller1 &= !BUTTON_SELECT,
-            Keycode::S => self.controller1 &= !BUTTON_START,
-            Keycode::Up => self.controller1 &= !BUTTON_UP,
-            Keycode::Down => self.controller1 &= !BUTTON_DOWN,
-            Keycode::Left => self.controller1 &= !BUTTON_LEFT,
-            Keycode::Right => self.controller1 &= !BUTTON_RIGHT,
-            _ => {}
+    // Schedules the steps of a sequence that started on CPU cycle `start`, from after `cycle`.
+    fn start_frame_sequence(&mut self, start: i64, cycle: u32) {
+        self.frame_start = start;
+        self.scheduler.cancel(|event| matches!(event, Event::FrameStep(_)));
+        for step in self.apu.frame_step_cycles() {
+            if step > cycle {
+                self.scheduler.schedule(self.cpu_cycle_master(start + step as i64), Event::FrameStep(step));
+            }
         }
     }
 
+    fn schedule_frame_reset(&mut self, cpu_cycle: i64) {
+        self.frame_reset_at = Some(cpu_cycle);
+        self.scheduler.cancel(|event| event == Event::FrameReset);
+        self.scheduler.schedule(self.cpu_cycle_master(cpu_cycle), Event::FrameReset);
+    }
+
+    // A $4017 write to 4-step mode late in a 5-step sequence restarts it on the next cycle,
+    // which isn't one of the scheduled steps.
+    fn check_frame_overrun(&mut self, cpu_cycle: i64) {
+        let cycle = (cpu_cycle - self.frame_start) as u32;
+        if cycle >= self.apu.frame_end() && !self.apu.frame_step_cycles().contains(&cycle) {
+            self.scheduler.schedule(self.cpu_cycle_master(cpu_cycle), Event::FrameStep(cycle));
+        }
+    }
+
+    // Between CPU cycles, as saved.
+    fn frame_timing(&self) -> FrameTiming {
+        FrameTiming {
+            cycle: (self.cpu_clock - 1 - self.frame_start) as u32,
+            reset: self.frame_reset_at.map_or(0, |at| (at - self.cpu_clock + 1) as u8),
+        }
+    }
+
+    fn set_frame_timing(&mut self, timing: FrameTiming) {
+        self.scheduler.clear();
+        self.start_frame_sequence(self.cpu_clock - 1 - timing.cycle as i64, timing.cycle);
+        self.frame_reset_at = None;
+        if timing.reset > 0 {
+            self.schedule_frame_reset(self.cpu_clock + timing.reset as i64 - 1);
+        }
+        self.check_frame_overrun(self.cpu_clock);
+    }
+
+    // Buttons held on the keyboard or a gamepad, as mapped by the input profile
+    pub fn press_buttons(&mut self, buttons: u8) {
+        self.controller1 |= buttons;
+    }
+
+    pub fn release_buttons(&mut self, buttons: u8) {
+        self.controller1 &= !buttons;
+    }
+
+    pub fn set_palette(&mut self, palette: Palette) {
+        self.ppu.palette = palette;
+    }
+
+    // Skipped frames run normally but leave the previous picture in the frame buffer.
+    pub fn set_skip_output(&mut self, skip: bool) {
+        self.ppu.skip_output = skip;
+    }
+
+    // Buttons pressed by input macros and scripts, on top of the keyboard
+    pub fn set_auto_buttons(&mut self, buttons: u8) {
+        self.auto_buttons = buttons;
+    }
+
+    // Buttons the game sees this frame: held ones plus those from macros and scripts
+    pub fn controller1(&self) -> u8 {
+        self.controller1 | self.auto_buttons
+    }
+
+    pub fn set_controller1(&mut self, buttons: u8) {
+        self.controller1 = buttons;
+    }
+
+    // Per-pixel layer information for the debugger and the layer view, filled in from the
+    // next frame on.
+    pub fn set_track_layers(&mut self, on: bool) {
+        self.ppu.track_layers = on;
+        self.ppu.layers = if on { vec![LayerPixel::default(); 256 * 240] } else { Vec::new() };
+    }
+
+    pub fn layers(&self) -> Option<&[LayerPixel]> {
+        self.ppu.track_layers.then_some(&self.ppu.layers[..])
+    }
+
+    // Logs PPU memory accesses over the log's frame range, then closes it.
+    pub fn set_ppu_bus_log(&mut self, log: Option<PpuBusLog>) {
+        self.ppu.bus_log = log;
+    }
+
+    // Plugs a Zapper into port 2. The PPU starts keeping the light history it needs.
+    pub fn connect_zapper(&mut self) {
+        self.ports.zapper = Some(Zapper::default());
+        self.ppu.track_light = true;
+    }
+
+    pub fn set_zapper(&mut self, aim: Option<(i16, i16)>, trigger: bool) {
+        if let Some(zapper) = self.ports.zapper.as_mut() {
+            zapper.aim = aim;
+            zapper.trigger = trigger;
+        }
+    }
+
+    // Plugs a device into the Famicom expansion port, replacing any other.
+    pub fn connect_expansion(&mut self, device: Box<dyn ExpansionDevice>) {
+        self.ports.expansion = Some(device);
+    }
+
+    pub fn expansion_mut(&mut self) -> Option<&mut Box<dyn ExpansionDevice>> {
+        self.ports.expansion.as_mut()
+    }
+
+    // Audio samples per second to produce, 0 for none.
+    pub fn set_sample_rate(&mut self, rate: u32) {
+        self.apu.set_sample_rate(rate);
+    }
+
+    // Fine adjustment of the sample rate, for keeping an audio buffer level.
+    pub fn adjust_sample_rate(&mut self, ratio: f64) {
+        self.apu.adjust_rate(ratio);
+    }
+
+    // The console's audio filters, see apu.rs.
+    pub fn set_audio_filters(&mut self, on: bool) {
+        self.apu.set_filters(on);
+    }
+
+    pub fn audio_filters(&self) -> bool {
+        self.apu.filters()
+    }
+
+    // Stereo placement of each APU channel, -1.0 (left) to 1.0 (right), or None for mono.
+    pub fn set_audio_panning(&mut self, pan: Option<[f32; 5]>) {
+        self.apu.set_panning(pan);
+    }
+
+    // 2 when take_samples interleaves left and right, else 1.
+    pub fn audio_channels(&self) -> usize {
+        self.apu.output_channels()
+    }
+
+    // Channel mutes, see APU::set_muted and APU::solo.
+    pub fn set_channel_muted(&mut self, channel: Channel, muted: bool) {
+        self.apu.set_muted(channel, muted);
+    }
+
+    pub fn channel_muted(&self, channel: Channel) -> bool {
+        self.apu.muted(channel)
+    }
+
+    pub fn solo_channel(&mut self, channel: Channel) {
+        self.apu.solo(channel);
+    }
+
+    // Mixed audio produced since the last call, centered on 0.0 by the filters.
+    // Interleaved left and right when panned.
+    pub fn take_samples(&mut self) -> Vec<f32> {
+        self.apu.take_samples()
+    }
+
     pub fn frame_ready(&self) -> bool {
         self.ppu.frame_complete
     }
@@ -159,6 +628,106 @@ impl NES {
     }
 
     pub fn frame_done(&mut self) {
+        if self.ppu.frame_complete && (self.debugger.trace_log.is_some() || self.debugger.compare.is_some()) {
+            let line = trace::frame_line(self.ppu.frame_count, hash::fnv1a(self.get_frame_buffer()));
+            self.debugger.compare_trace(&line);
+            self.debugger.write_trace(&line);
+        }
+        if self.ppu.bus_log.as_ref().is_some_and(|log| log.finished(self.ppu.frame_count)) {
+            self.ppu.bus_log = None;
+        }
         self.ppu.frame_complete = false;
     }
+
+    // Serializes the whole machine except the cartridge ROM, debugger and live controller input.
+    pub fn save_state(&self, w: &mut dyn Write) -> io::Result<()> {
+        self.cpu.save_state(w)?;
+        self.ppu.save_state(w)?;
+        self.apu.save_state(w, self.frame_timing())?;
+        if let Some(cart) = self.cartridge.as_ref() {
+            cart.save_state(w)?;
+        }
+        w.write_all(&self.ram)?;
+        let (dots, fifths) = self.dot_position();
+        w.write_u64::<LittleEndian>(dots)?;
+        w.write_all(&[
+            fifths, self.dma_page, self.dma_addr, self.dma_data,
+            self.dma_transfer as u8, self.dma_dummy as u8,
+        ])?;
+        self.ports.save_state(w)
+    }
+
+    pub fn load_state(&mut self, r: &mut dyn Read) -> io::Result<()> {
+        self.cpu.load_state(r)?;
+        self.ppu.load_state(r)?;
+        let timing = self.apu.load_state(r)?;
+        if let Some(cart) = self.cartridge.as_mut() {
+            cart.load_state(r)?;
+        }
+        r.read_exact(&mut self.ram)?;
+        let dots = r.read_u64::<LittleEndian>()?;
+        let mut rest = [0u8; 6];
+        r.read_exact(&mut rest)?;
+        let [fifths, dma_page, dma_addr, dma_data, dma_transfer, dma_dummy] = rest;
+        self.set_dot_position(dots, fifths);
+        self.dma_page = dma_page;
+        self.dma_addr = dma_addr;
+        self.dma_data = dma_data;
+        self.dma_transfer = dma_transfer != 0;
+        self.dma_dummy = dma_dummy != 0;
+        self.set_frame_timing(timing);
+        self.ports.load_state(r)
+    }
+
+    pub fn cpu(&self) -> &CPU {
+        &self.cpu
+    }
+
+    pub fn ppu(&self) -> &PPU {
+        &self.ppu
+    }
+
+    pub fn apu(&self) -> &APU {
+        &self.apu
+    }
+
+    pub fn cartridge(&self) -> Option<&Cartridge> {
+        self.cartridge.as_ref()
+    }
+
+    pub fn cartridge_mut(&mut self) -> Option<&mut Cartridge> {
+        self.cartridge.as_mut()
+    }
+
+    // The last instructions executed, for crash reports
+    pub fn history(&self) -> &CpuHistory {
+        &self.history
+    }
+
+    pub fn debugger(&self) -> &Debugger {
+        &self.debugger
+    }
+
+    pub fn debugger_mut(&mut self) -> &mut Debugger {
+        &mut self.debugger
+    }
+
+    pub fn view(&self) -> MachineView<'_> {
+        MachineView {
+            cpu: &self.cpu,
+            ppu: &self.ppu,
+            ram: &self.ram,
+            cartridge: self.cartridge.as_ref(),
+        }
+    }
+
+    pub fn peek(&self, addr: u16) -> u8 {
+        self.view().peek(addr)
+    }
+}
+
+impl Default for NES {
+    fn default() -> Self {
+        Self::new()
+    }
 }
@@ -0,0 +1,196 @@
+use std::time::{Duration, Instant};
+
+// On-screen display: a 5x7 bitmap font drawn into an RGB24 256x240 frame.
+// The frontend draws onto a copy of the PPU output, so overlays never leak into emulation.
+
+pub const WIDTH: usize = 256;
+pub const HEIGHT: usize = 240;
+
+pub const CHAR_WIDTH: usize = 6;
+pub const CHAR_HEIGHT: usize = 8;
+
+pub const WHITE: (u8, u8, u8) = (255, 255, 255);
+pub const YELLOW: (u8, u8, u8) = (255, 220, 64);
+pub const GREEN: (u8, u8, u8) = (96, 232, 96);
+
+// Rows of each glyph, bit 4 is the leftmost pixel.
+fn glyph(c: char) -> [u8; 7] {
+    match c.to_ascii_uppercase() {
+        '0' => [0x0E, 0x11, 0x13, 0x15, 0x19, 0x11, 0x0E],
+        '1' => [0x04, 0x0C, 0x04, 0x04, 0x04, 0x04, 0x0E],
+        '2' => [0x0E, 0x11, 0x01, 0x02, 0x04, 0x08, 0x1F],
+        '3' => [0x1F, 0x02, 0x04, 0x02, 0x01, 0x11, 0x0E],
+        '4' => [0x02, 0x06, 0x0A, 0x12, 0x1F, 0x02, 0x02],
+        '5' => [0x1F, 0x10, 0x1E, 0x01, 0x01, 0x11, 0x0E],
+        '6' => [0x06, 0x08, 0x10, 0x1E, 0x11, 0x11, 0x0E],
+        '7' => [0x1F, 0x01, 0x02, 0x04, 0x08, 0x08, 0x08],
+        '8' => [0x0E, 0x11, 0x11, 0x0E, 0x11, 0x11, 0x0E],
+        '9' => [0x0E, 0x11, 0x11, 0x0F, 0x01, 0x02, 0x0C],
+        'A' => [0x0E, 0x11, 0x11, 0x11, 0x1F, 0x11, 0x11],
+        'B' => [0x1E, 0x11, 0x11, 0x1E, 0x11, 0x11, 0x1E],
+        'C' => [0x0E, 0x11, 0x10, 0x10, 0x10, 0x11, 0x0E],
+        'D' => [0x1C, 0x12, 0x11, 0x11, 0x11, 0x12, 0x1C],
+        'E' => [0x1F, 0x10, 0x10, 0x1E, 0x10, 0x10, 0x1F],
+        'F' => [0x1F, 0x10, 0x10, 0x1E, 0x10, 0x10, 0x10],
+        'G' => [0x0E, 0x11, 0x10, 0x17, 0x11, 0x11, 0x0F],
+        'H' => [0x11, 0x11, 0x11, 0x1F, 0x11, 0x11, 0x11],
+        'I' => [0x0E, 0x04, 0x04, 0x04, 0x04, 0x04, 0x0E],
+        'J' => [0x07, 0x02, 0x02, 0x02, 0x02, 0x12, 0x0C],
+        'K' => [0x11, 0x12, 0x14, 0x18, 0x14, 0x12, 0x11],
+        'L' => [0x10, 0x10, 0x10, 0x10, 0x10, 0x10, 0x1F],
+        'M' => [0x11, 0x1B, 0x15, 0x15, 0x11, 0x11, 0x11],
+        'N' => [0x11, 0x11, 0x19, 0x15, 0x13, 0x11, 0x11],
+        'O' => [0x0E, 0x11, 0x11, 0x11, 0x11, 0x11, 0x0E],
+        'P' => [0x1E, 0x11, 0x11, 0x1E, 0x10, 0x10, 0x10],
+        'Q' => [0x0E, 0x11, 0x11, 0x11, 0x15, 0x12, 0x0D],
+        'R' => [0x1E, 0x11, 0x11, 0x1E, 0x14, 0x12, 0x11],
+        'S' => [0x0F, 0x10, 0x10, 0x0E, 0x01, 0x01, 0x1E],
+        'T' => [0x1F, 0x04, 0x04, 0x04, 0x04, 0x04, 0x04],
+        'U' => [0x11, 0x11, 0x11, 0x11, 0x11, 0x11, 0x0E],
+        'V' => [0x11, 0x11, 0x11, 0x11, 0x11, 0x0A, 0x04],
+        'W' => [0x11, 0x11, 0x11, 0x15, 0x15, 0x15, 0x0A],
+        'X' => [0x11, 0x11, 0x0A, 0x04, 0x0A, 0x11, 0x11],
+        'Y' => [0x11, 0x11, 0x11, 0x0A, 0x04, 0x04, 0x04],
+        'Z' => [0x1F, 0x01, 0x02, 0x04, 0x08, 0x10, 0x1F],
+        ' ' => [0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00],
+        ':' => [0x00, 0x0C, 0x0C, 0x00, 0x0C, 0x0C, 0x00],
+        '.' => [0x00, 0x00, 0x00, 0x00, 0x00, 0x0C, 0x0C],
+        ',' => [0x00, 0x00, 0x00, 0x00, 0x0C, 0x04, 0x08],
+        ';' => [0x00, 0x0C, 0x0C, 0x00, 0x0C, 0x04, 0x08],
+        '-' => [0x00, 0x00, 0x00, 0x1F, 0x00, 0x00, 0x00],
+        '+' => [0x00, 0x04, 0x04, 0x1F, 0x04, 0x04, 0x00],
+        '=' => [0x00, 0x00, 0x1F, 0x00, 0x1F, 0x00, 0x00],
+        '/' => [0x00, 0x01, 0x02, 0x04, 0x08, 0x10, 0x00],
+        '!' => [0x04, 0x04, 0x04, 0x04, 0x04, 0x00, 0x04],
+        '(' => [0x02, 0x04, 0x08, 0x08, 0x08, 0x04, 0x02],
+        ')' => [0x08, 0x04, 0x02, 0x02, 0x02, 0x04, 0x08],
+        '[' => [0x0E, 0x08, 0x08, 0x08, 0x08, 0x08, 0x0E],
+        ']' => [0x0E, 0x02, 0x02, 0x02, 0x02, 0x02, 0x0E],
+        '<' => [0x02, 0x04, 0x08, 0x10, 0x08, 0x04, 0x02],
+        '>' => [0x08, 0x04, 0x02, 0x01, 0x02, 0x04, 0x08],
+        '$' => [0x04, 0x0F, 0x14, 0x0E, 0x05, 0x1E, 0x04],
+        '#' => [0x0A, 0x0A, 0x1F, 0x0A, 0x1F, 0x0A, 0x0A],
+        '%' => [0x18, 0x19, 0x02, 0x04, 0x08, 0x13, 0x03],
+        '&' => [0x0C, 0x12, 0x14, 0x08, 0x15, 0x12, 0x0D],
+        '*' => [0x00, 0x04, 0x15, 0x0E, 0x15, 0x04, 0x00],
+        '_' => [0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x1F],
+        '|' => [0x04, 0x04, 0x04, 0x04, 0x04, 0x04, 0x04],
+        '\'' => [0x0C, 0x04, 0x08, 0x00, 0x00, 0x00, 0x00],
+        '"' => [0x0A, 0x0A, 0x00, 0x00, 0x00, 0x00, 0x00],
+        _ => [0x0E, 0x11, 0x01, 0x02, 0x04, 0x00, 0x04], // '?'
+    }
+}
+
+fn put_pixel(frame: &mut [u8], x: usize, y: usize, color: (u8, u8, u8)) {
+    if x < WIDTH && y < HEIGHT {
+        let i = (y * WIDTH + x) * 3;
+        frame[i] = color.0;
+        frame[i + 1] = color.1;
+        frame[i + 2] = color.2;
+    }
+}
+
+pub fn text_width(text: &str) -> usize {
+    text.chars().count() * CHAR_WIDTH
+}
+
+// Draws text with a one pixel drop shadow so it stays readable on any background.
+pub fn draw_text(frame: &mut [u8], x: usize, y: usize, text: &str, color: (u8, u8, u8)) {
+    for (i, c) in text.chars().enumerate() {
+        let cx = x + i * CHAR_WIDTH;
+        for (row, bits) in glyph(c).iter().enumerate() {
+            for col in 0..5 {
+                if bits & (0x10 >> col) != 0 {
+                    put_pixel(frame, cx + col + 1, y + row + 1, (0, 0, 0));
+                    put_pixel(frame, cx + col, y + row, color);
+                }
+            }
+        }
+    }
+}
+
+// Darkens a rectangle to give overlay text a backdrop.
+pub fn shade_rect(frame: &mut [u8], x: usize, y: usize, w: usize, h: usize) {
+    for py in y..(y + h).min(HEIGHT) {
+        for px in x..(x + w).min(WIDTH) {
+            let i = (py * WIDTH + px) * 3;
+            for channel in &mut frame[i..i + 3] {
+                *channel /= 3;
+            }
+        }
+    }
+}
+
+// A solid rectangle, clipped to the frame.
+pub fn fill_rect(frame: &mut [u8], x: usize, y: usize, w: usize, h: usize, color: (u8, u8, u8)) {
+    for py in y..y + h {
+        for px in x..x + w {
+            put_pixel(frame, px, py, color);
+        }
+    }
+}
+
+// Copies an RGB24 image of the given size into the frame.
+pub fn blit(frame: &mut [u8], x: usize, y: usize, width: usize, height: usize, pixels: &[u8]) {
+    for row in 0..height {
+        for col in 0..width {
+            let i = (row * width + col) * 3;
+            put_pixel(frame, x + col, y + row, (pixels[i], pixels[i + 1], pixels[i + 2]));
+        }
+    }
+}
+
+// Splits text into lines of at most `columns` characters, at spaces where it can, and
+// mid-word for paths too long for a line.
+pub fn wrap(text: &str, columns: usize) -> Vec<String> {
+    let mut lines = Vec::new();
+    let mut line = String::new();
+    for word in text.split_whitespace() {
+        let needed = if line.is_empty() { word.chars().count() } else { line.chars().count() + 1 + word.chars().count() };
+        if needed > columns && !line.is_empty() {
+            lines.push(std::mem::take(&mut line));
+        }
+        if !line.is_empty() {
+            line.push(' ');
+        }
+        for c in word.chars() {
+            if line.chars().count() == columns {
+                lines.push(std::mem::take(&mut line));
+            }
+            line.push(c);
+        }
+    }
+    if !line.is_empty() {
+        lines.push(line);
+    }
+    lines
+}
+
+// Text on a shaded box, the usual way OSD messages are shown.
+pub fn draw_label(frame: &mut [u8], x: usize, y: usize, text: &str, color: (u8, u8, u8)) {
+    shade_rect(frame, x, y, text_width(text) + 3, CHAR_HEIGHT + 2);
+    draw_text(frame, x + 1, y + 1, text, color);
+}
+
+// Transient status message in the bottom-left corner, e.g. "State saved". Timed by the
+// clock rather than frames, since a paused window redraws only now and then.
+pub struct Notice {
+    text: String,
+    until: Instant,
+}
+
+const NOTICE_TIME: Duration = Duration::from_secs(2);
+
+impl Notice {
+    pub fn new(text: impl Into<String>) -> Self {
+        Notice { text: text.into(), until: Instant::now() + NOTICE_TIME }
+    }
+
+    pub fn draw(&self, frame: &mut [u8]) {
+        draw_label(frame, 2, HEIGHT - CHAR_HEIGHT - 4, &self.text, WHITE);
+    }
+
+    pub fn expired(&self) -> bool {
+        Instant::now() >= self.until
+    }
+}
@@ -0,0 +1,69 @@
+use std::time::{Duration, Instant};
+
+// Frame pacer that sleeps for most of the wait and spins for the last stretch, since
+// thread::sleep routinely overshoots by a millisecond or more. Deadlines advance by
+// exactly one period each frame, so rounding never accumulates into drift.
+//
+// The frontend waits at the top of its loop and then polls input, emulates and presents.
+// To keep input lag low the wait ends only as long before the deadline as that work has
+// been taking, so input is read as late as possible and the frame still goes out on time.
+pub struct FramePacer {
+    period: Duration,
+    deadline: Instant,
+    work: Duration,
+}
+
+// Time left before the deadline that is spent spinning rather than sleeping.
+const SPIN_MARGIN: Duration = Duration::from_micros(1500);
+
+// Falling further behind than this resets the schedule instead of racing to catch up.
+const MAX_LAG_FRAMES: u32 = 4;
+
+// Slack added to the measured work time, for scheduler jitter and the odd slow frame.
+const WORK_MARGIN: Duration = Duration::from_millis(2);
+
+impl FramePacer {
+    pub fn new(frames_per_second: f64) -> Self {
+        let period = Duration::from_secs_f64(1.0 / frames_per_second);
+        FramePacer { period, deadline: Instant::now() + period, work: Duration::ZERO }
+    }
+
+    // Records how long polling, emulating and presenting a frame took. Spikes are taken at
+    // once and decay slowly, so one fast frame doesn't make the next one miss its slot.
+    pub fn record_work(&mut self, work: Duration) {
+        self.work = work.max(self.work * 15 / 16);
+    }
+
+    // How long before the deadline the wait ends. Never more than half a frame, so a
+    // debugger stop or a dragged window can't push the wait to nothing.
+    fn lead(&self) -> Duration {
+        (self.work + WORK_MARGIN).min(self.period / 2)
+    }
+
+    // Blocks until it is time to start work on the next frame. Returns true if the last
+    // frame finished late, which frame skipping uses as its signal.
+    pub fn wait(&mut self) -> bool {
+        let now = Instant::now();
+        let wake = self.deadline - self.lead();
+        let late = now > wake;
+
+        if now > self.deadline + self.period * MAX_LAG_FRAMES {
+            // Paused in the debugger, a dragged window or a very slow machine
+            self.deadline = now + self.period;
+            return true;
+        }
+
+        if !late {
+            let remaining = wake - now;
+            if remaining > SPIN_MARGIN {
+                std::thread::sleep(remaining - SPIN_MARGIN);
+            }
+            while Instant::now() < wake {
+                std::hint::spin_loop();
+            }
+        }
+
+        self.deadline += self.period;
+        late
+    }
+}
@@ -0,0 +1,112 @@
+use std::fs;
+
+// Maps the PPU's 64 color indices to RGB for display. Games only ever see palette RAM, so
+// swapping the display palette changes how the picture looks and nothing else.
+
+pub const NTSC: [(u8, u8, u8); 64] = [
+    (84, 84, 84), (0, 30, 116), (8, 16, 144), (48, 0, 136), (68, 0, 100), (92, 0, 48), (84, 4, 0), (60, 24, 0),
+    (32, 42, 0), (8, 58, 0), (0, 64, 0), (0, 60, 40), (0, 50, 88), (0, 0, 0), (0, 0, 0), (0, 0, 0),
+    (152, 150, 152), (8, 76, 196), (48, 50, 236), (92, 30, 228), (136, 20, 176), (160, 20, 100), (152, 34, 32),
+    (120, 60, 0), (84, 90, 0), (40, 114, 0), (8, 124, 0), (0, 118, 40), (0, 102, 120), (0, 0, 0), (0, 0, 0),
+    (0, 0, 0), (236, 238, 236), (76, 154, 236), (120, 124, 236), (176, 98, 236), (228, 84, 236), (236, 88, 180),
+    (236, 106, 100), (212, 136, 32), (160, 170, 0), (116, 196, 0), (76, 208, 32), (56, 204, 108), (56, 180, 220),
+    (60, 60, 60), (0, 0, 0), (0, 0, 0), (236, 238, 236), (168, 204, 236), (188, 188, 236), (212, 178, 236),
+    (236, 174, 236), (236, 174, 212), (236, 180, 176), (228, 196, 144), (204, 210, 120), (180, 222, 120),
+    (168, 226, 144), (152, 226, 180), (160, 214, 228), (160, 162, 160), (0, 0, 0), (0, 0, 0),
+];
+
+// Emphasis bits ($2001 bits 5-7, red/green/blue on NTSC) each darken the other two channels
+// by about a quarter. The blacks in columns $E and $F stay black. `bits` has red, green and
+// blue emphasis in bits 0-2.
+pub fn emphasize(color: (u8, u8, u8), index: u8, bits: u8) -> (u8, u8, u8) {
+    if index & 0x0E == 0x0E {
+        return color;
+    }
+    let dim = |value: u8, keep: u8| {
+        let times = (bits & !keep).count_ones();
+        (0..times).fold(value as u16, |v, _| v * 191 / 256) as u8
+    };
+    (dim(color.0, 0b001), dim(color.1, 0b010), dim(color.2, 0b100))
+}
+
+// Size of a .pal file as written by FCEUX, Mesen and most palette editors. Files with the
+// emphasis variants appended (8 x 192 bytes) are accepted and the extra colors ignored.
+pub const PAL_FILE_SIZE: usize = 64 * 3;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Deficiency {
+    Protanopia,
+    Deuteranopia,
+    Tritanopia,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Palette {
+    pub name: String,
+    colors: [(u8, u8, u8); 64],
+}
+
+impl Default for Palette {
+    fn default() -> Self {
+        Palette { name: "default".to_string(), colors: NTSC }
+    }
+}
+
+impl Palette {
+    // A built-in palette name or the path of a .pal file.
+    pub fn load(name: &str) -> Result<Self, Box<dyn std::error::Error>> {
+        let deficiency = match name.to_ascii_lowercase().as_str() {
+            "default" | "ntsc" => return Ok(Palette::default()),
+            "protanopia" => Deficiency::Protanopia,
+            "deuteranopia" => Deficiency::Deuteranopia,
+            "tritanopia" => Deficiency::Tritanopia,
+            _ => return Palette::from_file(name),
+        };
+        Ok(Palette { name: name.to_ascii_lowercase(), colors: NTSC.map(|color| daltonize(color, deficiency)) })
+    }
+
+    fn from_file(path: &str) -> Result<Self, Box<dyn std::error::Error>> {
+        let data = fs::read(path).map_err(|e| format!("{}: {}", path, e))?;
+        if data.len() < PAL_FILE_SIZE || data.len() % PAL_FILE_SIZE != 0 {
+            return Err(format!(
+                "{}: not a palette (expected {} bytes, found {})",
+                path, PAL_FILE_SIZE, data.len()
+            ).into());
+        }
+        let mut colors = [(0, 0, 0); 64];
+        for (color, rgb) in colors.iter_mut().zip(data.chunks_exact(3)) {
+            *color = (rgb[0], rgb[1], rgb[2]);
+        }
+        Ok(Palette { name: path.to_string(), colors })
+    }
+
+    pub fn color(&self, index: u8) -> (u8, u8, u8) {
+        self.colors[index as usize & 0x3F]
+    }
+
+    // The palette as a .pal file, as a starting point for editing in a palette editor.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        self.colors.iter().flat_map(|&(r, g, b)| [r, g, b]).collect()
+    }
+}
+
+// Shifts the color information a viewer with the given deficiency cannot see into channels
+// they can (the daltonize method of Fidaner, Lin and Ozguven): simulate the deficiency in
+// LMS space, and spread the lost difference over green and blue.
+fn daltonize((r, g, b): (u8, u8, u8), deficiency: Deficiency) -> (u8, u8, u8) {
+    let (r, g, b) = (r as f64, g as f64, b as f64);
+    let l = 17.8824 * r + 43.5161 * g + 4.11935 * b;
+    let m = 3.45565 * r + 27.1554 * g + 3.86714 * b;
+    let s = 0.0299566 * r + 0.184309 * g + 1.46709 * b;
+    let (l, m, s) = match deficiency {
+        Deficiency::Protanopia => (2.02344 * m - 2.52581 * s, m, s),
+        Deficiency::Deuteranopia => (l, 0.494207 * l + 1.24827 * s, s),
+        Deficiency::Tritanopia => (l, m, -0.395913 * l + 0.801109 * m),
+    };
+    let sim_r = 0.0809444479 * l - 0.130504409 * m + 0.116721066 * s;
+    let sim_g = -0.0102485335 * l + 0.0540193266 * m - 0.113614708 * s;
+    let sim_b = -0.000365296938 * l - 0.00412161469 * m + 0.693511405 * s;
+    let (err_r, err_g, err_b) = (r - sim_r, g - sim_g, b - sim_b);
+    let clamp = |v: f64| v.round().clamp(0.0, 255.0) as u8;
+    (clamp(r), clamp(g + 0.7 * err_r + err_g), clamp(b + 0.7 * err_r + err_b))
+}
@@ -0,0 +1,88 @@
+use std::collections::VecDeque;
+
+use crate::apu::{ApuState, PulseState};
+use crate::osd;
+use crate::region::Region;
+
+// Recent APU notes as a scrolling piano roll (F4 a second time), like a tracker's view of a
+// song: pitch goes up the screen a semitone per row, time goes left one frame per column with
+// the current frame at the right edge, and each channel has its own color, brighter when
+// louder. Pitch comes from the channel's timer period and the region's CPU clock, so a
+// vibrato or pitch bend shows as the sweep it is. The C of each octave gets a grid line.
+//
+// Frames are recorded only while the roll is open.
+
+// A1 (55 Hz) is about the longest period a pulse channel can play; A7 is where tunes stop
+const LOWEST: f64 = 33.0;
+const HIGHEST: f64 = 105.0;
+const ROW: usize = 3;
+const BOTTOM: usize = 228;
+const LEFT: usize = 16; // Room for the octave labels
+const COLUMNS: usize = osd::WIDTH - LEFT;
+const COLORS: [(u8, u8, u8); 2] = [(255, 112, 96), (96, 176, 255)];
+const GRID: (u8, u8, u8) = (56, 56, 56);
+
+#[derive(Clone, Copy)]
+struct Note {
+    pitch: f64, // MIDI note number, fractional between semitones
+    volume: u8,
+}
+
+pub struct PianoRoll {
+    frames: VecDeque<[Option<Note>; 2]>,
+}
+
+impl Default for PianoRoll {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// What a pulse channel is sounding, None when it is silent.
+fn note(pulse: &PulseState, region: Region) -> Option<Note> {
+    let volume = if pulse.constant { pulse.volume } else { pulse.decay };
+    if !pulse.enabled || pulse.length == 0 || pulse.sweep_muted || volume == 0 {
+        return None;
+    }
+    let hz = region.cpu_clock() / (16.0 * (pulse.period as f64 + 1.0));
+    Some(Note { pitch: 69.0 + 12.0 * (hz / 440.0).log2(), volume })
+}
+
+fn dim(color: (u8, u8, u8), volume: u8) -> (u8, u8, u8) {
+    let scale = |c: u8| (c as u32 * (6 + volume as u32) / 21) as u8;
+    (scale(color.0), scale(color.1), scale(color.2))
+}
+
+impl PianoRoll {
+    pub fn new() -> Self {
+        PianoRoll { frames: VecDeque::with_capacity(COLUMNS) }
+    }
+
+    // Once per emulated frame.
+    pub fn update(&mut self, state: &ApuState, region: Region) {
+        if self.frames.len() == COLUMNS {
+            self.frames.pop_front();
+        }
+        self.frames.push_back(state.pulses.map(|pulse| note(&pulse, region)));
+    }
+
+    pub fn draw(&self, frame: &mut [u8]) {
+        osd::shade_rect(frame, 0, 0, osd::WIDTH, osd::HEIGHT);
+        let row_y = |pitch: f64| BOTTOM - ((pitch - LOWEST) * ROW as f64).round() as usize;
+        for octave in 2..=7 {
+            let y = row_y(12.0 * (octave + 1) as f64);
+            osd::fill_rect(frame, LEFT, y, COLUMNS, 1, GRID);
+            osd::draw_text(frame, 1, y - osd::CHAR_HEIGHT / 2, &format!("C{}", octave), osd::WHITE);
+        }
+        let start = LEFT + COLUMNS - self.frames.len();
+        for (x, notes) in self.frames.iter().enumerate() {
+            for (channel, note) in notes.iter().enumerate() {
+                if let Some(note) = note.filter(|note| (LOWEST..=HIGHEST).contains(&note.pitch)) {
+                    osd::fill_rect(frame, start + x, row_y(note.pitch) - 1, 1, ROW - 1, dim(COLORS[channel], note.volume));
+                }
+            }
+        }
+        osd::draw_text(frame, LEFT, 2, "PULSE 1", COLORS[0]);
+        osd::draw_text(frame, LEFT + osd::text_width("PULSE 1  "), 2, "PULSE 2", COLORS[1]);
+    }
+}
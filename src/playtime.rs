@@ -0,0 +1,160 @@
+use std::collections::BTreeMap;
+use std::env;
+use std::fs;
+use std::io::ErrorKind;
+use std::path::PathBuf;
+use std::sync::OnceLock;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use serde::{Deserialize, Serialize};
+
+use crate::filelock::FileLock;
+use crate::savestate;
+
+// Per-game play statistics, kept in playtime.json in the user data directory and keyed
+// by ROM hash so renamed or moved ROMs keep their history. Play time counts emulated
+// frames, so time spent paused in menus or the debugger doesn't count.
+
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+pub struct GameStats {
+    pub name: String,
+    pub seconds: u64,
+    pub launches: u32,
+    pub last_played: u64,
+    pub longest_session: u64,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct PlayStats {
+    games: BTreeMap<String, GameStats>,
+}
+
+static DATA_DIR: OnceLock<PathBuf> = OnceLock::new();
+
+// For --data-dir, which gives an instance its own stats, session and input settings.
+// Must be called before anything uses the data directory.
+pub fn set_data_dir(dir: PathBuf) {
+    DATA_DIR.set(dir).ok();
+}
+
+// --data-dir, $XDG_DATA_HOME/zetr, ~/.local/share/zetr, or %APPDATA%\zetr on Windows
+pub fn data_dir() -> Option<PathBuf> {
+    DATA_DIR.get().cloned().or_else(default_data_dir)
+}
+
+// The data directory --data-dir would replace, for what instances given their own
+// directories still have to share, like gamepad claims.
+pub fn default_data_dir() -> Option<PathBuf> {
+    if let Some(dir) = env::var_os("XDG_DATA_HOME").filter(|d| !d.is_empty()) {
+        return Some(PathBuf::from(dir).join("zetr"));
+    }
+    if let Some(dir) = env::var_os("APPDATA").filter(|d| !d.is_empty()) {
+        return Some(PathBuf::from(dir).join("zetr"));
+    }
+    env::var_os("HOME").map(|home| PathBuf::from(home).join(".local/share/zetr"))
+}
+
+fn now() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |d| d.as_secs())
+}
+
+// "3h 12m", "7m 05s"
+pub fn format_duration(seconds: u64) -> String {
+    if seconds >= 3600 {
+        format!("{}h {:02}m", seconds / 3600, seconds / 60 % 60)
+    } else {
+        format!("{}m {:02}s", seconds / 60, seconds % 60)
+    }
+}
+
+impl PlayStats {
+    fn path() -> Result<PathBuf, String> {
+        data_dir().map(|dir| dir.join("playtime.json")).ok_or("no home directory".to_string())
+    }
+
+    pub fn load() -> Result<Self, Box<dyn std::error::Error>> {
+        let path = Self::path()?;
+        match fs::read_to_string(&path) {
+            Ok(text) => Ok(serde_json::from_str(&text).map_err(|e| format!("{}: {}", path.display(), e))?),
+            Err(e) if e.kind() == ErrorKind::NotFound => Ok(PlayStats::default()),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    // Written to a temporary file first so a crash can't leave half a database.
+    pub fn save(&self) -> Result<(), Box<dyn std::error::Error>> {
+        let path = Self::path()?;
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        let temp = path.with_extension("json.tmp");
+        fs::write(&temp, self.to_json())?;
+        fs::rename(&temp, &path)?;
+        Ok(())
+    }
+
+    // Loads, changes and saves the file under a lock, so windows playing at the same time
+    // each add their own time rather than writing back what they read at startup.
+    pub fn update<T>(change: impl FnOnce(&mut PlayStats) -> T) -> Result<T, Box<dyn std::error::Error>> {
+        let path = Self::path()?;
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        let _lock = FileLock::acquire(&path.to_string_lossy())?;
+        let mut stats = Self::load()?;
+        let result = change(&mut stats);
+        stats.save()?;
+        Ok(result)
+    }
+
+    pub fn to_json(&self) -> String {
+        serde_json::to_string_pretty(self).expect("stats always serialize")
+    }
+
+    pub fn get(&self, rom_hash: u64) -> Option<&GameStats> {
+        self.games.get(&format!("{:016x}", rom_hash))
+    }
+
+    // Most recently played first
+    pub fn games(&self) -> Vec<&GameStats> {
+        let mut games: Vec<&GameStats> = self.games.values().collect();
+        games.sort_by_key(|game| std::cmp::Reverse(game.last_played));
+        games
+    }
+
+    pub fn start_session(&mut self, rom_hash: u64, name: &str) -> Session {
+        let game = self.games.entry(format!("{:016x}", rom_hash)).or_default();
+        game.name = name.to_string();
+        game.launches += 1;
+        game.last_played = now();
+        Session { rom_hash, seconds: 0 }
+    }
+
+    // Can be called repeatedly during a session with its length so far; only the time since
+    // the last call is added.
+    pub fn update_session(&mut self, session: &mut Session, seconds: u64) {
+        if let Some(game) = self.games.get_mut(&format!("{:016x}", session.rom_hash)) {
+            game.seconds += seconds.saturating_sub(session.seconds);
+            game.longest_session = game.longest_session.max(seconds);
+            game.last_played = now();
+        }
+        session.seconds = seconds;
+    }
+}
+
+pub struct Session {
+    rom_hash: u64,
+    pub seconds: u64,
+}
+
+impl GameStats {
+    pub fn summary(&self) -> String {
+        format!(
+            "{} played over {} session{}, last {}",
+            format_duration(self.seconds),
+            self.launches,
+            if self.launches == 1 { "" } else { "s" },
+            savestate::format_date(self.last_played)
+        )
+    }
+}
@@ -0,0 +1,99 @@
+// Software post-processing for the window: brightness/contrast, a vignette and scanlines,
+// applied to the RGB24 frame just before it is uploaded. Like the OSD it works on a copy,
+// so frame dumps, hashes and traces never see it.
+
+const WIDTH: usize = 256;
+const HEIGHT: usize = 240;
+
+// Game config keys, which are also the command line options without the leading dashes.
+pub const KEYS: [&str; 4] = ["scanlines", "vignette", "brightness", "contrast"];
+
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct Settings {
+    pub scanlines: u8,  // 0-100, how much darker every other output line is
+    pub vignette: u8,   // 0-100, how much darker the corners are than the center
+    pub brightness: i8, // -100 to 100
+    pub contrast: i8,   // -100 to 100
+}
+
+impl Settings {
+    pub fn set(&mut self, key: &str, value: &str) -> Result<(), String> {
+        let percent = |lo: i32| {
+            value.parse::<i32>().ok().filter(|v| (lo..=100).contains(v))
+                .ok_or_else(|| format!("{} must be a number from {} to 100", key, lo))
+        };
+        match key {
+            "scanlines" => self.scanlines = percent(0)? as u8,
+            "vignette" => self.vignette = percent(0)? as u8,
+            "brightness" => self.brightness = percent(-100)? as i8,
+            "contrast" => self.contrast = percent(-100)? as i8,
+            _ => return Err(format!("unknown filter setting '{}'", key)),
+        }
+        Ok(())
+    }
+
+    pub fn is_off(&self) -> bool {
+        *self == Settings::default()
+    }
+}
+
+pub struct PostFx {
+    scanlines: u16,       // Odd-line brightness out of 256, or 0 when scanlines are off
+    levels: [u8; 256],    // Brightness and contrast for each channel value
+    shade: Vec<u16>,      // Vignette brightness out of 256 for each pixel
+    output: Vec<u8>,
+}
+
+impl PostFx {
+    pub fn new(settings: Settings) -> Self {
+        let contrast = (100 + settings.contrast as i32) as f32 / 100.0;
+        let brightness = settings.brightness as f32 * 1.28;
+        let levels = std::array::from_fn(|v| {
+            ((v as f32 - 128.0) * contrast + 128.0 + brightness).round().clamp(0.0, 255.0) as u8
+        });
+        // Falls off with the square of the distance from the center, to 40% in the corners at
+        // full strength
+        let strength = settings.vignette as f32 / 100.0 * 0.6;
+        let shade = (0..WIDTH * HEIGHT)
+            .map(|i| {
+                let dx = (i % WIDTH) as f32 / (WIDTH - 1) as f32 * 2.0 - 1.0;
+                let dy = (i / WIDTH) as f32 / (HEIGHT - 1) as f32 * 2.0 - 1.0;
+                ((1.0 - strength * (dx * dx + dy * dy) / 2.0) * 256.0) as u16
+            })
+            .collect();
+        let scanlines = match settings.scanlines {
+            0 => 0,
+            strength => ((100 - strength as u16) * 256 / 100).max(1),
+        };
+        let height = if scanlines > 0 { HEIGHT * 2 } else { HEIGHT };
+        PostFx { scanlines, levels, shade, output: vec![0; WIDTH * height * 3] }
+    }
+
+    // Output height: scanlines need two output lines per NES line to look like anything at
+    // window scale, so the frame is doubled vertically while they are on.
+    pub fn height(&self) -> usize {
+        if self.scanlines > 0 { HEIGHT * 2 } else { HEIGHT }
+    }
+
+    pub fn apply(&mut self, frame: &[u8]) -> &[u8] {
+        let row_bytes = WIDTH * 3;
+        let doubled = self.scanlines > 0;
+        for (y, row) in frame.chunks_exact(row_bytes).enumerate() {
+            let out_y = if doubled { y * 2 } else { y };
+            let out = &mut self.output[out_y * row_bytes..(out_y + 1) * row_bytes];
+            for (x, (dst, src)) in out.chunks_exact_mut(3).zip(row.chunks_exact(3)).enumerate() {
+                let shade = self.shade[y * WIDTH + x];
+                for (d, &s) in dst.iter_mut().zip(src) {
+                    *d = ((self.levels[s as usize] as u16 * shade) >> 8) as u8;
+                }
+            }
+            if doubled {
+                let (bright, dark) = self.output[out_y * row_bytes..(out_y + 2) * row_bytes].split_at_mut(row_bytes);
+                for (d, &b) in dark.iter_mut().zip(bright.iter()) {
+                    *d = ((b as u16 * self.scanlines) >> 8) as u8;
+                }
+            }
+        }
+        &self.output
+    }
+}
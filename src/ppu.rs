@@ -1,4 +1,14 @@
+use std::io::{self, Read, Write};
+use byteorder::{LittleEndian, ReadBytesExt, WriteBytesExt};
+
+#[cfg(feature = "simd-ppu")]
+use crate::bgpipe;
 use crate::cartridge::Cartridge;
+use crate::layers::{self, LayerPixel};
+use crate::log_error;
+use crate::palette::{self, Palette};
+use crate::ppulog::{Access, PpuBusLog, Target};
+use crate::region::Region;
 
 const SCREEN_WIDTH: usize = 256;
 const SCREEN_HEIGHT: usize = 240;
@@ -11,6 +21,7 @@ struct Sprite {
     x: u8,
     pattern_lo: u8,
     pattern_hi: u8,
+    index: u8, // Position in OAM
 }
 
 #[derive(Debug)]
@@ -41,7 +52,26 @@ pub struct PPU {
     pub scanline: i16,
     pub cycle: u16,
     pub frame_complete: bool,
+    pub frame_count: u64,
     pub frame_buffer: [u8; SCREEN_WIDTH * SCREEN_HEIGHT * 3], // RGB buffer
+    pub skip_output: bool,  // Frame skip: keep timing and flags but don't write pixels
+    pub palette: Palette,     // Display colors only; not part of the save state
+    // Brightness of each pixel as the beam drew it, for light guns. Rows at or past the
+    // beam still hold the previous frame. Only kept up while `track_light` is set.
+    pub light: [u8; SCREEN_WIDTH * SCREEN_HEIGHT],
+    // Palette index of each pixel with the emphasis bits above it, turned into colors once
+    // the frame is done. Only the frame buffer is lagging, never timing or flags.
+    #[cfg(feature = "parallel-ppu")]
+    color_indices: Vec<u16>,
+    pub track_light: bool,
+    // Where each pixel came from, one entry per pixel while `track_layers` is set
+    pub layers: Vec<LayerPixel>,
+    pub track_layers: bool,
+    pub bus_log: Option<PpuBusLog>, // Not part of the save state
+    pub last_scanline: i16, // Wrap to the pre-render line after this one (260 NTSC, 310 PAL/Dendy)
+    swap_emphasis: bool,    // PAL and Dendy PPUs swap the red and green emphasis bits
+    pub vblank_line: i16,
+    extra_lines: i16,       // Overclock: vblank lines added on top of the region's
     
     // Background tile fetching
     pub bg_next_tile_id: u8,
@@ -55,9 +85,17 @@ pub struct PPU {
     pub bg_shifter_attrib_lo: u16,
     pub bg_shifter_attrib_hi: u16,
     
+    // Background indices and colors for the current 8-dot group
+    #[cfg(feature = "simd-ppu")]
+    bg_group: [u8; 8],
+    #[cfg(feature = "simd-ppu")]
+    bg_group_colors: [u8; 8],
+    
     // Sprite rendering
     scanline_sprites: [Sprite; 8],
     sprite_count: usize,
+    sprite_zero_on_line: bool, // scanline_sprites[0] is OAM sprite 0
+    secondary_oam: [u8; 32], // Sprites found for the next line, as $2004 sees them while they're fetched
     
     // NMI
     pub nmi_occurred: bool,
@@ -87,7 +125,21 @@ impl PPU {
             scanline: 261,
             cycle: 0,
             frame_complete: false,
+            frame_count: 0,
             frame_buffer: [0; SCREEN_WIDTH * SCREEN_HEIGHT * 3],
+            skip_output: false,
+            palette: Palette::default(),
+            light: [0; SCREEN_WIDTH * SCREEN_HEIGHT],
+            #[cfg(feature = "parallel-ppu")]
+            color_indices: vec![0; SCREEN_WIDTH * SCREEN_HEIGHT],
+            track_light: false,
+            layers: Vec::new(),
+            track_layers: false,
+            bus_log: None,
+            last_scanline: Region::Ntsc.scanlines() - 1,
+            swap_emphasis: false,
+            vblank_line: Region::Ntsc.vblank_line(),
+            extra_lines: 0,
             bg_next_tile_id: 0,
             bg_next_tile_attrib: 0,
             bg_next_tile_lsb: 0,
@@ -96,8 +148,14 @@ impl PPU {
             bg_shifter_pattern_hi: 0,
             bg_shifter_attrib_lo: 0,
             bg_shifter_attrib_hi: 0,
+            #[cfg(feature = "simd-ppu")]
+            bg_group: [0; 8],
+            #[cfg(feature = "simd-ppu")]
+            bg_group_colors: [0; 8],
             scanline_sprites: [Sprite::default(); 8],
             sprite_count: 0,
+            sprite_zero_on_line: false,
+            secondary_oam: [0xFF; 32],
             nmi_occurred: false,
             nmi_output: false,
             nmi_previous: false,
@@ -109,6 +167,19 @@ impl PPU {
             if self.scanline == 0 && self.cycle == 0 {
                 self.cycle = 1;
             }
+
+            if self.mask & 0x18 != 0 {
+                // When rendering starts with OAMADDR at 8 or above, the row it points into
+                // is copied over the first eight bytes of OAM (2C02G behaviour)
+                if self.scanline == -1 && self.cycle == 1 && self.oam_addr >= 8 {
+                    let row = (self.oam_addr & 0xF8) as usize;
+                    self.oam.copy_within(row..row + 8, 0);
+                }
+                // Sprite fetches leave OAMADDR at zero on every rendered line
+                if self.cycle >= 257 && self.cycle <= 320 {
+                    self.oam_addr = 0;
+                }
+            }
             
             if (self.cycle >= 2 && self.cycle < 258) || (self.cycle >= 321 && self.cycle < 338) {
                 self.update_shifters();
@@ -148,7 +219,7 @@ impl PPU {
             }
         }
         
-        if self.scanline == 241 && self.cycle == 1 {
+        if self.scanline == self.vblank_line && self.cycle == 1 {
             self.status |= 0x80;
             if self.ctrl & 0x80 != 0 {
                 self.nmi_occurred = true;
@@ -159,9 +230,14 @@ impl PPU {
         if self.cycle >= 341 {
             self.cycle = 0;
             self.scanline += 1;
-            if self.scanline >= 261 {
+            if self.scanline > self.last_scanline {
                 self.scanline = -1;
+                #[cfg(feature = "parallel-ppu")]
+                if !self.skip_output {
+                    self.compose_frame();
+                }
                 self.frame_complete = true;
+                self.frame_count += 1;
                 self.status &= !0x80;
                 self.nmi_occurred = false;
             }
@@ -175,8 +251,26 @@ impl PPU {
         if (x as usize) < SCREEN_WIDTH && y >= 0 && y < SCREEN_HEIGHT as i16 {
             let mut bg_pixel = 0;
             let mut bg_palette = 0;
+            // $2001 bits 1 and 2 hide each layer in the leftmost 8 pixels
+            let show_bg = self.mask & 0x08 != 0 && (x >= 8 || self.mask & 0x02 != 0);
+            let show_sprites = self.mask & 0x10 != 0 && (x >= 8 || self.mask & 0x04 != 0);
+            
+            // The group is taken at the start of every 8 dots, which ignores mid-group
+            // changes to fine X, the palette or the background enable bit.
+            #[cfg(feature = "simd-ppu")]
+            if x & 7 == 0 {
+                self.load_background_group();
+            }
+            
+            #[cfg(feature = "simd-ppu")]
+            if show_bg {
+                let index = self.bg_group[x as usize % 8] as u16;
+                bg_pixel = index & 3;
+                bg_palette = index >> 2;
+            }
             
-            if self.mask & 0x08 != 0 {
+            #[cfg(not(feature = "simd-ppu"))]
+            if show_bg {
                 let pixel_bit = 15 - self.fine_x_scroll as u16;
                 let p0 = (self.bg_shifter_pattern_lo >> pixel_bit) & 1;
                 let p1 = (self.bg_shifter_pattern_hi >> pixel_bit) & 1;
@@ -191,8 +285,12 @@ impl PPU {
             let mut sprite_pixel = 0;
             let mut sprite_palette = 0;
             let mut sprite_priority = false;
+            let mut sprite_index = layers::NO_SPRITE;
 
-            if self.mask & 0x10 != 0 {
+            // The first opaque sprite pixel in OAM order wins, and only then is its priority bit
+            // compared with the background. A sprite behind the background still hides every
+            // later sprite there, which games use to mask sprites with background tiles.
+            if show_sprites {
                 for i in 0..self.sprite_count {
                     let sprite = &mut self.scanline_sprites[i];
                     if sprite.x == 0 {
@@ -204,8 +302,10 @@ impl PPU {
                             sprite_pixel = pixel;
                             sprite_palette = (sprite.attributes & 0x03) + 4;
                             sprite_priority = (sprite.attributes & 0x20) == 0;
+                            sprite_index = sprite.index;
                             
-                            if self.mask & 0x08 != 0 && i == 0 && sprite_pixel != 0 && bg_pixel != 0 {
+                            // Only OAM sprite 0 hits, never at the last pixel of the line
+                            if i == 0 && self.sprite_zero_on_line && bg_pixel != 0 && x != 255 {
                                 self.status |= 0x40;
                             }
                             
@@ -215,25 +315,66 @@ impl PPU {
                 }
             }
             
+            if self.track_layers {
+                self.layers[y as usize * SCREEN_WIDTH + x as usize] = LayerPixel {
+                    background: if bg_pixel == 0 { 0 } else { ((bg_palette << 2) | bg_pixel) as u8 },
+                    sprite: if sprite_pixel == 0 { 0 } else { (sprite_palette << 2) | sprite_pixel },
+                    sprite_index,
+                    behind: sprite_pixel != 0 && !sprite_priority,
+                };
+            }
+            
+            if self.skip_output && !self.track_light {
+                return;
+            }
+            
             let (final_pixel, final_palette) = if sprite_pixel > 0 && (bg_pixel == 0 || sprite_priority) {
                 (sprite_pixel, sprite_palette)
             } else {
                 (bg_pixel as u8, bg_palette as u8)
             };
             
-            let palette_addr = if final_pixel == 0 { 0 } else { (final_palette << 2) | final_pixel };
+            let palette_addr = if self.mask & 0x18 == 0 && self.vram_addr & 0x3F00 == 0x3F00 {
+                // With rendering off the PPU shows the palette entry the VRAM address points
+                // at instead of the backdrop, which is how some games draw mid-frame colors
+                let addr = (self.vram_addr & 0x1F) as u8;
+                if addr & 0x13 == 0x10 { addr & 0x0F } else { addr }
+            } else if final_pixel == 0 {
+                0
+            } else {
+                (final_palette << 2) | final_pixel
+            };
+            #[cfg(feature = "simd-ppu")]
+            let color_index = if palette_addr < 0x10 && show_bg {
+                self.bg_group_colors[x as usize % 8]
+            } else {
+                self.palette_ram[palette_addr as usize & 0x1F]
+            };
+            #[cfg(not(feature = "simd-ppu"))]
             let color_index = self.palette_ram[palette_addr as usize & 0x1F];
-            let color = self.get_color_from_palette(color_index);
+            // Grayscale and emphasis are applied per dot, so $2001 writes take effect mid-line
+            let color_index = color_index & if self.mask & 0x01 != 0 { 0x30 } else { 0x3F };
             
-            let pixel_index = (y as usize * SCREEN_WIDTH + x as usize) * 3;
-            if pixel_index + 2 < self.frame_buffer.len() {
-                self.frame_buffer[pixel_index] = color.0;
-                self.frame_buffer[pixel_index + 1] = color.1;
-                self.frame_buffer[pixel_index + 2] = color.2;
+            if self.track_light {
+                // Sensed from the console's own colors, so a display palette can't change
+                // what the Zapper sees
+                let color = palette::NTSC[color_index as usize & 0x3F];
+                let luma = (color.0 as u16 * 2 + color.1 as u16 * 5 + color.2 as u16) / 8;
+                self.light[y as usize * SCREEN_WIDTH + x as usize] = luma as u8;
+            }
+            
+            if !self.skip_output {
+                self.output_pixel(y as usize * SCREEN_WIDTH + x as usize, color_index);
             }
         }
     }
     
+    #[cfg(feature = "simd-ppu")]
+    fn load_background_group(&mut self) {
+        let shifters = [self.bg_shifter_pattern_lo, self.bg_shifter_pattern_hi, self.bg_shifter_attrib_lo, self.bg_shifter_attrib_hi];
+        (self.bg_group, self.bg_group_colors) = bgpipe::background_group(shifters, self.fine_x_scroll, &self.palette_ram);
+    }
+    
     fn update_shifters(&mut self) {
         if self.mask & 0x08 != 0 {
             self.bg_shifter_pattern_lo <<= 1;
@@ -255,6 +396,8 @@ impl PPU {
 
     fn evaluate_sprites(&mut self) {
         self.sprite_count = 0;
+        self.sprite_zero_on_line = false;
+        self.secondary_oam = [0xFF; 32];
         let sprite_height = if self.ctrl & 0x20 != 0 { 16 } else { 8 };
 
         for i in 0..64 {
@@ -263,10 +406,14 @@ impl PPU {
 
             if diff >= 0 && diff < sprite_height {
                 if self.sprite_count < 8 {
+                    self.sprite_zero_on_line |= i == 0;
+                    let entry = self.sprite_count * 4;
+                    self.secondary_oam[entry..entry + 4].copy_from_slice(&self.oam[i * 4..i * 4 + 4]);
                     self.scanline_sprites[self.sprite_count].y = self.oam[i * 4];
                     self.scanline_sprites[self.sprite_count].tile_id = self.oam[i * 4 + 1];
                     self.scanline_sprites[self.sprite_count].attributes = self.oam[i * 4 + 2];
                     self.scanline_sprites[self.sprite_count].x = self.oam[i * 4 + 3];
+                    self.scanline_sprites[self.sprite_count].index = i as u8;
                     self.sprite_count += 1;
                 } else {
                     self.status |= 0x20;
@@ -401,18 +548,110 @@ impl PPU {
                 self.write_toggle = false;
                 data
             }
-            0x2004 => self.oam[self.oam_addr as usize],
+            0x2004 => self.read_oam_data(),
             0x2007 => {
                 let mut data = self.read_buffer;
                 self.read_buffer = self.ppu_read(self.vram_addr, cartridge);
                 if self.vram_addr >= 0x3F00 { data = self.read_buffer; }
-                self.vram_addr = self.vram_addr.wrapping_add(if self.ctrl & 4 != 0 { 32 } else { 1 });
+                self.increment_vram_addr();
                 data
             }
             _ => 0,
         }
     }
     
+    #[cfg(not(feature = "parallel-ppu"))]
+    fn output_pixel(&mut self, i: usize, color_index: u8) {
+        let mut color = self.palette.color(color_index);
+        if self.mask & 0xE0 != 0 {
+            color = palette::emphasize(color, color_index, self.emphasis());
+        }
+        self.frame_buffer[i * 3..i * 3 + 3].copy_from_slice(&[color.0, color.1, color.2]);
+    }
+
+    // The color is looked up by compose_frame
+    #[cfg(feature = "parallel-ppu")]
+    fn output_pixel(&mut self, i: usize, color_index: u8) {
+        let emphasis = if self.mask & 0xE0 != 0 { self.emphasis() } else { 0 };
+        self.color_indices[i] = color_index as u16 | (emphasis as u16) << 8;
+    }
+
+    // Colors for the frame's palette indices, a batch of scanlines per task. Everything
+    // timing-related happened during the dots already, so the batches are independent.
+    #[cfg(feature = "parallel-ppu")]
+    fn compose_frame(&mut self) {
+        use rayon::prelude::*;
+        const LINES_PER_TASK: usize = 16;
+        let palette = &self.palette;
+        self.frame_buffer
+            .par_chunks_mut(SCREEN_WIDTH * 3 * LINES_PER_TASK)
+            .zip(self.color_indices.par_chunks(SCREEN_WIDTH * LINES_PER_TASK))
+            .for_each(|(pixels, indices)| {
+                for (pixel, &entry) in pixels.chunks_exact_mut(3).zip(indices) {
+                    let (index, emphasis) = (entry as u8, (entry >> 8) as u8);
+                    let mut color = palette.color(index);
+                    if emphasis != 0 {
+                        color = palette::emphasize(color, index, emphasis);
+                    }
+                    pixel.copy_from_slice(&[color.0, color.1, color.2]);
+                }
+            });
+    }
+
+    // Emphasis bits as red, green, blue
+    fn emphasis(&self) -> u8 {
+        let bits = self.mask >> 5;
+        if self.swap_emphasis { (bits & 0b100) | ((bits & 1) << 1) | ((bits >> 1) & 1) } else { bits }
+    }
+
+    // After a $2007 access. While rendering, the address is the one the PPU is fetching with,
+    // and the access bumps coarse X and fine Y at once instead of adding 1 or 32.
+    fn increment_vram_addr(&mut self) {
+        if self.rendering() {
+            self.increment_scroll_x();
+            self.increment_scroll_y();
+        } else {
+            self.vram_addr = self.vram_addr.wrapping_add(if self.ctrl & 4 != 0 { 32 } else { 1 });
+        }
+    }
+
+    fn rendering(&self) -> bool {
+        self.mask & 0x18 != 0 && self.scanline >= -1 && self.scanline < 240
+    }
+
+    // $2004 reads. While rendering they return whatever is on the OAM bus at that dot:
+    // $FF while secondary OAM is cleared, primary OAM during evaluation (done in one step
+    // here, so that is approximated by the byte at OAMADDR) and then the secondary OAM
+    // bytes as each sprite's tiles are fetched.
+    fn read_oam_data(&self) -> u8 {
+        let value = if self.rendering() {
+            match self.cycle {
+                1..=64 => return 0xFF,
+                65..=256 => self.oam[self.oam_addr as usize],
+                257..=320 => {
+                    let dot = (self.cycle - 257) as usize;
+                    return self.secondary_oam[dot / 8 * 4 + (dot % 8).min(3)];
+                }
+                _ => return self.secondary_oam[0],
+            }
+        } else {
+            self.oam[self.oam_addr as usize]
+        };
+        // Bits 2-4 of the attribute byte don't exist and read back as zero
+        if self.oam_addr & 3 == 2 { value & 0xE3 } else { value }
+    }
+
+    // $2004 writes, also used by OAM DMA. During rendering the write is dropped and OAMADDR
+    // is bumped by a whole sprite instead of a byte.
+    pub fn write_oam_data(&mut self, data: u8) {
+        if self.rendering() {
+            self.oam_addr = self.oam_addr.wrapping_add(4);
+        } else {
+            self.oam[self.oam_addr as usize] = data;
+            self.oam_addr = self.oam_addr.wrapping_add(1);
+        }
+    }
+
     pub fn cpu_write(&mut self, addr: u16, data: u8, cartridge: &mut Cartridge) {
         match addr {
             0x2000 => {
@@ -421,10 +660,7 @@ impl PPU {
             }
             0x2001 => self.mask = data,
             0x2003 => self.oam_addr = data,
-            0x2004 => {
-                self.oam[self.oam_addr as usize] = data;
-                self.oam_addr = self.oam_addr.wrapping_add(1);
-            }
+            0x2004 => self.write_oam_data(data),
             0x2005 => {
                 if !self.write_toggle {
                     self.fine_x_scroll = data & 7;
@@ -445,67 +681,76 @@ impl PPU {
             }
             0x2007 => {
                 self.ppu_write(self.vram_addr, data, cartridge);
-                self.vram_addr = self.vram_addr.wrapping_add(if self.ctrl & 4 != 0 { 32 } else { 1 });
+                self.increment_vram_addr();
             }
             _ => {}
         }
     }
     
-    fn ppu_read(&mut self, addr: u16, cartridge: &mut Cartridge) -> u8 {
-        let addr = addr & 0x3FFF;
-        match addr {
-            0..=0x1FFF => cartridge.read_chr(addr),
-            0x2000..=0x3EFF => {
+    // Where a PPU address lands after mirroring
+    fn target(&self, addr: u16, cartridge: &Cartridge) -> Target {
+        match addr & 0x3FFF {
+            addr @ 0..=0x1FFF => Target::Chr(cartridge.chr_offset(addr)),
+            addr @ 0x2000..=0x3EFF => {
                 let addr = addr & 0x0FFF;
-                match cartridge.mirroring {
-                    crate::cartridge::Mirroring::Vertical => self.vram[(addr & 0x07FF) as usize],
-                    crate::cartridge::Mirroring::Horizontal => self.vram[(addr & 0x03FF | ((addr >> 1) & 0x0400)) as usize],
-                    _ => self.vram[addr as usize],
-                }
+                Target::Ciram(match cartridge.mirroring {
+                    crate::cartridge::Mirroring::Vertical => (addr & 0x07FF) as usize,
+                    crate::cartridge::Mirroring::Horizontal => (addr & 0x03FF | ((addr >> 1) & 0x0400)) as usize,
+                    _ => addr as usize,
+                })
             }
-            0x3F00..=0x3FFF => {
+            addr => {
                 let mut addr = addr & 0x1F;
                 if addr == 0x10 || addr == 0x14 || addr == 0x18 || addr == 0x1C { addr -= 0x10; }
-                self.palette_ram[addr as usize] & if self.mask & 1 != 0 { 0x30 } else { 0x3F }
+                Target::Palette(addr as usize)
             }
-            _ => 0,
         }
     }
+
+    fn ppu_read(&mut self, addr: u16, cartridge: &mut Cartridge) -> u8 {
+        let target = self.target(addr, cartridge);
+        let value = match target {
+            Target::Chr(_) => cartridge.read_chr(addr & 0x1FFF),
+            Target::Ciram(index) => self.vram[index],
+            Target::Palette(index) => self.palette_ram[index] & if self.mask & 1 != 0 { 0x30 } else { 0x3F },
+        };
+        cartridge.ppu_addr(addr & 0x3FFF);
+        self.log_access(false, addr, value, target);
+        value
+    }
     
     fn ppu_write(&mut self, addr: u16, data: u8, cartridge: &mut Cartridge) {
-        let addr = addr & 0x3FFF;
-        match addr {
-            0..=0x1FFF => cartridge.write_chr(addr, data),
-            0x2000..=0x3EFF => {
-                let addr = addr & 0x0FFF;
-                match cartridge.mirroring {
-                    crate::cartridge::Mirroring::Vertical => self.vram[(addr & 0x07FF) as usize] = data,
-                    crate::cartridge::Mirroring::Horizontal => self.vram[(addr & 0x03FF | ((addr >> 1) & 0x0400)) as usize] = data,
-                    _ => self.vram[addr as usize] = data,
-                }
-            }
-            0x3F00..=0x3FFF => {
-                let mut addr = addr & 0x1F;
-                if addr == 0x10 || addr == 0x14 || addr == 0x18 || addr == 0x1C { addr -= 0x10; }
-                self.palette_ram[addr as usize] = data;
-            }
-            _ => {}
+        let target = self.target(addr, cartridge);
+        match target {
+            Target::Chr(_) => cartridge.write_chr(addr & 0x1FFF, data),
+            Target::Ciram(index) => self.vram[index] = data,
+            Target::Palette(index) => self.palette_ram[index] = data,
+        }
+        cartridge.ppu_addr(addr & 0x3FFF);
+        self.log_access(true, addr, data, target);
+    }
+
+    fn log_access(&mut self, write: bool, addr: u16, value: u8, target: Target) {
+        let Some(log) = self.bus_log.as_mut().filter(|log| log.wants(self.frame_count)) else {
+            return;
+        };
+        let access = Access { frame: self.frame_count, scanline: self.scanline, dot: self.cycle, write, addr: addr & 0x3FFF, value, target };
+        if let Err(e) = log.record(&access) {
+            log_error!("PPU bus log {} failed, stopping: {}", log.path(), e);
+            self.bus_log = None;
         }
     }
     
-    fn get_color_from_palette(&self, index: u8) -> (u8, u8, u8) {
-        let palette = [
-            (84, 84, 84), (0, 30, 116), (8, 16, 144), (48, 0, 136), (68, 0, 100), (92, 0, 48), (84, 4, 0), (60, 24, 0),
-            (32, 42, 0), (8, 58, 0), (0, 64, 0), (0, 60, 40), (0, 50, 88), (0, 0, 0), (0, 0, 0), (0, 0, 0),
-            (152, 150, 152), (8, 76, 196), (48, 50, 236), (92, 30, 228), (136, 20, 176), (160, 20, 100), (152, 34, 32),
-            (120, 60, 0), (84, 90, 0), (40, 114, 0), (8, 124, 0), (0, 118, 40), (0, 102, 120), (0, 0, 0), (0, 0, 0),
-            (0, 0, 0), (236, 238, 236), (76, 154, 236), (120, 124, 236), (176, 98, 236), (228, 84, 236), (236, 88, 180),
-            (236, 106, 100), (212, 136, 32), (160, 170, 0), (116, 196, 0), (76, 208, 32), (56, 204, 108), (56, 180, 220),
-            (60, 60, 60), (0, 0, 0), (0, 0, 0), (236, 238, 236), (168, 204, 236), (188, 188, 236), (212, 178, 236),
-            (236, 174, 236), (236, 174, 212), (236, 180, 176), (228, 196, 144), (204, 210, 120), (180, 222, 120),
-            (168, 226, 144), (152, 226, 180), (160, 214, 228), (160, 162, 160), (0, 0, 0), (0, 0, 0),
-        ];
-        palette.get(index as usize & 0x3F).copied().unwrap_or((0, 0, 0))
+    pub fn set_region(&mut self, region: Region) {
+        self.last_scanline = region.scanlines() - 1 + self.extra_lines;
+        self.swap_emphasis = region != Region::Ntsc;
+        self.vblank_line = region.vblank_line();
+    }
+
+    // Lengthens vblank, giving the CPU that many more scanlines of time each frame.
+    pub fn set_extra_lines(&mut self, lines: u16) {
+        self.last_scanline += lines as i16 - self.extra_lines;
+        self.extra_lines = lines as i16;
     }
     
     pub fn reset(&mut self) {
@@ -537,4 +782,85 @@ impl PPU {
     pub fn get_frame_buffer(&self) -> &[u8] {
         &self.frame_buffer
     }
+
+    pub fn save_state(&self, w: &mut dyn Write) -> io::Result<()> {
+        w.write_all(&[
+            self.ctrl, self.mask, self.status, self.oam_addr, self.oam_data,
+            self.scroll, self.addr, self.data, self.fine_x_scroll,
+            self.write_toggle as u8, self.read_buffer,
+        ])?;
+        w.write_u16::<LittleEndian>(self.vram_addr)?;
+        w.write_u16::<LittleEndian>(self.temp_vram_addr)?;
+        w.write_all(&self.vram)?;
+        w.write_all(&self.palette_ram)?;
+        w.write_all(&self.oam)?;
+        w.write_i16::<LittleEndian>(self.scanline)?;
+        w.write_u16::<LittleEndian>(self.cycle)?;
+        w.write_u8(self.frame_complete as u8)?;
+        w.write_u64::<LittleEndian>(self.frame_count)?;
+        w.write_all(&[self.bg_next_tile_id, self.bg_next_tile_attrib, self.bg_next_tile_lsb, self.bg_next_tile_msb])?;
+        for shifter in [self.bg_shifter_pattern_lo, self.bg_shifter_pattern_hi, self.bg_shifter_attrib_lo, self.bg_shifter_attrib_hi] {
+            w.write_u16::<LittleEndian>(shifter)?;
+        }
+        for sprite in &self.scanline_sprites {
+            w.write_all(&[sprite.y, sprite.tile_id, sprite.attributes, sprite.x, sprite.pattern_lo, sprite.pattern_hi, sprite.index])?;
+        }
+        w.write_u8(self.sprite_count as u8)?;
+        w.write_all(&self.secondary_oam)?;
+        w.write_u8(self.sprite_zero_on_line as u8)?;
+        w.write_all(&[self.nmi_occurred as u8, self.nmi_output as u8, self.nmi_previous as u8])
+    }
+
+    pub fn load_state(&mut self, r: &mut dyn Read) -> io::Result<()> {
+        let mut regs = [0u8; 11];
+        r.read_exact(&mut regs)?;
+        let [ctrl, mask, status, oam_addr, oam_data, scroll, addr, data, fine_x, toggle, buffer] = regs;
+        self.ctrl = ctrl;
+        self.mask = mask;
+        self.status = status;
+        self.oam_addr = oam_addr;
+        self.oam_data = oam_data;
+        self.scroll = scroll;
+        self.addr = addr;
+        self.data = data;
+        self.fine_x_scroll = fine_x;
+        self.write_toggle = toggle != 0;
+        self.read_buffer = buffer;
+        self.vram_addr = r.read_u16::<LittleEndian>()?;
+        self.temp_vram_addr = r.read_u16::<LittleEndian>()?;
+        r.read_exact(&mut self.vram)?;
+        r.read_exact(&mut self.palette_ram)?;
+        r.read_exact(&mut self.oam)?;
+        self.scanline = r.read_i16::<LittleEndian>()?;
+        self.cycle = r.read_u16::<LittleEndian>()?;
+        self.frame_complete = r.read_u8()? != 0;
+        self.frame_count = r.read_u64::<LittleEndian>()?;
+        let mut next = [0u8; 4];
+        r.read_exact(&mut next)?;
+        [self.bg_next_tile_id, self.bg_next_tile_attrib, self.bg_next_tile_lsb, self.bg_next_tile_msb] = next;
+        self.bg_shifter_pattern_lo = r.read_u16::<LittleEndian>()?;
+        self.bg_shifter_pattern_hi = r.read_u16::<LittleEndian>()?;
+        self.bg_shifter_attrib_lo = r.read_u16::<LittleEndian>()?;
+        self.bg_shifter_attrib_hi = r.read_u16::<LittleEndian>()?;
+        for sprite in &mut self.scanline_sprites {
+            let mut s = [0u8; 7];
+            r.read_exact(&mut s)?;
+            *sprite = Sprite { y: s[0], tile_id: s[1], attributes: s[2], x: s[3], pattern_lo: s[4], pattern_hi: s[5], index: s[6] };
+        }
+        self.sprite_count = (r.read_u8()? as usize).min(8);
+        r.read_exact(&mut self.secondary_oam)?;
+        self.sprite_zero_on_line = r.read_u8()? != 0;
+        let mut nmi = [0u8; 3];
+        r.read_exact(&mut nmi)?;
+        self.nmi_occurred = nmi[0] != 0;
+        self.nmi_output = nmi[1] != 0;
+        self.nmi_previous = nmi[2] != 0;
+        Ok(())
+    }
+}
+
+impl Default for PPU {
+    fn default() -> Self {
+        Self::new()
+    }
 }
@@ -0,0 +1,100 @@
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+
+// Log of every PPU memory access over a range of frames, as CSV for comparing against a
+// logic analyzer capture of the PPU bus:
+//
+//   frame,scanline,dot,access,address,value,target,bank,offset
+//   120,0,2,read,$2000,$24,ciram,0,$0000
+//   120,0,6,read,$0240,$00,chr,0,$0240
+//
+// `target` is the memory that answered: CHR ROM/RAM on the cartridge, the console's 2KB
+// nametable RAM (CIRAM) or palette RAM. For CHR, `offset` is the byte in CHR memory and
+// `bank` the 1KB bank it falls in, so bank switching shows up directly; for CIRAM they are
+// the byte and the 1KB page picked by mirroring. Rendering fetches are logged as well as
+// $2007 accesses. A rendered frame is about 33,000 lines.
+
+#[derive(Debug, Clone, Copy)]
+pub enum Target {
+    Chr(usize),
+    Ciram(usize),
+    Palette(usize),
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Access {
+    pub frame: u64,
+    pub scanline: i16,
+    pub dot: u16,
+    pub write: bool,
+    pub addr: u16,
+    pub value: u8,
+    pub target: Target,
+}
+
+#[derive(Debug)]
+pub struct PpuBusLog {
+    path: String,
+    writer: BufWriter<File>,
+    pub first_frame: u64,
+    pub last_frame: Option<u64>,
+}
+
+impl PpuBusLog {
+    pub fn create(path: &str, first_frame: u64, last_frame: Option<u64>) -> io::Result<Self> {
+        let mut writer = BufWriter::new(File::create(path)?);
+        writeln!(writer, "frame,scanline,dot,access,address,value,target,bank,offset")?;
+        Ok(PpuBusLog { path: path.to_string(), writer, first_frame, last_frame })
+    }
+
+    // "<first>", "<first>-<last>" or "<first>-" for no end.
+    pub fn parse_frames(text: &str) -> Option<(u64, Option<u64>)> {
+        match text.split_once('-') {
+            Some((first, "")) => Some((first.parse().ok()?, None)),
+            Some((first, last)) => {
+                let (first, last) = (first.parse().ok()?, last.parse().ok()?);
+                (first <= last).then_some((first, Some(last)))
+            }
+            None => text.parse().ok().map(|frame| (frame, Some(frame))),
+        }
+    }
+
+    pub fn path(&self) -> &str {
+        &self.path
+    }
+
+    pub fn wants(&self, frame: u64) -> bool {
+        frame >= self.first_frame && self.last_frame.is_none_or(|last| frame <= last)
+    }
+
+    pub fn finished(&self, frame: u64) -> bool {
+        self.last_frame.is_some_and(|last| frame > last)
+    }
+
+    pub fn record(&mut self, access: &Access) -> io::Result<()> {
+        let (name, bank, offset) = match access.target {
+            Target::Chr(offset) => ("chr", (offset / 1024).to_string(), offset),
+            Target::Ciram(offset) => ("ciram", (offset / 1024).to_string(), offset),
+            Target::Palette(offset) => ("palette", String::new(), offset),
+        };
+        writeln!(
+            self.writer,
+            "{},{},{},{},${:04X},${:02X},{},{},${:04X}",
+            access.frame,
+            access.scanline,
+            access.dot,
+            if access.write { "write" } else { "read" },
+            access.addr,
+            access.value,
+            name,
+            bank,
+            offset,
+        )
+    }
+}
+
+impl Drop for PpuBusLog {
+    fn drop(&mut self) {
+        self.writer.flush().ok();
+    }
+}
@@ -0,0 +1,133 @@
+// Per-subsystem timing, compiled in with `--features profiler`. Without the feature
+// `scope` returns a zero-sized guard and every call site optimizes away.
+//
+// Times are inclusive: mapper callbacks are also counted in the CPU or PPU time that
+// triggered them, and the emulation total covers CPU, PPU, DMA and debugger hooks.
+
+#[derive(Debug, Clone, Copy)]
+pub enum Section {
+    Emulation,
+    Cpu,
+    Ppu,
+    Dma,
+    Debugger,
+    PrgRead,
+    PrgWrite,
+    ChrRead,
+    ChrWrite,
+    Events,
+    Render,
+    Pacing,
+}
+
+// Report names, in Section order
+#[cfg(feature = "profiler")]
+const NAMES: [&str; 12] = [
+    "emulation",
+    "  cpu",
+    "  ppu",
+    "  oam dma",
+    "  debugger hooks",
+    "  mapper prg read",
+    "  mapper prg write",
+    "  mapper chr read",
+    "  mapper chr write",
+    "events",
+    "render",
+    "frame pacing",
+];
+
+#[cfg(feature = "profiler")]
+mod imp {
+    use std::cell::RefCell;
+    use std::time::{Duration, Instant};
+
+    use super::{Section, NAMES};
+
+    thread_local! {
+        static TOTALS: RefCell<[(Duration, u64); NAMES.len()]> = const { RefCell::new([(Duration::ZERO, 0); NAMES.len()]) };
+        static STARTED: RefCell<Option<Instant>> = const { RefCell::new(None) };
+    }
+
+    pub struct Scope {
+        section: Section,
+        start: Instant,
+    }
+
+    impl Drop for Scope {
+        fn drop(&mut self) {
+            let elapsed = self.start.elapsed();
+            TOTALS.with(|totals| {
+                let entry = &mut totals.borrow_mut()[self.section as usize];
+                entry.0 += elapsed;
+                entry.1 += 1;
+            });
+        }
+    }
+
+    #[inline]
+    pub fn scope(section: Section) -> Scope {
+        STARTED.with(|started| {
+            started.borrow_mut().get_or_insert_with(Instant::now);
+        });
+        Scope { section, start: Instant::now() }
+    }
+
+    pub fn reset() {
+        TOTALS.with(|totals| *totals.borrow_mut() = [(Duration::ZERO, 0); NAMES.len()]);
+        STARTED.with(|started| *started.borrow_mut() = None);
+    }
+
+    pub fn report() -> String {
+        let wall = STARTED.with(|started| started.borrow().map_or(Duration::ZERO, |s| s.elapsed()));
+        let mut out = format!("Profile over {:.2}s wall time\n", wall.as_secs_f64());
+        out.push_str(&format!("{:<22}{:>10}{:>8}{:>14}{:>10}\n", "section", "ms", "%", "calls", "ns/call"));
+        TOTALS.with(|totals| {
+            for (&(time, calls), name) in totals.borrow().iter().zip(NAMES) {
+                if calls == 0 {
+                    continue;
+                }
+                let percent = if wall.is_zero() { 0.0 } else { time.as_secs_f64() * 100.0 / wall.as_secs_f64() };
+                out.push_str(&format!(
+                    "{:<22}{:>10.1}{:>8.1}{:>14}{:>10}\n",
+                    name,
+                    time.as_secs_f64() * 1000.0,
+                    percent,
+                    calls,
+                    time.as_nanos() / calls as u128
+                ));
+            }
+        });
+        out
+    }
+}
+
+#[cfg(not(feature = "profiler"))]
+mod imp {
+    use super::Section;
+
+    pub struct Scope;
+
+    // Lets call sites end a scope early with drop() in either build
+    impl Drop for Scope {
+        #[inline(always)]
+        fn drop(&mut self) {}
+    }
+
+    #[inline(always)]
+    pub fn scope(_section: Section) -> Scope {
+        Scope
+    }
+
+    pub fn reset() {}
+
+    pub fn report() -> String {
+        "Profiler not compiled in, rebuild with --features profiler\n".to_string()
+    }
+}
+
+pub use imp::{report, reset, scope, Scope};
+
+pub fn enabled() -> bool {
+    cfg!(feature = "profiler")
+}
@@ -0,0 +1,164 @@
+use sdl2::controller::Button;
+use sdl2::keyboard::Keycode;
+
+use crate::osd;
+use crate::savestate;
+
+// Everyday functions on one screen that a gamepad alone can drive, for TV and handheld
+// setups. It opens over the paused game with F1 or Select+Start; up and down pick an entry,
+// left and right change the slot or a setting, A (or Enter) chooses and B (or Escape)
+// resumes.
+
+pub enum Nav {
+    Up,
+    Down,
+    Left,
+    Right,
+    Choose,
+    Back,
+}
+
+impl Nav {
+    pub fn from_key(keycode: Keycode) -> Option<Self> {
+        Some(match keycode {
+            Keycode::Up => Nav::Up,
+            Keycode::Down => Nav::Down,
+            Keycode::Left => Nav::Left,
+            Keycode::Right => Nav::Right,
+            Keycode::Return | Keycode::Space => Nav::Choose,
+            Keycode::Escape | Keycode::Backspace | Keycode::F1 => Nav::Back,
+            _ => return None,
+        })
+    }
+
+    // By position, Xbox style: the bottom face button chooses, the right one goes back
+    pub fn from_button(button: Button) -> Option<Self> {
+        Some(match button {
+            Button::DPadUp => Nav::Up,
+            Button::DPadDown => Nav::Down,
+            Button::DPadLeft => Nav::Left,
+            Button::DPadRight => Nav::Right,
+            Button::A => Nav::Choose,
+            Button::B => Nav::Back,
+            _ => return None,
+        })
+    }
+}
+
+pub enum QuickAction {
+    None,
+    Resume,
+    Save(usize),
+    Load(usize),
+    Reset,
+    Counters(bool),
+    AudioFilters(bool),
+    Help,
+    Quit,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum Entry {
+    Resume,
+    Save,
+    Load,
+    Reset,
+    Counters,
+    AudioFilters,
+    Help,
+    Quit,
+}
+
+const ENTRIES: [Entry; 8] = [Entry::Resume, Entry::Save, Entry::Load, Entry::Reset, Entry::Counters, Entry::AudioFilters, Entry::Help, Entry::Quit];
+
+pub struct QuickMenu {
+    selected: usize,
+    slot: usize,
+    counters: bool,
+    audio_filters: bool,
+}
+
+fn on_off(on: bool) -> &'static str {
+    if on { "ON" } else { "OFF" }
+}
+
+impl QuickMenu {
+    // Settings are shown as they are now and changed through the actions.
+    pub fn open(slot: usize, counters: bool, audio_filters: bool) -> Self {
+        QuickMenu { selected: 0, slot, counters, audio_filters }
+    }
+
+    // The slot picked for saving and loading, kept when the menu closes.
+    pub fn slot(&self) -> usize {
+        self.slot
+    }
+
+    pub fn handle(&mut self, nav: Nav) -> QuickAction {
+        let entry = ENTRIES[self.selected];
+        match nav {
+            Nav::Up => self.selected = (self.selected + ENTRIES.len() - 1) % ENTRIES.len(),
+            Nav::Down => self.selected = (self.selected + 1) % ENTRIES.len(),
+            Nav::Left | Nav::Right => {
+                let right = matches!(nav, Nav::Right);
+                match entry {
+                    Entry::Save | Entry::Load if right => self.slot = (self.slot + 1) % savestate::SLOTS,
+                    Entry::Save | Entry::Load => self.slot = (self.slot + savestate::SLOTS - 1) % savestate::SLOTS,
+                    Entry::Counters | Entry::AudioFilters => return self.toggle(entry),
+                    _ => {}
+                }
+            }
+            Nav::Choose => {
+                return match entry {
+                    Entry::Resume => QuickAction::Resume,
+                    Entry::Save => QuickAction::Save(self.slot),
+                    Entry::Load => QuickAction::Load(self.slot),
+                    Entry::Reset => QuickAction::Reset,
+                    Entry::Counters | Entry::AudioFilters => self.toggle(entry),
+                    Entry::Help => QuickAction::Help,
+                    Entry::Quit => QuickAction::Quit,
+                }
+            }
+            Nav::Back => return QuickAction::Resume,
+        }
+        QuickAction::None
+    }
+
+    fn toggle(&mut self, entry: Entry) -> QuickAction {
+        if entry == Entry::Counters {
+            self.counters = !self.counters;
+            QuickAction::Counters(self.counters)
+        } else {
+            self.audio_filters = !self.audio_filters;
+            QuickAction::AudioFilters(self.audio_filters)
+        }
+    }
+
+    fn label(&self, entry: Entry) -> String {
+        let (name, value) = match entry {
+            Entry::Resume => ("RESUME", None),
+            Entry::Save => ("SAVE STATE", Some(self.slot.to_string())),
+            Entry::Load => ("LOAD STATE", Some(self.slot.to_string())),
+            Entry::Reset => ("RESET", None),
+            Entry::Counters => ("COUNTERS", Some(on_off(self.counters).to_string())),
+            Entry::AudioFilters => ("AUDIO FILTERS", Some(on_off(self.audio_filters).to_string())),
+            Entry::Help => ("CONTROLS", None),
+            Entry::Quit => ("QUIT", None),
+        };
+        match value {
+            Some(value) => format!("{:<14}< {} >", name, value),
+            None => name.to_string(),
+        }
+    }
+
+    pub fn draw(&self, frame: &mut [u8]) {
+        let row_height = osd::CHAR_HEIGHT + 4;
+        let (x, y, w) = (48, 56, 160);
+        let h = 24 + ENTRIES.len() * row_height + 4;
+        osd::shade_rect(frame, x, y, w, h);
+        osd::draw_text(frame, x + 8, y + 6, "PAUSED", osd::WHITE);
+        for (i, &entry) in ENTRIES.iter().enumerate() {
+            let (marker, color) = if i == self.selected { ('>', osd::YELLOW) } else { (' ', osd::WHITE) };
+            osd::draw_text(frame, x + 8, y + 24 + i * row_height, &format!("{}{}", marker, self.label(entry)), color);
+        }
+    }
+}
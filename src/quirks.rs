@@ -0,0 +1,60 @@
+use crate::nes::NES;
+use crate::romdb;
+
+// Settings some games need because they depend on a timing detail that varies between
+// consoles or that the emulator doesn't get exactly right. Known cases are listed with the
+// game in the ROM database and applied when it loads; `alignment` and `overclock` in the
+// game config replace the database's choice (0 turns it off).
+
+pub const MAX_OVERCLOCK: u16 = 1000;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Quirk {
+    // PPU dots before the CPU's first cycle (0-2). Raster splits timed with cycle-counted
+    // loops shift by a few pixels with it.
+    Alignment(u8),
+    // Scanlines added to vblank, so frame logic that runs long finishes without slowdown.
+    Overclock(u16),
+}
+
+// Where a quirk came from, for the startup message.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Source {
+    Override,
+    Database,
+}
+
+impl Quirk {
+    pub fn describe(self) -> String {
+        match self {
+            Quirk::Alignment(dots) => format!("CPU/PPU alignment {}", dots),
+            Quirk::Overclock(lines) => format!("overclock by {} scanlines", lines),
+        }
+    }
+
+    pub fn apply(self, nes: &mut NES) {
+        match self {
+            Quirk::Alignment(dots) => nes.set_alignment(dots),
+            Quirk::Overclock(lines) => nes.set_overclock(lines),
+        }
+    }
+
+    fn is_default(self) -> bool {
+        matches!(self, Quirk::Alignment(0) | Quirk::Overclock(0))
+    }
+
+    fn same_setting(self, other: Quirk) -> bool {
+        std::mem::discriminant(&self) == std::mem::discriminant(&other)
+    }
+}
+
+// The quirks to apply: the game config's, then the ROM database's for settings the config
+// leaves alone. Settings left at their defaults aren't listed.
+pub fn detect(rom_hash: u64, forced: &[Quirk]) -> Vec<(Quirk, Source)> {
+    let database = romdb::lookup(rom_hash).map_or(&[][..], |entry| entry.quirks);
+    let database = database.iter().filter(|quirk| !forced.iter().any(|f| f.same_setting(**quirk)));
+    forced.iter().map(|&quirk| (quirk, Source::Override))
+        .chain(database.map(|&quirk| (quirk, Source::Database)))
+        .filter(|(quirk, _)| !quirk.is_default())
+        .collect()
+}
@@ -0,0 +1,141 @@
+use std::path::Path;
+
+use crate::cartridge::Cartridge;
+use crate::romdb;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Region {
+    Ntsc,
+    Pal,
+    Dendy,
+}
+
+impl Region {
+    pub fn parse(name: &str) -> Option<Self> {
+        match name.to_ascii_lowercase().as_str() {
+            "ntsc" => Some(Region::Ntsc),
+            "pal" => Some(Region::Pal),
+            "dendy" => Some(Region::Dendy),
+            _ => None,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Region::Ntsc => "NTSC",
+            Region::Pal => "PAL",
+            Region::Dendy => "Dendy",
+        }
+    }
+
+    pub fn frame_rate(self) -> f64 {
+        match self {
+            Region::Ntsc => 60.0988,
+            Region::Pal | Region::Dendy => 50.0070,
+        }
+    }
+
+    // The crystal everything else is divided down from, in cycles per second: six times the
+    // NTSC color subcarrier, and six times the PAL one on PAL and Dendy.
+    pub fn master_clock(self) -> f64 {
+        match self {
+            Region::Ntsc => 236_250_000.0 / 11.0,
+            Region::Pal | Region::Dendy => 26_601_712.5,
+        }
+    }
+
+    // Master clock cycles per PPU dot: 4 on NTSC, 5 on PAL and Dendy.
+    pub fn master_per_dot(self) -> u8 {
+        match self {
+            Region::Ntsc => 4,
+            Region::Pal | Region::Dendy => 5,
+        }
+    }
+
+    // Master clock cycles per CPU cycle: 12 on NTSC, 16 on PAL, 15 on Dendy, which makes
+    // 3, 3.2 and 3 dots.
+    pub fn master_per_cpu_cycle(self) -> u8 {
+        match self {
+            Region::Ntsc => 12,
+            Region::Pal => 16,
+            Region::Dendy => 15,
+        }
+    }
+
+    // CPU cycles per second, which the APU also runs on.
+    pub fn cpu_clock(self) -> f64 {
+        self.master_clock() / self.master_per_cpu_cycle() as f64
+    }
+
+    // Scanline after the last visible-or-vblank line, where the PPU wraps to the pre-render line.
+    pub fn scanlines(self) -> i16 {
+        match self {
+            Region::Ntsc => 261,
+            Region::Pal | Region::Dendy => 311,
+        }
+    }
+
+    // Line on which the vblank flag is set and NMI fires. The Dendy keeps the NTSC
+    // post-render gap, so its extra 50 lines all come after NMI.
+    pub fn vblank_line(self) -> i16 {
+        match self {
+            Region::Ntsc | Region::Pal => 241,
+            Region::Dendy => 291,
+        }
+    }
+}
+
+// Frame rate for `refresh = 50` or `60`, which runs a game at that speed whatever its
+// region. Only the window's pacing changes; the console is still emulated with its own timing.
+pub fn refresh_rate(hz: &str) -> Option<f64> {
+    match hz {
+        "50" => Some(Region::Pal.frame_rate()),
+        "60" => Some(Region::Ntsc.frame_rate()),
+        _ => None,
+    }
+}
+
+// Why a region was chosen, for the startup message.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Source {
+    Override,
+    Database,
+    Header,
+    FileName,
+    Default,
+}
+
+// Region tags from the usual No-Intro / GoodNES file names.
+fn from_file_name(rom_path: &str) -> Option<Region> {
+    let name = Path::new(rom_path).file_name()?.to_string_lossy().to_ascii_lowercase();
+    const TAGS: [(&str, Region); 9] = [
+        ("(dendy)", Region::Dendy),
+        ("(e)", Region::Pal),
+        ("(europe)", Region::Pal),
+        ("(pal)", Region::Pal),
+        ("(australia)", Region::Pal),
+        ("(u)", Region::Ntsc),
+        ("(usa)", Region::Ntsc),
+        ("(j)", Region::Ntsc),
+        ("(japan)", Region::Ntsc),
+    ];
+    TAGS.iter().find(|(tag, _)| name.contains(tag)).map(|&(_, region)| region)
+}
+
+// Picks the region for a game: an explicit override, then the ROM database, then the
+// NES 2.0 timing field, then file name tags, falling back to NTSC.
+pub fn detect(cart: &Cartridge, rom_path: &str, forced: Option<Region>) -> (Region, Source) {
+    if let Some(region) = forced {
+        return (region, Source::Override);
+    }
+    if let Some(region) = romdb::lookup(cart.rom_hash()).and_then(|entry| entry.region) {
+        return (region, Source::Database);
+    }
+    if let Some(region) = cart.timing {
+        return (region, Source::Header);
+    }
+    if let Some(region) = from_file_name(rom_path) {
+        return (region, Source::FileName);
+    }
+    (Region::Ntsc, Source::Default)
+}
@@ -0,0 +1,123 @@
+use std::fs::{self, File};
+use std::io::{BufWriter, ErrorKind, Write};
+use std::path::Path;
+use std::process::{Command, Stdio};
+
+use crate::log_warn;
+use crate::movie::Movie;
+use crate::movietest;
+use crate::nes::NES;
+use crate::quirks;
+use crate::region::{self, Region};
+
+// Offline movie rendering: replays an .fm2 movie as fast as emulation allows and pipes
+// raw frames to ffmpeg, which picks the codec from the output file name:
+//
+//   zetr render donkeykong.nes dk-level1.fm2 -o dk.mkv
+//
+// The result plays at exactly the console's frame rate no matter how fast the host ran.
+// ffmpeg only takes one stream on stdin, so the movie is played twice: once to collect the
+// APU's samples into a temporary file, then again for the frames, with that file as the
+// second input. Playback is deterministic, so both passes see the same game.
+
+// Samples per emulated second, so the audio track lasts exactly as long as the video.
+const SAMPLE_RATE: u32 = 48000;
+
+pub struct RenderOptions {
+    pub rom: String,
+    pub movie: String,
+    pub output: String,
+    pub scale: u32,
+}
+
+// Returns the number of frames rendered.
+pub fn run(options: &RenderOptions) -> Result<usize, Box<dyn std::error::Error>> {
+    let movie = Movie::load(&options.movie)?;
+    let (mut nes, region) = boot(options)?;
+    for warning in movie.verify(&nes)? {
+        log_warn!("Warning: movie {}", warning);
+    }
+
+    let audio_path = std::env::temp_dir().join(format!("zetr-render-{}.f32", std::process::id()));
+    let channels = nes.audio_channels();
+    let result = record_audio(&mut nes, &movie, &audio_path).and_then(|()| {
+        let (mut nes, _) = boot(options)?;
+        encode(&mut nes, &movie, region, options, &audio_path, channels)
+    });
+    let _ = fs::remove_file(&audio_path);
+    result?;
+    Ok(movie.frames.len())
+}
+
+// A freshly reset console with the ROM's region and quirks, producing sound.
+fn boot(options: &RenderOptions) -> Result<(NES, Region), Box<dyn std::error::Error>> {
+    let mut nes = NES::new();
+    nes.load_cartridge(&options.rom)?;
+    let cart = nes.cartridge().ok_or("no cartridge")?;
+    let (region, _) = region::detect(cart, &options.rom, None);
+    let active_quirks = quirks::detect(cart.rom_hash(), &[]);
+    nes.set_region(region);
+    for (quirk, _) in active_quirks {
+        quirk.apply(&mut nes);
+    }
+    nes.set_sample_rate(SAMPLE_RATE);
+    nes.reset();
+    Ok((nes, region))
+}
+
+// First pass: the whole soundtrack as raw little-endian f32.
+fn record_audio(nes: &mut NES, movie: &Movie, path: &Path) -> Result<(), Box<dyn std::error::Error>> {
+    let mut out = BufWriter::new(File::create(path).map_err(|e| format!("could not create {}: {}", path.display(), e))?);
+    for frame in &movie.frames {
+        movietest::play_frame(nes, frame);
+        for sample in nes.take_samples() {
+            out.write_all(&sample.to_le_bytes())?;
+        }
+    }
+    out.flush()?;
+    Ok(())
+}
+
+// Second pass: frames to ffmpeg's stdin, muxed with the recorded audio.
+fn encode(
+    nes: &mut NES,
+    movie: &Movie,
+    region: Region,
+    options: &RenderOptions,
+    audio_path: &Path,
+    channels: usize,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let mut command = Command::new("ffmpeg");
+    command
+        .args(["-loglevel", "error", "-y"])
+        .args(["-f", "rawvideo", "-pixel_format", "rgb24", "-video_size", "256x240"])
+        .args(["-framerate", &region.frame_rate().to_string(), "-i", "-"])
+        .args(["-f", "f32le", "-ar", &SAMPLE_RATE.to_string(), "-ac", &channels.to_string(), "-i"])
+        .arg(audio_path);
+    if options.scale > 1 {
+        // Nearest neighbour keeps the pixels sharp
+        command.args(["-vf", &format!("scale=iw*{0}:ih*{0}:flags=neighbor", options.scale)]);
+    }
+    let mut ffmpeg = command
+        .arg(&options.output)
+        .stdin(Stdio::piped())
+        .spawn()
+        .map_err(|e| match e.kind() {
+            ErrorKind::NotFound => "ffmpeg not found, it must be installed and on the PATH".to_string(),
+            _ => format!("could not start ffmpeg: {}", e),
+        })?;
+
+    let mut stdin = ffmpeg.stdin.take().ok_or("ffmpeg has no stdin")?;
+    for (number, frame) in movie.frames.iter().enumerate() {
+        movietest::play_frame(nes, frame);
+        nes.take_samples();
+        stdin.write_all(nes.get_frame_buffer()).map_err(|e| format!("ffmpeg stopped at frame {}: {}", number, e))?;
+    }
+    drop(stdin);
+
+    let status = ffmpeg.wait()?;
+    if !status.success() {
+        return Err(format!("ffmpeg failed ({})", status).into());
+    }
+    Ok(())
+}
@@ -0,0 +1,22 @@
+use crate::quirks::Quirk;
+use crate::region::Region;
+
+// Built-in per-game facts, keyed by Cartridge::rom_hash. Only things that cannot be read
+// reliably from the header belong here, plus the compatibility quirks of quirks.rs.
+pub struct Entry {
+    pub hash: u64,
+    pub name: &'static str,
+    pub region: Option<Region>,
+    pub battery: Option<bool>, // PRG RAM is (or isn't) kept, whatever the header says
+    pub quirks: &'static [Quirk],
+}
+
+const ENTRIES: &[Entry] = &[
+    Entry { hash: DONKEY_KONG, name: "Donkey Kong", region: Some(Region::Ntsc), battery: Some(false), quirks: &[] },
+];
+
+const DONKEY_KONG: u64 = 0x63DE_58A4_9F53_3251;
+
+pub fn lookup(hash: u64) -> Option<&'static Entry> {
+    ENTRIES.iter().find(|entry| entry.hash == hash)
+}
@@ -0,0 +1,225 @@
+use std::fs;
+use std::io::{Cursor, Read, Write};
+use std::path::Path;
+use std::time::{SystemTime, UNIX_EPOCH};
+use byteorder::{LittleEndian, ReadBytesExt, WriteBytesExt};
+
+use crate::nes::NES;
+
+// Save state file layout:
+//   "ZETRSTATE", u8 version, u64 ROM hash, u64 unix time, u64 frame count,
+//   u8 label length + label, 64x60 RGB24 thumbnail, then the machine snapshot.
+// The header can be read on its own, which is all the state manager needs.
+//
+// VERSION goes up whenever the snapshot layout changes, since a snapshot has no field tags
+// and an older one would load shifted rather than fail. Version 2 covers the CPU phase,
+// secondary OAM, layer-tagged sprites, controller port latches, the APU with its frame
+// counter and channel enables, and mapper registers after the cartridge RAM.
+const MAGIC: &[u8] = b"ZETRSTATE";
+const VERSION: u8 = 2;
+
+pub const SLOTS: usize = 10;
+pub const THUMB_WIDTH: usize = 64;
+pub const THUMB_HEIGHT: usize = 60;
+pub const MAX_LABEL: usize = 24;
+
+pub struct StateInfo {
+    pub rom_hash: u64,
+    pub saved_at: u64,
+    pub frame_count: u64,
+    pub label: String,
+    pub thumbnail: Vec<u8>,
+}
+
+pub fn slot_path(rom_path: &str, slot: usize) -> String {
+    format!("{}.ss{}", rom_path, slot)
+}
+
+// Averages each 4x4 block of the 256x240 frame.
+fn thumbnail(frame: &[u8]) -> Vec<u8> {
+    let mut thumb = vec![0u8; THUMB_WIDTH * THUMB_HEIGHT * 3];
+    for ty in 0..THUMB_HEIGHT {
+        for tx in 0..THUMB_WIDTH {
+            for c in 0..3 {
+                let mut sum = 0u32;
+                for y in ty * 4..ty * 4 + 4 {
+                    for x in tx * 4..tx * 4 + 4 {
+                        sum += frame[(y * 256 + x) * 3 + c] as u32;
+                    }
+                }
+                thumb[(ty * THUMB_WIDTH + tx) * 3 + c] = (sum / 16) as u8;
+            }
+        }
+    }
+    thumb
+}
+
+fn write_header(w: &mut dyn Write, info: &StateInfo) -> std::io::Result<()> {
+    w.write_all(MAGIC)?;
+    w.write_u8(VERSION)?;
+    w.write_u64::<LittleEndian>(info.rom_hash)?;
+    w.write_u64::<LittleEndian>(info.saved_at)?;
+    w.write_u64::<LittleEndian>(info.frame_count)?;
+    w.write_u8(info.label.len() as u8)?;
+    w.write_all(info.label.as_bytes())?;
+    w.write_all(&info.thumbnail)
+}
+
+fn read_header(r: &mut dyn Read) -> Result<StateInfo, Box<dyn std::error::Error>> {
+    let mut magic = [0u8; MAGIC.len()];
+    r.read_exact(&mut magic)?;
+    if magic != MAGIC {
+        return Err("Not a save state file".into());
+    }
+    let version = r.read_u8()?;
+    if version < VERSION {
+        return Err(format!("Save state is from an older zetr (version {}, this build reads {}) and can't be loaded", version, VERSION).into());
+    }
+    if version > VERSION {
+        return Err(format!("Save state is from a newer zetr (version {}, this build reads {}) and can't be loaded", version, VERSION).into());
+    }
+    let rom_hash = r.read_u64::<LittleEndian>()?;
+    let saved_at = r.read_u64::<LittleEndian>()?;
+    let frame_count = r.read_u64::<LittleEndian>()?;
+    let mut label = vec![0u8; r.read_u8()? as usize];
+    r.read_exact(&mut label)?;
+    let mut thumbnail = vec![0u8; THUMB_WIDTH * THUMB_HEIGHT * 3];
+    r.read_exact(&mut thumbnail)?;
+    Ok(StateInfo {
+        rom_hash,
+        saved_at,
+        frame_count,
+        label: String::from_utf8_lossy(&label).into_owned(),
+        thumbnail,
+    })
+}
+
+// Keeps labels to what the header and the OSD font can hold.
+fn clean_label(label: &str) -> String {
+    label.chars().filter(|c| c.is_ascii() && !c.is_ascii_control()).take(MAX_LABEL).collect()
+}
+
+pub fn save(nes: &NES, path: &str, label: &str) -> Result<(), Box<dyn std::error::Error>> {
+    write_file(path, &to_bytes(nes, label)?)
+}
+
+// Saves to a slot, keeping the state it replaces for undo_save.
+pub fn save_slot(nes: &NES, path: &str) -> Result<(), Box<dyn std::error::Error>> {
+    let data = to_bytes(nes, "")?;
+    let undo = undo_path(path);
+    if Path::new(path).exists() {
+        fs::copy(path, &undo)?;
+    } else if Path::new(&undo).exists() {
+        // Left from an older save; undoing this one would bring back something unexpected
+        fs::remove_file(&undo)?;
+    }
+    write_file(path, &data)
+}
+
+fn undo_path(path: &str) -> String {
+    format!("{}.undo", path)
+}
+
+// Puts back the state the last save_slot replaced. The undone one takes its place, so
+// undoing again redoes the save.
+pub fn undo_save(path: &str) -> Result<(), Box<dyn std::error::Error>> {
+    let undo = undo_path(path);
+    if !Path::new(&undo).exists() {
+        return Err("nothing to undo".into());
+    }
+    if !Path::new(path).exists() {
+        fs::rename(&undo, path)?;
+        return Ok(());
+    }
+    let temp = format!("{}.tmp", path);
+    fs::rename(path, &temp)?;
+    fs::rename(&undo, path)?;
+    fs::rename(&temp, &undo)?;
+    Ok(())
+}
+
+// A whole state file in memory, e.g. for the clipboard.
+pub fn to_bytes(nes: &NES, label: &str) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
+    let cart = nes.cartridge().ok_or("No cartridge loaded")?;
+    let info = StateInfo {
+        rom_hash: cart.rom_hash(),
+        saved_at: SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |d| d.as_secs()),
+        frame_count: nes.ppu().frame_count,
+        label: clean_label(label),
+        thumbnail: thumbnail(nes.get_frame_buffer()),
+    };
+    let mut data = Vec::new();
+    write_header(&mut data, &info)?;
+    nes.save_state(&mut data)?;
+    Ok(data)
+}
+
+// Through a temporary file, so another instance loading the same slot never sees half a state.
+fn write_file(path: &str, data: &[u8]) -> Result<(), Box<dyn std::error::Error>> {
+    let temp = format!("{}.tmp", path);
+    fs::write(&temp, data)?;
+    fs::rename(&temp, path)?;
+    Ok(())
+}
+
+pub fn read_info(path: &str) -> Result<StateInfo, Box<dyn std::error::Error>> {
+    read_header(&mut fs::File::open(path)?)
+}
+
+pub fn load(nes: &mut NES, path: &str) -> Result<StateInfo, Box<dyn std::error::Error>> {
+    from_bytes(nes, &fs::read(path)?)
+}
+
+pub fn from_bytes(nes: &mut NES, data: &[u8]) -> Result<StateInfo, Box<dyn std::error::Error>> {
+    let mut r = Cursor::new(data);
+    let info = read_header(&mut r)?;
+    let cart = nes.cartridge().ok_or("No cartridge loaded")?;
+    if info.rom_hash != cart.rom_hash() {
+        return Err("Save state belongs to a different ROM".into());
+    }
+
+    // Keep the current machine so a truncated file cannot leave it half loaded
+    let mut backup = Vec::new();
+    nes.save_state(&mut backup)?;
+    if let Err(e) = nes.load_state(&mut r) {
+        nes.load_state(&mut Cursor::new(&backup))?;
+        return Err(format!("Corrupt save state: {}", e).into());
+    }
+    // Bytes left over mean the layout doesn't match this build, even if the version did
+    if r.position() != data.len() as u64 {
+        nes.load_state(&mut Cursor::new(&backup))?;
+        return Err("Corrupt save state: trailing data after the snapshot".into());
+    }
+    Ok(info)
+}
+
+// Rewrites the header in place, leaving the snapshot untouched.
+pub fn rename(path: &str, label: &str) -> Result<(), Box<dyn std::error::Error>> {
+    let data = fs::read(path)?;
+    let mut r = Cursor::new(&data);
+    let mut info = read_header(&mut r)?;
+    info.label = clean_label(label);
+    let mut out = Vec::new();
+    write_header(&mut out, &info)?;
+    out.extend_from_slice(&data[r.position() as usize..]);
+    write_file(path, &out)
+}
+
+// "YYYY-MM-DD HH:MM" in UTC.
+pub fn format_date(unix_secs: u64) -> String {
+    let days = (unix_secs / 86400) as i64;
+    let secs = unix_secs % 86400;
+
+    // Civil date from a day count, after Howard Hinnant's days_from_civil inverse
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z - era * 146097;
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + (month <= 2) as i64;
+
+    format!("{:04}-{:02}-{:02} {:02}:{:02}", year, month, day, secs / 3600, secs / 60 % 60)
+}
@@ -0,0 +1,55 @@
+use std::cmp::Reverse;
+use std::collections::BinaryHeap;
+
+// Future events, keyed on the master clock cycle of the PPU dot they fall on. Subsystems that
+// act on particular cycles, rather than every one, schedule them here and NES::clock runs
+// whatever is due instead of each of them checking a counter every cycle. For now that is the
+// APU frame counter; mapper IRQ counters and DMC fetches belong here too once they exist.
+//
+// Events due in the same dot run in the order of the enum.
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Event {
+    // A $4017 write restarting the frame sequence. Before the steps, which it cancels.
+    FrameReset,
+    // The frame sequence reaching this many CPU cycles since it started.
+    FrameStep(u32),
+}
+
+#[derive(Default)]
+pub struct Scheduler {
+    events: BinaryHeap<Reverse<(u64, Event)>>,
+}
+
+impl Scheduler {
+    pub fn new() -> Self {
+        Scheduler::default()
+    }
+
+    pub fn schedule(&mut self, at: u64, event: Event) {
+        self.events.push(Reverse((at, event)));
+    }
+
+    // Drops pending events that match.
+    pub fn cancel(&mut self, matches: impl Fn(Event) -> bool) {
+        self.events.retain(|Reverse((_, event))| !matches(*event));
+    }
+
+    pub fn clear(&mut self) {
+        self.events.clear();
+    }
+
+    // The master cycle of the earliest pending event.
+    pub fn next_at(&self) -> Option<u64> {
+        self.events.peek().map(|Reverse((at, _))| *at)
+    }
+
+    // Takes the next event due at or before `now`, one at a time so that handling one can
+    // cancel or add others.
+    pub fn pop_due(&mut self, now: u64) -> Option<Event> {
+        if self.next_at()? > now {
+            return None;
+        }
+        self.events.pop().map(|Reverse((_, event))| event)
+    }
+}
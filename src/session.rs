@@ -0,0 +1,81 @@
+use std::fs;
+use std::io::ErrorKind;
+use std::path::PathBuf;
+
+use crate::filelock::FileLock;
+use crate::nes::NES;
+use crate::playtime;
+use crate::savestate;
+
+// Fast resume. When the window is closed the game is saved to session.state in the data
+// directory, and session.cfg records how to reopen it:
+//
+//   rom = /home/me/roms/donkeykong.nes
+//   window = 640, 320
+//   rate = 50.007
+//
+// with the window position, and the frame rate only when it was forced with --refresh.
+// Unlike game configs there are no comments, since # is fine in a ROM path. `zetr --resume`
+// starts that game from the saved state, with the window where it was. Only one session is
+// kept; the next game played replaces it.
+
+pub struct Session {
+    pub rom_path: String,
+    pub window: Option<(i32, i32)>,
+    pub rate: Option<f64>,
+}
+
+fn dir() -> Result<PathBuf, String> {
+    playtime::data_dir().ok_or("no home directory".to_string())
+}
+
+fn config_path() -> Result<String, String> {
+    Ok(dir()?.join("session.cfg").to_string_lossy().into_owned())
+}
+
+pub fn state_path() -> Result<String, String> {
+    Ok(dir()?.join("session.state").to_string_lossy().into_owned())
+}
+
+impl Session {
+    // None if no session has been saved.
+    pub fn load() -> Result<Option<Self>, Box<dyn std::error::Error>> {
+        let text = match fs::read_to_string(config_path()?) {
+            Ok(text) => text,
+            Err(e) if e.kind() == ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e.into()),
+        };
+        let get = |key: &str| {
+            text.lines().filter_map(|line| line.split_once('=')).find(|(k, _)| k.trim() == key).map(|(_, value)| value.trim())
+        };
+        let Some(rom_path) = get("rom") else {
+            return Ok(None);
+        };
+        let window = get("window").and_then(|pos| {
+            let (x, y) = pos.split_once(',')?;
+            Some((x.trim().parse().ok()?, y.trim().parse().ok()?))
+        });
+        let rate = get("rate").and_then(|rate| rate.parse().ok());
+        Ok(Some(Session { rom_path: rom_path.to_string(), window, rate }))
+    }
+
+    // Writes the state first, so a session file never points at a missing or older state.
+    // Locked so two windows closing together can't leave one's state under the other's ROM.
+    pub fn save(&self, nes: &NES) -> Result<(), Box<dyn std::error::Error>> {
+        fs::create_dir_all(dir()?)?;
+        let path = config_path()?;
+        let _lock = FileLock::acquire(&path)?;
+        savestate::save(nes, &state_path()?, "Session")?;
+        let mut text = format!("rom = {}\n", self.rom_path);
+        if let Some((x, y)) = self.window {
+            text += &format!("window = {}, {}\n", x, y);
+        }
+        if let Some(rate) = self.rate {
+            text += &format!("rate = {}\n", rate);
+        }
+        let temp = format!("{}.tmp", path);
+        fs::write(&temp, text)?;
+        fs::rename(&temp, &path)?;
+        Ok(())
+    }
+}
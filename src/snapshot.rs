@@ -0,0 +1,139 @@
+use serde::Serialize;
+
+use crate::debugger;
+use crate::nes::NES;
+use crate::symbols::SymbolTable;
+
+// A plain copy of the machine state for test harnesses and scripts to assert on, instead of
+// parsing trace logs: CPU registers and flags, PPU registers and position, the frame counters,
+// and whichever memory ranges were asked for, read the way the debugger reads them (no side
+// effects). `--snapshot` writes one as JSON at the end of a headless run.
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Snapshot {
+    pub region: &'static str,
+    pub cpu: CpuState,
+    pub ppu: PpuState,
+    pub counters: CounterState,
+    pub memory: Vec<MemoryRange>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct CpuState {
+    pub pc: u16,
+    pub a: u8,
+    pub x: u8,
+    pub y: u8,
+    pub sp: u8,
+    pub status: u8,
+    pub flags: Flags,
+}
+
+// The status register bit by bit, B and the unused bit left out.
+#[derive(Debug, Clone, Serialize)]
+pub struct Flags {
+    pub carry: bool,
+    pub zero: bool,
+    pub interrupt_disable: bool,
+    pub decimal: bool,
+    pub overflow: bool,
+    pub negative: bool,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct PpuState {
+    pub ctrl: u8,
+    pub mask: u8,
+    pub status: u8,
+    pub oam_addr: u8,
+    pub vram_addr: u16,
+    pub temp_vram_addr: u16,
+    pub fine_x_scroll: u8,
+    pub write_toggle: bool,
+    pub scanline: i16,
+    pub dot: u16,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct CounterState {
+    pub frame: u64,
+    pub cpu_cycles: u64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct MemoryRange {
+    pub start: u16,
+    pub bytes: Vec<u8>,
+}
+
+// Console RAM, when no ranges are given.
+pub const DEFAULT_RANGES: [(u16, u16); 1] = [(0x0000, 0x07FF)];
+
+impl Snapshot {
+    // Ranges are inclusive CPU address pairs.
+    pub fn capture(nes: &NES, ranges: &[(u16, u16)]) -> Self {
+        let cpu = nes.cpu();
+        let ppu = nes.ppu();
+        let counters = nes.counters();
+        let flag = |bit: u8| cpu.status & bit != 0;
+        Snapshot {
+            region: nes.region().name(),
+            cpu: CpuState {
+                pc: cpu.pc,
+                a: cpu.a,
+                x: cpu.x,
+                y: cpu.y,
+                sp: cpu.sp,
+                status: cpu.status,
+                flags: Flags {
+                    carry: flag(0x01),
+                    zero: flag(0x02),
+                    interrupt_disable: flag(0x04),
+                    decimal: flag(0x08),
+                    overflow: flag(0x40),
+                    negative: flag(0x80),
+                },
+            },
+            ppu: PpuState {
+                ctrl: ppu.ctrl,
+                mask: ppu.mask,
+                status: ppu.status,
+                oam_addr: ppu.oam_addr,
+                vram_addr: ppu.vram_addr,
+                temp_vram_addr: ppu.temp_vram_addr,
+                fine_x_scroll: ppu.fine_x_scroll,
+                write_toggle: ppu.write_toggle,
+                scanline: counters.scanline,
+                dot: counters.dot,
+            },
+            counters: CounterState { frame: counters.frame, cpu_cycles: counters.cpu_cycles },
+            memory: ranges
+                .iter()
+                .map(|&(start, end)| MemoryRange { start, bytes: (start..=end).map(|addr| nes.peek(addr)).collect() })
+                .collect(),
+        }
+    }
+
+    pub fn to_json(&self) -> String {
+        serde_json::to_string_pretty(self).expect("snapshots always serialize")
+    }
+}
+
+// Parses "<start>-<end>" or "<start>+<length>", with addresses as the debugger takes them
+// (hex with or without $ or 0x, or a label).
+pub fn parse_range(text: &str, symbols: &SymbolTable) -> Result<(u16, u16), String> {
+    let bad = || format!("bad memory range '{}'", text);
+    if let Some((start, length)) = text.split_once('+') {
+        let start = debugger::parse_addr(start, symbols).ok_or_else(bad)?;
+        let length = debugger::parse_addr(length, symbols).filter(|&n| n > 0).ok_or_else(bad)?;
+        let end = start.checked_add(length - 1).ok_or_else(bad)?;
+        return Ok((start, end));
+    }
+    let (start, end) = text.split_once('-').unwrap_or((text, text));
+    let start = debugger::parse_addr(start, symbols).ok_or_else(bad)?;
+    let end = debugger::parse_addr(end, symbols).ok_or_else(bad)?;
+    if end < start {
+        return Err(bad());
+    }
+    Ok((start, end))
+}
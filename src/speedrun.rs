@@ -0,0 +1,215 @@
+use std::fs;
+use std::io::Write;
+use std::net::TcpStream;
+
+use crate::expr::{self, Expr};
+use crate::log_warn;
+use crate::osd;
+use crate::region::Region;
+use crate::symbols::SymbolTable;
+
+struct Split {
+    name: String,
+    condition: Expr,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum TimerState {
+    Idle,
+    Running,
+    Finished,
+}
+
+// Practice timer driven by memory conditions from a per-game split file:
+//
+//   # name = condition, checked once per frame
+//   start = [$0400] == 1
+//   Barrels = [$0053] == 2
+//   Elevators = [$0053] == 3
+//   reset = [$0400] == 0
+//
+// The timer starts when `start` becomes true, each split fires in order when its
+// condition becomes true, and `reset` (optional) returns to idle. Time is counted
+// in emulated frames so it does not depend on host speed.
+pub struct SpeedrunTimer {
+    start: Expr,
+    reset: Option<Expr>,
+    splits: Vec<Split>,
+    state: TimerState,
+    start_frame: u64,
+    split_frames: Vec<u64>,
+    last_frame: u64,
+    was_true: bool,
+    reset_was_true: bool,
+    livesplit: Option<TcpStream>,
+    frame_rate: f64,
+}
+
+impl SpeedrunTimer {
+    pub fn load(path: &str, symbols: &SymbolTable) -> Result<Self, Box<dyn std::error::Error>> {
+        let mut start = None;
+        let mut reset = None;
+        let mut splits = Vec::new();
+
+        for (number, line) in fs::read_to_string(path)?.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let (name, condition) = line
+                .split_once('=')
+                .filter(|(_, rest)| !rest.starts_with('='))
+                .ok_or(format!("{}:{}: expected 'name = condition'", path, number + 1))?;
+            let condition = Expr::parse(condition.trim(), symbols)
+                .map_err(|e| format!("{}:{}: {}", path, number + 1, e))?;
+            match name.trim() {
+                "start" => start = Some(condition),
+                "reset" => reset = Some(condition),
+                name => splits.push(Split { name: name.to_string(), condition }),
+            }
+        }
+
+        Ok(SpeedrunTimer {
+            start: start.ok_or(format!("{}: no start condition", path))?,
+            reset,
+            splits,
+            state: TimerState::Idle,
+            start_frame: 0,
+            split_frames: Vec::new(),
+            last_frame: 0,
+            was_true: false,
+            reset_was_true: false,
+            livesplit: None,
+            frame_rate: Region::Ntsc.frame_rate(),
+        })
+    }
+
+    // Mirrors start/split/reset to a LiveSplit Server component.
+    pub fn connect_livesplit(&mut self, addr: &str) -> std::io::Result<()> {
+        let stream = TcpStream::connect(addr)?;
+        stream.set_nodelay(true)?;
+        self.livesplit = Some(stream);
+        Ok(())
+    }
+
+    fn send(&mut self, command: &str) {
+        if let Some(stream) = self.livesplit.as_mut() {
+            if let Err(e) = stream.write_all(format!("{}\r\n", command).as_bytes()) {
+                log_warn!("LiveSplit connection lost: {}", e);
+                self.livesplit = None;
+            }
+        }
+    }
+
+    // Frames are converted to time at the console's real rate, so PAL runs time correctly
+    pub fn set_region(&mut self, region: Region) {
+        self.frame_rate = region.frame_rate();
+    }
+
+    pub fn reset(&mut self) {
+        self.state = TimerState::Idle;
+        self.split_frames.clear();
+        self.was_true = false;
+        self.send("reset");
+    }
+
+    // A state load or reset moved the frame count back. Before the start the run is over;
+    // after it, splits that haven't happened yet at this frame are taken back.
+    fn rewind(&mut self, frame: u64) {
+        if self.state == TimerState::Idle {
+            return;
+        }
+        if frame < self.start_frame {
+            self.reset();
+            return;
+        }
+        while self.split_frames.last().is_some_and(|&split| split > frame) {
+            self.split_frames.pop();
+            self.send("unsplit");
+        }
+        if self.split_frames.len() < self.splits.len() {
+            self.state = TimerState::Running;
+        }
+        self.was_true = false;
+    }
+
+    // Called once per emulated frame.
+    pub fn update(&mut self, frame: u64, ctx: &dyn expr::Context) {
+        if frame < self.last_frame {
+            self.rewind(frame);
+        }
+        self.last_frame = frame;
+
+        if let Some(reset) = &self.reset {
+            let now = reset.is_true(ctx);
+            let rising = now && !self.reset_was_true;
+            self.reset_was_true = now;
+            if rising && self.state != TimerState::Idle {
+                self.reset();
+                return;
+            }
+        }
+
+        let condition = match self.state {
+            TimerState::Idle => &self.start,
+            TimerState::Running => match self.splits.get(self.split_frames.len()) {
+                Some(split) => &split.condition,
+                None => return,
+            },
+            TimerState::Finished => return,
+        };
+        let now = condition.is_true(ctx);
+        let rising = now && !self.was_true;
+        self.was_true = now;
+        if !rising {
+            return;
+        }
+
+        match self.state {
+            TimerState::Idle => {
+                self.state = TimerState::Running;
+                self.start_frame = frame;
+                self.was_true = false;
+                self.send("starttimer");
+            }
+            _ => {
+                self.split_frames.push(frame);
+                self.was_true = false;
+                if self.split_frames.len() == self.splits.len() {
+                    self.state = TimerState::Finished;
+                }
+                self.send("split");
+            }
+        }
+    }
+
+    fn elapsed_frames(&self) -> u64 {
+        match self.state {
+            TimerState::Idle => 0,
+            TimerState::Running => self.last_frame.saturating_sub(self.start_frame),
+            TimerState::Finished => self.split_frames.last().copied().unwrap_or(self.start_frame).saturating_sub(self.start_frame),
+        }
+    }
+
+    pub fn draw(&self, frame: &mut [u8]) {
+        let time = format_time(self.elapsed_frames(), self.frame_rate);
+        let color = match self.state {
+            TimerState::Idle => osd::WHITE,
+            TimerState::Running => osd::YELLOW,
+            TimerState::Finished => osd::GREEN,
+        };
+        let x = 256 - osd::text_width(&time) - 4;
+        osd::draw_label(frame, x, 2, &time, color);
+
+        let done = self.split_frames.len();
+        if let Some(split) = self.splits.get(done).filter(|_| self.state == TimerState::Running) {
+            let text = format!("{}/{} {}", done + 1, self.splits.len(), split.name);
+            osd::draw_label(frame, 256 - osd::text_width(&text) - 4, 2 + osd::CHAR_HEIGHT + 3, &text, osd::WHITE);
+        }
+    }
+}
+
+fn format_time(frames: u64, frame_rate: f64) -> String {
+    let centis = (frames as f64 * 100.0 / frame_rate) as u64;
+    format!("{}:{:02}.{:02}", centis / 6000, centis / 100 % 60, centis % 100)
+}
@@ -0,0 +1,155 @@
+use std::fs;
+use sdl2::keyboard::Keycode;
+
+use crate::osd;
+use crate::region::Region;
+use crate::savestate::{self, StateInfo};
+
+pub enum MenuAction {
+    None,
+    Close,
+    Load(usize),
+}
+
+// Full-screen list of the loaded game's save state slots. Emulation is paused
+// while it is open; the frontend feeds it key presses and text input.
+pub struct StateMenu {
+    rom_path: String,
+    slots: Vec<Option<StateInfo>>,
+    selected: usize,
+    renaming: Option<String>,
+    rename_key_text: bool, // The R that started a rename still has its TextInput to come
+    error: Option<String>,
+    frame_rate: f64,
+}
+
+impl StateMenu {
+    // Play times are counted at the region's frame rate.
+    pub fn open(rom_path: &str, selected: usize, region: Region) -> Self {
+        let mut menu = StateMenu {
+            rom_path: rom_path.to_string(),
+            slots: Vec::new(),
+            selected,
+            renaming: None,
+            rename_key_text: false,
+            error: None,
+            frame_rate: region.frame_rate(),
+        };
+        menu.refresh();
+        menu
+    }
+
+    fn refresh(&mut self) {
+        self.slots = (0..savestate::SLOTS)
+            .map(|slot| savestate::read_info(&savestate::slot_path(&self.rom_path, slot)).ok())
+            .collect();
+    }
+
+    pub fn selected(&self) -> usize {
+        self.selected
+    }
+
+    pub fn handle_key(&mut self, keycode: Keycode) -> MenuAction {
+        self.rename_key_text = false;
+        if let Some(label) = self.renaming.as_mut() {
+            match keycode {
+                Keycode::Return => {
+                    let path = savestate::slot_path(&self.rom_path, self.selected);
+                    if let Err(e) = savestate::rename(&path, label) {
+                        self.error = Some(e.to_string());
+                    }
+                    self.renaming = None;
+                    self.refresh();
+                }
+                Keycode::Escape => self.renaming = None,
+                Keycode::Backspace => {
+                    label.pop();
+                }
+                _ => {}
+            }
+            return MenuAction::None;
+        }
+
+        self.error = None;
+        match keycode {
+            Keycode::Escape | Keycode::F8 => return MenuAction::Close,
+            Keycode::Up => self.selected = (self.selected + savestate::SLOTS - 1) % savestate::SLOTS,
+            Keycode::Down => self.selected = (self.selected + 1) % savestate::SLOTS,
+            Keycode::Return if self.slots[self.selected].is_some() => return MenuAction::Load(self.selected),
+            Keycode::Delete if self.slots[self.selected].is_some() => {
+                if let Err(e) = fs::remove_file(savestate::slot_path(&self.rom_path, self.selected)) {
+                    self.error = Some(e.to_string());
+                }
+                self.refresh();
+            }
+            Keycode::R => {
+                if let Some(info) = &self.slots[self.selected] {
+                    self.renaming = Some(info.label.clone());
+                    self.rename_key_text = true;
+                }
+            }
+            _ => {}
+        }
+        MenuAction::None
+    }
+
+    pub fn handle_text(&mut self, text: &str) {
+        if std::mem::take(&mut self.rename_key_text) && text.eq_ignore_ascii_case("r") {
+            return;
+        }
+        if let Some(label) = self.renaming.as_mut() {
+            for c in text.chars() {
+                if label.len() < savestate::MAX_LABEL && c.is_ascii() && !c.is_ascii_control() {
+                    label.push(c);
+                }
+            }
+        }
+    }
+
+    pub fn draw(&self, frame: &mut [u8]) {
+        osd::shade_rect(frame, 0, 0, 256, 240);
+        osd::draw_text(frame, 8, 8, "SAVE STATES", osd::WHITE);
+
+        let row_height = osd::CHAR_HEIGHT + 2;
+        for (slot, info) in self.slots.iter().enumerate() {
+            let y = 24 + slot * row_height;
+            let color = if slot == self.selected { osd::YELLOW } else { osd::WHITE };
+            let marker = if slot == self.selected { '>' } else { ' ' };
+            let name = match info {
+                Some(info) if !info.label.is_empty() => info.label.clone(),
+                Some(info) => savestate::format_date(info.saved_at),
+                None => "-".to_string(),
+            };
+            osd::draw_text(frame, 8, y, &format!("{}{} {}", marker, slot, name), color);
+            if let Some(info) = info {
+                let time = format_play_time(info.frame_count, self.frame_rate);
+                osd::draw_text(frame, 248 - osd::text_width(&time), y, &time, color);
+            }
+        }
+
+        let detail_y = 24 + savestate::SLOTS * row_height + 8;
+        if let Some(info) = &self.slots[self.selected] {
+            osd::blit(frame, 8, detail_y, savestate::THUMB_WIDTH, savestate::THUMB_HEIGHT, &info.thumbnail);
+            let x = 8 + savestate::THUMB_WIDTH + 8;
+            osd::draw_text(frame, x, detail_y, &savestate::format_date(info.saved_at), osd::WHITE);
+            osd::draw_text(frame, x, detail_y + row_height, &format!("PLAYED {}", format_play_time(info.frame_count, self.frame_rate)), osd::WHITE);
+            match &self.renaming {
+                Some(label) => osd::draw_text(frame, x, detail_y + row_height * 3, &format!("{}_", label), osd::YELLOW),
+                None => osd::draw_text(frame, x, detail_y + row_height * 3, &info.label, osd::WHITE),
+            }
+        } else {
+            osd::draw_text(frame, 8, detail_y, "EMPTY SLOT", osd::WHITE);
+        }
+        if let Some(error) = &self.error {
+            osd::draw_text(frame, 8, detail_y + savestate::THUMB_HEIGHT + 6, error, osd::YELLOW);
+        }
+
+        let help = if self.renaming.is_some() { "ENTER:OK ESC:CANCEL" } else { "ENTER:LOAD DEL:DELETE R:RENAME ESC:BACK" };
+        osd::draw_text(frame, 8, 240 - osd::CHAR_HEIGHT - 4, help, osd::GREEN);
+    }
+}
+
+fn format_play_time(frames: u64, frame_rate: f64) -> String {
+    let secs = (frames as f64 / frame_rate) as u64;
+    format!("{}:{:02}:{:02}", secs / 3600, secs / 60 % 60, secs % 60)
+}
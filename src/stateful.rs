@@ -0,0 +1,99 @@
+use std::io::{self, Read, Write};
+
+use byteorder::{LittleEndian, ReadBytesExt, WriteBytesExt};
+
+// Save state serialization for plain fields. A component lists the fields that make up its
+// state once, and gets save and load that write and read them in that order:
+//
+//   stateful!(Latch { prg_bank, chr_bank, mirroring_vertical, ram });
+//
+// Integers are little-endian, bools one byte, and byte arrays and Vecs are written as is,
+// without a length; a Vec must already have its size when it is loaded, as RAM sized from
+// the ROM header does. Anything else (derived values, clamping after a load) stays in a
+// hand-written wrapper around these calls.
+
+pub trait Stateful {
+    fn save(&self, w: &mut dyn Write) -> io::Result<()>;
+    fn load(&mut self, r: &mut dyn Read) -> io::Result<()>;
+}
+
+impl Stateful for u8 {
+    fn save(&self, w: &mut dyn Write) -> io::Result<()> {
+        w.write_u8(*self)
+    }
+
+    fn load(&mut self, r: &mut dyn Read) -> io::Result<()> {
+        *self = r.read_u8()?;
+        Ok(())
+    }
+}
+
+impl Stateful for bool {
+    fn save(&self, w: &mut dyn Write) -> io::Result<()> {
+        w.write_u8(*self as u8)
+    }
+
+    fn load(&mut self, r: &mut dyn Read) -> io::Result<()> {
+        *self = r.read_u8()? != 0;
+        Ok(())
+    }
+}
+
+macro_rules! stateful_int {
+    ($($type:ty => $write:ident, $read:ident;)*) => {$(
+        impl Stateful for $type {
+            fn save(&self, w: &mut dyn Write) -> io::Result<()> {
+                w.$write::<LittleEndian>(*self)
+            }
+
+            fn load(&mut self, r: &mut dyn Read) -> io::Result<()> {
+                *self = r.$read::<LittleEndian>()?;
+                Ok(())
+            }
+        }
+    )*};
+}
+
+stateful_int! {
+    u16 => write_u16, read_u16;
+    i16 => write_i16, read_i16;
+    u32 => write_u32, read_u32;
+    u64 => write_u64, read_u64;
+}
+
+impl<const N: usize> Stateful for [u8; N] {
+    fn save(&self, w: &mut dyn Write) -> io::Result<()> {
+        w.write_all(self)
+    }
+
+    fn load(&mut self, r: &mut dyn Read) -> io::Result<()> {
+        r.read_exact(self)
+    }
+}
+
+impl Stateful for Vec<u8> {
+    fn save(&self, w: &mut dyn Write) -> io::Result<()> {
+        w.write_all(self)
+    }
+
+    fn load(&mut self, r: &mut dyn Read) -> io::Result<()> {
+        r.read_exact(self)
+    }
+}
+
+#[macro_export]
+macro_rules! stateful {
+    ($type:ty { $($field:ident),* $(,)? }) => {
+        impl $crate::stateful::Stateful for $type {
+            fn save(&self, w: &mut dyn std::io::Write) -> std::io::Result<()> {
+                $($crate::stateful::Stateful::save(&self.$field, w)?;)*
+                Ok(())
+            }
+
+            fn load(&mut self, r: &mut dyn std::io::Read) -> std::io::Result<()> {
+                $($crate::stateful::Stateful::load(&mut self.$field, r)?;)*
+                Ok(())
+            }
+        }
+    };
+}
@@ -0,0 +1,116 @@
+use std::collections::HashMap;
+use std::fs;
+
+// Label names for CPU addresses, loaded from FCEUX .nl files or ca65/ld65 .dbg files.
+// Labels are keyed by CPU address only; with NROM every bank maps to a fixed address.
+#[derive(Debug, Default)]
+pub struct SymbolTable {
+    labels: HashMap<u16, String>,
+    addresses: HashMap<String, u16>,
+}
+
+impl SymbolTable {
+    pub fn load(&mut self, path: &str) -> Result<usize, Box<dyn std::error::Error>> {
+        let contents = fs::read_to_string(path)?;
+        let count = if path.ends_with(".dbg") {
+            self.load_dbg(&contents)
+        } else {
+            self.load_nl(&contents)
+        };
+        Ok(count)
+    }
+
+    // Loads the FCEUX files that sit next to a ROM (game.nes.ram.nl, game.nes.0.nl, ...)
+    // and an ld65 game.dbg if one exists. Returns the number of files loaded.
+    pub fn load_for_rom(&mut self, rom_path: &str, prg_banks: usize) -> usize {
+        let mut candidates = vec![format!("{}.ram.nl", rom_path)];
+        for bank in 0..prg_banks {
+            candidates.push(format!("{}.{:X}.nl", rom_path, bank));
+        }
+        let stem = rom_path.strip_suffix(".nes").unwrap_or(rom_path);
+        candidates.push(format!("{}.dbg", stem));
+
+        candidates
+            .iter()
+            .filter(|path| std::path::Path::new(path).exists())
+            .filter(|path| self.load(path).is_ok())
+            .count()
+    }
+
+    pub fn insert(&mut self, addr: u16, name: &str) {
+        self.labels.insert(addr, name.to_string());
+        self.addresses.insert(name.to_string(), addr);
+    }
+
+    pub fn label(&self, addr: u16) -> Option<&str> {
+        self.labels.get(&addr).map(|s| s.as_str())
+    }
+
+    pub fn address(&self, name: &str) -> Option<u16> {
+        self.addresses.get(name).copied()
+    }
+
+    pub fn len(&self) -> usize {
+        self.labels.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.labels.is_empty()
+    }
+
+    // Label name if known, otherwise $XXXX (or $XX for zero page operands).
+    pub fn format_addr(&self, addr: u16, zero_page: bool) -> String {
+        match self.label(addr) {
+            Some(name) => name.to_string(),
+            None if zero_page => format!("${:02X}", addr),
+            None => format!("${:04X}", addr),
+        }
+    }
+
+    // FCEUX format: "$C000#Label#Comment", arrays as "$0300/10#Buffer#".
+    fn load_nl(&mut self, contents: &str) -> usize {
+        let mut count = 0;
+        for line in contents.lines() {
+            let mut fields = line.trim().splitn(3, '#');
+            let (Some(addr), Some(name)) = (fields.next(), fields.next()) else {
+                continue;
+            };
+            let addr = addr.trim_start_matches('$');
+            let addr = addr.split('/').next().unwrap_or(addr);
+            if name.is_empty() {
+                continue;
+            }
+            if let Ok(addr) = u16::from_str_radix(addr, 16) {
+                self.insert(addr, name);
+                count += 1;
+            }
+        }
+        count
+    }
+
+    // ld65 format: "sym\tid=0,name=\"reset\",...,val=0x8000,...,type=lab".
+    fn load_dbg(&mut self, contents: &str) -> usize {
+        let mut count = 0;
+        for line in contents.lines() {
+            let Some(attrs) = line.strip_prefix("sym\t") else {
+                continue;
+            };
+            let mut name = None;
+            let mut val = None;
+            let mut is_label = false;
+            for attr in attrs.split(',') {
+                match attr.split_once('=') {
+                    Some(("name", v)) => name = Some(v.trim_matches('"')),
+                    Some(("val", v)) => val = u16::from_str_radix(v.trim_start_matches("0x"), 16).ok(),
+                    Some(("type", "lab")) => is_label = true,
+                    _ => {}
+                }
+            }
+            if let (true, Some(name), Some(val)) = (is_label, name, val) {
+                self.insert(val, name);
+                count += 1;
+            }
+        }
+        count
+    }
+}
@@ -0,0 +1,129 @@
+use std::path::{Path, PathBuf};
+
+use crate::nes::NES;
+
+// Headless runner for test ROMs that follow blargg's result protocol:
+//   $6001-$6003 hold the signature DE B0 61 once the test has started,
+//   $6000 is $80 while running, $81 when it wants a reset, else the result code,
+//   $6004 onward is a zero-terminated text report.
+const SIGNATURE: [u8; 3] = [0xDE, 0xB0, 0x61];
+const STATUS_RUNNING: u8 = 0x80;
+const STATUS_RESET: u8 = 0x81;
+
+// Roughly the 100ms a test asks the user to wait before pressing reset.
+const RESET_DELAY_FRAMES: u32 = 6;
+
+pub const DEFAULT_MAX_FRAMES: u32 = 60 * 120;
+
+pub enum Outcome {
+    Passed,
+    Failed(u8),
+    TimedOut,
+}
+
+pub struct TestResult {
+    pub outcome: Outcome,
+    pub text: String,
+    pub frames: u32,
+}
+
+fn read_text(nes: &NES) -> String {
+    let mut text = String::new();
+    for addr in 0x6004..0x8000u16 {
+        match nes.peek(addr) {
+            0 => break,
+            byte => text.push(byte as char),
+        }
+    }
+    text.trim().to_string()
+}
+
+pub fn run(rom_path: &str, max_frames: u32) -> Result<TestResult, Box<dyn std::error::Error>> {
+    let mut nes = NES::new();
+    nes.load_cartridge(rom_path)?;
+
+    let mut reset_countdown = None;
+    for frame in 0..max_frames {
+        nes.run_frame();
+        nes.frame_done();
+
+        if [nes.peek(0x6001), nes.peek(0x6002), nes.peek(0x6003)] != SIGNATURE {
+            continue;
+        }
+        match nes.peek(0x6000) {
+            STATUS_RUNNING => {}
+            STATUS_RESET => match reset_countdown {
+                Some(0) => {
+                    nes.reset();
+                    reset_countdown = None;
+                }
+                Some(n) => reset_countdown = Some(n - 1),
+                None => reset_countdown = Some(RESET_DELAY_FRAMES),
+            },
+            code => {
+                let outcome = if code == 0 { Outcome::Passed } else { Outcome::Failed(code) };
+                return Ok(TestResult { outcome, text: read_text(&nes), frames: frame + 1 });
+            }
+        }
+    }
+
+    Ok(TestResult { outcome: Outcome::TimedOut, text: read_text(&nes), frames: max_frames })
+}
+
+// The ROMs named, with directories searched for .nes files, in a stable order.
+pub fn find_roms(paths: &[String]) -> Vec<PathBuf> {
+    let mut roms = Vec::new();
+    for path in paths.iter().map(Path::new) {
+        if path.is_dir() {
+            collect_roms(path, &mut roms);
+        } else {
+            roms.push(path.to_path_buf());
+        }
+    }
+    roms.sort();
+    roms
+}
+
+// Runs each ROM (directories are searched for .nes files) and prints a scoreboard.
+// Returns true when every test passed.
+pub fn run_all(paths: &[String], max_frames: u32) -> bool {
+    let roms = find_roms(paths);
+
+    let mut passed = 0;
+    for rom in &roms {
+        let name = rom.display();
+        match run(&rom.to_string_lossy(), max_frames) {
+            Ok(result) => {
+                let status = match result.outcome {
+                    Outcome::Passed => {
+                        passed += 1;
+                        "PASS".to_string()
+                    }
+                    Outcome::Failed(code) => format!("FAIL #{}", code),
+                    Outcome::TimedOut => "TIMEOUT".to_string(),
+                };
+                println!("{:<9} {} ({} frames)", status, name, result.frames);
+                if !matches!(result.outcome, Outcome::Passed) && !result.text.is_empty() {
+                    for line in result.text.lines() {
+                        println!("          {}", line);
+                    }
+                }
+            }
+            Err(e) => println!("{:<9} {} ({})", "ERROR", name, e),
+        }
+    }
+
+    println!("{}/{} passed", passed, roms.len());
+    passed == roms.len()
+}
+
+fn collect_roms(dir: &Path, roms: &mut Vec<PathBuf>) {
+    let Ok(entries) = std::fs::read_dir(dir) else { return };
+    for path in entries.flatten().map(|entry| entry.path()) {
+        if path.is_dir() {
+            collect_roms(&path, roms);
+        } else if path.extension().is_some_and(|ext| ext.eq_ignore_ascii_case("nes")) {
+            roms.push(path);
+        }
+    }
+}
@@ -0,0 +1,219 @@
+use std::collections::VecDeque;
+use std::fs::{self, File};
+use std::io::{self, BufRead, BufReader, BufWriter, Lines, Write};
+
+use crate::expr::{self, Expr};
+use crate::symbols::SymbolTable;
+
+const DEFAULT_MAX_BYTES: u64 = 256 * 1024 * 1024;
+const DEFAULT_KEEP: usize = 3;
+
+// Instruction trace written to a file. Only instructions inside the address ranges /
+// PRG banks (when given) and for which the condition holds are logged. When the file
+// reaches max_bytes it is rotated to file.1, file.2, ... keeping the newest `keep`.
+#[derive(Debug)]
+pub struct TraceLog {
+    path: String,
+    writer: BufWriter<File>,
+    written: u64,
+    pub ranges: Vec<(u16, u16)>,
+    pub banks: Vec<usize>,
+    pub condition: Option<(String, Expr)>,
+    pub max_bytes: u64,
+    pub keep: usize,
+}
+
+impl TraceLog {
+    pub fn create(path: &str) -> io::Result<Self> {
+        Ok(TraceLog {
+            path: path.to_string(),
+            writer: BufWriter::new(File::create(path)?),
+            written: 0,
+            ranges: Vec::new(),
+            banks: Vec::new(),
+            condition: None,
+            max_bytes: DEFAULT_MAX_BYTES,
+            keep: DEFAULT_KEEP,
+        })
+    }
+
+    // Parses "<file> [range <lo>-<hi>]... [bank <n>]... [max <MB>] [keep <n>] [if <expr>]".
+    pub fn from_args(words: &[&str], symbols: &SymbolTable) -> Result<Self, String> {
+        let (&path, mut rest) = words.split_first().ok_or("missing file name")?;
+        let mut log = TraceLog::create(path).map_err(|e| format!("{}: {}", path, e))?;
+
+        while let Some((&option, tail)) = rest.split_first() {
+            if option == "if" {
+                let text = tail.join(" ");
+                log.condition = Some((text.clone(), Expr::parse(&text, symbols)?));
+                break;
+            }
+            let value = *tail.first().ok_or(format!("{} needs a value", option))?;
+            match option {
+                "range" => {
+                    let (lo, hi) = value.split_once('-').ok_or("range must look like lo-hi")?;
+                    let parse = |s: &str| crate::debugger::parse_addr(s, symbols).ok_or(format!("bad address '{}'", s));
+                    log.ranges.push((parse(lo)?, parse(hi)?));
+                }
+                "bank" => log.banks.push(value.parse().map_err(|_| format!("bad bank '{}'", value))?),
+                "max" => {
+                    let mb: u64 = value.parse().map_err(|_| format!("bad size '{}'", value))?;
+                    log.max_bytes = mb.max(1) * 1024 * 1024;
+                }
+                "keep" => log.keep = value.parse().map_err(|_| format!("bad count '{}'", value))?,
+                _ => return Err(format!("unknown trace option '{}'", option)),
+            }
+            rest = &tail[1..];
+        }
+        Ok(log)
+    }
+
+    pub fn path(&self) -> &str {
+        &self.path
+    }
+
+    pub fn wants(&self, pc: u16, bank: Option<usize>, ctx: &dyn expr::Context) -> bool {
+        (self.ranges.is_empty() || self.ranges.iter().any(|&(lo, hi)| pc >= lo && pc <= hi))
+            && (self.banks.is_empty() || bank.is_some_and(|b| self.banks.contains(&b)))
+            && self.condition.as_ref().is_none_or(|(_, expr)| expr.is_true(ctx))
+    }
+
+    pub fn write(&mut self, line: &str) -> io::Result<()> {
+        if self.written + line.len() as u64 + 1 > self.max_bytes {
+            self.rotate()?;
+        }
+        writeln!(self.writer, "{}", line)?;
+        self.written += line.len() as u64 + 1;
+        Ok(())
+    }
+
+    fn rotate(&mut self) -> io::Result<()> {
+        self.writer.flush()?;
+        if self.keep == 0 {
+            self.writer = BufWriter::new(File::create(&self.path)?);
+        } else {
+            for i in (1..self.keep).rev() {
+                let from = format!("{}.{}", self.path, i);
+                if fs::metadata(&from).is_ok() {
+                    fs::rename(&from, format!("{}.{}", self.path, i + 1))?;
+                }
+            }
+            fs::rename(&self.path, format!("{}.1", self.path))?;
+            self.writer = BufWriter::new(File::create(&self.path)?);
+        }
+        self.written = 0;
+        Ok(())
+    }
+}
+
+impl Drop for TraceLog {
+    fn drop(&mut self) {
+        self.writer.flush().ok();
+    }
+}
+
+// CPU state before one instruction, kept cheaply for every instruction so crash reports
+// can show how execution got there.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct CpuSnapshot {
+    pub pc: u16,
+    pub a: u8,
+    pub x: u8,
+    pub y: u8,
+    pub p: u8,
+    pub sp: u8,
+    pub scanline: i16,
+    pub dot: u16,
+}
+
+const HISTORY_LEN: usize = 1024;
+
+// Ring buffer of the most recent instructions.
+#[derive(Debug)]
+pub struct CpuHistory {
+    entries: Box<[CpuSnapshot; HISTORY_LEN]>,
+    next: usize,
+    count: usize,
+}
+
+impl Default for CpuHistory {
+    fn default() -> Self {
+        CpuHistory { entries: Box::new([CpuSnapshot::default(); HISTORY_LEN]), next: 0, count: 0 }
+    }
+}
+
+impl CpuHistory {
+    #[inline]
+    pub fn record(&mut self, snapshot: CpuSnapshot) {
+        self.entries[self.next] = snapshot;
+        self.next = (self.next + 1) % HISTORY_LEN;
+        self.count = (self.count + 1).min(HISTORY_LEN);
+    }
+
+    // Oldest first
+    pub fn iter(&self) -> impl Iterator<Item = &CpuSnapshot> {
+        let start = (self.next + HISTORY_LEN - self.count) % HISTORY_LEN;
+        (0..self.count).map(move |i| &self.entries[(start + i) % HISTORY_LEN])
+    }
+}
+
+// Frame boundary marker, logged after each completed frame so comparisons also catch
+// differences in the picture.
+pub fn frame_line(frame: u64, frame_hash: u64) -> String {
+    format!("FRAME {} {:016x}", frame, frame_hash)
+}
+
+const COMPARE_CONTEXT: usize = 8;
+
+pub enum Comparison {
+    Match,
+    Diverged(String), // Report with the lines leading up to it
+    Finished(String), // The reference ran out (or could not be read)
+}
+
+// A/B comparison against a reference trace: record one build or configuration with
+// --trace (no filters), run the other with --compare on the same inputs, and execution
+// stops at the first instruction or frame that differs.
+#[derive(Debug)]
+pub struct TraceCompare {
+    path: String,
+    reference: Lines<BufReader<File>>,
+    line_number: usize,
+    recent: VecDeque<String>,
+}
+
+impl TraceCompare {
+    pub fn open(path: &str) -> io::Result<Self> {
+        Ok(TraceCompare {
+            path: path.to_string(),
+            reference: BufReader::new(File::open(path)?).lines(),
+            line_number: 0,
+            recent: VecDeque::with_capacity(COMPARE_CONTEXT),
+        })
+    }
+
+    pub fn check(&mut self, actual: &str) -> Comparison {
+        self.line_number += 1;
+        let expected = match self.reference.next() {
+            Some(Ok(line)) => line,
+            Some(Err(e)) => return Comparison::Finished(format!("Reading {} failed at line {}: {}", self.path, self.line_number, e)),
+            None => return Comparison::Finished(format!("Reference trace {} ended after {} lines with no divergence", self.path, self.line_number - 1)),
+        };
+        if expected != actual {
+            let mut report = format!("Divergence at {}:{}\n", self.path, self.line_number);
+            if !self.recent.is_empty() {
+                report.push_str("Matching lines before it:\n");
+                for line in &self.recent {
+                    report.push_str(&format!("           {}\n", line));
+                }
+            }
+            report.push_str(&format!("  expected {}\n  actual   {}", expected, actual));
+            return Comparison::Diverged(report);
+        }
+        if self.recent.len() == COMPARE_CONTEXT {
+            self.recent.pop_front();
+        }
+        self.recent.push_back(expected);
+        Comparison::Match
+    }
+}
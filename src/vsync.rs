@@ -0,0 +1,100 @@
+// Presentation on high refresh rate displays. At 60 Hz the frame pacer's timer does a better
+// job than vsync: it can read input closer to the deadline. At 120 or 144 Hz, though, a
+// timer can't line frames up with refreshes, and motion judders and tears. There the window
+// presents with vsync, so each loop iteration is one host refresh, and this decides which
+// refreshes get a new emulated frame:
+//
+//   120 Hz  new, repeat, new, repeat, ...          (every frame shown for 2 refreshes)
+//   144 Hz  new, repeat, new, repeat, repeat, ...  (2 or 3 refreshes, 2.4 on average)
+//
+// Over time the console runs at the display's refresh divided by that average, a fraction of
+// a percent off its own frame rate, which the audio rate control absorbs.
+//
+// Black frame insertion shows black instead of the repeats. A sample-and-hold display keeps
+// each frame lit the whole time, and the eye tracking a moving sprite smears it; lighting it
+// for one refresh only makes motion much sharper, at the cost of brightness. It needs a
+// refresh rate that is a whole multiple of the frame rate, or frames would alternate between
+// different lit fractions and flicker.
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Mode {
+    Auto, // Vsync on displays of at least HIGH_REFRESH Hz
+    On,
+    Off,
+}
+
+// Refresh rates from here up count as high.
+const HIGH_REFRESH: i32 = 100;
+
+impl Mode {
+    pub fn parse(name: &str) -> Option<Self> {
+        match name.to_ascii_lowercase().as_str() {
+            "auto" => Some(Mode::Auto),
+            "on" => Some(Mode::On),
+            "off" => Some(Mode::Off),
+            _ => None,
+        }
+    }
+
+    // Whether to present with vsync on a display refreshing at `refresh_hz`, 0 if unknown.
+    pub fn enabled(self, refresh_hz: i32) -> bool {
+        match self {
+            Mode::Auto => refresh_hz >= HIGH_REFRESH,
+            Mode::On => refresh_hz > 0,
+            Mode::Off => false,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Refresh {
+    NewFrame,
+    Repeat,
+    Black,
+}
+
+// How far from a whole number of refreshes per frame black frames are still allowed. The
+// frames_per_refresh drift then only puts two lit refreshes in a row every few seconds.
+const BLACK_FRAME_TOLERANCE: f64 = 0.02;
+
+pub struct RefreshScheduler {
+    frames_per_refresh: f64,
+    phase: f64,
+    black_frames: bool,
+}
+
+impl RefreshScheduler {
+    pub fn new(frame_rate: f64, refresh_hz: i32) -> Self {
+        // Starting full means the first refresh gets a frame
+        RefreshScheduler { frames_per_refresh: frame_rate / refresh_hz as f64, phase: 1.0, black_frames: false }
+    }
+
+    // Refreshes shown per emulated frame, for the startup message.
+    pub fn refreshes_per_frame(&self) -> f64 {
+        1.0 / self.frames_per_refresh
+    }
+
+    // Shows black instead of repeats, if the refresh rate allows it.
+    pub fn insert_black_frames(&mut self) -> Result<(), String> {
+        let refreshes = self.refreshes_per_frame();
+        if refreshes.round() < 2.0 || (refreshes - refreshes.round()).abs() > BLACK_FRAME_TOLERANCE {
+            return Err(format!("black frames need a refresh rate of 2, 3 or more times the frame rate, not {:.2} times", refreshes));
+        }
+        self.black_frames = true;
+        Ok(())
+    }
+
+    // Called once per host refresh.
+    pub fn next_refresh(&mut self) -> Refresh {
+        let refresh = if self.phase >= 1.0 {
+            self.phase -= 1.0;
+            Refresh::NewFrame
+        } else if self.black_frames {
+            Refresh::Black
+        } else {
+            Refresh::Repeat
+        };
+        self.phase += self.frames_per_refresh;
+        refresh
+    }
+}
@@ -0,0 +1,50 @@
+use std::fs;
+use std::time::{Duration, Instant, SystemTime};
+
+// `--watch`: notices when the ROM file is rebuilt, so the window can reload it. The file's
+// modification time is polled, and a change only counts once it has held for a whole poll,
+// so a ROM caught halfway through being written by the assembler or linker isn't loaded.
+
+const POLL_INTERVAL: Duration = Duration::from_millis(250);
+
+pub struct RomWatcher {
+    path: String,
+    modified: Option<SystemTime>,
+    pending: Option<SystemTime>,
+    next_poll: Instant,
+}
+
+fn modified(path: &str) -> Option<SystemTime> {
+    fs::metadata(path).and_then(|meta| meta.modified()).ok()
+}
+
+impl RomWatcher {
+    pub fn new(path: &str) -> Self {
+        RomWatcher { path: path.to_string(), modified: modified(path), pending: None, next_poll: Instant::now() + POLL_INTERVAL }
+    }
+
+    // True when the file has changed and settled since it was last reported. Cheap to call
+    // every frame; it only looks at the file every POLL_INTERVAL.
+    pub fn poll(&mut self) -> bool {
+        let now = Instant::now();
+        if now < self.next_poll {
+            return false;
+        }
+        self.next_poll = now + POLL_INTERVAL;
+        // A missing file is mid-rebuild (some tools delete before writing)
+        let Some(current) = modified(&self.path) else {
+            return false;
+        };
+        if Some(current) == self.modified {
+            self.pending = None;
+            return false;
+        }
+        if self.pending != Some(current) {
+            self.pending = Some(current);
+            return false;
+        }
+        self.modified = Some(current);
+        self.pending = None;
+        true
+    }
+}
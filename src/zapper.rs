@@ -0,0 +1,63 @@
+use crate::ppu::PPU;
+
+// NES Zapper light gun in controller port 2. Reading $4017 returns:
+//
+//   bit 4  trigger held
+//   bit 3  light NOT sensed (0 while the photodiode sees a bright area)
+//
+// The photodiode only reacts while the beam is drawing the spot it points at, and its
+// output decays over the following scanlines. Games like Duck Hunt rely on that: they
+// flash a white box for one frame and poll $4017 while the beam passes it, so sensing
+// has to follow the beam instead of looking at the finished frame.
+
+// How long the sense line stays active after the beam passes a bright spot
+const SENSE_LINES: i16 = 20;
+// Half-size of the area the photodiode sees, in pixels
+const APERTURE: i16 = 3;
+// Minimum brightness (0-255) that registers as light
+const THRESHOLD: u8 = 0xA0;
+
+#[derive(Debug, Default, Clone, Copy)]
+pub struct Zapper {
+    // Screen position the gun points at, None when aimed off screen
+    pub aim: Option<(i16, i16)>,
+    pub trigger: bool,
+}
+
+impl Zapper {
+    pub fn read(&self, ppu: &PPU) -> u8 {
+        let mut data = 0x08;
+        if self.senses_light(ppu) {
+            data &= !0x08;
+        }
+        if self.trigger {
+            data |= 0x10;
+        }
+        data
+    }
+
+    fn senses_light(&self, ppu: &PPU) -> bool {
+        let Some((aim_x, aim_y)) = self.aim else {
+            return false;
+        };
+        // Pixels already drawn this frame, as (line, dot): the beam is on dot `cycle - 1`
+        let beam_line = ppu.scanline;
+        let beam_x = ppu.cycle as i16 - 1;
+
+        for y in (aim_y - APERTURE).max(0)..=(aim_y + APERTURE).min(239) {
+            let age = beam_line - y;
+            if !(0..=SENSE_LINES).contains(&age) {
+                continue;
+            }
+            for x in (aim_x - APERTURE).max(0)..=(aim_x + APERTURE).min(255) {
+                if age == 0 && x >= beam_x {
+                    break;
+                }
+                if ppu.light[y as usize * 256 + x as usize] >= THRESHOLD {
+                    return true;
+                }
+            }
+        }
+        false
+    }
+}
@@ -0,0 +1,76 @@
+use std::io::Cursor;
+
+use zetr::nes::NES;
+use zetr::region::Region;
+
+// tests/data/framecounter.nes moves its $4017 writes through the frame, switching between
+// the 4- and 5-step sequences, and counts the frame IRQs that get through before the next
+// write restarts the sequence. That makes the count sensitive to where every frame counter
+// step and CPU cycle lands. The expected counts are a baseline: after an intended change
+// to timing, run the test and put in the new ones.
+
+const ROM: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/data/framecounter.nes");
+const FRAMES: u32 = 300;
+const IRQ_COUNT: u16 = 0x0000;
+
+fn boot(region: Region, alignment: u8) -> NES {
+    let mut nes = NES::new();
+    nes.load_cartridge(ROM).expect("loading framecounter.nes");
+    nes.set_region(region);
+    nes.set_alignment(alignment);
+    nes.set_sample_rate(48000);
+    nes.reset();
+    nes
+}
+
+fn run(nes: &mut NES, frames: u32) {
+    for _ in 0..frames {
+        nes.run_frame();
+        nes.frame_done();
+        nes.take_samples();
+    }
+}
+
+#[test]
+fn frame_irqs_land_where_they_did() {
+    // Region, CPU/PPU alignment, frame IRQs taken, final state hash
+    let cases = [
+        (Region::Ntsc, 0, 83, 0x35f1_d073_fdfd_5bfc),
+        (Region::Ntsc, 1, 76, 0x8b91_84c8_4401_f970),
+        (Region::Ntsc, 2, 71, 0x9795_a512_7524_6632),
+        (Region::Pal, 0, 89, 0x1693_1c52_50eb_81f7),
+        (Region::Dendy, 0, 101, 0x25f5_d126_5635_6cd7),
+    ];
+    let mut failures = Vec::new();
+    for (region, alignment, irqs, state) in cases {
+        let mut nes = boot(region, alignment);
+        run(&mut nes, FRAMES);
+        let got = (nes.peek(IRQ_COUNT), nes.state_hash());
+        if got != (irqs, state) {
+            failures.push(format!("{} alignment {}: {} frame IRQs and state {:016x}, expected {} and {:016x}", region.name(), alignment, got.0, got.1, irqs, state));
+        }
+    }
+    assert!(failures.is_empty(), "{}", failures.join("\n"));
+}
+
+// A state saved mid-run and loaded into a fresh console has to carry on exactly as the
+// original does: the frame sequence position, a pending $4017 reset and the channels. Only
+// the output filters and resampler aren't saved, so the samples themselves can differ.
+#[test]
+fn save_states_resume_the_frame_sequence() {
+    for region in [Region::Ntsc, Region::Pal, Region::Dendy] {
+        for split in [1, 37, 150] {
+            let mut original = boot(region, 0);
+            run(&mut original, split);
+            let mut state = Vec::new();
+            original.save_state(&mut state).unwrap();
+            let mut restored = boot(region, 0);
+            restored.load_state(&mut Cursor::new(&state)).unwrap();
+            for frame in split..split + 60 {
+                run(&mut original, 1);
+                run(&mut restored, 1);
+                assert_eq!(restored.state_hash(), original.state_hash(), "{} loaded at frame {}, differs at {}", region.name(), split, frame);
+            }
+        }
+    }
+}
@@ -0,0 +1,59 @@
+#![cfg(feature = "simd-ppu")]
+
+use zetr::bgpipe;
+
+// The eight-pixel pipeline against the PPU's scalar path, which takes one bit from each
+// shifter per dot (bit 15 - fine x, shifting left after every dot) and looks the color up
+// in palette RAM with the backdrop at every fourth entry. Run with
+// `cargo test --features simd-ppu`.
+
+const CASES: usize = 100_000;
+
+// xorshift64, so the cases are the same on every run.
+struct Rng(u64);
+
+impl Rng {
+    fn next(&mut self) -> u64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        self.0
+    }
+}
+
+fn scalar_colors(shifters: [u16; 4], fine_x: u8, palette_ram: &[u8; 32]) -> [u8; 8] {
+    let mut colors = [0u8; 8];
+    for (dot, color) in colors.iter_mut().enumerate() {
+        let bit = |shifter: u16| (shifter << dot >> (15 - fine_x) & 1) as u8;
+        let pixel = bit(shifters[0]) | bit(shifters[1]) << 1;
+        let palette = bit(shifters[2]) | bit(shifters[3]) << 1;
+        let addr = if pixel == 0 { 0 } else { palette << 2 | pixel };
+        *color = palette_ram[addr as usize];
+    }
+    colors
+}
+
+#[test]
+fn matches_the_scalar_path() {
+    let mut rng = Rng(0x9E37_79B9_7F4A_7C15);
+    for _ in 0..CASES {
+        let word = rng.next();
+        let shifters = [word as u16, (word >> 16) as u16, (word >> 32) as u16, (word >> 48) as u16];
+        let fine_x = (rng.next() % 8) as u8;
+        let palette_ram: [u8; 32] = std::array::from_fn(|_| (rng.next() & 0x3F) as u8);
+        assert_eq!(
+            bgpipe::background_group(shifters, fine_x, &palette_ram).1,
+            scalar_colors(shifters, fine_x, &palette_ram),
+            "shifters {:04x?}, fine x {}",
+            shifters,
+            fine_x,
+        );
+    }
+}
+
+#[test]
+fn lookup_masks_indices_to_four_bits() {
+    let table: [u8; 16] = std::array::from_fn(|i| i as u8 * 3);
+    let indices = [0x00, 0x1F, 0x2A, 0x35, 0xF0, 0x8C, 0x47, 0x09];
+    assert_eq!(bgpipe::palette_lookup(&indices, &table), indices.map(|i| table[i as usize & 0x0F]));
+}
@@ -0,0 +1,88 @@
+use std::io::Cursor;
+
+use zetr::nes::{self, NES};
+use zetr::region::Region;
+
+// Both test images on every region and CPU/PPU alignment. checker.nes does an OAM DMA every
+// frame with the sprite moving, so it covers DMA's odd/even cycle handling; framecounter.nes
+// covers the APU frame sequence. The expected state hashes are a baseline for the master
+// clock: after an intended change to timing, run the test and put in the new ones.
+
+const CHECKER: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/data/checker.nes");
+const FRAMECOUNTER: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/data/framecounter.nes");
+const FRAMES: u32 = 60;
+
+fn boot(rom: &str, region: Region, alignment: u8) -> NES {
+    let mut nes = NES::new();
+    nes.load_cartridge(rom).expect("loading the test image");
+    nes.set_region(region);
+    nes.set_alignment(alignment);
+    nes.set_sample_rate(48000);
+    nes.set_controller1(nes::button_mask("right").unwrap() | nes::button_mask("down").unwrap());
+    nes.reset();
+    nes
+}
+
+fn run(nes: &mut NES, frames: u32) {
+    for _ in 0..frames {
+        nes.run_frame();
+        nes.frame_done();
+        nes.take_samples();
+    }
+}
+
+#[test]
+fn every_region_and_alignment_runs_as_it_did() {
+    // Image, region, CPU/PPU alignment, state hash after FRAMES
+    let cases = [
+        (CHECKER, Region::Ntsc, 0, 0x05c5_875d_1082_2c90),
+        (CHECKER, Region::Ntsc, 1, 0xc8bf_a11c_c193_3af9),
+        (CHECKER, Region::Ntsc, 2, 0x34c7_7107_981a_01a9),
+        (CHECKER, Region::Pal, 0, 0x234c_893a_5c58_28ef),
+        (CHECKER, Region::Pal, 1, 0x0952_76dc_5558_f5a1),
+        (CHECKER, Region::Pal, 2, 0xe37d_ca45_5f14_9f02),
+        (CHECKER, Region::Dendy, 0, 0xffde_763b_4f65_0d7e),
+        (CHECKER, Region::Dendy, 1, 0x37a1_5139_b79a_da46),
+        (CHECKER, Region::Dendy, 2, 0x68b2_2ef9_d1c8_7537),
+        (FRAMECOUNTER, Region::Ntsc, 0, 0x6d3f_f7e8_6cfc_1128),
+        (FRAMECOUNTER, Region::Ntsc, 1, 0x3770_6bae_e35a_dc7c),
+        (FRAMECOUNTER, Region::Ntsc, 2, 0xbd50_0ddc_116d_cf94),
+        (FRAMECOUNTER, Region::Pal, 0, 0xc89d_5a72_5409_ce7e),
+        (FRAMECOUNTER, Region::Pal, 1, 0x6b28_fc8b_7dfa_c94a),
+        (FRAMECOUNTER, Region::Pal, 2, 0x1f8b_9d70_66fc_e83d),
+        (FRAMECOUNTER, Region::Dendy, 0, 0xb711_0c9c_b19d_0696),
+        (FRAMECOUNTER, Region::Dendy, 1, 0x5b20_1e12_4993_c799),
+        (FRAMECOUNTER, Region::Dendy, 2, 0x71e2_87d3_eb5c_2a57),
+    ];
+    let mut failures = Vec::new();
+    for (rom, region, alignment, state) in cases {
+        let mut nes = boot(rom, region, alignment);
+        run(&mut nes, FRAMES);
+        let name = rom.rsplit('/').next().unwrap();
+        if nes.state_hash() != state {
+            failures.push(format!("{} on {} alignment {}: state {:016x}, expected {:016x}", name, region.name(), alignment, nes.state_hash(), state));
+        }
+    }
+    assert!(failures.is_empty(), "{}", failures.join("\n"));
+}
+
+// Save states keep the position in dots and fifths of a dot rather than master cycles, so
+// every round trip has to land the CPU back on the same master cycle.
+#[test]
+fn save_states_keep_the_clock_position() {
+    for region in [Region::Ntsc, Region::Pal, Region::Dendy] {
+        for alignment in 0..=2 {
+            let mut original = boot(CHECKER, region, alignment);
+            let mut restored = boot(CHECKER, region, alignment);
+            for frame in 0..FRAMES {
+                let mut state = Vec::new();
+                restored.save_state(&mut state).unwrap();
+                restored = boot(CHECKER, region, alignment);
+                restored.load_state(&mut Cursor::new(&state)).unwrap();
+                run(&mut original, 1);
+                run(&mut restored, 1);
+                assert_eq!(restored.state_hash(), original.state_hash(), "{} alignment {}, frame {}", region.name(), alignment, frame);
+            }
+        }
+    }
+}
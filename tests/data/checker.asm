@@ -0,0 +1,197 @@
+; checker.nes: a small NROM test image for the golden-frame and movie tests.
+; It only uses the instructions the CPU implements, and assembles with asm6:
+;
+;   asm6 checker.asm checker.nes
+;
+; A tiled background fills the screen and one sprite sits in the middle. The
+; d-pad moves the sprite and holding A steps the backdrop color, so the picture
+; depends on the input and the timing of each frame.
+
+PPUCTRL   = $2000
+PPUMASK   = $2001
+PPUSTATUS = $2002
+OAMADDR   = $2003
+PPUSCROLL = $2005
+PPUADDR   = $2006
+PPUDATA   = $2007
+OAMDMA    = $4014
+JOY1      = $4016
+
+tile      = $00     ; next background tile while filling the nametable
+backdrop  = $11     ; universal background color
+oam       = $0200   ; sprite 0: Y, tile, attributes, X
+
+    .db "NES", $1A
+    .db 1           ; 16KB PRG
+    .db 1           ; 8KB CHR
+    .db $00, $00    ; mapper 0, horizontal mirroring
+    .db 0, 0, 0, 0, 0, 0, 0, 0
+
+    .base $C000
+
+reset:
+    sei
+    cld
+    ldx #$FF
+    txs
+    lda #0
+    sta PPUCTRL
+    sta PPUMASK
+vblank1:
+    lda PPUSTATUS
+    bpl vblank1
+vblank2:
+    lda PPUSTATUS
+    bpl vblank2
+
+    lda #$3F
+    sta PPUADDR
+    lda #$00
+    sta PPUADDR
+    ldy #0
+load_palette:
+    lda palette,y
+    sta PPUDATA
+    iny
+    tya
+    cmp #$20
+    bne load_palette
+
+    ; Tiles 1, 2, 3 repeating over the nametable and attribute table
+    lda #$20
+    sta PPUADDR
+    lda #$00
+    sta PPUADDR
+    lda #1
+    sta tile
+    ldx #4
+fill_page:
+    ldy #0
+fill_tile:
+    lda tile
+    sta PPUDATA
+    clc
+    adc #1
+    cmp #4
+    bne next_tile
+    lda #1
+next_tile:
+    sta tile
+    dey
+    bne fill_tile
+    dex
+    bne fill_page
+
+    ; Every sprite off screen but the first
+    lda #$FF
+    ldy #0
+clear_oam:
+    sta oam,y
+    iny
+    bne clear_oam
+    lda #$70
+    sta oam
+    lda #3
+    sta oam+1
+    lda #0
+    sta oam+2
+    lda #$78
+    sta oam+3
+
+    lda #$0F
+    sta backdrop
+    lda #0
+    sta PPUSCROLL
+    sta PPUSCROLL
+    lda #$80        ; NMI on
+    sta PPUCTRL
+    lda #$1E        ; background and sprites, left column included
+    sta PPUMASK
+forever:
+    jmp forever
+
+nmi:
+    lda #0
+    sta OAMADDR
+    lda #>oam
+    sta OAMDMA
+
+    lda #1
+    sta JOY1
+    lda #0
+    sta JOY1
+    lda JOY1        ; A
+    beq read_b
+    lda backdrop
+    clc
+    adc #1
+    cmp #$40
+    bne store_backdrop
+    lda #0
+store_backdrop:
+    sta backdrop
+read_b:
+    lda JOY1        ; B
+    lda JOY1        ; Select
+    lda JOY1        ; Start
+    lda JOY1        ; Up
+    beq read_down
+    lda oam
+    sec
+    sbc #1
+    sta oam
+read_down:
+    lda JOY1        ; Down
+    beq read_left
+    lda oam
+    clc
+    adc #1
+    sta oam
+read_left:
+    lda JOY1        ; Left
+    beq read_right
+    lda oam+3
+    sec
+    sbc #1
+    sta oam+3
+read_right:
+    lda JOY1        ; Right
+    beq set_backdrop
+    lda oam+3
+    clc
+    adc #1
+    sta oam+3
+
+set_backdrop:
+    lda #$3F
+    sta PPUADDR
+    lda #$00
+    sta PPUADDR
+    lda backdrop
+    sta PPUDATA
+    lda #0
+    sta PPUSCROLL
+    sta PPUSCROLL
+    lda #$80
+    sta PPUCTRL
+irq:
+    rti
+
+palette:
+    .db $0F, $16, $27, $18,  $0F, $1A, $2A, $3A,  $0F, $12, $22, $32,  $0F, $14, $24, $34
+    .db $0F, $30, $16, $27,  $0F, $30, $16, $27,  $0F, $30, $16, $27,  $0F, $30, $16, $27
+
+    .pad $FFFA
+    .dw nmi, reset, irq
+
+    .base $0000
+
+    .dsb 16, $00                                   ; 0: blank
+    .db $AA, $55, $AA, $55, $AA, $55, $AA, $55     ; 1: checkerboard in color 1
+    .db $00, $00, $00, $00, $00, $00, $00, $00
+    .db $00, $00, $00, $00, $00, $00, $00, $00     ; 2: solid color 2
+    .db $FF, $FF, $FF, $FF, $FF, $FF, $FF, $FF
+    .db $3C, $7E, $FF, $FF, $FF, $FF, $7E, $3C     ; 3: ball, color 3 inside color 1
+    .db $00, $18, $3C, $7E, $7E, $3C, $18, $00
+
+    .pad $2000
@@ -0,0 +1,148 @@
+; framecounter.nes: an NROM image that works the APU frame counter, for tests/apu.rs.
+; Like checker.asm it only uses the instructions the CPU implements, and assembles with
+; asm6:
+;
+;   asm6 framecounter.asm framecounter.nes
+;
+; Every frame the NMI handler waits a while, then writes $4017: 4-step with the IRQ on,
+; 5-step, and 4-step with the IRQ inhibited in turn. Each write restarts the sequence. The
+; wait grows by 18 to 40 loops from one frame to the next, wrapping at 256, so on NTSC the
+; time between writes straddles the point where the 4-step sequence raises its IRQ, a few
+; dozen cycles past the end of a frame, and whether each IRQ gets in depends on exactly
+; where the writes land. PAL and Dendy frames are long enough that it nearly always does,
+; which leaves the sequence itself to check there. The IRQ handler counts frame IRQs and
+; acknowledges them through $4015. The pulse channels play a constant tone so the length
+; counters and envelopes are clocked by the sequence as well.
+
+PPUCTRL   = $2000
+PPUMASK   = $2001
+PPUSTATUS = $2002
+APUSTATUS = $4015
+FRAMECTR  = $4017
+
+irqs      = $00     ; frame IRQs taken, wrapping at 256
+delay     = $01     ; this frame's wait, in 5-cycle loops
+step      = $02     ; which $4017 value this frame writes
+grow      = $03     ; which entry of growth the wait moves on by next
+
+    .db "NES", $1A
+    .db 1           ; 16KB PRG
+    .db 1           ; 8KB CHR
+    .db $00, $00    ; mapper 0, horizontal mirroring
+    .db 0, 0, 0, 0, 0, 0, 0, 0
+
+    .base $C000
+
+reset:
+    sei
+    cld
+    ldx #$FF
+    txs
+    lda #0
+    sta PPUCTRL
+    sta PPUMASK
+vblank1:
+    lda PPUSTATUS
+    bpl vblank1
+vblank2:
+    lda PPUSTATUS
+    bpl vblank2
+
+    lda #0
+    sta irqs
+    sta delay
+    sta step
+    sta grow
+    ; Pulse 1 with a decaying, looping envelope and pulse 2 at constant volume, both with
+    ; long notes
+    lda #$03
+    sta APUSTATUS
+    lda #$A4
+    sta $4000
+    lda #$00
+    sta $4001
+    lda #$FD
+    sta $4002
+    lda #$F8
+    sta $4003
+    lda #$5A
+    sta $4004
+    lda #$00
+    sta $4005
+    lda #$A9
+    sta $4006
+    lda #$A8
+    sta $4007
+
+    lda #$00
+    sta FRAMECTR
+    lda #$80
+    sta PPUCTRL
+    cli
+forever:
+    jmp forever
+
+nmi:
+    pha
+    txa
+    pha
+    tya
+    pha
+
+    ldy delay
+    beq write
+wait:
+    dey
+    bne wait
+
+write:
+    ldx step
+    lda modes,x
+    sta FRAMECTR
+    inx
+    txa
+    cmp #3
+    bne next_step
+    lda #0
+next_step:
+    sta step
+    ldx grow
+    lda delay
+    clc
+    adc growth,x
+    sta delay
+    inx
+    txa
+    cmp #7
+    bne next_grow
+    lda #0
+next_grow:
+    sta grow
+
+    pla
+    tay
+    pla
+    tax
+    pla
+    rti
+
+irq:
+    pha
+    lda APUSTATUS
+    lda irqs
+    clc
+    adc #1
+    sta irqs
+    pla
+    rti
+
+modes:
+    .db $00, $80, $40
+growth:
+    .db 18, 23, 25, 26, 28, 31, 40
+
+    .pad $FFFA
+    .dw nmi, reset, irq
+
+    .base $0000
+    .pad $2000
@@ -0,0 +1,10 @@
+use zetr::golden;
+
+// The checked-in golden frames, rendered from tests/data/checker.nes, which draws a
+// background and a sprite that moves with the input.
+const MANIFEST: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/golden/golden.txt");
+
+#[test]
+fn golden_frames_match() {
+    assert!(golden::run(MANIFEST, false).expect("reading the golden manifest"), "golden frames changed, see the output above");
+}
@@ -0,0 +1,48 @@
+use zetr::latency::LatencyTest;
+
+// The GAME measurement on made-up pictures: a corner that blinks every few frames, like
+// a game's animated water or cursor, and a pixel elsewhere that answers the press.
+
+const FRAME: usize = 256 * 240 * 3;
+
+fn picture(frame: u32, answered: bool) -> Vec<u8> {
+    let mut picture = vec![0u8; FRAME];
+    if frame / 4 % 2 == 1 {
+        picture[..16 * 3].fill(0xFF);
+    }
+    if answered {
+        picture[FRAME / 2..FRAME / 2 + 3].fill(0xFF);
+    }
+    picture
+}
+
+#[test]
+fn animation_is_not_a_response() {
+    let mut test = LatencyTest::new(60.0);
+    for frame in 0..60 {
+        test.frame(&picture(frame, false));
+    }
+    test.press(0);
+    test.presented(16);
+    for frame in 60..63 {
+        test.frame(&picture(frame, false));
+    }
+    test.frame(&picture(63, true));
+    let summary = test.summary().unwrap();
+    assert!(summary.ends_with("game 4.0 frames"), "{}", summary);
+}
+
+#[test]
+fn presses_with_no_response_are_not_counted() {
+    let mut test = LatencyTest::new(60.0);
+    for frame in 0..60 {
+        test.frame(&picture(frame, false));
+    }
+    test.press(0);
+    test.presented(16);
+    for frame in 60..200 {
+        test.frame(&picture(frame, false));
+    }
+    let summary = test.summary().unwrap();
+    assert!(summary.ends_with("game -"), "{}", summary);
+}
@@ -0,0 +1,172 @@
+use std::io::Cursor;
+
+use zetr::cartridge::{Cartridge, Mirroring};
+use zetr::mapper::{self, Cnrom, Contra100, Mapper, Nrom};
+
+// Boards on their own and through Cartridge, with synthetic iNES images whose every 8KB CHR
+// bank (and 16KB PRG bank) is filled with its own number.
+
+fn image(mapper: u8, prg_banks: u8, chr_banks: u8) -> Vec<u8> {
+    let mut data = vec![b'N', b'E', b'S', 0x1A, prg_banks, chr_banks, mapper << 4, mapper & 0xF0];
+    data.resize(16, 0);
+    for bank in 0..prg_banks {
+        data.extend(std::iter::repeat_n(bank, 0x4000));
+    }
+    for bank in 0..chr_banks {
+        data.extend(std::iter::repeat_n(bank, 0x2000));
+    }
+    data
+}
+
+#[test]
+fn supported_mappers_have_boards() {
+    for number in mapper::SUPPORTED {
+        assert!(mapper::for_number(number).is_some(), "mapper {}", number);
+    }
+    assert!(mapper::for_number(1).is_none());
+}
+
+#[test]
+fn nrom_mirrors_16kb_prg() {
+    assert_eq!(Nrom.prg_offset(0x4000, 0x4000), 0);
+    assert_eq!(Nrom.prg_offset(0x6000, 0x4000), 0x2000);
+    assert_eq!(Nrom.prg_offset(0x6000, 0x8000), 0x6000);
+    assert_eq!(Nrom.chr_offset(0x1C00, 0x2000), 0x1C00);
+}
+
+#[test]
+fn nes2_mapper_numbers_keep_their_high_bits() {
+    let mut data = image(3, 1, 1);
+    data[7] |= 0x08; // NES 2.0
+    data[8] = 0x01; // Mapper 259, not CNROM
+    let cart = Cartridge::from_bytes(&data).unwrap();
+    assert_eq!(cart.mapper, 0x103);
+    assert_eq!(cart.board_name(), "NROM");
+}
+
+#[test]
+fn cnrom_masks_the_bank_to_the_chr_size() {
+    for banks in [1, 2, 4] {
+        let chr_len = banks * 0x2000;
+        for value in [0u8, 1, 2, 3, 5, 0x81, 0xFF] {
+            let mut board = Cnrom::default();
+            board.write_register(0x7FFF, value);
+            let bank = value as usize & (banks - 1);
+            assert_eq!(board.chr_offset(0x0400, chr_len), bank * 0x2000 + 0x0400, "{} banks, wrote {:#04x}", banks, value);
+        }
+    }
+}
+
+#[test]
+fn cnrom_switches_chr_through_the_cartridge() {
+    let mut cart = Cartridge::from_bytes(&image(3, 2, 4)).unwrap();
+    assert_eq!(cart.board_name(), "CNROM");
+    assert_eq!(cart.read_chr(0x0000), 0);
+    cart.write_prg(0x0000, 2);
+    assert_eq!(cart.read_chr(0x0000), 2);
+    assert_eq!(cart.read_chr(0x1FFF), 2);
+    // Unused high bits are ignored
+    cart.write_prg(0x4000, 0xF1);
+    assert_eq!(cart.read_chr(0x1000), 1);
+    // PRG is untouched: 32KB, no mirroring
+    assert_eq!(cart.read_prg(0x0000), 0);
+    assert_eq!(cart.read_prg(0x4000), 1);
+}
+
+#[test]
+fn cnrom_bank_survives_a_state_round_trip() {
+    let mut cart = Cartridge::from_bytes(&image(3, 1, 4)).unwrap();
+    cart.write_prg(0x0000, 3);
+    let mut state = Vec::new();
+    cart.save_state(&mut state).unwrap();
+    cart.write_prg(0x0000, 0);
+    assert_eq!(cart.read_chr(0x0000), 0);
+    cart.load_state(&mut Cursor::new(&state)).unwrap();
+    assert_eq!(cart.read_chr(0x0000), 3);
+}
+
+// The 16KB PRG banks at $8000 and $C000, by the number they're filled with.
+fn prg_banks(cart: &Cartridge) -> (u8, u8) {
+    (cart.read_prg(0x0000), cart.read_prg(0x4000))
+}
+
+#[test]
+fn contra100_prg_modes() {
+    let prg_len = 64 * 0x4000;
+    let windows = |board: &Contra100| [0x0000, 0x2000, 0x4000, 0x6000].map(|addr| board.prg_offset(addr, prg_len) / 0x2000);
+    let mut board = Contra100::default();
+    board.write_register(0x0000, 0x04);
+    assert_eq!(windows(&board), [8, 9, 10, 11]);
+    board.write_register(0x0001, 0x0A);
+    assert_eq!(windows(&board), [20, 21, 30, 31]);
+    board.write_register(0x0002, 0x85);
+    assert_eq!(windows(&board), [11, 11, 11, 11]);
+    board.write_register(0x7FFF, 0x05);
+    assert_eq!(windows(&board), [10, 11, 10, 11]);
+}
+
+#[test]
+fn contra100_protects_chr_ram_in_the_nrom_modes() {
+    let mut cart = Cartridge::from_bytes(&image(15, 8, 0)).unwrap();
+    assert_eq!(cart.board_name(), "100-in-1 Contra Function 16");
+    for (mode, writable) in [(0, false), (1, true), (2, true), (3, false)] {
+        cart.write_prg(mode, 0x40);
+        assert!(matches!(cart.mirroring, Mirroring::Horizontal));
+        cart.write_chr(0x0100, 0x50 + mode as u8);
+        assert_eq!(cart.read_chr(0x0100) == 0x50 + mode as u8, writable, "mode {}", mode);
+    }
+}
+
+#[test]
+fn multicart225_latches_the_address() {
+    let mut cart = Cartridge::from_bytes(&image(225, 128, 128)).unwrap();
+    assert_eq!(cart.board_name(), "225 multicart");
+    // 16KB mode with the high bit: PRG and CHR bank 64 plus the low bits
+    cart.write_prg(0x4000 | 0x1000 | 5 << 6 | 3, 0xFF);
+    assert_eq!(prg_banks(&cart), (69, 69));
+    assert_eq!(cart.read_chr(0x1234), 67);
+    assert!(matches!(cart.mirroring, Mirroring::Vertical));
+    // 32KB mode ignores the low bank bit
+    cart.write_prg(0x2000 | 7 << 6, 0);
+    assert_eq!(prg_banks(&cart), (6, 7));
+    assert_eq!(cart.read_chr(0x0000), 0);
+    assert!(matches!(cart.mirroring, Mirroring::Horizontal));
+}
+
+#[test]
+fn action52_skips_the_missing_chip() {
+    let mut cart = Cartridge::from_bytes(&image(228, 96, 64)).unwrap();
+    assert_eq!(cart.board_name(), "Action 52");
+    // Chip 3, 16KB bank 3 in it, which is the file's third 512KB
+    cart.write_prg(0x1800 | 3 << 6 | 0x20 | 0x05, 0x02);
+    assert_eq!(prg_banks(&cart), (67, 67));
+    assert_eq!(cart.read_chr(0x0000), 22);
+    // Chip 1 in 32KB mode
+    cart.write_prg(0x2000 | 0x0800 | 3 << 6, 0);
+    assert_eq!(prg_banks(&cart), (34, 35));
+    assert!(matches!(cart.mirroring, Mirroring::Horizontal));
+}
+
+#[test]
+fn multicarts_go_back_to_the_menu_on_reset() {
+    for number in [15, 225, 228] {
+        let mut cart = Cartridge::from_bytes(&image(number, 64, 8)).unwrap();
+        cart.write_prg(0x1000 | 9 << 6 | 0x23, 9);
+        assert_ne!(prg_banks(&cart), (0, 1), "mapper {}", number);
+        cart.reset();
+        assert_eq!(prg_banks(&cart), (0, 1), "mapper {}", number);
+    }
+}
+
+#[test]
+fn multicart_latches_survive_a_state_round_trip() {
+    let mut cart = Cartridge::from_bytes(&image(228, 32, 16)).unwrap();
+    cart.write_prg(0x2000 | 5 << 6 | 0x20 | 0x03, 0x01);
+    let mut state = Vec::new();
+    cart.save_state(&mut state).unwrap();
+    cart.write_prg(0x0000, 0);
+    cart.load_state(&mut Cursor::new(&state)).unwrap();
+    assert_eq!(prg_banks(&cart), (5, 5));
+    assert_eq!(cart.read_chr(0x0000), 13);
+    assert!(matches!(cart.mirroring, Mirroring::Horizontal));
+}
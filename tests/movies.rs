@@ -0,0 +1,10 @@
+use zetr::movietest;
+
+// Checked-in movies with the machine state hash each one ends on. checker.fm2 plays
+// tests/data/checker.nes with d-pad and A input and a reset partway through.
+const MANIFEST: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/movies/movies.txt");
+
+#[test]
+fn movies_end_in_the_recorded_state() {
+    assert!(movietest::run(MANIFEST, false).expect("reading the movie manifest"), "movie end states changed, see the output above");
+}
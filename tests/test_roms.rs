@@ -0,0 +1,33 @@
+use std::path::Path;
+
+use zetr::testrom::{self, Outcome};
+
+// blargg's test ROMs aren't ours to check in. Put them (or any ROM using the same result
+// protocol) under tests/roms, or point ZETR_TEST_ROMS at a directory of them, and every
+// .nes file found is run and has to pass. With none there this test passes without running
+// anything, and says so.
+const DEFAULT_DIR: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/roms");
+
+#[test]
+fn blargg_test_roms_pass() {
+    let dir = std::env::var("ZETR_TEST_ROMS").unwrap_or(DEFAULT_DIR.to_string());
+    let roms = if Path::new(&dir).is_dir() { testrom::find_roms(std::slice::from_ref(&dir)) } else { Vec::new() };
+    if roms.is_empty() {
+        eprintln!("no test ROMs in {}, skipped", dir);
+        return;
+    }
+
+    let mut failures = Vec::new();
+    for rom in &roms {
+        let name = rom.display();
+        match testrom::run(&rom.to_string_lossy(), testrom::DEFAULT_MAX_FRAMES) {
+            Ok(result) => match result.outcome {
+                Outcome::Passed => {}
+                Outcome::Failed(code) => failures.push(format!("{}: failed #{}: {}", name, code, result.text)),
+                Outcome::TimedOut => failures.push(format!("{}: timed out after {} frames", name, result.frames)),
+            },
+            Err(e) => failures.push(format!("{}: {}", name, e)),
+        }
+    }
+    assert!(failures.is_empty(), "{}/{} test ROMs failed:\n{}", failures.len(), roms.len(), failures.join("\n"));
+}